@@ -0,0 +1,99 @@
+use std::{
+    sync::atomic::{AtomicU64, Ordering},
+    time::{Duration, Instant},
+};
+
+/// How long a light stays on after the activity that turned it on, so short bursts are visible
+const HOLD: Duration = Duration::from_millis(250);
+
+/// Level above which audio counts as signal, -60 dBFS
+const SIGNAL_THRESHOLD: f32 = 0.001;
+
+/// When something last happened, in milliseconds since the `Activity` was created plus one, or 0
+/// if it never has
+#[derive(Default)]
+struct Light(AtomicU64);
+
+/// Whether MIDI and audio are flowing through the host, for showing next to what's playing.
+///
+/// The audio thread and the host callbacks turn lights on, and anything holding the `Activity`
+/// can look at them without locking.
+pub struct Activity {
+    start: Instant,
+    midi_in: Light,
+    midi_out: Light,
+    audio_in: Light,
+    audio_out: Light,
+}
+
+impl Activity {
+    pub fn new() -> Self {
+        Self {
+            start: Instant::now(),
+            midi_in: Light::default(),
+            midi_out: Light::default(),
+            audio_in: Light::default(),
+            audio_out: Light::default(),
+        }
+    }
+
+    fn turn_on(&self, light: &Light) {
+        let now = self.start.elapsed().as_millis() as u64 + 1;
+        light.0.store(now, Ordering::Relaxed);
+    }
+
+    fn is_on(&self, light: &Light) -> bool {
+        let last = light.0.load(Ordering::Relaxed);
+        last != 0 && self.start.elapsed().as_millis() as u64 + 1 - last < HOLD.as_millis() as u64
+    }
+
+    /// Called when MIDI reaches the plugin
+    pub fn midi_in(&self) {
+        self.turn_on(&self.midi_in);
+    }
+
+    /// Called when the plugin sends MIDI
+    pub fn midi_out(&self) {
+        self.turn_on(&self.midi_out);
+    }
+
+    /// Called with the first `length` samples of each channel going into the plugin
+    pub fn audio_in(&self, channels: &[Vec<f32>], length: usize) {
+        if has_signal(channels, length) {
+            self.turn_on(&self.audio_in);
+        }
+    }
+
+    /// Called with the first `length` samples of each channel going to the output
+    pub fn audio_out(&self, channels: &[Vec<f32>], length: usize) {
+        if has_signal(channels, length) {
+            self.turn_on(&self.audio_out);
+        }
+    }
+
+    /// A line like `MIDI in * out -, audio in - out *`
+    pub fn summary(&self) -> String {
+        let light = |light| if self.is_on(light) { '*' } else { '-' };
+        format!(
+            "MIDI in {} out {}, audio in {} out {}",
+            light(&self.midi_in),
+            light(&self.midi_out),
+            light(&self.audio_in),
+            light(&self.audio_out)
+        )
+    }
+}
+
+impl Default for Activity {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn has_signal(channels: &[Vec<f32>], length: usize) -> bool {
+    channels.iter().any(|channel| {
+        channel[..length]
+            .iter()
+            .any(|sample| sample.abs() > SIGNAL_THRESHOLD)
+    })
+}
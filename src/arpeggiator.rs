@@ -0,0 +1,219 @@
+use std::str::FromStr;
+
+use anyhow::{anyhow, ensure, Context};
+use clap::ArgEnum;
+use vst::event::MidiEvent;
+
+use crate::transport::Transport;
+
+#[derive(ArgEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mode {
+    Up,
+    Down,
+    Random,
+}
+
+/// A note length such as 1/16, stored in quarter notes
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Rate(pub f64);
+
+impl FromStr for Rate {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (numerator, denominator) = s
+            .split_once('/')
+            .ok_or_else(|| anyhow!("expected a note length like 1/16"))?;
+        let numerator: f64 = numerator.trim().parse()?;
+        let denominator: f64 = denominator.trim().parse()?;
+        ensure!(
+            numerator > 0. && denominator > 0.,
+            "note lengths must be positive"
+        );
+
+        Ok(Self(4. * numerator / denominator))
+    }
+}
+
+/// How the arpeggiator plays, changeable while it runs
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Settings {
+    pub enabled: bool,
+    pub mode: Mode,
+    /// Time between notes
+    pub rate: Rate,
+    /// How many octaves the held notes are repeated over
+    pub octaves: u8,
+    /// Fraction of each step a note sounds for
+    pub gate: f32,
+}
+
+impl Settings {
+    pub fn validate(&self) -> anyhow::Result<()> {
+        ensure!((1..=4).contains(&self.octaves), "octaves go from 1 to 4");
+        ensure!(self.gate > 0. && self.gate <= 1., "gate goes from 0 to 1");
+        Ok(())
+    }
+
+    /// Changes a setting from a command like `mode up` or `rate 1/8`
+    pub fn set(&mut self, setting: &str, value: &str) -> anyhow::Result<()> {
+        let mut settings = *self;
+        match setting {
+            "mode" => {
+                settings.mode = Mode::from_str(value, true).map_err(|err| anyhow!(err))?;
+            }
+            "rate" => settings.rate = value.parse()?,
+            "octaves" => settings.octaves = value.parse().context("invalid octave count")?,
+            "gate" => settings.gate = value.parse().context("invalid gate")?,
+            _ => return Err(anyhow!("unknown setting {setting:?}")),
+        }
+
+        settings.validate()?;
+        *self = settings;
+        Ok(())
+    }
+}
+
+/// Turns held notes into a sequence of notes synced to the transport
+pub struct Arpeggiator {
+    settings: Settings,
+    /// Held notes as (channel, key, velocity), ordered by key
+    held: Vec<(u8, u8, u8)>,
+    step: usize,
+    /// The note currently sounding as (channel, key)
+    playing: Option<(u8, u8)>,
+    /// When the sounding note ends, in quarter notes
+    release_at: f64,
+    random: u32,
+    /// Scratch space for rewriting the events of a block
+    output: Vec<MidiEvent>,
+}
+
+impl Arpeggiator {
+    pub fn new(settings: Settings) -> Self {
+        Self {
+            settings,
+            held: Vec::new(),
+            step: 0,
+            playing: None,
+            release_at: 0.,
+            random: 0x2545_f491,
+            output: Vec::new(),
+        }
+    }
+
+    pub fn set(&mut self, settings: Settings) {
+        self.settings = settings;
+    }
+
+    /// Rewrites a block of `length` samples starting at `start` seconds
+    pub fn process(
+        &mut self,
+        events: &mut Vec<MidiEvent>,
+        transport: &Transport,
+        start: f64,
+        length: usize,
+        sample_rate: f64,
+    ) {
+        if !self.settings.enabled {
+            // Notes still held when it's turned off are released with their note-offs
+            self.held.clear();
+            if self.playing.is_none() {
+                return;
+            }
+        }
+
+        self.output.clear();
+        for event in events.drain(..) {
+            let [status, key, velocity] = event.data;
+            let channel = status & 0x0f;
+            match status & 0xf0 {
+                0x90 if velocity > 0 && self.settings.enabled => {
+                    if let Err(index) = self.held.binary_search_by_key(&key, |&(_, k, _)| k) {
+                        self.held.insert(index, (channel, key, velocity));
+                    }
+                }
+                0x80 | 0x90 if self.held.iter().any(|&(_, k, _)| k == key) => {
+                    self.held.retain(|&(_, k, _)| k != key);
+                }
+                _ => self.output.push(event),
+            }
+        }
+
+        let tempo_map = transport.tempo_map();
+        let delta = |quarters: f64| {
+            let seconds = tempo_map.seconds_at(quarters) - start;
+            ((seconds * sample_rate) as i32).clamp(0, length as i32 - 1)
+        };
+        let block_start = tempo_map.quarters_at(start);
+        let block_end = tempo_map.quarters_at(start + length as f64 / sample_rate);
+
+        if self.held.is_empty() {
+            self.step = 0;
+            if let Some(note) = self.playing.take() {
+                self.output.push(note_event(0x80, note, 0, 0));
+            }
+        }
+
+        let rate = self.settings.rate.0;
+        let mut step_at = (block_start / rate).ceil() * rate;
+        while step_at < block_end && !self.held.is_empty() {
+            if let Some(note) = self.playing.take() {
+                let at = self.release_at.min(step_at);
+                self.output.push(note_event(0x80, note, 0, delta(at)));
+            }
+
+            let (channel, key, velocity) = self.next_note();
+            self.output
+                .push(note_event(0x90, (channel, key), velocity, delta(step_at)));
+            self.playing = Some((channel, key));
+            self.release_at = step_at + rate * self.settings.gate as f64;
+
+            step_at += rate;
+        }
+
+        if let Some(note) = self.playing {
+            if self.release_at < block_end {
+                self.output
+                    .push(note_event(0x80, note, 0, delta(self.release_at)));
+                self.playing = None;
+            }
+        }
+
+        self.output.sort_by_key(|event| event.delta_frames);
+        events.append(&mut self.output);
+    }
+
+    /// Picks the next note to play, as (channel, key, velocity)
+    fn next_note(&mut self) -> (u8, u8, u8) {
+        let count = self.held.len() * self.settings.octaves as usize;
+        let index = match self.settings.mode {
+            Mode::Up => self.step % count,
+            Mode::Down => count - 1 - self.step % count,
+            Mode::Random => {
+                // xorshift32
+                self.random ^= self.random << 13;
+                self.random ^= self.random >> 17;
+                self.random ^= self.random << 5;
+                self.random as usize % count
+            }
+        };
+        self.step += 1;
+
+        let (channel, key, velocity) = self.held[index % self.held.len()];
+        let octave = (index / self.held.len()) as u8;
+        (channel, key.saturating_add(12 * octave).min(127), velocity)
+    }
+}
+
+fn note_event(status: u8, (channel, key): (u8, u8), velocity: u8, delta_frames: i32) -> MidiEvent {
+    MidiEvent {
+        data: [status | channel, key, velocity],
+        delta_frames,
+        live: true,
+        note_length: None,
+        note_offset: None,
+        detune: 0,
+        note_off_velocity: 0,
+    }
+}
@@ -0,0 +1,36 @@
+use std::{
+    io::{Read, Write},
+    net::Shutdown,
+    os::unix::net::UnixStream,
+    path::PathBuf,
+};
+
+use anyhow::{Context, Result};
+use y::control::default_socket_path;
+
+/// Sends a command to a host running with `--daemon`
+#[derive(clap::Args)]
+pub struct Args {
+    /// Path of the host's control socket
+    #[clap(long)]
+    socket: Option<PathBuf>,
+
+    /// The command to run, e.g. `replace /path/to/plugin.so`
+    #[clap(required = true)]
+    command: Vec<String>,
+}
+
+pub fn main(args: Args) -> Result<()> {
+    let path = args.socket.unwrap_or_else(default_socket_path);
+    let mut stream = UnixStream::connect(&path)
+        .with_context(|| format!("failed to connect to {}", path.display()))?;
+
+    writeln!(stream, "{}", args.command.join(" "))?;
+    stream.shutdown(Shutdown::Write)?;
+
+    let mut reply = String::new();
+    stream.read_to_string(&mut reply)?;
+    print!("{reply}");
+
+    Ok(())
+}
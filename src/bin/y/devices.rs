@@ -0,0 +1,31 @@
+use anyhow::Result;
+use rodio::DeviceTrait;
+use y::output;
+
+/// Lists the audio devices `y run --output-device` can play through
+#[derive(clap::Args)]
+pub struct Args {
+    /// Also list the channel counts, sample rates and buffer sizes each device supports
+    #[clap(long, short)]
+    verbose: bool,
+}
+
+pub fn main(args: Args) -> Result<()> {
+    for (device, is_default) in output::devices()? {
+        let name = device.name()?;
+        println!("{name}{}", if is_default { " (default)" } else { "" });
+
+        if args.verbose {
+            match output::describe(&device) {
+                Ok(description) => {
+                    for line in description.lines() {
+                        println!("    {line}");
+                    }
+                }
+                Err(err) => println!("    {err:#}"),
+            }
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,349 @@
+use std::{
+    env,
+    fs::{self, File},
+    path::{Path, PathBuf},
+    process::{self, Command, Stdio},
+    ptr,
+    sync::{Arc, Mutex},
+    thread,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{Context, Result};
+use vst::{
+    event::MidiEvent,
+    host::{Host, PluginLoader},
+    plugin::OpCode,
+};
+use y::{
+    crash, diagnose,
+    effect::dispatch,
+    hosted::{HostedPlugin, Vst2Plugin},
+    lifecycle::{Lifecycle, State},
+    search,
+};
+
+const SAMPLE_RATES: [f32; 6] = [22050., 44100., 48000., 88200., 96000., 192000.];
+const BLOCK_SIZES: [usize; 9] = [1, 32, 64, 128, 256, 512, 1024, 2048, 4096];
+/// Features asked about with `effCanDo`
+const FEATURES: [&str; 8] = [
+    "receiveVstEvents",
+    "receiveVstMidiEvent",
+    "sendVstEvents",
+    "sendVstMidiEvent",
+    "offline",
+    "bypass",
+    "midiProgramNames",
+    "MPE",
+];
+
+/// Sends a plugin randomized but well-formed calls, each run in its own process, and reports the
+/// runs that crash or hang it with the seed that reproduces them. A tool for plugin developers.
+///
+/// Exits with status 1 when any run crashed or hung.
+#[derive(clap::Args)]
+pub struct Args {
+    /// Path of the plugin, or its file or product name
+    plugin: PathBuf,
+
+    /// Seed of the first run, each run after it using the next one. Random by default.
+    #[clap(long)]
+    seed: Option<u64>,
+
+    /// Number of runs, each loading the plugin afresh
+    #[clap(long, default_value_t = 100)]
+    runs: u64,
+
+    /// Number of calls made in each run
+    #[clap(long, default_value_t = 1000)]
+    calls: usize,
+
+    /// Seconds after which a run counts as hung
+    #[clap(long, default_value_t = 30)]
+    timeout: u64,
+
+    /// Directory the calls of every run that crashed or hung are logged to, as
+    /// `y-fuzz-<seed>.log`
+    #[clap(long, default_value = ".")]
+    crash_dir: PathBuf,
+
+    /// Make the calls of the run with `--seed` in this process. Used for each run.
+    #[clap(long, hide = true, requires = "seed")]
+    child: bool,
+}
+
+struct FuzzHost;
+
+impl Host for FuzzHost {}
+
+pub fn main(args: Args) -> Result<()> {
+    let plugin_path = search::resolve(&args.plugin)?;
+    if args.child {
+        crash::install_handlers();
+        return fuzz(&plugin_path, args.seed.unwrap_or_default(), args.calls);
+    }
+
+    // Only has to differ between sessions, so the time it started is random enough
+    let first = args.seed.unwrap_or_else(|| {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_nanos() as u64)
+    });
+    let log = env::temp_dir().join(format!("y-fuzz-{}.log", process::id()));
+    println!(
+        "Fuzzing {} with {} runs of {} calls from seed {first}",
+        plugin_path.display(),
+        args.runs,
+        args.calls
+    );
+
+    let mut failures = 0;
+    for seed in (0..args.runs).map(|run| first.wrapping_add(run)) {
+        let mut child = Command::new(env::current_exe()?)
+            .arg("fuzz")
+            .arg(&plugin_path)
+            .arg("--child")
+            .args(["--seed", &seed.to_string()])
+            .args(["--calls", &args.calls.to_string()])
+            .stdout(Stdio::null())
+            .stderr(File::create(&log).context("failed to create the call log")?)
+            .spawn()
+            .context("failed to start a run")?;
+
+        let started = Instant::now();
+        let failure = loop {
+            if let Some(status) = child.try_wait()? {
+                break match crash::crash_of(status) {
+                    Some(name) => Some(format!("crashed with a {name}")),
+                    None if status.success() => None,
+                    None => Some(format!("exited with {status}")),
+                };
+            }
+            if started.elapsed() > Duration::from_secs(args.timeout) {
+                child.kill()?;
+                child.wait()?;
+                break Some(format!("hung for {}s", args.timeout));
+            }
+            thread::sleep(Duration::from_millis(10));
+        };
+
+        let Some(failure) = failure else { continue };
+        failures += 1;
+        let saved = args.crash_dir.join(format!("y-fuzz-{seed}.log"));
+        fs::copy(&log, &saved).with_context(|| format!("failed to write {}", saved.display()))?;
+        println!(
+            "FAIL: seed {seed} {failure}, calls logged to {}",
+            saved.display()
+        );
+        println!(
+            "    reproduce with: y fuzz {} --seed {seed} --runs 1 --calls {}",
+            plugin_path.display(),
+            args.calls
+        );
+    }
+    if log.exists() {
+        fs::remove_file(&log)?;
+    }
+
+    if failures == 0 {
+        println!("PASS: all {} runs survived", args.runs);
+        Ok(())
+    } else {
+        println!("{failures} of {} runs failed", args.runs);
+        process::exit(1);
+    }
+}
+
+/// xorshift64, so a seed always makes the same calls
+struct Random(u64);
+
+impl Random {
+    fn new(seed: u64) -> Self {
+        // xorshift never leaves 0
+        Self((seed ^ 0x2545_f491_4f6c_dd1d).max(1))
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn below(&mut self, count: usize) -> usize {
+        (self.next() % count as u64) as usize
+    }
+
+    fn pick<T: Copy>(&mut self, items: &[T]) -> T {
+        items[self.below(items.len())]
+    }
+
+    /// From 0 to 1
+    fn uniform(&mut self) -> f32 {
+        (self.next() >> 40) as f32 / (1u64 << 24) as f32
+    }
+
+    /// A value on the 0 to 1 scale of parameters, often one of the edges
+    fn parameter(&mut self) -> f32 {
+        match self.below(4) {
+            0 => 0.,
+            1 => 1.,
+            _ => self.uniform(),
+        }
+    }
+
+    /// An audio sample, mostly noise but sometimes silence, full scale or denormal
+    fn sample(&mut self) -> f32 {
+        match self.below(16) {
+            0 => 0.,
+            1 => 1.,
+            2 => -1.,
+            3 => f32::MIN_POSITIVE / 2.,
+            _ => self.uniform() * 2. - 1.,
+        }
+    }
+
+    /// A 7-bit MIDI data byte
+    fn data(&mut self) -> u8 {
+        self.below(128) as u8
+    }
+}
+
+/// Loads the plugin and makes `calls` random calls in the order `seed` gives, keeping to what a
+/// well-behaved host would do: parameters and programs in range, blocks no larger than the block
+/// size and configuration only while suspended. Every call is logged to stderr before it's made,
+/// so the last line of the log is the call that crashed.
+fn fuzz(path: &Path, seed: u64, calls: usize) -> Result<()> {
+    let host = Arc::new(Mutex::new(FuzzHost));
+    let mut loader = PluginLoader::load(path, host).map_err(|err| diagnose::explain(path, err))?;
+    let mut plugin = Vst2Plugin::new(loader.instance()?);
+    let info = plugin.info();
+    let mut random = Random::new(seed);
+
+    let mut lifecycle = Lifecycle::new(State::Created);
+    let mut sample_rate = 44100.;
+    let mut block_size = 512;
+    eprintln!("initialise at {sample_rate} Hz with blocks of {block_size}");
+    lifecycle.set(&mut plugin, State::Suspended);
+    plugin.set_sample_rate(sample_rate);
+    plugin.set_block_size(block_size);
+
+    for call in 0..calls {
+        eprint!("{call}: ");
+        match random.below(9) {
+            0 if info.parameters > 0 => {
+                let index = random.below(info.parameters as usize) as i32;
+                let value = random.parameter();
+                eprintln!("set parameter {index} to {value}");
+                plugin.parameters().set_parameter(index, value);
+            }
+            1 if info.parameters > 0 => {
+                let index = random.below(info.parameters as usize) as i32;
+                eprintln!("read parameter {index}");
+                let parameters = plugin.parameters();
+                parameters.get_parameter(index);
+                parameters.get_parameter_name(index);
+                parameters.get_parameter_text(index);
+                parameters.get_parameter_label(index);
+                plugin.parameter_properties(index);
+            }
+            2 if info.presets > 0 => {
+                let index = random.below(info.presets as usize) as i32;
+                eprintln!("change to program {index}");
+                let parameters = plugin.parameters();
+                parameters.change_preset(index);
+                parameters.get_preset_name(index);
+            }
+            3 => {
+                sample_rate = random.pick(&SAMPLE_RATES);
+                block_size = random.pick(&BLOCK_SIZES);
+                eprintln!("reconfigure to {sample_rate} Hz with blocks of {block_size}");
+                lifecycle.set(&mut plugin, State::Suspended);
+                plugin.set_sample_rate(sample_rate);
+                plugin.set_block_size(block_size);
+            }
+            4 => {
+                let state = random.pick(&[State::Suspended, State::Resumed, State::Processing]);
+                eprintln!("move to {state:?}");
+                lifecycle.set(&mut plugin, state);
+            }
+            5 => {
+                eprintln!("save and load the state");
+                let state = plugin.save_state();
+                plugin.load_state(&state);
+            }
+            6 => {
+                let feature = random.pick(&FEATURES);
+                eprintln!("ask whether it can do {feature}");
+                plugin.can_do(feature);
+            }
+            7 => {
+                let bypass = random.below(2) as isize;
+                eprintln!("set bypass to {bypass}, then ask for the tail size");
+                let effect = plugin.effect();
+                unsafe {
+                    dispatch(effect, OpCode::SetBypass, 0, bypass, ptr::null_mut(), 0.);
+                    dispatch(effect, OpCode::GetTailSize, 0, 0, ptr::null_mut(), 0.);
+                }
+            }
+            _ => {
+                let frames = match random.below(4) {
+                    0 => block_size,
+                    1 => 1,
+                    _ => 1 + random.below(block_size),
+                };
+                let (events, sysex) = events(&mut random, frames);
+                eprintln!(
+                    "process {frames} frames with {} events and {} SysEx messages",
+                    events.len(),
+                    sysex.len()
+                );
+                lifecycle.set(&mut plugin, State::Processing);
+                let inputs: Vec<Vec<f32>> = (0..info.inputs)
+                    .map(|_| (0..frames).map(|_| random.sample()).collect())
+                    .collect();
+                let mut outputs = vec![vec![0.; frames]; info.outputs as usize];
+                plugin.process_events(&events, &sysex);
+                plugin.process(&inputs, &mut outputs);
+            }
+        }
+    }
+
+    eprintln!("shut down");
+    lifecycle.set(&mut plugin, State::Suspended);
+    Ok(())
+}
+
+/// Up to 16 channel messages spread over a block of `frames`, and sometimes a SysEx message
+fn events(random: &mut Random, frames: usize) -> (Vec<MidiEvent>, Vec<Vec<u8>>) {
+    let mut events: Vec<MidiEvent> = (0..random.below(17))
+        .map(|_| {
+            let status = random.pick(&[0x80, 0x90, 0xa0, 0xb0, 0xc0, 0xd0, 0xe0]);
+            MidiEvent {
+                data: [
+                    status | random.below(16) as u8,
+                    random.data(),
+                    random.data(),
+                ],
+                delta_frames: random.below(frames) as i32,
+                live: true,
+                note_length: None,
+                note_offset: None,
+                detune: 0,
+                note_off_velocity: 0,
+            }
+        })
+        .collect();
+    events.sort_by_key(|event| event.delta_frames);
+
+    let sysex = if random.below(8) == 0 {
+        let mut message = vec![0xf0];
+        message.extend((0..random.below(64)).map(|_| random.data()));
+        message.push(0xf7);
+        vec![message]
+    } else {
+        Vec::new()
+    };
+
+    (events, sysex)
+}
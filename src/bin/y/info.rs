@@ -0,0 +1,481 @@
+use std::{
+    fmt::Write as _,
+    iter, mem,
+    os::raw::c_char,
+    path::PathBuf,
+    ptr,
+    sync::{Arc, Mutex},
+};
+
+use anyhow::Result;
+use vst::{
+    api::{AEffect, PluginFlags},
+    host::{Host, PluginLoader},
+    plugin::{Info, OpCode, Plugin, PluginParameters},
+};
+use y::{
+    diagnose,
+    effect::{c_string, dispatch, effect_of},
+    hosted::{HostedPlugin, Vst2Plugin},
+    json,
+    lifecycle::{Lifecycle, State},
+    memory::{self, Growth},
+    properties::{self, Properties},
+};
+
+/// Prints what a plugin reports about itself
+#[derive(clap::Args)]
+pub struct Args {
+    path: PathBuf,
+
+    /// Print the information as JSON
+    #[clap(long)]
+    json: bool,
+
+    /// Also list the plugin's MIDI programs and the names of their keys on every channel, like
+    /// the sounds of a drum kit
+    #[clap(long)]
+    midi_names: bool,
+}
+
+struct MyHost;
+
+impl Host for MyHost {}
+
+/// Everything reported about a plugin
+struct Report {
+    info: Info,
+    flags: PluginFlags,
+    /// Samples the plugin keeps sounding after its input stops, 0 meaning unknown and 1 none
+    tail_size: isize,
+    /// (name, text, label, value) of every parameter
+    parameters: Vec<(String, String, String, f32)>,
+    /// What the plugin says about how each parameter is shown
+    properties: Vec<Option<Properties>>,
+    /// MIDI programs and key names on each channel that has any, with `--midi-names`
+    midi_names: Vec<MidiChannel>,
+    /// How much the process grew loading, initialising and running the plugin, where the platform
+    /// reports it
+    memory: Option<[(&'static str, Growth); 3]>,
+}
+
+pub fn main(args: Args) -> Result<()> {
+    let host = Arc::new(Mutex::new(MyHost));
+
+    // load the plugin
+    let before = memory::resident();
+    let mut plugin_loader =
+        PluginLoader::load(&args.path, host).map_err(|err| diagnose::explain(&args.path, err))?;
+    let mut plugin = plugin_loader.instance()?;
+    let loaded = memory::resident();
+
+    let info = plugin.get_info();
+    let flags = PluginFlags::from_bits_truncate(unsafe { (*effect_of(&mut plugin)).flags });
+    let tail_size = plugin.get_tail_size();
+    let parameter_object = plugin.get_parameter_object();
+    let parameters = enumerate_parameters(&*parameter_object, info.parameters);
+
+    let mut plugin = Vst2Plugin::new(plugin);
+    let properties = (0..info.parameters)
+        .map(|index| plugin.parameter_properties(index))
+        .collect();
+
+    let memory = measure_memory(&mut plugin, &info, before, loaded);
+    // Plugins usually only know their MIDI programs once they're initialised
+    let midi_names = if args.midi_names {
+        query_midi_names(&mut plugin)
+    } else {
+        Vec::new()
+    };
+
+    let report = Report {
+        info,
+        flags,
+        tail_size,
+        parameters,
+        properties,
+        midi_names,
+        memory,
+    };
+    if args.json {
+        println!("{}", report.json());
+    } else {
+        report.print();
+    }
+
+    Ok(())
+}
+
+/// Initialises the plugin and processes a second of silence, measuring the memory each step
+/// takes starting from `before` loading and `loaded` after it.
+///
+/// The plugin is left initialised and suspended even where memory can't be measured.
+fn measure_memory(
+    plugin: &mut Vst2Plugin,
+    info: &Info,
+    before: Option<u64>,
+    loaded: Option<u64>,
+) -> Option<[(&'static str, Growth); 3]> {
+    const BLOCK_SIZE: usize = 512;
+    Lifecycle::new(State::Created).set(plugin, State::Suspended);
+    plugin.set_sample_rate(44_100.);
+    plugin.set_block_size(BLOCK_SIZE);
+    let mut lifecycle = Lifecycle::new(State::Suspended);
+    lifecycle.set(plugin, State::Processing);
+    let initialised = memory::resident();
+
+    let inputs = vec![vec![0f32; BLOCK_SIZE]; info.inputs as usize];
+    let mut outputs = vec![vec![0f32; BLOCK_SIZE]; info.outputs as usize];
+    for _ in 0..44_100 / BLOCK_SIZE {
+        plugin.process(&inputs, &mut outputs);
+    }
+    lifecycle.set(plugin, State::Suspended);
+    let processing = Growth::since(initialised)?;
+    let loading = Growth(loaded? as i64 - before? as i64);
+    let initialising = Growth(initialised? as i64 - loaded? as i64);
+
+    Some([
+        ("loading", loading),
+        ("initialising", initialising),
+        ("processing", processing),
+    ])
+}
+
+fn enumerate_parameters(
+    parameters: &(impl PluginParameters + ?Sized),
+    parameter_count: i32,
+) -> Vec<(String, String, String, f32)> {
+    (0..parameter_count)
+        .map(|i| {
+            (
+                parameters.get_parameter_name(i),
+                parameters.get_parameter_text(i),
+                parameters.get_parameter_label(i),
+                parameters.get_parameter(i),
+            )
+        })
+        .collect()
+}
+
+/// `MidiProgramName`, which `vst` doesn't define
+#[repr(C)]
+#[allow(dead_code)]
+struct MidiProgramName {
+    this_program_index: i32,
+    name: [c_char; 64],
+    midi_program: i8,
+    midi_bank_msb: i8,
+    midi_bank_lsb: i8,
+    reserved: i8,
+    parent_category_index: i32,
+    flags: i32,
+}
+
+/// `MidiKeyName`, which `vst` doesn't define
+#[repr(C)]
+#[allow(dead_code)]
+struct MidiKeyName {
+    this_program_index: i32,
+    this_key_number: i32,
+    key_name: [c_char; 64],
+    reserved: i32,
+    flags: i32,
+}
+
+/// A MIDI program as the plugin names it
+struct MidiProgram {
+    index: i32,
+    name: String,
+    /// The program change selecting it, if any
+    program: Option<u8>,
+    /// The bank select MSB and LSB in front of the program change, where used
+    bank: (Option<u8>, Option<u8>),
+    /// Names of the keys that have one, like the sounds of a drum kit
+    keys: Vec<(u8, String)>,
+}
+
+impl MidiProgram {
+    fn json(&self) -> String {
+        let number = |number: Option<u8>| number.map_or("null".to_string(), |n| n.to_string());
+        let keys: Vec<String> = self
+            .keys
+            .iter()
+            .map(|(key, name)| format!("{{\"key\": {key}, \"name\": {}}}", json::string(name)))
+            .collect();
+        format!(
+            "{{\"index\": {}, \"name\": {}, \"program\": {}, \"bank_msb\": {}, \"bank_lsb\": {}, \
+             \"keys\": [{}]}}",
+            self.index,
+            json::string(&self.name),
+            number(self.program),
+            number(self.bank.0),
+            number(self.bank.1),
+            keys.join(", "),
+        )
+    }
+}
+
+/// What the plugin names on one MIDI channel, from 0
+struct MidiChannel {
+    channel: i32,
+    programs: Vec<MidiProgram>,
+}
+
+/// Asks the plugin for the names of its MIDI programs and their keys on every channel, leaving
+/// out channels where it names nothing
+fn query_midi_names(plugin: &mut Vst2Plugin) -> Vec<MidiChannel> {
+    let effect = plugin.effect();
+    (0..16)
+        .filter_map(|channel| unsafe {
+            // Every call also says how many programs there are
+            let (count, first) = midi_program(effect, channel, 0)?;
+            let programs = iter::once(first)
+                .chain((1..count).filter_map(|index| Some(midi_program(effect, channel, index)?.1)))
+                .map(|mut program| {
+                    program.keys = midi_keys(effect, channel, program.index);
+                    program
+                })
+                .collect();
+            Some(MidiChannel { channel, programs })
+        })
+        .collect()
+}
+
+/// Program `index` on `channel`, without its keys, and how many programs there are on the
+/// channel, if the plugin names them
+unsafe fn midi_program(
+    effect: *mut AEffect,
+    channel: i32,
+    index: i32,
+) -> Option<(i32, MidiProgram)> {
+    let mut raw: MidiProgramName = mem::zeroed();
+    raw.this_program_index = index;
+    let count = dispatch(
+        effect,
+        OpCode::GetMidiProgramName,
+        channel,
+        0,
+        ptr::addr_of_mut!(raw).cast(),
+        0.,
+    );
+    if count <= 0 {
+        return None;
+    }
+
+    // Numbers the program isn't selected by are -1
+    let number = |value: i8| u8::try_from(value).ok();
+    let program = MidiProgram {
+        index,
+        name: c_string(&raw.name),
+        program: number(raw.midi_program),
+        bank: (number(raw.midi_bank_msb), number(raw.midi_bank_lsb)),
+        keys: Vec::new(),
+    };
+    Some((count as i32, program))
+}
+
+/// The keys of program `index` on `channel` that the plugin names
+unsafe fn midi_keys(effect: *mut AEffect, channel: i32, index: i32) -> Vec<(u8, String)> {
+    (0..128)
+        .filter_map(|key| {
+            let mut raw: MidiKeyName = mem::zeroed();
+            raw.this_program_index = index;
+            raw.this_key_number = key;
+            let named = dispatch(
+                effect,
+                OpCode::GetMidiKeyName,
+                channel,
+                0,
+                ptr::addr_of_mut!(raw).cast(),
+                0.,
+            );
+            let name = c_string(&raw.key_name);
+            (named != 0 && !name.is_empty()).then_some((key as u8, name))
+        })
+        .collect()
+}
+
+impl Report {
+    fn print(&self) {
+        let info = &self.info;
+        println!("{} by {}", info.name, info.vendor);
+        match four_char_id(info.unique_id) {
+            Some(id) => println!("Unique ID: {} ('{id}')", info.unique_id),
+            None => println!("Unique ID: {}", info.unique_id),
+        }
+        println!("Version: {}", info.version);
+        println!("Category: {:?}", info.category);
+        println!("Inputs: {}, outputs: {}", info.inputs, info.outputs);
+        println!("Presets: {}", info.presets);
+        println!("Latency: {} samples", info.initial_delay);
+        match self.tail_size {
+            0 => println!("Tail: unknown"),
+            1 => println!("Tail: none"),
+            size => println!("Tail: {size} samples"),
+        }
+        println!("Flags: {}", self.flag_names().join(", "));
+        if let Some(memory) = &self.memory {
+            let steps: Vec<String> = memory
+                .iter()
+                .map(|(step, growth)| format!("{growth} {step}"))
+                .collect();
+            println!("Memory: {}", steps.join(", "));
+        }
+
+        if !self.parameters.is_empty() {
+            println!("Parameters:");
+            let mut category = None;
+            for index in properties::display_order(&self.properties) {
+                let (name, text, label, value) = &self.parameters[index as usize];
+                let properties = self.properties[index as usize].as_ref();
+                let this_category = properties.and_then(|properties| properties.category.as_ref());
+                if this_category != category {
+                    category = this_category;
+                    match category {
+                        Some(category) if !category.label.is_empty() => {
+                            println!("  {}:", category.label)
+                        }
+                        Some(category) => println!("  Category {}:", category.index),
+                        None => println!("  Uncategorised:"),
+                    }
+                }
+
+                let mut line = format!("    {name} = {text}");
+                if !label.is_empty() {
+                    line = format!("{line} {label}");
+                }
+                match properties.and_then(Properties::kind) {
+                    Some(kind) => println!("{line} ({value}, {kind})"),
+                    None => println!("{line} ({value})"),
+                }
+            }
+        }
+
+        for channel in &self.midi_names {
+            println!("MIDI programs on channel {}:", channel.channel + 1);
+            for program in &channel.programs {
+                let mut selected_by = Vec::new();
+                if let (Some(msb), lsb) = program.bank {
+                    selected_by.push(format!("bank {msb}/{}", lsb.unwrap_or(0)));
+                }
+                if let Some(number) = program.program {
+                    selected_by.push(format!("program {number}"));
+                }
+                if selected_by.is_empty() {
+                    println!("    {}: {}", program.index, program.name);
+                } else {
+                    let selected_by = selected_by.join(", ");
+                    println!("    {}: {} ({selected_by})", program.index, program.name);
+                }
+                for (key, name) in &program.keys {
+                    println!("        Key {key}: {name}");
+                }
+            }
+        }
+    }
+
+    fn json(&self) -> String {
+        let info = &self.info;
+        let mut json = String::from("{");
+        let _ = write!(
+            json,
+            "\"name\": {}, \"vendor\": {}, \"unique_id\": {}, \"unique_id_string\": {}, \
+             \"version\": {}, \"category\": {}, \"inputs\": {}, \"outputs\": {}, \
+             \"presets\": {}, \"initial_delay\": {}, \"tail_size\": {}, \"flags\": [{}], ",
+            json::string(&info.name),
+            json::string(&info.vendor),
+            info.unique_id,
+            four_char_id(info.unique_id).map_or("null".to_string(), |id| json::string(&id)),
+            info.version,
+            json::string(&format!("{:?}", info.category)),
+            info.inputs,
+            info.outputs,
+            info.presets,
+            info.initial_delay,
+            self.tail_size,
+            self.flag_names()
+                .iter()
+                .map(|flag| json::string(flag))
+                .collect::<Vec<_>>()
+                .join(", "),
+        );
+
+        if let Some(memory) = &self.memory {
+            let steps: Vec<String> = memory
+                .iter()
+                .map(|(step, growth)| format!("\"{step}\": {}", growth.0))
+                .collect();
+            let _ = write!(json, "\"memory\": {{{}}}, ", steps.join(", "));
+        }
+        json.push_str("\"parameters\": [");
+        for (i, ((name, text, label, value), properties)) in
+            self.parameters.iter().zip(&self.properties).enumerate()
+        {
+            if i > 0 {
+                json.push_str(", ");
+            }
+            let _ = write!(
+                json,
+                "{{\"name\": {}, \"text\": {}, \"label\": {}, \"value\": {}, \
+                 \"properties\": {}}}",
+                json::string(name),
+                json::string(text),
+                json::string(label),
+                // NaN and infinities aren't valid JSON
+                if value.is_finite() {
+                    value.to_string()
+                } else {
+                    "null".to_string()
+                },
+                properties
+                    .as_ref()
+                    .map_or("null".to_string(), Properties::json),
+            );
+        }
+        json.push(']');
+
+        if !self.midi_names.is_empty() {
+            json.push_str(", \"midi_names\": [");
+            for (i, channel) in self.midi_names.iter().enumerate() {
+                if i > 0 {
+                    json.push_str(", ");
+                }
+                let programs: Vec<String> =
+                    channel.programs.iter().map(MidiProgram::json).collect();
+                let _ = write!(
+                    json,
+                    "{{\"channel\": {}, \"programs\": [{}]}}",
+                    channel.channel + 1,
+                    programs.join(", ")
+                );
+            }
+            json.push(']');
+        }
+        json.push('}');
+        json
+    }
+
+    fn flag_names(&self) -> Vec<&'static str> {
+        [
+            (PluginFlags::IS_SYNTH, "synth"),
+            (PluginFlags::NO_SOUND_IN_STOP, "silent_when_stopped"),
+            (PluginFlags::HAS_EDITOR, "editor"),
+            (PluginFlags::PROGRAM_CHUNKS, "preset_chunks"),
+            (PluginFlags::CAN_REPLACING, "f32"),
+            (PluginFlags::CAN_DOUBLE_REPLACING, "f64"),
+        ]
+        .into_iter()
+        .filter(|(flag, _)| self.flags.contains(*flag))
+        .map(|(_, name)| name)
+        .collect()
+    }
+}
+
+/// The unique ID as the four characters it's usually chosen as, if they're all printable
+fn four_char_id(id: i32) -> Option<String> {
+    let bytes = id.to_be_bytes();
+    bytes
+        .iter()
+        .all(|byte| byte.is_ascii_graphic() || *byte == b' ')
+        .then(|| bytes.iter().map(|&byte| byte as char).collect())
+}
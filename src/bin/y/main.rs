@@ -0,0 +1,189 @@
+#[cfg(unix)]
+mod ctl;
+mod devices;
+#[cfg(unix)]
+mod fuzz;
+mod info;
+mod presets;
+mod render;
+#[cfg(all(target_os = "linux", target_pointer_width = "64"))]
+mod rt_check;
+mod run;
+mod scan;
+mod verify;
+
+use std::{env, ffi::OsString};
+
+use anyhow::{Context, Result};
+use clap::{Arg, CommandFactory, Parser, Subcommand};
+use y::profile::Profile;
+
+/// A host for VST 2 plugins
+#[derive(Parser)]
+#[clap(args_override_self = true)]
+struct Cli {
+    /// Profile in $XDG_CONFIG_HOME/y/<name>.profile to take options from, `default` if there is
+    /// one. Options given on the command line override the profile's.
+    #[clap(long, global = true)]
+    profile: Option<String>,
+
+    #[clap(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    Run(Box<run::Args>),
+    Info(info::Args),
+    Scan(scan::Args),
+    Render(render::Args),
+    Verify(verify::Args),
+    Devices(devices::Args),
+    Presets(presets::Args),
+    #[cfg(unix)]
+    Ctl(ctl::Args),
+    #[cfg(unix)]
+    Fuzz(fuzz::Args),
+    #[cfg(all(target_os = "linux", target_pointer_width = "64"))]
+    RtCheck(rt_check::Args),
+}
+
+fn main() -> Result<()> {
+    let mut arguments: Vec<OsString> = env::args_os().collect();
+    let profile = match profile_name(&arguments)? {
+        Some(name) => Some(Profile::load(&name)?),
+        None => Profile::load_default()?,
+    };
+
+    if let Some(profile) = profile {
+        profile.apply_search_paths()?;
+
+        // The profile's options go right after `run`, so the ones given later on the command line
+        // override them
+        if let Some(index) = subcommand(&arguments).filter(|&index| arguments[index] == "run") {
+            let options = profile_arguments(profile, &arguments[index + 1..]);
+            arguments.splice(index + 1..index + 1, options);
+        }
+    }
+
+    let cli = Cli::try_parse_from(arguments).unwrap_or_else(|err| err.exit());
+
+    match cli.command {
+        Command::Run(args) => run::main(*args),
+        Command::Info(args) => info::main(args),
+        Command::Scan(args) => scan::main(args),
+        Command::Render(args) => render::main(args),
+        Command::Verify(args) => verify::main(args),
+        Command::Devices(args) => devices::main(args),
+        Command::Presets(args) => presets::main(args),
+        #[cfg(unix)]
+        Command::Ctl(args) => ctl::main(args),
+        #[cfg(unix)]
+        Command::Fuzz(args) => fuzz::main(args),
+        #[cfg(all(target_os = "linux", target_pointer_width = "64"))]
+        Command::RtCheck(args) => rt_check::main(args),
+    }
+}
+
+/// The profile named with `--profile <name>` or `--profile=<name>`, the last one if there are
+/// several
+fn profile_name(arguments: &[OsString]) -> Result<Option<String>> {
+    let mut name = None;
+    let mut arguments = arguments.iter().skip(1);
+    while let Some(argument) = arguments.next() {
+        let argument = argument.to_string_lossy();
+        if argument == "--" {
+            break;
+        } else if argument == "--profile" {
+            let value = arguments.next().context("--profile needs a name")?;
+            name = Some(value.to_string_lossy().into_owned());
+        } else if let Some(value) = argument.strip_prefix("--profile=") {
+            name = Some(value.to_owned());
+        }
+    }
+
+    Ok(name)
+}
+
+/// Where the subcommand is in `arguments`, after the options given before it
+fn subcommand(arguments: &[OsString]) -> Option<usize> {
+    let mut index = 1;
+    while index < arguments.len() {
+        match arguments[index].to_str() {
+            Some("--profile") => index += 2,
+            Some(argument) if argument.starts_with('-') => index += 1,
+            _ => return Some(index),
+        }
+    }
+
+    None
+}
+
+/// The profile's options as arguments to `run`, without those that conflict with the arguments
+/// given to `run` on the command line, so the command line overrides the profile instead of
+/// clashing with it
+fn profile_arguments(profile: Profile, given: &[OsString]) -> Vec<OsString> {
+    let cli = Cli::command();
+    let run = cli.find_subcommand("run").expect("run is a subcommand");
+    let given = given_arguments(run, given);
+    let conflict = |a: &Arg, b: &Arg| {
+        let conflicts = |a: &Arg, b: &Arg| {
+            run.get_arg_conflicts_with(a)
+                .iter()
+                .any(|arg| arg.get_id() == b.get_id())
+        };
+        conflicts(a, b) || conflicts(b, a)
+    };
+
+    let mut arguments = Vec::new();
+    for (option, value) in profile.options {
+        let arg = run
+            .get_arguments()
+            .find(|arg| arg.get_long() == Some(&option));
+        if arg.is_some_and(|arg| given.iter().any(|given| conflict(arg, given))) {
+            continue;
+        }
+        arguments.push(format!("--{option}").into());
+        arguments.extend(value.map(OsString::from));
+    }
+
+    arguments
+}
+
+/// The arguments of `run` that `arguments` give
+fn given_arguments<'a, 'help>(
+    run: &'a clap::Command<'help>,
+    arguments: &[OsString],
+) -> Vec<&'a Arg<'help>> {
+    let mut positionals = run.get_positionals();
+    let mut given = Vec::new();
+    let mut arguments = arguments.iter().map(|argument| argument.to_string_lossy());
+    while let Some(argument) = arguments.next() {
+        let (arg, has_value) = if argument == "--" {
+            given.extend(positionals.next());
+            break;
+        } else if let Some(long) = argument.strip_prefix("--") {
+            let (long, has_value) = match long.split_once('=') {
+                Some((long, _)) => (long, true),
+                None => (long, false),
+            };
+            let arg = run.get_arguments().find(|arg| arg.get_long() == Some(long));
+            (arg, has_value)
+        } else if argument.len() > 1 && argument.starts_with('-') {
+            let short = argument.chars().nth(1);
+            let arg = run.get_arguments().find(|arg| arg.get_short() == short);
+            (arg, argument.len() > 2)
+        } else {
+            (positionals.next(), true)
+        };
+
+        // Of the options `run` doesn't have itself, only `--profile` can be given here, and it
+        // takes a value
+        if !has_value && arg.is_none_or(|arg| arg.is_takes_value_set()) {
+            arguments.next();
+        }
+        given.extend(arg);
+    }
+
+    given
+}
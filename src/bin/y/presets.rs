@@ -0,0 +1,202 @@
+use std::{fs, path::PathBuf};
+
+use anyhow::{ensure, Context, Result};
+use clap::Subcommand;
+use y::{
+    library::{self, Entry, Library, Tags, FAVORITE},
+    search::{self, Database},
+};
+
+/// Keeps a library of FXP and FXB presets, which can be tagged, searched and stepped through with
+/// `preset next` while playing
+#[derive(clap::Args)]
+pub struct Args {
+    #[clap(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Finds the presets in the given directories, $XDG_CONFIG_HOME/y/presets by default, and
+    /// replaces the index with them
+    Index { directories: Vec<PathBuf> },
+    /// Lists the indexed presets matching every word of the query
+    List {
+        /// Only list presets for this plugin, a unique ID or a query for the scan database
+        #[clap(long)]
+        plugin: Option<String>,
+
+        /// Only list presets with this tag
+        #[clap(long)]
+        tag: Option<String>,
+
+        /// Only list favourite presets
+        #[clap(long)]
+        favorites: bool,
+
+        query: Vec<String>,
+    },
+    /// Adds tags to a preset
+    Tag {
+        preset: PathBuf,
+        #[clap(required = true)]
+        tags: Vec<String>,
+    },
+    /// Removes tags from a preset
+    Untag {
+        preset: PathBuf,
+        #[clap(required = true)]
+        tags: Vec<String>,
+    },
+    /// Marks a preset as a favourite
+    Favorite { preset: PathBuf },
+    /// Stops marking a preset as a favourite
+    Unfavorite { preset: PathBuf },
+}
+
+pub fn main(args: Args) -> Result<()> {
+    match args.command {
+        Command::Index { directories } => index(directories),
+        Command::List {
+            plugin,
+            tag,
+            favorites,
+            query,
+        } => list(plugin, tag, favorites, &query.join(" ")),
+        Command::Tag { preset, tags } => tag(preset, &tags, true),
+        Command::Untag { preset, tags } => tag(preset, &tags, false),
+        Command::Favorite { preset } => tag(preset, &[FAVORITE.to_owned()], true),
+        Command::Unfavorite { preset } => tag(preset, &[FAVORITE.to_owned()], false),
+    }
+}
+
+fn index(mut directories: Vec<PathBuf>) -> Result<()> {
+    if directories.is_empty() {
+        directories.push(
+            library::default_directory().context("no configuration directory, HOME isn't set")?,
+        );
+    }
+    // Tags are kept by path, so presets are indexed by the same paths however they're reached
+    let directories: Vec<PathBuf> = directories
+        .iter()
+        .map(|directory| {
+            fs::canonicalize(directory)
+                .with_context(|| format!("failed to read {}", directory.display()))
+        })
+        .collect::<Result<_>>()?;
+
+    let mut library = Library::default();
+    for path in library::preset_files(&directories) {
+        match Entry::read(&path) {
+            Ok(entry) => library.entries.push(entry),
+            Err(err) => eprintln!("skipping {}: {err:#}", path.display()),
+        }
+    }
+
+    let path = library.save()?;
+    println!(
+        "Found {} presets, saved to {}",
+        library.entries.len(),
+        path.display()
+    );
+
+    Ok(())
+}
+
+fn list(plugin: Option<String>, tag: Option<String>, favorites: bool, query: &str) -> Result<()> {
+    let unique_id = plugin.as_deref().map(plugin_id).transpose()?;
+    let library = Library::load()?;
+    let mut entries: Vec<&Entry> = match unique_id {
+        Some(unique_id) => library.for_plugin(unique_id, tag.as_deref()),
+        None => library
+            .entries
+            .iter()
+            .filter(|entry| tag.as_ref().is_none_or(|tag| entry.tags.contains(tag)))
+            .collect(),
+    };
+    entries.retain(|entry| (!favorites || entry.is_favorite()) && entry.matches(query));
+
+    // Plugins are named after the scan database where they can be
+    let database = Database::load().unwrap_or_default();
+    let plugin_name = |unique_id| {
+        database
+            .entries
+            .iter()
+            .find(|entry| entry.unique_id == unique_id)
+            .map_or_else(
+                || format!("plugin {unique_id}"),
+                |entry| entry.product.clone(),
+            )
+    };
+
+    for entry in &entries {
+        let tags: Vec<&str> = entry.tags.iter().map(String::as_str).collect();
+        println!(
+            "{} {} for {}{} ({})",
+            if entry.is_favorite() { '*' } else { ' ' },
+            entry.name,
+            plugin_name(entry.unique_id),
+            if tags.is_empty() {
+                String::new()
+            } else {
+                format!(" [{}]", tags.join(", "))
+            },
+            entry.path.display()
+        );
+    }
+    if entries.is_empty() {
+        println!("No presets found");
+    }
+
+    Ok(())
+}
+
+/// The unique ID of the plugin `plugin` refers to, either the ID itself or a query like
+/// `tal reverb`
+fn plugin_id(plugin: &str) -> Result<i32> {
+    if let Ok(unique_id) = plugin.parse() {
+        return Ok(unique_id);
+    }
+
+    let path = search::find(plugin)?;
+    let database = Database::load()?;
+    let entry = database
+        .entries
+        .iter()
+        .find(|entry| entry.path == path)
+        .with_context(|| format!("{} isn't in the scan database", path.display()))?;
+    Ok(entry.unique_id)
+}
+
+fn tag(preset: PathBuf, tags: &[String], add: bool) -> Result<()> {
+    let preset = fs::canonicalize(&preset)
+        .with_context(|| format!("failed to read {}", preset.display()))?;
+    ensure!(
+        library::is_preset(&preset),
+        "{} isn't an FXP or FXB file",
+        preset.display()
+    );
+
+    let mut all = Tags::load()?;
+    for tag in tags {
+        ensure!(
+            !tag.is_empty() && !tag.contains(char::is_whitespace),
+            "tags can't contain spaces: {tag:?}"
+        );
+        if add {
+            all.add(&preset, tag);
+        } else if !all.remove(&preset, tag) {
+            eprintln!("{} wasn't tagged {tag}", preset.display());
+        }
+    }
+    all.save()?;
+
+    let now: Vec<&str> = all.of(&preset).iter().map(String::as_str).collect();
+    if now.is_empty() {
+        println!("{} has no tags", preset.display());
+    } else {
+        println!("{} is tagged {}", preset.display(), now.join(", "));
+    }
+
+    Ok(())
+}
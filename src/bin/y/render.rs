@@ -0,0 +1,351 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::{bail, ensure, Context, Result};
+use hound::WavReader;
+use y::{
+    json,
+    render::{flush_denormals, normalize, write_wav, BitDepth, Level, Renderer, Routing, Tail},
+    search,
+};
+
+/// Processes WAV files through a plugin offline, as fast as the plugin allows
+#[derive(clap::Args)]
+pub struct Args {
+    /// Path of the plugin, or its file or product name
+    plugin: PathBuf,
+
+    /// Files to process: WAV files, directories of them, or patterns like 'stems/*.wav'
+    #[clap(long = "input", required = true, multiple_occurrences = true)]
+    inputs: Vec<String>,
+
+    /// Directory the processed files are written to, under their original names
+    #[clap(long)]
+    out_dir: PathBuf,
+
+    /// Number of samples processed per call to `process()`
+    #[clap(long, default_value_t = 512)]
+    block_size: usize,
+
+    #[clap(flatten)]
+    tail: Tail,
+
+    /// Load a fresh instance of the plugin for every file, rather than resetting one instance to
+    /// its initial state between files
+    #[clap(long)]
+    fresh: bool,
+
+    #[clap(flatten)]
+    routing: Routing,
+
+    /// Scale each output so its peak is at this level, like -1dBFS
+    #[clap(long, allow_hyphen_values = true)]
+    normalize: Option<Level>,
+
+    /// Sample format of the output files, 16, 24 or 32f, instead of the input's
+    #[clap(long)]
+    bit_depth: Option<BitDepth>,
+
+    /// Add triangular dither when writing integer samples
+    #[clap(long)]
+    dither: bool,
+
+    /// Print nothing but errors
+    #[clap(long, conflicts_with = "json-progress")]
+    quiet: bool,
+
+    /// Report progress as one JSON object per line on stdout, for scripts wrapping the renderer
+    #[clap(long)]
+    json_progress: bool,
+
+    /// Number of files to render at once, each with its own instance of the plugin
+    #[clap(long, short, default_value_t = 1)]
+    jobs: usize,
+
+    /// Make repeated renders of the same input bit-identical as far as the plugin allows: every
+    /// file gets a fresh instance, one at a time, denormals are flushed to zero and output
+    /// buffers are zeroed before every call. Blocks are always of `--block-size` samples, and
+    /// dither always starts from the same seed.
+    #[clap(long, conflicts_with = "jobs")]
+    deterministic: bool,
+}
+
+pub fn main(args: Args) -> Result<()> {
+    let plugin_path = search::resolve(&args.plugin)?;
+
+    let mut files = Vec::new();
+    for input in &args.inputs {
+        files.extend(expand(input)?);
+    }
+    ensure!(!files.is_empty(), "no input files found");
+    fs::create_dir_all(&args.out_dir)
+        .with_context(|| format!("failed to create {}", args.out_dir.display()))?;
+
+    let mut outputs = Vec::with_capacity(files.len());
+    for file in &files {
+        let name = file.file_name().context("input has no file name")?;
+        let output = args.out_dir.join(name);
+        ensure!(
+            fs::canonicalize(file)? != fs::canonicalize(&output).unwrap_or_default(),
+            "{} would be overwritten by its own output",
+            file.display()
+        );
+        ensure!(
+            !outputs.contains(&output),
+            "several inputs would be written to {}",
+            output.display()
+        );
+        outputs.push(output);
+    }
+
+    // Each job takes the next file from the queue with its own plugin instance. Instances loaded
+    // from the same library still share any global state the plugin keeps.
+    let queue = Mutex::new(files.iter().zip(&outputs));
+    let failed = AtomicBool::new(false);
+    let jobs = args.jobs.clamp(1, files.len());
+    thread::scope(|scope| {
+        let workers: Vec<_> = (0..jobs)
+            .map(|_| {
+                scope.spawn(|| {
+                    if args.deterministic {
+                        flush_denormals();
+                    }
+                    let mut renderer = None;
+                    while !failed.load(Ordering::Relaxed) {
+                        let (file, output) = match queue.lock().unwrap().next() {
+                            Some(next) => next,
+                            None => break,
+                        };
+                        let result = process(&mut renderer, &plugin_path, file, output, &args);
+                        if result.is_err() {
+                            failed.store(true, Ordering::Relaxed);
+                            return result;
+                        }
+                    }
+                    Ok(())
+                })
+            })
+            .collect();
+
+        // The scope joins every job, even when this returns at the first error
+        workers
+            .into_iter()
+            .try_for_each(|worker| worker.join().expect("render job panicked"))
+    })
+}
+
+/// Renders `file` into `output`, reusing the plugin in `renderer` unless `--fresh` is given
+fn process(
+    renderer: &mut Option<Renderer>,
+    plugin_path: &Path,
+    file: &Path,
+    output: &Path,
+    args: &Args,
+) -> Result<()> {
+    let sample_rate = WavReader::open(file)
+        .with_context(|| format!("failed to read {}", file.display()))?
+        .spec()
+        .sample_rate as f32;
+    let renderer = match renderer {
+        Some(renderer) if !args.fresh && !args.deterministic => {
+            renderer.reset(sample_rate);
+            renderer
+        }
+        _ => {
+            let renderer =
+                renderer.insert(Renderer::new(plugin_path, args.block_size, sample_rate)?);
+            renderer.instance.set_zero_outputs(args.deterministic);
+            renderer
+        }
+    };
+
+    let mut progress = Progress::new(args, file, sample_rate as f64);
+    let (spec, mut samples) = renderer
+        .render(file, &args.routing, &args.tail, |done, total| {
+            progress.update(done, total)
+        })
+        .with_context(|| format!("failed to process {}", file.display()))?;
+    if let Some(level) = args.normalize {
+        normalize(&mut samples, level.0);
+    }
+    write_wav(output, spec, &samples, args.bit_depth, args.dither)
+        .with_context(|| format!("failed to write {}", output.display()))?;
+    progress.finish(output);
+
+    Ok(())
+}
+
+/// Expands an input argument into the files it names
+fn expand(input: &str) -> Result<Vec<PathBuf>> {
+    let path = Path::new(input);
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy())
+        .unwrap_or_default();
+
+    let (directory, pattern) = if name.contains(['*', '?']) {
+        let parent = path
+            .parent()
+            .filter(|parent| !parent.as_os_str().is_empty());
+        (parent.unwrap_or(Path::new(".")), Some(name.as_ref()))
+    } else if path.is_dir() {
+        (path, None)
+    } else {
+        return Ok(vec![path.to_owned()]);
+    };
+
+    let mut files: Vec<PathBuf> = fs::read_dir(directory)
+        .with_context(|| format!("failed to read {}", directory.display()))?
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.is_file())
+        .filter(|path| match pattern {
+            Some(pattern) => {
+                let name = path.file_name().unwrap_or_default().to_string_lossy();
+                matches_pattern(pattern.as_bytes(), name.as_bytes())
+            }
+            // Every WAV file in a directory
+            None => path
+                .extension()
+                .is_some_and(|extension| extension.eq_ignore_ascii_case("wav")),
+        })
+        .collect();
+    files.sort();
+
+    if files.is_empty() {
+        bail!("{input} matches no files");
+    }
+    Ok(files)
+}
+
+/// Matches a file name against a pattern where `*` is any run of characters and `?` any one
+fn matches_pattern(pattern: &[u8], name: &[u8]) -> bool {
+    match (pattern.first(), name.first()) {
+        (None, None) => true,
+        (Some(b'*'), _) => {
+            matches_pattern(&pattern[1..], name)
+                || (!name.is_empty() && matches_pattern(pattern, &name[1..]))
+        }
+        (Some(b'?'), Some(_)) => matches_pattern(&pattern[1..], &name[1..]),
+        (Some(p), Some(n)) if p == n => matches_pattern(&pattern[1..], &name[1..]),
+        _ => false,
+    }
+}
+
+/// How progress is reported
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Report {
+    Bar,
+    /// Only a line for every finished file
+    Done,
+    Json,
+    Quiet,
+}
+
+/// Reports how far along rendering a file is
+struct Progress {
+    report: Report,
+    file: String,
+    sample_rate: f64,
+    started: Instant,
+    last_update: Option<Instant>,
+}
+
+impl Progress {
+    /// Time between updates, so reporting doesn't slow rendering down
+    const INTERVAL: Duration = Duration::from_millis(200);
+    const BAR_WIDTH: usize = 30;
+
+    fn new(args: &Args, file: &Path, sample_rate: f64) -> Self {
+        let report = if args.quiet {
+            Report::Quiet
+        } else if args.json_progress {
+            Report::Json
+        } else if args.jobs > 1 {
+            // Bars from several jobs would overwrite each other, so only finished files are shown
+            Report::Done
+        } else {
+            Report::Bar
+        };
+
+        Self {
+            report,
+            file: file.display().to_string(),
+            sample_rate,
+            started: Instant::now(),
+            last_update: None,
+        }
+    }
+
+    /// Reports `done` out of `total` frames being rendered
+    fn update(&mut self, done: usize, total: usize) {
+        if matches!(self.report, Report::Done | Report::Quiet)
+            || (done < total
+                && self
+                    .last_update
+                    .is_some_and(|last| last.elapsed() < Self::INTERVAL))
+        {
+            return;
+        }
+        self.last_update = Some(Instant::now());
+
+        let fraction = done as f64 / total.max(1) as f64;
+        let elapsed = self.started.elapsed().as_secs_f64();
+        let realtime = done as f64 / self.sample_rate / elapsed.max(1e-9);
+        let eta = if fraction > 0. {
+            elapsed / fraction - elapsed
+        } else {
+            0.
+        };
+
+        match self.report {
+            Report::Bar => {
+                let filled = (fraction * Self::BAR_WIDTH as f64) as usize;
+                eprint!(
+                    "\r{} [{}{}] {:5.1}% {:.1}s elapsed, {:.1}x realtime, ETA {:.1}s ",
+                    self.file,
+                    "#".repeat(filled),
+                    " ".repeat(Self::BAR_WIDTH - filled),
+                    fraction * 100.,
+                    elapsed,
+                    realtime,
+                    eta
+                );
+            }
+            Report::Json => println!(
+                "{{\"file\": {}, \"progress\": {fraction:.4}, \"elapsed\": {elapsed:.3}, \
+                 \"realtime\": {realtime:.2}, \"eta\": {eta:.3}}}",
+                json::string(&self.file)
+            ),
+            Report::Done | Report::Quiet => {}
+        }
+    }
+
+    /// Reports the file being done and written to `output`
+    fn finish(&self, output: &Path) {
+        let elapsed = self.started.elapsed().as_secs_f64();
+        match self.report {
+            Report::Bar | Report::Done => {
+                if self.last_update.is_some() {
+                    eprintln!();
+                }
+                println!("{} -> {} in {elapsed:.1}s", self.file, output.display());
+            }
+            Report::Json => println!(
+                "{{\"file\": {}, \"output\": {}, \"progress\": 1, \"elapsed\": {elapsed:.3}, \
+                 \"done\": true}}",
+                json::string(&self.file),
+                json::string(&output.display().to_string())
+            ),
+            Report::Quiet => {}
+        }
+    }
+}
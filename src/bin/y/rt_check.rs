@@ -0,0 +1,180 @@
+use std::{
+    path::{Path, PathBuf},
+    process,
+    sync::{Arc, Mutex},
+};
+
+use anyhow::{ensure, Result};
+use vst::{
+    event::MidiEvent,
+    host::{Host, PluginLoader},
+};
+use y::{
+    diagnose,
+    hosted::{HostedPlugin, Vst2Plugin},
+    lifecycle::{Lifecycle, State},
+    rt_check::{self, Kind},
+    search,
+};
+
+/// Notes played while checking, one after another
+const NOTES: [u8; 4] = [60, 64, 67, 72];
+
+/// Checks that plugins don't allocate, lock or do file I/O in `process()`, for vetting plugins
+/// before live use.
+///
+/// Calls the plugin's library makes to the allocator, to locks and to files are counted while it
+/// processes noise and a few notes, by hooking its imports. Calls made by the libraries it uses in
+/// turn aren't seen. Exits with status 1 when any plugin made such calls.
+#[derive(clap::Args)]
+pub struct Args {
+    /// Paths of the plugins, or their file or product names
+    #[clap(required = true)]
+    plugins: Vec<PathBuf>,
+
+    /// Seconds of audio processed with each plugin
+    #[clap(long, default_value_t = 10.)]
+    seconds: f64,
+
+    /// Number of samples processed per call to `process()`
+    #[clap(long, default_value_t = 512)]
+    block_size: usize,
+
+    /// Sample rate the plugins run at
+    #[clap(long, default_value_t = 44100.)]
+    sample_rate: f32,
+
+    /// Blocks processed before checking starts, for plugins that set themselves up on the first
+    /// call
+    #[clap(long, default_value_t = 0)]
+    skip_blocks: usize,
+}
+
+struct CheckHost;
+
+impl Host for CheckHost {}
+
+/// What a plugin did while it was checked
+struct Report {
+    name: String,
+    blocks: usize,
+    /// Blocks in which the plugin made any of the calls
+    offending: usize,
+    first: Option<usize>,
+    calls: Vec<(&'static str, Kind, usize)>,
+}
+
+pub fn main(args: Args) -> Result<()> {
+    ensure!(args.block_size > 0, "the block size has to be positive");
+
+    let mut failed = false;
+    for plugin in &args.plugins {
+        let path = search::resolve(plugin)?;
+        let report = check(&path, &args)?;
+        match report.first {
+            None => println!(
+                "PASS: {}: no allocation, locking or file I/O in {} blocks",
+                report.name, report.blocks
+            ),
+            Some(first) => {
+                failed = true;
+                println!(
+                    "FAIL: {}: {} of {} blocks made calls that aren't real-time safe, the first \
+                     in block {first}",
+                    report.name, report.offending, report.blocks
+                );
+                for (name, kind, calls) in report.calls {
+                    println!("    {name} ({}): {calls} calls", kind.describe());
+                }
+            }
+        }
+    }
+
+    if failed {
+        process::exit(1);
+    }
+    Ok(())
+}
+
+fn check(path: &Path, args: &Args) -> Result<Report> {
+    let host = Arc::new(Mutex::new(CheckHost));
+    let mut loader = PluginLoader::load(path, host).map_err(|err| diagnose::explain(path, err))?;
+    let mut plugin = Vst2Plugin::new(loader.instance()?);
+    rt_check::hook(path)?;
+    let info = plugin.info();
+
+    let mut lifecycle = Lifecycle::new(State::Created);
+    lifecycle.set(&mut plugin, State::Suspended);
+    plugin.set_sample_rate(args.sample_rate);
+    plugin.set_block_size(args.block_size);
+    lifecycle.set(&mut plugin, State::Processing);
+
+    // xorshift32 noise, the same for every plugin
+    let mut random = 0x2545_f491u32;
+    let inputs: Vec<Vec<f32>> = (0..info.inputs)
+        .map(|_| {
+            (0..args.block_size)
+                .map(|_| {
+                    random ^= random << 13;
+                    random ^= random >> 17;
+                    random ^= random << 5;
+                    random as f32 / u32::MAX as f32 * 2. - 1.
+                })
+                .collect()
+        })
+        .collect();
+    let mut outputs = vec![vec![0.; args.block_size]; info.outputs as usize];
+
+    let blocks = (args.seconds * args.sample_rate as f64 / args.block_size as f64).ceil() as usize;
+    // A note starts or stops every quarter of a second
+    let step = (args.sample_rate / 4.).max(1.) as usize;
+    let mut offending = 0;
+    let mut first = None;
+    rt_check::reset();
+    for block in 0..args.skip_blocks + blocks {
+        let start = block * args.block_size;
+        let events: Vec<MidiEvent> = (start..start + args.block_size)
+            .filter(|frame| frame % step == 0)
+            .map(|frame| {
+                let index = frame / step;
+                let note = NOTES[index / 2 % NOTES.len()];
+                let status = if index.is_multiple_of(2) { 0x90 } else { 0x80 };
+                MidiEvent {
+                    data: [status, note, 100],
+                    delta_frames: (frame - start) as i32,
+                    live: true,
+                    note_length: None,
+                    note_offset: None,
+                    detune: 0,
+                    note_off_velocity: 0,
+                }
+            })
+            .collect();
+
+        if block < args.skip_blocks {
+            plugin.process_events(&events, &[]);
+            plugin.process(&inputs, &mut outputs);
+            continue;
+        }
+        let before = rt_check::total();
+        rt_check::check(|| {
+            plugin.process_events(&events, &[]);
+            plugin.process(&inputs, &mut outputs);
+        });
+        if rt_check::total() > before {
+            offending += 1;
+            if first.is_none() {
+                first = Some(block - args.skip_blocks);
+            }
+        }
+    }
+
+    lifecycle.set(&mut plugin, State::Suspended);
+    Ok(Report {
+        name: info.name,
+        blocks,
+        offending,
+        first,
+        calls: rt_check::report(),
+    })
+}
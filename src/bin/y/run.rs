@@ -0,0 +1,3178 @@
+use std::{
+    collections::HashMap,
+    env, fmt,
+    fs::{self, File},
+    io::{BufReader, Write},
+    net::ToSocketAddrs,
+    path::{Path, PathBuf},
+    process, ptr,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        mpsc::{self, Receiver, RecvTimeoutError, Sender, SyncSender, TrySendError},
+        Arc, Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, bail, ensure, Context, Result};
+use log::{debug, error, info, trace, warn, LevelFilter};
+use rodio::{source::UniformSourceIterator, Decoder, Source};
+use vst::{
+    api::{Event, EventType, Events, MidiEvent, TimeInfo},
+    event,
+    host::{Host, PluginInstance, PluginLoader},
+    plugin::{Info, OpCode, Plugin},
+};
+use winit::{
+    event::Event as WindowEvent,
+    event_loop::{ControlFlow, EventLoop},
+};
+use y::{
+    activity::Activity,
+    arpeggiator::{self, Arpeggiator, Rate},
+    blind::{BlindTest, Side},
+    budget::{self, Budget},
+    bus::{self, Bus, Command, Stdin, WeakBus, AUDIO_QUEUE},
+    chord::{self, ChordTrigger, Intervals},
+    config::{Change, Config, Watched},
+    diagnose,
+    dsp::Hook,
+    editor::{EditorHost, WindowOptions, WinitEditorHost},
+    effect::{dispatch, trace_dispatcher},
+    envelope::{EnvelopeFollower, Sidechain},
+    hosted::{HostedPlugin, Vst2Plugin},
+    instance::{transfer_state, Instance, Parameters},
+    library::Library,
+    lifecycle::{Lifecycle, State},
+    limiter::{db_to_gain, Limiter, Protection},
+    logging,
+    mapping::{apply_cc, reverse_cc, CcMapping, Macro, Target},
+    mcu::Surface,
+    memory::{self, Growth},
+    meter::{self, Meter},
+    metronome::Metronome,
+    midi::{self, Live, MidiSink, MidiSource},
+    mpe::Zones,
+    output::{self, Backend, Output},
+    oversample::Oversample,
+    pool::{self, Pool, Returns},
+    preset::{self, Preset},
+    quirks::{QuirkDatabase, Quirks},
+    route::{Matrix, Routes},
+    rtp::{self, Feed},
+    scene::{Length, Scene, Transition},
+    search,
+    session::{self, Autosave, Settings},
+    smf::{Player, Sequence},
+    stereo,
+    sustain::Sustain,
+    take::{self, Automation, Chunk, PreRoll, Recording, Sidecar, Tap, Trigger},
+    timeout::{self, with_timeout, Deadline},
+    trace::TraceFile,
+    transport::{TempoMap, TimeSignature, Transport},
+    tuning::{self, KeyboardMap, Scale, Tuning},
+    velocity::{ChannelCurve, Velocity},
+    watchdog::Watchdog,
+};
+#[cfg(unix)]
+use y::{
+    bus::{PolledStdin, Socket},
+    capture::Capture,
+    control::{default_socket_path, SocketFile},
+    crash, generic, keyboard,
+};
+
+/// Plays a plugin live, with its editor and commands read from standard input
+#[derive(clap::Args)]
+pub struct Args {
+    /// Path of the plugin, or its file or product name to search for in VST_PATH and the standard
+    /// directories
+    #[clap(required_unless_present_any = &["plugin", "restore-last", "config"])]
+    path: Option<PathBuf>,
+
+    /// Find the plugin in the scan database by words of its vendor or product name, like
+    /// "tal reverb"
+    #[clap(long, conflicts_with = "path")]
+    plugin: Option<String>,
+
+    #[clap(long)]
+    disable_editor: bool,
+
+    /// Protection applied to the plugin's output
+    #[clap(long, arg_enum, default_value = "limit")]
+    protection: Protection,
+
+    /// Output ceiling in dBFS used by the limiter or clipper
+    #[clap(long, default_value_t = -1.0, allow_hyphen_values = true)]
+    ceiling: f32,
+
+    /// Split every buffer into sub-blocks of this many samples before calling `process()`
+    #[clap(long)]
+    internal_block: Option<usize>,
+
+    /// Run the plugin at a multiple of the output sample rate
+    #[clap(long, arg_enum, default_value = "1x")]
+    oversample: Oversample,
+
+    #[clap(flatten)]
+    output: Output,
+
+    #[clap(flatten)]
+    window: WindowOptions,
+
+    /// Load an FXP program or FXB bank into the plugin before starting
+    #[clap(long)]
+    preset: Option<PathBuf>,
+
+    /// Stream an audio file into the plugin's inputs
+    #[clap(long)]
+    play_input: Option<PathBuf>,
+
+    /// Loop the file given with `--play-input`
+    #[clap(long = "loop")]
+    looped: bool,
+
+    /// Route channels to the plugin and from it, like `in:0>0,0>1 out:0>0,1>0` to feed a mono
+    /// input file to both inputs and fold the outputs down to the left channel. Routes to the same
+    /// channel are summed, and channels without a route are dropped.
+    #[clap(long)]
+    route: Option<Routes>,
+
+    /// Play a standard MIDI file into the plugin
+    #[clap(long)]
+    play_midi: Option<PathBuf>,
+
+    /// Play MIDI from a raw MIDI device like /dev/snd/midiC1D0, merged with the other sources
+    #[clap(long = "midi-input", multiple_occurrences = true)]
+    midi_inputs: Vec<PathBuf>,
+
+    /// Load the presets in the preset library for the plugin with program changes from
+    /// `--midi-input` devices, instead of passing them on to the plugin
+    #[clap(long)]
+    presets_on_program_change: bool,
+
+    /// Send output meters as OSC bundles to this address, like `192.168.1.20:9000`: peak and RMS
+    /// levels per channel in dBFS, and momentary loudness in LUFS
+    #[clap(long)]
+    osc_meters: Option<String>,
+
+    /// Meter frames sent per second with `--osc-meters`
+    #[clap(long, default_value_t = 15., requires = "osc-meters")]
+    osc_meter_rate: f64,
+
+    /// Stream the output over RTP to this address, like `192.168.1.20:5004`, as 16-bit PCM
+    #[clap(long)]
+    rtp: Option<String>,
+
+    /// Write a session description of the `--rtp` stream here, which receivers like ffplay or VLC
+    /// open to listen
+    #[clap(long, requires = "rtp")]
+    rtp_sdp: Option<PathBuf>,
+
+    /// Directory takes are recorded into with `record start`, and autosaves into
+    #[clap(long, default_value = ".")]
+    session_dir: PathBuf,
+
+    /// Keep saving the plugin's state, the preset, bypass and hold to a rotating set of files in
+    /// the session directory, every few seconds and when the host quits
+    #[clap(long)]
+    autosave: bool,
+
+    /// Start from the latest autosave in the session directory, with the plugin it was of unless
+    /// another is given
+    #[clap(long)]
+    restore_last: bool,
+
+    /// File of the plugin, preset, bypass, hold and parameter values to play with, which is
+    /// watched and applied again, change by change, whenever it's edited
+    #[clap(long)]
+    config: Option<PathBuf>,
+
+    /// MIDI message from `--midi-input` devices that starts and stops recording, like `cc:64` for
+    /// a sustain footswitch or `note:36`
+    #[clap(long)]
+    record_trigger: Option<Trigger>,
+
+    /// MIDI message from `--midi-input` devices that toggles bypass, like `cc:67` for a
+    /// footswitch, so a whole effect can be switched on and off while playing
+    #[clap(long)]
+    bypass_trigger: Option<Trigger>,
+
+    /// Seconds of output from before recording started to begin takes with
+    #[clap(long, default_value_t = 0.)]
+    pre_roll: f64,
+
+    /// Also write the parameter changes the plugin reports during a take to a MIDI file next to
+    /// it, as the controllers mapped with `--map-cc` or as NRPNs numbered after the parameters,
+    /// timed from when recording started
+    #[clap(long)]
+    record_automation: bool,
+
+    /// Only step through the library's presets with this tag, like `favorite`
+    #[clap(long)]
+    preset_tag: Option<String>,
+
+    /// Send the MIDI the plugin outputs to a raw MIDI device
+    #[clap(long)]
+    midi_output: Option<PathBuf>,
+
+    /// Map a MIDI controller onto a parameter, as `<cc>:<parameter>[:<curve>][:invert]` where the
+    /// curve is linear, log, exp or table=<point>,<point>,...; use `@<name>` to map onto a macro
+    #[clap(long = "map-cc", multiple_occurrences = true)]
+    cc_mappings: Vec<CcMapping>,
+
+    /// Send the parameter changes made in the plugin's editor to `--midi-output`, as the
+    /// controllers mapped with `--map-cc` or as NRPNs numbered after the parameters. Only changes
+    /// between the plugin's begin and end edit are sent, so parameters set over MIDI aren't echoed
+    #[clap(long, requires = "midi-output")]
+    automation_to_midi: bool,
+
+    /// Define a macro moving several parameters, as `<name>=<target>,<target>,...` where each
+    /// target is `<parameter>:<min>:<max>[:<curve>][:invert]`
+    #[clap(long = "macro", multiple_occurrences = true)]
+    macros: Vec<Macro>,
+
+    /// Raw MIDI device of a Mackie Control surface to control parameters from, like
+    /// /dev/snd/midiC1D0
+    #[clap(long)]
+    mcu: Option<PathBuf>,
+
+    /// Separate device to send to the Mackie Control surface, if it isn't the one given with
+    /// `--mcu`
+    #[clap(long, requires = "mcu")]
+    mcu_output: Option<PathBuf>,
+
+    /// Reshape note velocities, as `[<channel>:]<curve>` where the curve is linear, soft, hard or
+    /// table=<played>/<sent>,...; curves for a single channel override one for all of them
+    #[clap(long = "velocity-curve", multiple_occurrences = true)]
+    velocity_curves: Vec<ChannelCurve>,
+
+    /// Set the plugin up for MPE, passing per-note pitch bend, pressure and controllers on to it
+    /// untouched
+    #[clap(long)]
+    mpe: bool,
+
+    /// MPE zones and their member channels, like `lower:15` or `lower:7,upper:7`
+    #[clap(long, default_value = "lower:15")]
+    mpe_zones: Zones,
+
+    /// Pitch bend range of MPE member channels, in semitones
+    #[clap(long, default_value_t = 48)]
+    mpe_bend_range: u8,
+
+    /// Handle the sustain pedal (CC64) in the host, for plugins that ignore it
+    #[clap(long)]
+    sustain: bool,
+
+    /// Retune the plugin to a Scala scale (.scl)
+    #[clap(long)]
+    scala: Option<PathBuf>,
+
+    /// Scala keyboard mapping (.kbm) saying which keys play which degrees of the `--scala` scale,
+    /// instead of middle C playing its first degree and A above it 440 Hz
+    #[clap(long, requires = "scala")]
+    kbm: Option<PathBuf>,
+
+    /// How the `--scala` tuning reaches the plugin: MTS single note tuning changes, detuning
+    /// notes, or MTS where the plugin says it supports it
+    #[clap(long, arg_enum, default_value = "auto")]
+    tuning_mode: tuning::Mode,
+
+    /// Play a chord for every note, given as intervals in semitones like 0,4,7
+    #[clap(long)]
+    chord: Option<Intervals>,
+
+    /// Arpeggiate held notes
+    #[clap(long)]
+    arp: bool,
+
+    /// Order in which the arpeggiator plays held notes
+    #[clap(long, arg_enum, default_value = "up")]
+    arp_mode: arpeggiator::Mode,
+
+    /// Time between arpeggiated notes, as a note length like 1/16
+    #[clap(long, default_value = "1/16")]
+    arp_rate: Rate,
+
+    /// Number of octaves the arpeggiator spans
+    #[clap(long, default_value_t = 1)]
+    arp_octaves: u8,
+
+    /// Fraction of each step an arpeggiated note sounds for
+    #[clap(long, default_value_t = 0.5)]
+    arp_gate: f32,
+
+    /// Input channel whose envelope modulates `--sidechain-parameter`
+    #[clap(long, requires = "sidechain-parameter", requires = "play-input")]
+    sidechain_channel: Option<usize>,
+
+    /// Index of the parameter modulated by the sidechain envelope
+    #[clap(long, requires = "sidechain-channel")]
+    sidechain_parameter: Option<i32>,
+
+    /// Attack time of the sidechain envelope, in milliseconds
+    #[clap(long, default_value_t = 10.)]
+    sidechain_attack: f32,
+
+    /// Release time of the sidechain envelope, in milliseconds
+    #[clap(long, default_value_t = 200.)]
+    sidechain_release: f32,
+
+    /// How far a full-scale sidechain input moves the parameter from its initial value
+    #[clap(long, default_value_t = -1., allow_hyphen_values = true)]
+    sidechain_amount: f32,
+
+    /// Run the built-in stereo tools on the first two channels
+    #[clap(long)]
+    stereo: bool,
+
+    /// Whether the stereo tools process the plugin's inputs or its output
+    #[clap(long, arg_enum, default_value = "post")]
+    stereo_position: stereo::Position,
+
+    /// Level of the side signal, 0 being mono, 1 leaving it as it is and 2 twice as wide
+    #[clap(long, default_value_t = 1.)]
+    stereo_width: f32,
+
+    /// Sum both channels to mono, to check how the output holds up on mono playback
+    #[clap(long)]
+    stereo_mono: bool,
+
+    /// Channels whose polarity the stereo tools invert
+    #[clap(long, arg_enum, default_value = "none")]
+    stereo_invert: stereo::Polarity,
+
+    /// Swap the left and right channels
+    #[clap(long)]
+    stereo_swap: bool,
+
+    /// Library exporting a `y_process` function to run on the plugin's inputs
+    #[clap(long)]
+    pre_dsp: Option<PathBuf>,
+
+    /// Library exporting a `y_process` function to run on the plugin's output
+    #[clap(long)]
+    post_dsp: Option<PathBuf>,
+
+    /// Tempo reported to the plugin, in beats per minute
+    #[clap(long, default_value_t = 120.)]
+    tempo: f64,
+
+    /// Time signature reported to the plugin
+    #[clap(long, default_value = "4/4")]
+    time_signature: TimeSignature,
+
+    /// Allow commands that call into the plugin directly, like `vendor-specific`, which can crash
+    /// plugins that don't expect them
+    #[clap(long)]
+    expert: bool,
+
+    /// Don't apply workarounds from the quirk database
+    #[clap(long)]
+    no_quirks: bool,
+
+    /// Vendor name reported to plugins asking which host they're in
+    #[clap(long, default_value = "jakobrs")]
+    host_vendor: String,
+
+    /// Product name reported to plugins asking which host they're in
+    #[clap(long, default_value = "y")]
+    host_product: String,
+
+    /// Vendor version reported to plugins asking which host they're in, e.g. 1000 for 1.0.0
+    #[clap(long, default_value_t = 100)]
+    host_version: isize,
+
+    /// File of tempo and time signature changes, which also retimes `--play-midi`
+    #[clap(long)]
+    tempo_map: Option<PathBuf>,
+
+    /// Mix a metronome click into the output
+    #[clap(long)]
+    metronome: bool,
+
+    /// Level of the metronome click in dBFS
+    #[clap(long, default_value_t = -12., allow_hyphen_values = true)]
+    metronome_level: f32,
+
+    /// Report calls to `process()` taking longer than this many milliseconds
+    #[clap(long, default_value_t = 1000)]
+    watchdog_ms: u64,
+
+    /// Percentage of each buffer's duration the plugin may spend processing it before it's
+    /// reported as over budget
+    #[clap(long)]
+    cpu_budget: Option<f64>,
+
+    /// Buffers in a row the plugin has to go over `--cpu-budget` in to count as overloaded
+    #[clap(long, default_value_t = 8, requires = "cpu-budget")]
+    budget_buffers: usize,
+
+    /// Bypass an overloaded plugin and stop calling it, rather than only reporting it, until
+    /// bypass is turned off again
+    #[clap(long, requires = "cpu-budget")]
+    bypass_over_budget: bool,
+
+    /// Send `/y/budget/overload` with the percentage of the buffers the plugin took to this OSC
+    /// address whenever it's overloaded, like `192.168.1.20:9000`
+    #[clap(long, requires = "cpu-budget")]
+    osc_overloads: Option<String>,
+
+    /// Send All Notes Off on every channel after the output underruns, so notes whose note-offs
+    /// went missing in the dropout don't hang
+    #[clap(long)]
+    panic_on_xrun: bool,
+
+    /// Seconds to wait for each of loading, instantiating and initialising a plugin
+    #[clap(long, default_value_t = 30.)]
+    load_timeout: f64,
+
+    /// Seconds to wait for the plugin to open its editor
+    #[clap(long, default_value_t = 10.)]
+    editor_timeout: f64,
+
+    /// Maximum level of the host's log messages
+    #[clap(long, default_value = "info")]
+    log_level: LevelFilter,
+
+    /// Capture everything the plugin prints to stdout and stderr and log it under its name
+    #[cfg(unix)]
+    #[clap(long)]
+    capture_output: bool,
+
+    /// Level at which captured plugin output is logged
+    #[cfg(unix)]
+    #[clap(long, default_value = "info")]
+    plugin_output_level: log::Level,
+
+    /// Detach from the terminal and read commands from a control socket instead of stdin
+    #[cfg(unix)]
+    #[clap(long)]
+    daemon: bool,
+
+    /// Path of the control socket used in daemon mode
+    #[cfg(unix)]
+    #[clap(long)]
+    socket: Option<PathBuf>,
+
+    /// Play notes from the computer keyboard in the terminal instead of reading commands
+    #[cfg(unix)]
+    #[clap(long, requires = "disable-editor", conflicts_with = "daemon")]
+    keyboard: bool,
+
+    /// Show the plugin's parameters in the terminal and edit them there instead of reading
+    /// commands, for plugins without an editor
+    #[cfg(unix)]
+    #[clap(long, conflicts_with_all = &["daemon", "keyboard"])]
+    generic_ui: bool,
+
+    /// Start the host again with the plugin's last state whenever the plugin crashes it
+    #[cfg(unix)]
+    #[clap(long, conflicts_with = "daemon")]
+    restart_on_crash: bool,
+
+    /// Run everything on the main thread, without an audio device, watchdog or timeouts, so plugins
+    /// can be checked under valgrind or sanitizers with as little noise from the host as possible.
+    /// Needs `--backend null`; commands are still read from stdin.
+    #[cfg(unix)]
+    #[clap(long, conflicts_with_all = &[
+        "daemon",
+        "keyboard",
+        "generic-ui",
+        "restart-on-crash",
+        "capture-output",
+        "midi-inputs",
+        "mcu",
+        "osc-meters",
+        "osc-overloads",
+        "rtp",
+    ])]
+    single_thread: bool,
+
+    /// Keep saving the plugin's state to this FXP file, and restore it from there on start. Used
+    /// by `--restart-on-crash`.
+    #[clap(long, hide = true)]
+    state_file: Option<PathBuf>,
+
+    /// Log every callback the plugin makes into the host to this file
+    #[clap(long)]
+    trace_callbacks: Option<PathBuf>,
+
+    /// Log every dispatcher call the host makes into the plugin to this file
+    #[clap(long)]
+    trace_dispatch: Option<PathBuf>,
+}
+
+struct MyHost {
+    transport: Arc<Transport>,
+    trace: Option<Arc<TraceFile>>,
+    /// Vendor version, vendor string and product string reported to plugins
+    identity: (isize, String, String),
+    /// Where MIDI sent by the plugin goes
+    midi_output: Option<Mutex<midi::Device>>,
+    activity: Arc<Activity>,
+    automation: Arc<Automation>,
+    /// The mappings edits in the plugin's editor go out through, with `--automation-to-midi`
+    automation_to_midi: Option<Vec<CcMapping>>,
+    /// Parameters the plugin is between begin and end edit of
+    editing: Mutex<Vec<i32>>,
+}
+
+impl MyHost {
+    /// Records a callback in the trace file, if there is one
+    fn trace(&self, name: &str, args: fmt::Arguments, result: &dyn fmt::Debug) {
+        if let Some(trace) = &self.trace {
+            trace.call(name, args, result);
+        }
+    }
+}
+
+impl Host for MyHost {
+    fn automate(&self, index: i32, value: f32) {
+        self.trace("automate", format_args!("{index}, {value}"), &());
+        debug!("automate {index} {value}");
+        self.automation
+            .record(self.transport.seconds(), index, value);
+
+        if let (Some(mappings), Some(output)) = (&self.automation_to_midi, &self.midi_output) {
+            if self.editing.lock().unwrap().contains(&index) {
+                let mut output = output.lock().unwrap();
+                for data in reverse_cc(mappings, index, value) {
+                    output.send(data);
+                }
+            }
+        }
+    }
+
+    fn begin_edit(&self, index: i32) {
+        self.trace("begin_edit", format_args!("{index}"), &());
+        if self.automation_to_midi.is_some() {
+            let mut editing = self.editing.lock().unwrap();
+            if !editing.contains(&index) {
+                editing.push(index);
+            }
+        }
+    }
+
+    fn end_edit(&self, index: i32) {
+        self.trace("end_edit", format_args!("{index}"), &());
+        self.editing
+            .lock()
+            .unwrap()
+            .retain(|&editing| editing != index);
+    }
+
+    fn get_plugin_id(&self) -> i32 {
+        self.trace("get_plugin_id", format_args!(""), &0);
+        0
+    }
+
+    fn idle(&self) {
+        self.trace("idle", format_args!(""), &());
+    }
+
+    fn get_info(&self) -> (isize, String, String) {
+        let info = self.identity.clone();
+        self.trace("get_info", format_args!(""), &info);
+        info
+    }
+
+    fn process_events(&self, events: &vst::api::Events) {
+        self.trace(
+            "process_events",
+            format_args!("{} events", events.num_events),
+            &(),
+        );
+        debug!("process_events with {} events", events.num_events);
+        self.activity.midi_out();
+
+        if let Some(output) = &self.midi_output {
+            let mut output = output.lock().unwrap();
+            for event in events.events() {
+                if let vst::event::Event::Midi(event) = event {
+                    output.send(event.data);
+                }
+            }
+        }
+    }
+
+    fn get_time_info(&self, mask: i32) -> Option<TimeInfo> {
+        let time_info = self.transport.time_info();
+        self.trace(
+            "get_time_info",
+            format_args!("{mask:#x}"),
+            &format_args!(
+                "sample_pos {}, ppq_pos {}, tempo {}",
+                time_info.sample_pos, time_info.ppq_pos, time_info.tempo
+            ),
+        );
+        Some(time_info)
+    }
+
+    fn get_block_size(&self) -> isize {
+        self.trace("get_block_size", format_args!(""), &0);
+        0
+    }
+
+    fn update_display(&self) {
+        self.trace("update_display", format_args!(""), &());
+        debug!("update_display called");
+    }
+}
+
+/// Frames processed at a time when the backend doesn't ask for a fixed number
+const DEFAULT_LENGTH: usize = 1024;
+
+/// Interleaved samples fed into the plugin's inputs
+type InputStream = Box<dyn Iterator<Item = f32> + Send>;
+
+/// Opens an audio file, converted to the host's sample rate and the plugin's input channel count
+fn open_input(path: &Path, looped: bool, channels: u16, sample_rate: u32) -> Result<InputStream> {
+    let file = BufReader::new(File::open(path)?);
+
+    let input: InputStream = if looped {
+        let decoder = Decoder::new_looped(file)?;
+        Box::new(UniformSourceIterator::new(decoder, channels, sample_rate))
+    } else {
+        let decoder = Decoder::new(file)?;
+        Box::new(UniformSourceIterator::new(decoder, channels, sample_rate))
+    };
+
+    Ok(input)
+}
+
+/// An instance being faded out after it was replaced
+struct Fade {
+    instance: Instance,
+    position: usize,
+}
+
+/// An iterator over the samples produced by a plugin
+struct PluginSource {
+    /// The plugin playing, or `None` while it's unloaded
+    instance: Option<Instance>,
+    fade: Option<Fade>,
+    /// New instances to crossfade to, or `None` to fade out and give the current one back
+    replacements: Receiver<Option<Instance>>,
+    retired: Sender<Instance>,
+    /// Output played instead of the plugin's while the chain is frozen
+    frozen: Option<Frozen>,
+    freezes: Receiver<Option<Frozen>>,
+    thawed: Sender<Frozen>,
+    input: Option<InputStream>,
+    inputs: Vec<Vec<f32>>,
+    outputs: Vec<Vec<f32>>,
+    routes: Routes,
+    /// The input file's channels before they're routed to `inputs`, with an input route
+    file_channels: Vec<Vec<f32>>,
+    /// The plugin's outputs before they're routed to `outputs`, with an output route
+    plugin_outputs: Vec<Vec<f32>>,
+    /// Where MIDI comes from: the terminal keyboard, MIDI devices and the MIDI file
+    midi_sources: Vec<Box<dyn MidiSource>>,
+    /// The MIDI events for the current sub-block
+    events: Vec<event::MidiEvent>,
+    cc_mappings: Vec<CcMapping>,
+    /// MPE zones, whose member channels' controllers aren't mapped
+    mpe: Option<Zones>,
+    macros: Vec<Macro>,
+    transitions: Receiver<Transition>,
+    transition: Option<Transition>,
+    velocity: Velocity,
+    sustain: Sustain,
+    /// Notes are detuned to this tuning, unless it's sent as MTS
+    tuning: Option<Tuning>,
+    chord: ChordTrigger,
+    chord_commands: Receiver<chord::Command>,
+    arpeggiator: Arpeggiator,
+    arpeggiator_settings: Receiver<arpeggiator::Settings>,
+    stereo: stereo::Settings,
+    stereo_settings: Receiver<stereo::Settings>,
+    sidechain: Option<Sidechain>,
+    pre_dsp: Option<Hook>,
+    post_dsp: Option<Hook>,
+    transport: Arc<Transport>,
+    watchdog: Arc<Watchdog>,
+    activity: Arc<Activity>,
+    meter: Option<Meter>,
+    /// Where the output goes while a take is recorded
+    recording: Option<Tap>,
+    recordings: Receiver<Option<Tap>>,
+    /// Buffers the output is sent to takes and the stream in
+    pool: Pool,
+    /// Buffers missing from takes since the controller last reported them, because writing them
+    /// fell behind
+    dropped: Arc<AtomicUsize>,
+    /// The last `--pre-roll` seconds of output, which new takes start with
+    pre_roll: PreRoll,
+    /// An empty pre-roll to carry on with while the current one is written to a take
+    spare_pre_roll: Option<PreRoll>,
+    /// Pre-rolls given back by take writers
+    pre_rolls: Receiver<PreRoll>,
+    /// Where the output is streamed to, if anywhere
+    stream: Option<Feed>,
+    metronome: Option<Metronome>,
+    limiter: Limiter,
+    /// Whether the input is passed through instead of the plugin's output
+    bypass: Arc<AtomicBool>,
+    /// How far the output has faded towards the input, from 0 to 1
+    bypass_mix: f32,
+    budget: Option<Budget>,
+    bypass_over_budget: bool,
+    /// Whether the plugin was bypassed for going over its budget, so it isn't called once it has
+    /// faded out
+    shed: bool,
+    /// Where overloads are sent to be published over OSC
+    overloads: Option<SyncSender<f32>>,
+    /// Underruns counted by the output backend, see [`output::AudioBackend::underruns`]
+    underruns: Arc<AtomicUsize>,
+    /// The underrun count the last panic was sent for
+    underruns_seen: usize,
+    panic_on_xrun: bool,
+    /// Whether the output underran since the last buffer
+    panic_pending: bool,
+
+    current_position: usize,
+    current_channel: usize,
+
+    sample_rate: u32,
+    length: usize,
+    block_size: usize,
+    factor: usize,
+    channels: usize,
+}
+
+impl PluginSource {
+    /// Number of samples over which a replaced plugin is crossfaded
+    const FADE_LENGTH: usize = 4096;
+    /// Number of samples over which bypassing fades between the plugin and its input
+    const BYPASS_LENGTH: usize = 441;
+
+    /// Fills `outputs` with the next `length` samples, calling the plugin once per sub-block
+    fn process(&mut self) {
+        let underruns = self.underruns.load(Ordering::Relaxed);
+        if underruns != self.underruns_seen {
+            self.underruns_seen = underruns;
+            self.panic_pending = self.panic_on_xrun;
+        }
+
+        let sample_rate = self.sample_rate as f64;
+
+        if let Some(input) = &mut self.input {
+            let channels = match &self.routes.input {
+                Some(_) => &mut self.file_channels,
+                None => &mut self.inputs,
+            };
+            for i in 0..self.length {
+                for channel in channels.iter_mut() {
+                    channel[i] = input.next().unwrap_or(0.);
+                }
+            }
+            if let Some(matrix) = &self.routes.input {
+                for (channel, input) in self.inputs.iter_mut().enumerate() {
+                    matrix.mix(&self.file_channels, channel, input);
+                }
+            }
+        }
+
+        if let Some(hook) = &mut self.pre_dsp {
+            hook.process(&mut self.inputs, self.length, sample_rate as f32);
+        }
+        self.stereo
+            .process(stereo::Position::Pre, &mut self.inputs, self.length);
+
+        for frozen in self.freezes.try_iter() {
+            if let Some(previous) = std::mem::replace(&mut self.frozen, frozen) {
+                let _ = self.thawed.send(previous);
+            }
+        }
+        // Frozen output lines up with the transport, like the clip it was rendered from
+        let frame = self.transport.position() as usize / self.factor;
+
+        if let Ok(instance) = self.replacements.try_recv() {
+            if let Some(previous) = std::mem::replace(&mut self.instance, instance) {
+                let fade = Fade {
+                    instance: previous,
+                    position: 0,
+                };
+                if let Some(fade) = self.fade.replace(fade) {
+                    let _ = self.retired.send(fade.instance);
+                }
+            }
+        }
+
+        if let Ok(transition) = self.transitions.try_recv() {
+            self.transition = Some(transition);
+        }
+        for command in self.chord_commands.try_iter() {
+            self.chord.command(command);
+        }
+        if let Some(settings) = self.arpeggiator_settings.try_iter().last() {
+            self.arpeggiator.set(settings);
+        }
+        if let Some(settings) = self.stereo_settings.try_iter().last() {
+            self.stereo = settings;
+        }
+
+        let start_time = self.transport.seconds();
+        if self.shed && !self.bypass.load(Ordering::Relaxed) {
+            self.shed = false;
+        }
+        let mut plugin_time = Duration::ZERO;
+
+        for start in (0..self.length).step_by(self.block_size) {
+            let range = start..start + self.block_size;
+
+            self.events.clear();
+            if self.panic_pending {
+                self.panic_pending = false;
+                self.events.extend((0..16).map(|channel| event::MidiEvent {
+                    data: [0xb0 | channel, 123, 0],
+                    delta_frames: 0,
+                    live: true,
+                    note_length: None,
+                    note_offset: None,
+                    detune: 0,
+                    note_off_velocity: 0,
+                }));
+            }
+            // Only MIDI from the sources counts as coming in, not the panic sent above
+            let queued = self.events.len();
+            midi::merge(
+                &mut self.midi_sources,
+                self.transport.seconds(),
+                self.block_size * self.factor,
+                sample_rate * self.factor as f64,
+                &mut self.events,
+            );
+            if self.events.len() > queued {
+                self.activity.midi_in();
+            }
+            self.velocity.process(&mut self.events);
+            let parameters = self
+                .instance
+                .as_mut()
+                .map(|instance| instance.plugin.parameters());
+            if let Some(parameters) = parameters.as_ref().filter(|_| !self.cc_mappings.is_empty()) {
+                apply_cc(
+                    &self.cc_mappings,
+                    &self.macros,
+                    &mut self.events,
+                    &**parameters,
+                    self.mpe,
+                );
+            }
+            self.sustain.process(&mut self.events);
+            self.chord.process(&mut self.events);
+            self.arpeggiator.process(
+                &mut self.events,
+                &self.transport,
+                self.transport.seconds(),
+                self.block_size * self.factor,
+                sample_rate * self.factor as f64,
+            );
+            if let Some(tuning) = &self.tuning {
+                tuning.retune(&mut self.events);
+            }
+            if let (Some(transition), Some(parameters)) = (&self.transition, &parameters) {
+                if transition.process(self.transport.seconds(), &**parameters) {
+                    self.transition = None;
+                }
+            }
+
+            let shed = self.shed && self.bypass_mix >= 1.;
+            if self.frozen.is_none() && !shed {
+                let plugin_started = Instant::now();
+                self.watchdog.enter();
+                if let (Some(sidechain), Some(parameters)) = (&mut self.sidechain, &parameters) {
+                    sidechain.process(&self.inputs, range.clone(), &**parameters);
+                }
+                if let Some(instance) = &mut self.instance {
+                    instance.process(&self.inputs, &self.events, range.clone());
+                }
+                if let Some(fade) = &mut self.fade {
+                    fade.instance.process(&self.inputs, &self.events, range);
+                }
+                self.watchdog.leave();
+                plugin_time += plugin_started.elapsed();
+            }
+
+            self.transport
+                .advance((self.block_size * self.factor) as u64);
+        }
+
+        if let Some(budget) = &mut self.budget {
+            let share = plugin_time.as_secs_f64() / (self.length as f64 / sample_rate);
+            if let Some(share) = budget.check(share) {
+                warn!(
+                    "the plugin is over its CPU budget, taking {:.0}% of each buffer's time",
+                    share * 100.
+                );
+                if self.bypass_over_budget && !self.shed {
+                    self.bypass.store(true, Ordering::Relaxed);
+                    self.shed = true;
+                    warn!("bypassed the plugin until bypass is turned off");
+                }
+                if let Some(overloads) = &self.overloads {
+                    let _ = overloads.try_send(share as f32);
+                }
+            }
+        }
+
+        let outputs = match &self.routes.output {
+            Some(_) => &mut self.plugin_outputs,
+            None => &mut self.outputs,
+        };
+        for (channel, output) in outputs.iter_mut().enumerate() {
+            let samples = self
+                .instance
+                .as_ref()
+                .and_then(|instance| instance.outputs.get(channel));
+            match (&self.frozen, samples) {
+                (Some(frozen), _) => frozen.read(channel, frame, output),
+                (None, Some(samples)) => output.copy_from_slice(samples),
+                _ => output.fill(0.),
+            }
+
+            if let Some(fade) = &self.fade {
+                let previous = fade.instance.outputs.get(channel);
+                for (i, sample) in output.iter_mut().enumerate() {
+                    let gain = ((fade.position + i) as f32 / Self::FADE_LENGTH as f32).min(1.);
+                    let old = previous.map_or(0., |previous| previous[i]);
+                    *sample = *sample * gain + old * (1. - gain);
+                }
+            }
+        }
+
+        if let Some(matrix) = &self.routes.output {
+            for (channel, output) in self.outputs.iter_mut().enumerate() {
+                matrix.mix(&self.plugin_outputs, channel, output);
+            }
+        }
+
+        if let Some(mut fade) = self.fade.take() {
+            fade.position += self.length;
+            if fade.position < Self::FADE_LENGTH {
+                self.fade = Some(fade);
+            } else {
+                let _ = self.retired.send(fade.instance);
+            }
+        }
+
+        let target = if self.bypass.load(Ordering::Relaxed) {
+            1.
+        } else {
+            0.
+        };
+        if self.bypass_mix > 0. || target > 0. {
+            let step = 1. / Self::BYPASS_LENGTH as f32;
+            for i in 0..self.length {
+                self.bypass_mix = if target > self.bypass_mix {
+                    (self.bypass_mix + step).min(target)
+                } else {
+                    (self.bypass_mix - step).max(target)
+                };
+                for (channel, output) in self.outputs.iter_mut().enumerate() {
+                    // Without an input file there is nothing to pass through
+                    let dry = match (&self.input, self.inputs.len()) {
+                        (Some(_), inputs) if inputs > 0 => self.inputs[channel % inputs][i],
+                        _ => 0.,
+                    };
+                    output[i] += (dry - output[i]) * self.bypass_mix;
+                }
+            }
+        }
+
+        self.stereo
+            .process(stereo::Position::Post, &mut self.outputs, self.length);
+        if let Some(hook) = &mut self.post_dsp {
+            hook.process(&mut self.outputs, self.length, sample_rate as f32);
+        }
+
+        if let Some(metronome) = &mut self.metronome {
+            metronome.process(&self.transport, start_time, &mut self.outputs, self.length);
+        }
+
+        self.limiter.process(&mut self.outputs, self.length);
+
+        if self.input.is_some() {
+            self.activity.audio_in(&self.inputs, self.length);
+        }
+        self.activity.audio_out(&self.outputs, self.length);
+        if let Some(meter) = &mut self.meter {
+            meter.process(&self.outputs, self.length);
+        }
+
+        if let Some(recording) = self.recordings.try_iter().last() {
+            if self.spare_pre_roll.is_none() {
+                self.spare_pre_roll = self.pre_rolls.try_recv().ok();
+            }
+            // The writer gets the pre-roll whole and the spare carries on in its place. Without a
+            // spare, while the last take's writer still has it, the take starts without one.
+            if let (Some(tap), Some(mut spare)) = (&recording, self.spare_pre_roll.take()) {
+                spare.clear();
+                let pre_roll = std::mem::replace(&mut self.pre_roll, spare);
+                if let Err(err) = tap.try_send(Chunk::PreRoll(pre_roll)) {
+                    let (TrySendError::Full(chunk) | TrySendError::Disconnected(chunk)) = err;
+                    if let Chunk::PreRoll(pre_roll) = chunk {
+                        self.spare_pre_roll = Some(pre_roll);
+                    }
+                }
+            }
+            self.recording = recording;
+        }
+        self.pre_roll.push(&self.outputs, self.length);
+        // A listener just hears a gap if the stream falls behind
+        if let (Some(stream), Some(mut buffer)) = (&self.stream, self.pool.take()) {
+            pool::interleave(&self.outputs, self.length, &mut buffer);
+            if let Err(err) = stream.try_send(buffer) {
+                if let TrySendError::Disconnected(_) = err {
+                    self.stream = None;
+                }
+                let (TrySendError::Full(buffer) | TrySendError::Disconnected(buffer)) = err;
+                self.pool.give(buffer);
+            }
+        }
+        if let Some(tap) = &self.recording {
+            match self.pool.take() {
+                Some(mut buffer) => {
+                    pool::interleave(&self.outputs, self.length, &mut buffer);
+                    if let Err(err) = tap.try_send(Chunk::Buffer(buffer)) {
+                        if let TrySendError::Full(_) = err {
+                            self.dropped.fetch_add(1, Ordering::Relaxed);
+                        } else {
+                            self.recording = None;
+                        }
+                        let (TrySendError::Full(chunk) | TrySendError::Disconnected(chunk)) = err;
+                        if let Chunk::Buffer(buffer) = chunk {
+                            self.pool.give(buffer);
+                        }
+                    }
+                }
+                None => {
+                    self.dropped.fetch_add(1, Ordering::Relaxed);
+                }
+            }
+        }
+    }
+}
+
+impl Iterator for PluginSource {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.current_position == self.length {
+            self.process();
+            self.current_position = 0;
+        }
+
+        let result = self.outputs[self.current_channel][self.current_position];
+
+        self.current_channel += 1;
+        if self.current_channel == self.channels {
+            self.current_channel = 0;
+            self.current_position += 1;
+        }
+
+        Some(result)
+    }
+}
+impl Source for PluginSource {
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        self.channels as u16
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn total_duration(&self) -> Option<std::time::Duration> {
+        None
+    }
+}
+
+/// A chain's output rendered ahead of time, at the host sample rate
+struct Frozen {
+    outputs: Vec<Vec<f32>>,
+}
+
+impl Frozen {
+    /// Copies `channel` from `start` into `output`, with silence past the end
+    fn read(&self, channel: usize, start: usize, output: &mut [f32]) {
+        let samples = self
+            .outputs
+            .get(channel)
+            .and_then(|samples| samples.get(start..))
+            .unwrap_or_default();
+        let length = samples.len().min(output.len());
+        output[..length].copy_from_slice(&samples[..length]);
+        output[length..].fill(0.);
+    }
+}
+
+/// Loads plugins for processing in sub-blocks of `block_size` at `sample_rate`, oversampled by
+/// `factor`
+#[derive(Clone)]
+struct Loader {
+    host: Arc<Mutex<MyHost>>,
+    sample_rate: u32,
+    block_size: usize,
+    factor: usize,
+    /// Limit for each of loading the library, creating the instance and initialising it
+    timeout: Duration,
+    /// Where to trace dispatcher calls into the plugin, if anywhere
+    trace: Option<Arc<TraceFile>>,
+}
+
+impl Loader {
+    /// Loads and initialises the plugin at `path`.
+    ///
+    /// Each step runs on a helper thread, so a plugin that deadlocks produces an error instead of
+    /// hanging the host.
+    fn load(&self, path: &Path) -> Result<Vst2Plugin> {
+        let host = self.host.clone();
+        let owned_path = path.to_owned();
+        let mut plugin_loader = with_timeout(self.timeout, "loading the library", move || {
+            PluginLoader::load(&owned_path, host)
+        })?
+        .map_err(|err| diagnose::explain(path, err))?;
+
+        let mut plugin = with_timeout(self.timeout, "creating the instance", move || {
+            plugin_loader.instance()
+        })??;
+
+        if let Some(trace) = &self.trace {
+            trace_dispatcher(&mut plugin, trace.clone());
+        }
+
+        let mut plugin = Vst2Plugin::new(plugin);
+        let sample_rate = (self.sample_rate as usize * self.factor) as f32;
+        let block_size = self.block_size * self.factor;
+        let plugin = with_timeout(self.timeout, "initialising the plugin", move || {
+            Lifecycle::new(State::Created).set(&mut plugin, State::Suspended);
+            plugin.set_sample_rate(sample_rate);
+            plugin.set_block_size(block_size);
+            plugin
+        })?;
+
+        Ok(plugin)
+    }
+}
+
+/// One of the versions compared by a blind test
+enum Version {
+    Bypass(bool),
+    Scene(Scene),
+}
+
+/// Runs commands from the control surfaces against the running host
+struct Controller {
+    loader: Loader,
+    parameters: Parameters,
+    info: Info,
+    macros: Vec<Macro>,
+    scenes: HashMap<String, Scene>,
+    transitions: SyncSender<Transition>,
+    /// Whether note-offs are held back, see [`Sustain`]
+    hold: Arc<AtomicBool>,
+    bypass: Arc<AtomicBool>,
+    /// The blind test being run and the two versions it compares
+    blind: Option<(BlindTest, [Version; 2])>,
+    chord_commands: SyncSender<chord::Command>,
+    arpeggiator: arpeggiator::Settings,
+    arpeggiator_settings: SyncSender<arpeggiator::Settings>,
+    stereo: stereo::Settings,
+    stereo_settings: SyncSender<stereo::Settings>,
+    transport: Arc<Transport>,
+    activity: Arc<Activity>,
+    editor_open: bool,
+    /// Whether the terminal shows the plugin's parameters with `--generic-ui`, which hold on to
+    /// them, so the plugin can't be replaced
+    generic_ui: bool,
+    /// Resident size of the host before the plugin was loaded
+    memory_baseline: Option<u64>,
+    replacements: SyncSender<Option<Instance>>,
+    retired: Receiver<Instance>,
+    /// Instances sent to the audio thread that it hasn't given back yet
+    instances: usize,
+    /// Path of the plugin currently playing
+    path: PathBuf,
+    /// The plugin's state from when `reload` unloaded it but couldn't load it again. Nothing may
+    /// call into `parameters` until a reload or replace succeeds.
+    unloaded: Option<Preset>,
+    /// Whether a call into the plugin timed out. The helper thread that gave up on it may still be
+    /// inside it, so nothing calls into it again: it can only be replaced, and it's leaked rather
+    /// than shut down.
+    hung: bool,
+    /// How many of the instances the audio thread gives back next are leaked, since they hung
+    abandoned: usize,
+    /// The MIDI file and audio file played into the plugin, which freezing renders
+    clip: Option<Sequence>,
+    input: Option<PathBuf>,
+    freezes: SyncSender<Option<Frozen>>,
+    thawed: Receiver<Frozen>,
+    frozen: bool,
+    surface: Option<Surface>,
+    /// Where the plugin's state is saved every `AUTOSAVE`, and when it last was
+    state_file: Option<PathBuf>,
+    saved: Instant,
+    /// The session's autosaves, written every `AUTOSAVE` as well
+    autosave: Option<Autosave>,
+    /// The config file, checked for changes every `AUTOSAVE`
+    config: Option<Watched>,
+    session_dir: PathBuf,
+    /// Seconds of output takes start with from before they were started
+    pre_roll: f64,
+    recording: Option<Recording>,
+    recordings: SyncSender<Option<Tap>>,
+    /// Where take writers give the audio thread's buffers and pre-rolls back
+    buffers: Returns,
+    pre_rolls: SyncSender<PreRoll>,
+    /// Buffers the audio thread couldn't send to the take, see [`PluginSource::dropped`]
+    dropped: Arc<AtomicUsize>,
+    /// Underruns counted by the output backend, see [`output::AudioBackend::underruns`]
+    underruns: Arc<AtomicUsize>,
+    /// The underrun count last logged
+    underruns_reported: usize,
+    /// Where the plugin's parameter changes are collected during takes, with `--record-automation`
+    automation: Option<Arc<Automation>>,
+    /// The mappings automation is written through in reverse
+    cc_mappings: Vec<CcMapping>,
+    /// The preset loaded last, where `preset next` continues from
+    preset: Option<PathBuf>,
+    preset_tag: Option<String>,
+    /// Whether commands calling into the plugin directly are allowed
+    expert: bool,
+    /// What every newly loaded plugin is sent before it plays
+    setup: Setup,
+    /// Whether the audio is processed between commands rather than on a thread of its own, so
+    /// nothing may wait for it
+    single_thread: bool,
+
+    sample_rate: u32,
+    length: usize,
+    block_size: usize,
+    factor: usize,
+}
+
+impl Controller {
+    /// Seconds rendered after the clip ends when freezing, unless given
+    const FREEZE_TAIL: f64 = 2.;
+    /// Seconds over which a blind test moves between two scenes
+    const BLIND_FADE: f64 = 0.05;
+    /// How often the plugin's state is saved to the state file
+    const AUTOSAVE: Duration = Duration::from_secs(2);
+
+    /// Runs commands from the bus until every control surface has ended or a command quits
+    fn run(&mut self, commands: Receiver<Command>) -> Result<()> {
+        self.check_config();
+        loop {
+            match commands.recv_timeout(Self::AUTOSAVE) {
+                Ok(command) => {
+                    let mut output = Vec::new();
+                    let running = self.execute(&command.line, &mut output)?;
+                    command.reply(String::from_utf8_lossy(&output).into_owned());
+                    if !running {
+                        break;
+                    }
+                }
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => break,
+            }
+            if self.saved.elapsed() >= Self::AUTOSAVE {
+                self.tick();
+            }
+        }
+
+        self.autosave();
+        if self.recording.is_some() {
+            info!("{}", self.stop_recording()?);
+        }
+        if self.hung {
+            // Taken away from the audio thread here, since shutting it down with it would call
+            // into it
+            self.abandoned = self.instances;
+            self.unload()?;
+        }
+
+        Ok(())
+    }
+
+    /// Plays `source` on this thread and runs the commands typed on stdin between its buffers,
+    /// writing their output to `out`, until stdin is closed or a command quits
+    #[cfg(unix)]
+    fn run_inline(
+        &mut self,
+        output: &Output,
+        backend: Box<dyn output::AudioBackend>,
+        source: PluginSource,
+        mut out: impl Write,
+    ) -> Result<()> {
+        let mut stdin = PolledStdin::new();
+        self.check_config();
+        output.play_inline(backend, source, || {
+            while let Some(line) = stdin.poll()? {
+                if !self.execute(&line, &mut out)? {
+                    return Ok(false);
+                }
+            }
+            if self.saved.elapsed() >= Self::AUTOSAVE {
+                self.tick();
+            }
+            Ok(!stdin.closed())
+        })?;
+        self.autosave();
+
+        Ok(())
+    }
+
+    /// Does what the controller does every `AUTOSAVE` between commands
+    fn tick(&mut self) {
+        self.autosave();
+        self.check_config();
+        self.report_dropped();
+        self.report_underruns();
+    }
+
+    /// Logs the underruns since this was last called, if there were any
+    fn report_underruns(&mut self) {
+        let underruns = self.underruns.load(Ordering::Relaxed);
+        let new = underruns - self.underruns_reported;
+        if new > 0 {
+            warn!("the output underran {new} times");
+            self.underruns_reported = underruns;
+        }
+    }
+
+    /// Logs the buffers missing from the take since this was last called, if there are any
+    fn report_dropped(&self) {
+        let dropped = self.dropped.swap(0, Ordering::Relaxed);
+        if dropped > 0 {
+            warn!("writing the take has fallen behind, {dropped} buffers are missing from it");
+        }
+    }
+
+    /// Saves the plugin's state to the state file and the session's autosaves, if there are any
+    fn autosave(&mut self) {
+        self.saved = Instant::now();
+        // The last state saved stays the plugin's while it isn't loaded
+        if self.unloaded.is_some()
+            || self.hung
+            || (self.state_file.is_none() && self.autosave.is_none())
+        {
+            return;
+        }
+        let state = Preset::capture(&self.parameters, &self.info);
+
+        if let Some(path) = &self.state_file {
+            // Written next to it first, so a crash while saving leaves the previous state intact
+            let partial = path.with_extension("partial");
+            let result = state
+                .write(&partial)
+                .and_then(|()| Ok(fs::rename(&partial, path)?));
+            if let Err(err) = result {
+                warn!("failed to save the plugin's state: {err:#}");
+            }
+        }
+        if let Some(autosave) = &mut self.autosave {
+            let settings = Settings {
+                plugin: self.path.clone(),
+                preset: self.preset.clone(),
+                bypass: self.bypass.load(Ordering::Relaxed),
+                hold: self.hold.load(Ordering::Relaxed),
+            };
+            if let Err(err) = autosave.save(&state, &settings) {
+                warn!("failed to autosave the session: {err:#}");
+            }
+        }
+    }
+
+    /// Applies what changed in the config file since it was last applied, logging each change
+    fn check_config(&mut self) {
+        let Some(watched) = &mut self.config else {
+            return;
+        };
+        let config = match watched.poll() {
+            Ok(Some(config)) => config,
+            Ok(None) => return,
+            Err(err) => {
+                warn!("can't apply the config: {err:#}");
+                return;
+            }
+        };
+        let changes = watched.applied.changes(&config);
+        watched.applied = config;
+
+        for change in changes {
+            let result = match &change {
+                Change::Plugin(path) => search::resolve(path).and_then(|path| self.replace(&path)),
+                _ if self.unloaded.is_some() => Err(anyhow!("the plugin isn't loaded")),
+                _ if self.hung => Err(anyhow!("the plugin stopped responding")),
+                Change::Preset(path) => self.load_preset(path).map(|()| String::new()),
+                Change::Bypass(on) => self.bypass(if *on { "on" } else { "off" }),
+                Change::Hold(on) => self.hold(if *on { "on" } else { "off" }),
+                Change::Parameter(index, value) => {
+                    if (0..self.info.parameters).contains(index) {
+                        self.parameters.set_parameter(*index, *value);
+                        Ok(String::new())
+                    } else {
+                        Err(anyhow!(
+                            "{} has {} parameters",
+                            self.info.name,
+                            self.info.parameters
+                        ))
+                    }
+                }
+                Change::Removed(_) => Ok(String::new()),
+            };
+            match result {
+                Ok(_) => info!("config: {change}"),
+                Err(err) => warn!("config: {change} failed: {err:#}"),
+            }
+        }
+    }
+
+    /// Runs a single command line, writing its output to `out`.
+    ///
+    /// Returns `false` once the host should quit.
+    fn execute(&mut self, line: &str, out: &mut impl Write) -> Result<bool> {
+        // Instances are dropped here rather than on the audio thread, since shutting a plugin down
+        // and unloading its library can take a while
+        while let Ok(instance) = self.retired.try_recv() {
+            self.dispose(instance);
+        }
+        for frozen in self.thawed.try_iter() {
+            drop(frozen);
+        }
+
+        let (command, argument) = match line.trim().split_once(' ') {
+            Some((command, argument)) => (command, argument.trim()),
+            None => (line.trim(), ""),
+        };
+
+        // A plugin that stopped responding can only be replaced
+        if self.hung && !matches!(command, "" | "quit" | "exit" | "replace") {
+            writeln!(
+                out,
+                "error: {} stopped responding, replace it or quit",
+                self.info.name
+            )?;
+            return Ok(true);
+        }
+        // Without a plugin, the only commands left are the ones that load one
+        if self.unloaded.is_some()
+            && !matches!(command, "" | "quit" | "exit" | "replace" | "reload")
+        {
+            writeln!(
+                out,
+                "error: the plugin isn't loaded, reload or replace it first"
+            )?;
+            return Ok(true);
+        }
+
+        let result = match command {
+            "" => return Ok(true),
+            "quit" | "exit" => return Ok(false),
+            "replace" => search::resolve(Path::new(argument)).and_then(|path| self.replace(&path)),
+            "reload" => self.reload(),
+            "preset" => self.preset(argument),
+            "macro" => self.set_macro(argument),
+            "scene" => self.scene(argument),
+            "hold" => self.hold(argument),
+            "bypass" => self.bypass(argument),
+            "blind" => self.blind(argument),
+            "arp" => self.arpeggiator(argument),
+            "stereo" => self.stereo(argument),
+            "chord" => self.chord(argument),
+            "memory" => self.memory(),
+            "activity" => Ok(self.activity.summary()),
+            "record" => self.record(argument),
+            "freeze" => self.freeze(argument),
+            "unfreeze" => self.unfreeze(),
+            "vendor-specific" => self.vendor_specific(argument),
+            "export-programs" => self.export_programs(argument),
+            "import-programs" => self.import_programs(argument),
+            _ => Err(anyhow!(
+                "unknown command {command:?}, expected replace <path>, preset <path>|next|previous|<number>, \
+                 macro <name> <value>, \
+                 scene save|load <name>, hold [on|off], bypass [on|off], \
+                 blind start|a|b|vote|reveal, arp <setting> <value>, stereo <setting> <value>, \
+                 chord <intervals>|learn|off, memory, activity, record [start|stop], freeze [<tail>], unfreeze, \
+                 vendor-specific <index> <value> [<hex data>], export-programs <dir>, \
+                 import-programs <dir>, reload or quit"
+            )),
+        };
+
+        match result {
+            Ok(message) => writeln!(out, "{message}")?,
+            Err(err) => writeln!(out, "error: {err:#}")?,
+        }
+
+        Ok(true)
+    }
+
+    /// Sets a macro from an argument like `morph 0.5`
+    fn set_macro(&mut self, argument: &str) -> Result<String> {
+        let (name, value) = argument
+            .split_once(' ')
+            .context("expected macro <name> <value>")?;
+        let value: f32 = value.trim().parse().context("invalid value")?;
+        ensure!((0. ..=1.).contains(&value), "macro values go from 0 to 1");
+        let macro_ = self
+            .macros
+            .iter()
+            .find(|macro_| macro_.name == name)
+            .with_context(|| format!("there is no macro called {name:?}"))?;
+
+        macro_.apply(value, &*self.parameters);
+        Ok(format!("Set {name} to {value}"))
+    }
+
+    /// Loads an FXP or FXB file, or one of the plugin's presets in the preset library with
+    /// `next`, `previous` or its number
+    fn preset(&mut self, argument: &str) -> Result<String> {
+        let number: Option<usize> = argument.parse().ok();
+        let step = match argument {
+            "next" => 1,
+            "previous" => -1,
+            _ if number.is_some() => 0,
+            _ => {
+                self.load_preset(Path::new(argument))?;
+                return Ok(format!("Loaded {argument}"));
+            }
+        };
+
+        let library = Library::load()?;
+        let presets = library.for_plugin(self.info.unique_id, self.preset_tag.as_deref());
+        ensure!(
+            !presets.is_empty(),
+            "the preset library has no presets{} for {}",
+            self.preset_tag
+                .as_ref()
+                .map_or(String::new(), |tag| format!(" tagged {tag}")),
+            self.info.name
+        );
+
+        let current = self
+            .preset
+            .as_ref()
+            .and_then(|current| presets.iter().position(|entry| &entry.path == current));
+        let position = match (number, current) {
+            (Some(number), _) => {
+                ensure!(
+                    number < presets.len(),
+                    "there are only {} presets for {}",
+                    presets.len(),
+                    self.info.name
+                );
+                number
+            }
+            (None, Some(current)) => {
+                (current as isize + step).rem_euclid(presets.len() as isize) as usize
+            }
+            (None, None) if step > 0 => 0,
+            (None, None) => presets.len() - 1,
+        };
+
+        let entry = presets[position];
+        self.load_preset(&entry.path)?;
+        Ok(format!(
+            "Loaded {} ({}/{})",
+            entry.name,
+            position + 1,
+            presets.len()
+        ))
+    }
+
+    /// Loads an FXP or FXB file into the plugin
+    fn load_preset(&mut self, path: &Path) -> Result<()> {
+        let preset = Preset::read(path)?;
+        preset
+            .check(&self.info)
+            .with_context(|| format!("can't load {}", path.display()))?;
+        self.apply(preset)?;
+        // The library refers to presets by their full paths
+        self.preset = Some(fs::canonicalize(path).unwrap_or_else(|_| path.to_owned()));
+
+        Ok(())
+    }
+
+    /// Turns note hold on or off, or toggles it without an argument
+    fn hold(&mut self, argument: &str) -> Result<String> {
+        let hold = match argument {
+            "on" => true,
+            "off" => false,
+            "" => !self.hold.load(Ordering::Relaxed),
+            _ => bail!("expected hold [on|off]"),
+        };
+        self.hold.store(hold, Ordering::Relaxed);
+
+        Ok(format!("Hold is {}", if hold { "on" } else { "off" }))
+    }
+
+    /// Turns bypass on or off, or toggles it without an argument
+    fn bypass(&mut self, argument: &str) -> Result<String> {
+        ensure!(self.blind.is_none(), "a blind test is running");
+        let bypass = match argument {
+            "on" => true,
+            "off" => false,
+            "" => !self.bypass.load(Ordering::Relaxed),
+            _ => bail!("expected bypass [on|off]"),
+        };
+        self.bypass.store(bypass, Ordering::Relaxed);
+
+        Ok(format!("Bypass is {}", if bypass { "on" } else { "off" }))
+    }
+
+    /// Runs a blind test, from an argument like `start`, `start <scene> <scene>`, `a`, `vote b` or
+    /// `reveal`.
+    ///
+    /// Without scenes, the processed output is compared with the bypassed one.
+    fn blind(&mut self, argument: &str) -> Result<String> {
+        let mut words = argument.split_whitespace();
+        let usage = "expected blind start [<scene> <scene>], blind a|b, blind vote a|b or \
+                     blind reveal";
+
+        match words.next().context(usage)? {
+            "start" => {
+                ensure!(self.blind.is_none(), "a blind test is running already");
+                let (labels, versions) = match (words.next(), words.next()) {
+                    (None, _) => (
+                        ["processed".to_owned(), "bypassed".to_owned()],
+                        [Version::Bypass(false), Version::Bypass(true)],
+                    ),
+                    (Some(first), Some(second)) => {
+                        let scene = |name: &str| {
+                            self.scenes
+                                .get(name)
+                                .cloned()
+                                .map(Version::Scene)
+                                .with_context(|| format!("there is no scene called {name:?}"))
+                        };
+                        (
+                            [first.to_owned(), second.to_owned()],
+                            [scene(first)?, scene(second)?],
+                        )
+                    }
+                    _ => bail!(usage),
+                };
+                self.blind = Some((BlindTest::new(labels), versions));
+                self.play_blind(Side::A)?;
+                Ok("Round 1, playing A".to_owned())
+            }
+            "reveal" => {
+                let (test, _) = self.blind.take().context("no blind test is running")?;
+                self.bypass.store(false, Ordering::Relaxed);
+                Ok(test.reveal())
+            }
+            "vote" => {
+                let side: Side = words.next().context(usage)?.parse()?;
+                let (test, _) = self.blind.as_mut().context("no blind test is running")?;
+                test.vote(side);
+                let round = test.round();
+                self.play_blind(Side::A)?;
+                Ok(format!("Voted for {side:?}; round {round}, playing A"))
+            }
+            side => {
+                let side: Side = side.parse().context(usage)?;
+                self.play_blind(side)?;
+                Ok(format!("Playing {side:?}"))
+            }
+        }
+    }
+
+    /// Switches to the version `side` plays in the current round of the blind test
+    fn play_blind(&self, side: Side) -> Result<()> {
+        let (test, versions) = self.blind.as_ref().context("no blind test is running")?;
+        match &versions[test.version(side)] {
+            Version::Bypass(bypass) => self.bypass.store(*bypass, Ordering::Relaxed),
+            Version::Scene(scene) => self.morph(scene, Length::Seconds(Self::BLIND_FADE))?,
+        }
+
+        Ok(())
+    }
+
+    /// Reports the host's memory use and how much it grew since before the plugin was loaded
+    fn memory(&self) -> Result<String> {
+        let resident = memory::resident().context("memory use isn't available on this platform")?;
+        let growth = Growth::since(self.memory_baseline).context("no baseline to compare with")?;
+
+        Ok(format!(
+            "Resident {:.1} MiB, {growth} since before loading the plugin",
+            resident as f64 / (1 << 20) as f64
+        ))
+    }
+
+    /// Saves every program of the plugin to an FXP file of its own in the directory `argument`.
+    ///
+    /// The plugin is bypassed while it's switched through its programs, so they aren't heard.
+    fn export_programs(&mut self, argument: &str) -> Result<String> {
+        ensure!(!argument.is_empty(), "expected export-programs <dir>");
+        let bypassed = self.bypass.swap(true, Ordering::Relaxed);
+        // With --single-thread the plugin doesn't process again until this returns anyway
+        if !bypassed && !self.single_thread {
+            let faded = PluginSource::BYPASS_LENGTH + 2 * self.length;
+            thread::sleep(Duration::from_secs_f64(
+                faded as f64 / self.sample_rate as f64,
+            ));
+        }
+        let result = preset::export_programs(
+            &self.parameters,
+            &self.info,
+            Path::new(argument),
+            self.loader.timeout,
+        );
+        self.bypass.store(bypassed, Ordering::Relaxed);
+        let count = self.check_hung(result)?;
+
+        Ok(format!("Exported {count} programs to {argument}"))
+    }
+
+    /// Loads the FXP files in the directory `argument` into the plugin's programs, in order
+    fn import_programs(&mut self, argument: &str) -> Result<String> {
+        ensure!(!argument.is_empty(), "expected import-programs <dir>");
+        let result = preset::import_programs(
+            self.parameters.clone(),
+            &self.info,
+            Path::new(argument),
+            self.loader.timeout,
+        );
+        let count = self.check_hung(result)?;
+        Ok(format!("Imported {count} programs from {argument}"))
+    }
+
+    /// Calls `effVendorSpecific` from an argument like `0xdeadbeef 0xdeadf00d 74657374`, passing
+    /// the plugin a pointer to the data if there is any
+    fn vendor_specific(&self, argument: &str) -> Result<String> {
+        ensure!(
+            self.expert,
+            "vendor-specific calls into the plugin directly; start with --expert to use it"
+        );
+        let usage = "expected vendor-specific <index> <value> [<hex data>]";
+        let mut words = argument.split_whitespace();
+        let index = parse_integer(words.next().context(usage)?)?;
+        let value = parse_integer(words.next().context(usage)?)?;
+        let mut data = match words.next() {
+            Some(hex) => parse_hex(hex)?,
+            None => Vec::new(),
+        };
+        ensure!(words.next().is_none(), usage);
+
+        let index = i32::try_from(index)
+            .or_else(|_| u32::try_from(index).map(|index| index as i32))
+            .context("the index has to fit in 32 bits")?;
+        let value = isize::try_from(value).context("the value doesn't fit in a pointer")?;
+        let ptr = if data.is_empty() {
+            ptr::null_mut()
+        } else {
+            data.as_mut_ptr().cast()
+        };
+        let result = unsafe {
+            dispatch(
+                self.parameters.effect(),
+                OpCode::VendorSpecific,
+                index,
+                value,
+                ptr,
+                0.,
+            )
+        };
+
+        let mut reply = format!("effVendorSpecific({index:#x}, {value:#x}) returned {result}");
+        if !data.is_empty() {
+            let hex: String = data.iter().map(|byte| format!("{byte:02x}")).collect();
+            reply = format!("{reply}, data is {hex}");
+        }
+        info!("{reply}");
+        Ok(reply)
+    }
+
+    /// Starts or stops recording a take, or toggles it without an argument
+    fn record(&mut self, argument: &str) -> Result<String> {
+        let start = match argument {
+            "start" => true,
+            "stop" => false,
+            "" => self.recording.is_none(),
+            _ => bail!("expected record [start|stop]"),
+        };
+        ensure!(
+            !self.single_thread,
+            "takes are written on a thread of their own, which --single-thread doesn't allow"
+        );
+
+        if !start {
+            return self.stop_recording();
+        }
+        ensure!(self.recording.is_none(), "a take is being recorded already");
+
+        let tempo_map = self.transport.tempo_map();
+        let seconds = self.transport.seconds();
+        let time_signature = tempo_map.time_signature_at(seconds);
+        let sidecar = Sidecar {
+            plugin: self.info.name.clone(),
+            vendor: self.info.vendor.clone(),
+            plugin_path: fs::canonicalize(&self.path).unwrap_or_else(|_| self.path.clone()),
+            preset: self.preset.clone(),
+            tempo: tempo_map.tempo_at(seconds),
+            time_signature: (time_signature.numerator, time_signature.denominator),
+            pre_roll: self.pre_roll,
+        };
+        let (recording, tap) = Recording::start(
+            &self.session_dir,
+            2,
+            self.sample_rate,
+            sidecar,
+            self.buffers.clone(),
+            self.pre_rolls.clone(),
+        )?;
+        bus::feed(&self.recordings, Some(tap))?;
+        if let Some(automation) = &self.automation {
+            automation.start(seconds);
+        }
+
+        let message = format!("Recording {}", recording.path.display());
+        self.recording = Some(recording);
+        Ok(message)
+    }
+
+    fn stop_recording(&mut self) -> Result<String> {
+        let recording = self.recording.take().context("no take is being recorded")?;
+        // The writer finishes once the audio thread lets go of the tap
+        bus::feed(&self.recordings, None)?;
+        self.report_dropped();
+
+        let path = recording.path.clone();
+        let tempo = recording.tempo();
+        let seconds = recording.finish()?;
+        let mut message = format!("Recorded {seconds:.1}s to {}", path.display());
+
+        if let Some(automation) = &self.automation {
+            let changes = automation.stop();
+            let automation_path = path.with_extension("mid");
+            take::write_automation(&automation_path, &changes, &self.cc_mappings, tempo)?;
+            message.push_str(&format!(
+                " and {} parameter changes to {}",
+                changes.len(),
+                automation_path.display()
+            ));
+        }
+        Ok(message)
+    }
+
+    /// Sets the chord trigger from an argument like `0,4,7`, `learn` or `off`
+    fn chord(&mut self, argument: &str) -> Result<String> {
+        let (command, message) = match argument {
+            "off" => (chord::Command::Off, "Chord trigger is off".to_owned()),
+            "learn" => (chord::Command::Learn, "Play a chord to learn it".to_owned()),
+            _ => {
+                let intervals: Intervals = argument.parse()?;
+                let message = format!("Playing chords of {argument}");
+                (chord::Command::Play(intervals), message)
+            }
+        };
+
+        bus::feed(&self.chord_commands, command)?;
+        Ok(message)
+    }
+
+    /// Changes the arpeggiator from an argument like `on`, `mode down` or `rate 1/8`
+    fn arpeggiator(&mut self, argument: &str) -> Result<String> {
+        let mut settings = self.arpeggiator;
+        match argument.split_once(' ') {
+            _ if argument == "on" => settings.enabled = true,
+            _ if argument == "off" => settings.enabled = false,
+            Some((setting, value)) => settings.set(setting, value.trim())?,
+            None => bail!("expected arp on|off or arp mode|rate|octaves|gate <value>"),
+        }
+
+        bus::feed(&self.arpeggiator_settings, settings)?;
+        self.arpeggiator = settings;
+        Ok(format!("{settings:?}"))
+    }
+
+    /// Changes the stereo tools from an argument like `on`, `width 1.5` or `invert left`
+    fn stereo(&mut self, argument: &str) -> Result<String> {
+        let mut settings = self.stereo;
+        match argument.split_once(' ') {
+            _ if argument == "on" => settings.enabled = true,
+            _ if argument == "off" => settings.enabled = false,
+            Some((setting, value)) => settings.set(setting, value.trim())?,
+            None => {
+                bail!("expected stereo on|off or stereo position|width|mono|invert|swap <value>")
+            }
+        }
+
+        bus::feed(&self.stereo_settings, settings)?;
+        self.stereo = settings;
+        Ok(format!("{settings:?}"))
+    }
+
+    /// Saves or loads a scene, from an argument like `save verse` or `load chorus --over 2bars`
+    fn scene(&mut self, argument: &str) -> Result<String> {
+        let mut words = argument.split_whitespace();
+        let usage = "expected scene save <name> or scene load <name> [--over <length>]";
+        let action = words.next().context(usage)?;
+        let name = words.next().context(usage)?;
+
+        match action {
+            "save" => {
+                let scene = Scene::capture(&*self.parameters, self.info.parameters);
+                self.scenes.insert(name.to_owned(), scene);
+                Ok(format!("Saved scene {name}"))
+            }
+            "load" => {
+                let length = match (words.next(), words.next()) {
+                    (Some("--over"), Some(length)) => length.parse()?,
+                    (None, _) => Length::Seconds(0.),
+                    _ => bail!(usage),
+                };
+                let to = self
+                    .scenes
+                    .get(name)
+                    .with_context(|| format!("there is no scene called {name:?}"))?;
+
+                self.morph(to, length)?;
+                Ok(format!("Loading scene {name}"))
+            }
+            _ => bail!(usage),
+        }
+    }
+
+    /// Moves the parameters from where they are now to `to` over `length`
+    fn morph(&self, to: &Scene, length: Length) -> Result<()> {
+        let start = self.transport.seconds();
+        let transition = Transition {
+            from: Scene::capture(&*self.parameters, self.info.parameters),
+            to: to.clone(),
+            start,
+            end: length.end(&self.transport, start),
+        };
+        bus::feed(&self.transitions, transition)
+    }
+
+    /// Renders the clip through a second instance of the plugin in the current state, then plays
+    /// the result in place of the plugin, which stops being called.
+    ///
+    /// The argument is how many seconds to keep rendering after the clip ends, for the plugin's
+    /// tail. MIDI effects like the arpeggiator aren't part of the frozen output.
+    fn freeze(&mut self, argument: &str) -> Result<String> {
+        ensure!(!self.frozen, "the plugin is frozen already");
+        ensure!(
+            self.clip.is_some() || self.input.is_some(),
+            "there's nothing to freeze; start with --play-midi or --play-input"
+        );
+        let tail = match argument {
+            "" => Self::FREEZE_TAIL,
+            _ => argument
+                .parse()
+                .context("expected freeze [<tail in seconds>]")?,
+        };
+        ensure!(tail >= 0., "the tail can't be negative");
+
+        let started = Instant::now();
+        let sample_rate = self.sample_rate as f64;
+        let mut plugin = self
+            .loader
+            .load(&self.path)
+            .with_context(|| format!("failed to load {}", self.path.display()))?;
+        let info = plugin.info();
+        transfer_state(&*self.parameters, &self.info, &*plugin.parameters(), &info);
+        let mut instance = self.instance(plugin);
+
+        let mut input = match &self.input {
+            Some(path) => Some(
+                open_input(path, false, info.inputs as u16, self.sample_rate)
+                    .with_context(|| format!("failed to open {}", path.display()))?,
+            ),
+            None => None,
+        };
+        let mut player = self.clip.clone().map(Player::new);
+        let clip_end = self
+            .clip
+            .as_ref()
+            .and_then(|clip| clip.messages.last())
+            .map_or(0, |message| (message.seconds * sample_rate) as usize);
+        let mut end = clip_end;
+
+        // The same buffers and sub-blocks as the audio thread, so the plugin sees the same calls
+        let mut inputs = vec![vec![1.; self.length]; info.inputs as usize];
+        let mut outputs = vec![Vec::new(); instance.outputs.len()];
+        let mut events = Vec::new();
+        let mut frame = 0;
+        while input.is_some() || frame < end + (tail * sample_rate) as usize {
+            if let Some(stream) = &mut input {
+                let mut ended = false;
+                for i in 0..self.length {
+                    for channel in inputs.iter_mut() {
+                        channel[i] = stream.next().unwrap_or_else(|| {
+                            ended = true;
+                            0.
+                        });
+                    }
+                }
+                if ended {
+                    input = None;
+                    end = end.max(frame + self.length);
+                }
+            }
+
+            for start in (0..self.length).step_by(self.block_size) {
+                events.clear();
+                if let Some(player) = &mut player {
+                    player.events(
+                        (frame + start) as f64 / sample_rate,
+                        self.block_size * self.factor,
+                        sample_rate * self.factor as f64,
+                        &mut events,
+                    );
+                }
+                instance.process(&inputs, &events, start..start + self.block_size);
+            }
+
+            for (output, samples) in outputs.iter_mut().zip(&instance.outputs) {
+                output.extend_from_slice(samples);
+            }
+            frame += self.length;
+        }
+
+        bus::feed(&self.freezes, Some(Frozen { outputs }))?;
+        self.frozen = true;
+
+        Ok(format!(
+            "Froze {} into {:.1}s of audio in {:.1}s",
+            self.info.name,
+            frame as f64 / sample_rate,
+            started.elapsed().as_secs_f64()
+        ))
+    }
+
+    /// Goes back to processing live after `freeze`
+    fn unfreeze(&mut self) -> Result<String> {
+        ensure!(self.frozen, "the plugin isn't frozen");
+        bus::feed(&self.freezes, None)?;
+        self.frozen = false;
+
+        Ok(format!("Processing {} live again", self.info.name))
+    }
+
+    /// Wraps a newly loaded plugin in an instance, set up like the first one
+    fn instance(&self, plugin: Vst2Plugin) -> Instance {
+        let mut instance = Instance::new(plugin, self.length, self.block_size, self.factor);
+        self.setup.apply(&mut instance);
+        instance
+    }
+
+    /// Loads the plugin at `path` and crossfades to it from the current one
+    fn replace(&mut self, path: &Path) -> Result<String> {
+        ensure!(!self.frozen, "unfreeze the plugin before replacing it");
+        ensure!(
+            !self.editor_open,
+            "the current plugin's editor is open; start with --disable-editor to replace plugins"
+        );
+        ensure!(
+            !self.generic_ui,
+            "plugins can't be replaced while --generic-ui shows their parameters"
+        );
+
+        let mut plugin = self
+            .loader
+            .load(path)
+            .with_context(|| format!("failed to load {}", path.display()))?;
+        let info = plugin.info();
+        let parameters = plugin.parameters();
+
+        if self.unloaded.is_none() && !self.hung {
+            transfer_state(&*self.parameters, &self.info, &*parameters, &info);
+        }
+
+        let instance = self.instance(plugin);
+        bus::feed(&self.replacements, Some(instance))?;
+        if self.hung {
+            // Every instance the audio thread still has goes before the new one
+            self.abandoned = self.instances;
+            self.hung = false;
+        }
+        self.instances += 1;
+
+        let message = match self.unloaded.take() {
+            Some(_) => format!("Loaded {}", info.name),
+            None => format!("Replaced {} with {}", self.info.name, info.name),
+        };
+        self.parameters = parameters;
+        self.info = info;
+        self.path = path.to_owned();
+        if let Some(surface) = &self.surface {
+            surface.set_plugin(self.parameters.clone(), self.info.clone());
+        }
+
+        Ok(message)
+    }
+
+    /// Unloads the plugin completely and loads it again from its file, with the state it had.
+    ///
+    /// This picks up a plugin that was rebuilt while the host was running. The library can only be
+    /// loaded again once the old one is gone, so if that fails the host carries on without a
+    /// plugin, playing silence, until a reload or replace succeeds.
+    fn reload(&mut self) -> Result<String> {
+        self.check_reload()?;
+
+        if self.unloaded.is_none() {
+            // The plugin may be gone from here on, even if unloading it fails
+            self.unloaded = Some(Preset::capture(&self.parameters, &self.info));
+            self.unload()
+                .with_context(|| format!("failed to unload {}", self.info.name))?;
+            info!("unloaded {}", self.info.name);
+        }
+
+        let started = Instant::now();
+        self.load_again()
+            .context("the plugin stays unloaded until a reload or replace succeeds")?;
+
+        Ok(format!(
+            "Reloaded {} from {} in {:.1}s",
+            self.info.name,
+            self.path.display(),
+            started.elapsed().as_secs_f64()
+        ))
+    }
+
+    /// Loads the plugin from its file again after `reload` unloaded it, with the state it had
+    fn load_again(&mut self) -> Result<()> {
+        let state = self
+            .unloaded
+            .clone()
+            .context("the plugin is still loaded")?;
+        let mut plugin = self
+            .loader
+            .load(&self.path)
+            .with_context(|| format!("failed to load {} again", self.path.display()))?;
+        let info = plugin.info();
+        let parameters = plugin.parameters();
+        match state.check(&info) {
+            Ok(()) => {
+                if let Err(err) = state.apply(parameters.clone(), self.loader.timeout) {
+                    if timeout::timed_out(&err) {
+                        // The helper thread that gave up may still be inside it
+                        std::mem::forget(plugin);
+                    }
+                    return Err(err);
+                }
+            }
+            // Rebuilding the plugin may have changed its parameters
+            Err(err) => warn!("can't restore the plugin's state: {err:#}"),
+        }
+
+        let instance = self.instance(plugin);
+        bus::feed(&self.replacements, Some(instance))?;
+        self.instances += 1;
+        self.unloaded = None;
+        self.parameters = parameters;
+        self.info = info;
+        if let Some(surface) = &self.surface {
+            surface.set_plugin(self.parameters.clone(), self.info.clone());
+        }
+
+        Ok(())
+    }
+
+    fn check_reload(&self) -> Result<()> {
+        ensure!(!self.frozen, "unfreeze the plugin before reloading it");
+        // Unloading waits for the audio to give back every instance
+        ensure!(
+            !self.single_thread,
+            "plugins can't be reloaded with --single-thread"
+        );
+        ensure!(
+            !self.editor_open,
+            "the plugin's editor is open; start with --disable-editor to reload plugins"
+        );
+        ensure!(
+            !self.generic_ui,
+            "plugins can't be reloaded while --generic-ui shows their parameters"
+        );
+        Ok(())
+    }
+
+    /// Takes the plugin away from the audio thread and disposes of every instance of it: each is
+    /// stopped, suspended and closed, and the library is unloaded with the last one
+    fn unload(&mut self) -> Result<()> {
+        bus::feed(&self.replacements, None)?;
+        // The surface mustn't call into the plugin once its library is gone
+        if let Some(surface) = &self.surface {
+            surface.detach();
+        }
+
+        let deadline = Instant::now() + self.loader.timeout;
+        while self.instances > 0 {
+            let instance = self
+                .retired
+                .recv_timeout(deadline.saturating_duration_since(Instant::now()))
+                .map_err(|_| anyhow!("the audio thread didn't give the plugin back"))?;
+            self.dispose(instance);
+        }
+
+        Ok(())
+    }
+
+    /// Shuts down an instance the audio thread gave back, or leaks it if it hung
+    fn dispose(&mut self, instance: Instance) {
+        self.instances -= 1;
+        if self.abandoned > 0 {
+            self.abandoned -= 1;
+            std::mem::forget(instance);
+        } else {
+            drop(instance);
+        }
+    }
+
+    /// Loads `preset` into the plugin
+    fn apply(&mut self, preset: Preset) -> Result<()> {
+        let result = preset.apply(self.parameters.clone(), self.loader.timeout);
+        self.check_hung(result)
+    }
+
+    /// Gives up on the plugin if `result` is a call into it timing out
+    fn check_hung<T>(&mut self, result: Result<T>) -> Result<T> {
+        if let Err(err) = &result {
+            if timeout::timed_out(err) {
+                self.hung = true;
+                // The surface mustn't call into the plugin anymore either
+                if let Some(surface) = &self.surface {
+                    surface.detach();
+                }
+                error!("{} stopped responding, replace it or quit", self.info.name);
+            }
+        }
+        result
+    }
+}
+
+pub fn main(args: Args) -> Result<()> {
+    #[cfg(unix)]
+    if args.restart_on_crash && args.state_file.is_none() {
+        logging::init(args.log_level, Box::new(std::io::stderr()), None);
+        let state_file = env::temp_dir().join(format!("y-{}.fxp", process::id()));
+        let mut command = process::Command::new(env::current_exe()?);
+        command
+            .args(env::args_os().skip(1))
+            .arg("--state-file")
+            .arg(&state_file);
+        return crash::supervise(command, &state_file);
+    }
+    #[cfg(unix)]
+    crash::install_handlers();
+
+    #[cfg(unix)]
+    let single_thread = args.single_thread;
+    #[cfg(not(unix))]
+    let single_thread = false;
+    if single_thread {
+        ensure!(
+            args.output.backend == Backend::Null,
+            "--single-thread needs --backend null, since devices play on threads of their own"
+        );
+        timeout::run_inline();
+    }
+
+    let (restored_state, restored) = if args.restore_last {
+        let (state, settings) = session::latest(&args.session_dir)?
+            .with_context(|| format!("no autosave to restore in {}", args.session_dir.display()))?;
+        (Some(state), Some(settings))
+    } else {
+        (None, None)
+    };
+    let config = args.config.as_deref().map(Config::read).transpose()?;
+    let path = match (&args.plugin, &args.path, &restored) {
+        (Some(query), _, _) => search::find(query)?,
+        (None, Some(path), _) => search::resolve(path)?,
+        (None, None, Some(settings)) => settings.plugin.clone(),
+        (None, None, None) => match config.as_ref().and_then(|config| config.plugin.as_ref()) {
+            Some(plugin) => search::resolve(plugin)?,
+            None => bail!("the config names no plugin, give one to load"),
+        },
+    };
+
+    // This has to happen before any threads are started, since only the forking thread survives
+    #[cfg(unix)]
+    let listener = if args.daemon {
+        let path = args.socket.clone().unwrap_or_else(default_socket_path);
+        // The socket's file is removed when `file` is dropped, whichever way this returns
+        let (listener, file) = SocketFile::bind(path)?;
+
+        if unsafe { libc::daemon(1, 0) } != 0 {
+            bail!("failed to detach: {}", std::io::Error::last_os_error());
+        }
+
+        Some((listener, file))
+    } else {
+        None
+    };
+
+    // Plugins write straight to the standard streams, so our own output has to bypass the capture
+    #[cfg(unix)]
+    let capture = if args.capture_output {
+        let name = path.file_stem().map_or_else(
+            || "plugin".into(),
+            |stem| stem.to_string_lossy().into_owned(),
+        );
+        Some(Capture::start(name, args.plugin_output_level)?)
+    } else {
+        None
+    };
+
+    let create_trace = |path: &PathBuf| -> Result<Arc<TraceFile>> {
+        let trace = TraceFile::create(path)
+            .with_context(|| format!("failed to create {}", path.display()))?;
+        Ok(Arc::new(trace))
+    };
+    let trace = args
+        .trace_callbacks
+        .as_ref()
+        .map(create_trace)
+        .transpose()?;
+    // Both traces go into the same file if they're given the same path
+    let dispatch_trace = match &args.trace_dispatch {
+        Some(path) if args.trace_callbacks.as_ref() == Some(path) => trace.clone(),
+        Some(path) => Some(create_trace(path)?),
+        None => None,
+    };
+
+    #[cfg(unix)]
+    let (output, log_output): (Box<dyn Write + Send>, Box<dyn Write + Send>) = match &capture {
+        Some(capture) => (Box::new(capture.stdout()?), Box::new(capture.stderr()?)),
+        None => (Box::new(std::io::stdout()), Box::new(std::io::stderr())),
+    };
+    #[cfg(not(unix))]
+    let (output, log_output) = (std::io::stdout(), Box::new(std::io::stderr()));
+
+    logging::init(args.log_level, log_output, trace.clone());
+
+    // The plugin runs at the backend's rate and is asked for a buffer at a time, so its output
+    // plays as it is
+    let backend = args.output.open()?;
+    let underruns = backend.underruns();
+    let sample_rate = backend.sample_rate();
+    let length = backend
+        .buffer_size()
+        .map_or(DEFAULT_LENGTH, |size| size as usize);
+    ensure!(length > 0, "the buffer size has to be positive");
+
+    let factor = args.oversample.factor();
+    let tempo_map = match &args.tempo_map {
+        Some(path) => TempoMap::read(path, args.tempo, args.time_signature)?,
+        None => TempoMap::constant(args.tempo, args.time_signature),
+    };
+    let transport = Arc::new(Transport::new(
+        (sample_rate as usize * factor) as f64,
+        tempo_map,
+    ));
+
+    let activity = Arc::new(Activity::new());
+    let automation = Arc::new(Automation::default());
+    let host = Arc::new(Mutex::new(MyHost {
+        transport: transport.clone(),
+        trace,
+        identity: (args.host_version, args.host_vendor, args.host_product),
+        midi_output: match &args.midi_output {
+            Some(path) => Some(Mutex::new(midi::Device::create(path)?)),
+            None => None,
+        },
+        activity: activity.clone(),
+        automation: automation.clone(),
+        automation_to_midi: args.automation_to_midi.then(|| args.cc_mappings.clone()),
+        editing: Mutex::new(Vec::new()),
+    }));
+
+    let mut block_size = args.internal_block.unwrap_or(length);
+    ensure!(
+        block_size > 0 && length % block_size == 0,
+        "the internal block size must divide the buffer size ({length})"
+    );
+
+    // load and initialise the plugin
+    let mut loader = Loader {
+        host,
+        sample_rate,
+        block_size,
+        factor,
+        timeout: Duration::from_secs_f64(args.load_timeout),
+        trace: dispatch_trace,
+    };
+    let memory_baseline = memory::resident();
+    let mut plugin = loader.load(&path)?;
+    if let Some(growth) = Growth::since(memory_baseline) {
+        info!("loading the plugin took {growth}");
+    }
+
+    let plugin_info = plugin.info();
+    let parameters = plugin.parameters();
+    // Only the generic UI shows how parameters are grouped and stepped
+    #[cfg(unix)]
+    let properties: Vec<_> = if args.generic_ui {
+        (0..plugin_info.parameters)
+            .map(|index| plugin.parameter_properties(index))
+            .collect()
+    } else {
+        Vec::new()
+    };
+
+    let quirks = if args.no_quirks {
+        Quirks::default()
+    } else {
+        let database = QuirkDatabase::load()?;
+        database
+            .get(plugin_info.unique_id)
+            .cloned()
+            .unwrap_or_default()
+    };
+    if quirks != Quirks::default() {
+        info!("applying quirks for {}: {quirks:?}", plugin_info.name);
+    }
+
+    if let (None, Some(quirk_block_size)) = (args.internal_block, quirks.block_size) {
+        ensure!(
+            length % quirk_block_size == 0,
+            "the quirk block size must divide the buffer size ({length})"
+        );
+        // The plugin hasn't been resumed yet, so its block size can still change
+        block_size = quirk_block_size;
+        loader.block_size = block_size;
+        plugin.set_block_size(block_size * factor);
+    }
+
+    let restore = || -> Result<()> {
+        if let Some(path) = &args.preset {
+            let preset = Preset::read(path)?;
+            preset
+                .check(&plugin_info)
+                .with_context(|| format!("can't load {}", path.display()))?;
+            preset.apply(parameters.clone(), loader.timeout)?;
+        }
+        if let Some(path) = args.state_file.as_deref().filter(|path| path.exists()) {
+            let state = Preset::read(path)?;
+            match state.check(&plugin_info) {
+                Ok(()) => {
+                    state.apply(parameters.clone(), loader.timeout)?;
+                    info!("restored the plugin's state from before the crash");
+                }
+                // The plugin was replaced before the crash
+                Err(err) => warn!("can't restore the plugin's state: {err:#}"),
+            }
+        } else if let Some(state) = restored_state {
+            match state.check(&plugin_info) {
+                Ok(()) => {
+                    state.apply(parameters.clone(), loader.timeout)?;
+                    info!("restored the plugin's state from the last autosave");
+                }
+                // The autosave was of another plugin
+                Err(err) => warn!("can't restore the plugin's state: {err:#}"),
+            }
+        }
+        Ok(())
+    };
+    if let Err(err) = restore() {
+        if timeout::timed_out(&err) {
+            // The helper thread that gave up may still be inside it
+            std::mem::forget(plugin);
+        }
+        return Err(err);
+    }
+
+    #[cfg(unix)]
+    let headless = args.disable_editor
+        || args.daemon
+        || args.generic_ui
+        || args.single_thread
+        || quirks.no_editor;
+    #[cfg(not(unix))]
+    let headless = args.disable_editor || quirks.no_editor;
+
+    let effect = plugin.effect();
+
+    let editor = if headless { None } else { plugin.editor() };
+    #[cfg(unix)]
+    if !headless && editor.is_none() {
+        info!("The plugin has no editor, --generic-ui shows its parameters in the terminal");
+    }
+
+    let routes = args.route.clone().unwrap_or_default();
+    if let Some(matrix) = &routes.input {
+        ensure!(
+            args.play_input.is_some(),
+            "input routes need a file to route from, given with --play-input"
+        );
+        ensure!(
+            matrix.destinations() <= plugin_info.inputs as usize,
+            "the plugin only has {} inputs to route to",
+            plugin_info.inputs
+        );
+    }
+    if let Some(matrix) = &routes.output {
+        ensure!(
+            matrix.sources() <= plugin_info.outputs as usize,
+            "the plugin only has {} outputs to route from",
+            plugin_info.outputs
+        );
+    }
+
+    let input = match &args.play_input {
+        Some(path) => {
+            ensure!(
+                plugin_info.inputs > 0,
+                "the plugin has no inputs to play into"
+            );
+            // With an input route the file's channels are kept apart until they're routed
+            let channels = routes
+                .input
+                .as_ref()
+                .map_or(plugin_info.inputs as usize, Matrix::sources);
+            let input = open_input(path, args.looped, channels as u16, sample_rate)
+                .with_context(|| format!("failed to open {}", path.display()))?;
+            Some(input)
+        }
+        None => None,
+    };
+
+    let clip = match &args.play_midi {
+        Some(path) => {
+            let mut sequence = Sequence::read(path)?;
+            if args.tempo_map.is_some() {
+                sequence.follow(transport.tempo_map());
+            }
+            Some(sequence)
+        }
+        None => None,
+    };
+    #[cfg_attr(not(unix), allow(unused_variables))]
+    let (live_midi_sender, live_midi) = Live::new();
+    let mut midi_sources: Vec<Box<dyn MidiSource>> = vec![Box::new(live_midi)];
+    let (bus, commands) = Bus::new();
+    ensure!(args.pre_roll >= 0., "the pre-roll can't be negative");
+    let program_changes = args.presets_on_program_change;
+    ensure!(
+        args.bypass_trigger.is_none() || args.bypass_trigger != args.record_trigger,
+        "recording and bypass can't share a trigger"
+    );
+    // Each trigger runs its command on a press instead of reaching the plugin
+    let triggers: Vec<(Trigger, &str)> = [
+        (args.record_trigger, "record"),
+        (args.bypass_trigger, "bypass"),
+    ]
+    .into_iter()
+    .filter_map(|(trigger, command)| Some((trigger?, command)))
+    .collect();
+    for path in &args.midi_inputs {
+        let bus = bus.downgrade();
+        let triggers = triggers.clone();
+        let live = Live::open(path, move |message| {
+            for (trigger, command) in &triggers {
+                if let Some(pressed) = trigger.matches(message) {
+                    if pressed {
+                        send_logged(&bus, command.to_string());
+                    }
+                    return true;
+                }
+            }
+            if program_changes && message[0] & 0xf0 == 0xc0 {
+                send_logged(&bus, format!("preset {}", message[1]));
+                return true;
+            }
+            false
+        })?;
+        midi_sources.push(Box::new(live));
+    }
+    if let Some(clip) = &clip {
+        midi_sources.push(Box::new(Player::new(clip.clone())));
+    }
+
+    let has_parameter = |parameter| (0..plugin_info.parameters).contains(&parameter);
+    for target in args.macros.iter().flat_map(|macro_| &macro_.targets) {
+        ensure!(
+            has_parameter(target.parameter),
+            "the plugin has no parameter {}",
+            target.parameter
+        );
+    }
+    for mapping in &args.cc_mappings {
+        match &mapping.target {
+            Target::Parameter(parameter) => ensure!(
+                has_parameter(*parameter),
+                "the plugin has no parameter {parameter}"
+            ),
+            Target::Macro(name) => ensure!(
+                args.macros.iter().any(|macro_| &macro_.name == name),
+                "there is no macro called {name:?}"
+            ),
+        }
+    }
+
+    let sidechain = match (args.sidechain_channel, args.sidechain_parameter) {
+        (Some(channel), Some(parameter)) => {
+            ensure!(
+                channel < plugin_info.inputs as usize,
+                "the plugin has no input channel {channel}"
+            );
+            ensure!(
+                (0..plugin_info.parameters).contains(&parameter),
+                "the plugin has no parameter {parameter}"
+            );
+            Some(Sidechain {
+                follower: EnvelopeFollower::new(
+                    args.sidechain_attack / 1000.,
+                    args.sidechain_release / 1000.,
+                    sample_rate as f32,
+                ),
+                channel,
+                parameter,
+                base: parameters.get_parameter(parameter),
+                amount: args.sidechain_amount,
+            })
+        }
+        _ => None,
+    };
+
+    let pre_dsp = args.pre_dsp.as_deref().map(Hook::load).transpose()?;
+    let post_dsp = args.post_dsp.as_deref().map(Hook::load).transpose()?;
+
+    let channels = 2;
+    let inputs = vec![vec![1.; length]; plugin_info.inputs as usize];
+    let outputs = vec![vec![0.; length]; channels];
+    if let Some(matrix) = &routes.output {
+        ensure!(
+            matrix.destinations() <= channels,
+            "the host only has {channels} output channels to route to"
+        );
+    }
+    let file_channels = vec![vec![0.; length]; routes.input.as_ref().map_or(0, Matrix::sources)];
+    let plugin_outputs = vec![vec![0.; length]; routes.output.as_ref().map_or(0, Matrix::sources)];
+
+    let (replacement_sender, replacements) = mpsc::sync_channel(AUDIO_QUEUE);
+    let (transition_sender, transitions) = mpsc::sync_channel(AUDIO_QUEUE);
+    let hold = Arc::new(AtomicBool::new(
+        restored.as_ref().is_some_and(|settings| settings.hold),
+    ));
+    let bypass = Arc::new(AtomicBool::new(
+        restored.as_ref().is_some_and(|settings| settings.bypass),
+    ));
+    let (chord_sender, chord_commands) = mpsc::sync_channel(AUDIO_QUEUE);
+    let (arpeggiator_sender, arpeggiator_settings) = mpsc::sync_channel(AUDIO_QUEUE);
+
+    let arpeggiator = arpeggiator::Settings {
+        enabled: args.arp,
+        mode: args.arp_mode,
+        rate: args.arp_rate,
+        octaves: args.arp_octaves,
+        gate: args.arp_gate,
+    };
+    arpeggiator.validate()?;
+    let (stereo_sender, stereo_settings) = mpsc::sync_channel(AUDIO_QUEUE);
+    let stereo = stereo::Settings {
+        enabled: args.stereo,
+        position: args.stereo_position,
+        width: args.stereo_width,
+        mono: args.stereo_mono,
+        invert: args.stereo_invert,
+        swap: args.stereo_swap,
+    };
+    stereo.validate()?;
+
+    let tuning = match &args.scala {
+        Some(path) => {
+            let scale = Scale::read(path)?;
+            let map = match &args.kbm {
+                Some(path) => KeyboardMap::read(path)?,
+                None => KeyboardMap::default(),
+            };
+            let mode = match args.tuning_mode {
+                tuning::Mode::Auto if plugin.can_do("midiSingleNoteTuningChange") == Some(true) => {
+                    tuning::Mode::Mts
+                }
+                tuning::Mode::Auto => tuning::Mode::Detune,
+                mode => mode,
+            };
+            info!("tuning to {:?} with {mode:?}", scale.description);
+            Some((Tuning::new(&scale, &map)?, mode))
+        }
+        None => None,
+    };
+
+    let mpe = args.mpe.then_some(args.mpe_zones);
+    if mpe.is_some() {
+        ensure!(
+            args.mpe_bend_range <= 96,
+            "MPE pitch bend ranges go up to 96 semitones"
+        );
+        match plugin.can_do("MPE") {
+            Some(true) => {}
+            Some(false) => warn!("{} says it doesn't support MPE", plugin_info.name),
+            None => warn!("{} doesn't say whether it supports MPE", plugin_info.name),
+        }
+    }
+
+    let setup = Setup {
+        sysex: match &tuning {
+            Some((tuning, tuning::Mode::Mts)) => tuning.sysex(),
+            _ => Vec::new(),
+        },
+        midi: mpe.map_or_else(Vec::new, |zones| zones.configuration(args.mpe_bend_range)),
+    };
+    let mut instance = Instance::new(plugin, length, block_size, factor);
+    setup.apply(&mut instance);
+    let (retired, retired_receiver) = mpsc::channel();
+    let (freeze_sender, freezes) = mpsc::sync_channel(AUDIO_QUEUE);
+    let (thawed, thawed_receiver) = mpsc::channel();
+    let (recording_sender, recordings) = mpsc::sync_channel(AUDIO_QUEUE);
+    // Enough buffers for a full queue to the take writer and one to the RTP stream, and nothing is
+    // recorded with --single-thread
+    let mut pooled = if single_thread {
+        0
+    } else {
+        take::PENDING_BUFFERS
+    };
+    if args.rtp.is_some() {
+        pooled += rtp::PENDING_BUFFERS;
+    }
+    let pool = Pool::new(pooled, length * channels);
+    let buffers = pool.returns();
+    let pre_roll_length = (args.pre_roll * sample_rate as f64) as usize * channels;
+    let (pre_roll_sender, pre_rolls) = mpsc::sync_channel(2);
+    let dropped = Arc::new(AtomicUsize::new(0));
+
+    let watchdog = Arc::new(Watchdog::new());
+    if !single_thread {
+        watchdog.spawn(Duration::from_millis(args.watchdog_ms));
+    }
+
+    let meter = match &args.osc_meters {
+        Some(target) => {
+            ensure!(
+                args.osc_meter_rate > 0.,
+                "the meter rate has to be positive"
+            );
+            let target = target
+                .to_socket_addrs()
+                .with_context(|| format!("invalid OSC address {target:?}"))?
+                .next()
+                .with_context(|| format!("{target} doesn't resolve to an address"))?;
+            let (meter, frames) = Meter::new(channels, sample_rate as f64, args.osc_meter_rate);
+            meter::publish(target, frames)?;
+            Some(meter)
+        }
+        None => None,
+    };
+
+    if let Some(budget) = args.cpu_budget {
+        ensure!(budget > 0., "the CPU budget has to be positive");
+        ensure!(
+            args.budget_buffers > 0,
+            "the plugin has to be over budget in at least one buffer"
+        );
+    }
+    let overloads = match &args.osc_overloads {
+        Some(target) => {
+            let target = target
+                .to_socket_addrs()
+                .with_context(|| format!("invalid OSC address {target:?}"))?
+                .next()
+                .with_context(|| format!("{target} doesn't resolve to an address"))?;
+            let (overloads, receiver) = mpsc::sync_channel(AUDIO_QUEUE);
+            budget::publish(target, receiver)?;
+            Some(overloads)
+        }
+        None => None,
+    };
+
+    let stream = match &args.rtp {
+        Some(target) => {
+            let target = target
+                .to_socket_addrs()
+                .with_context(|| format!("invalid RTP address {target:?}"))?
+                .next()
+                .with_context(|| format!("{target} doesn't resolve to an address"))?;
+            if let Some(path) = &args.rtp_sdp {
+                fs::write(path, rtp::sdp(target, channels, sample_rate))
+                    .with_context(|| format!("failed to write {}", path.display()))?;
+            }
+            info!("streaming the output to {target}");
+            Some(rtp::stream(target, channels, sample_rate, pool.returns())?)
+        }
+        None => None,
+    };
+
+    let surface = match &args.mcu {
+        Some(input) => {
+            let output = args.mcu_output.as_deref().unwrap_or(input);
+            let surface = Surface::open(input, output, parameters.clone(), plugin_info.clone())?;
+            Some(surface)
+        }
+        None => None,
+    };
+
+    let source = PluginSource {
+        instance: Some(instance),
+        fade: None,
+        replacements,
+        retired,
+        frozen: None,
+        freezes,
+        thawed,
+        input,
+        inputs,
+        outputs,
+        routes,
+        file_channels,
+        plugin_outputs,
+        midi_sources,
+        events: Vec::new(),
+        cc_mappings: args.cc_mappings.clone(),
+        mpe,
+        macros: args.macros.clone(),
+        transitions,
+        transition: None,
+        velocity: Velocity::new(&args.velocity_curves),
+        sustain: Sustain::new(args.sustain, hold.clone(), mpe),
+        tuning: tuning.and_then(|(tuning, mode)| (mode == tuning::Mode::Detune).then_some(tuning)),
+        chord: ChordTrigger::new(args.chord),
+        chord_commands,
+        arpeggiator: Arpeggiator::new(arpeggiator),
+        arpeggiator_settings,
+        stereo,
+        stereo_settings,
+        sidechain,
+        pre_dsp,
+        post_dsp,
+        transport: transport.clone(),
+        watchdog,
+        activity: activity.clone(),
+        meter,
+        recording: None,
+        recordings,
+        pool,
+        dropped: dropped.clone(),
+        pre_roll: PreRoll::new(pre_roll_length),
+        spare_pre_roll: Some(PreRoll::new(pre_roll_length)),
+        pre_rolls,
+        stream,
+        metronome: args
+            .metronome
+            .then(|| Metronome::new(sample_rate as f32, db_to_gain(args.metronome_level))),
+        limiter: Limiter::new(args.protection, args.ceiling, sample_rate as f32),
+        bypass: bypass.clone(),
+        bypass_mix: 0.,
+        budget: args
+            .cpu_budget
+            .map(|budget| Budget::new(budget / 100., args.budget_buffers)),
+        bypass_over_budget: args.bypass_over_budget,
+        shed: false,
+        overloads,
+        underruns: underruns.clone(),
+        underruns_seen: 0,
+        panic_on_xrun: args.panic_on_xrun,
+        panic_pending: false,
+
+        current_position: 0,
+        current_channel: 0,
+
+        sample_rate,
+        length,
+        block_size,
+        factor,
+        channels,
+    };
+    // Without threads the source is played once the controller is ready to run between buffers
+    #[cfg_attr(not(unix), allow(unused_variables))]
+    let (inline, mut stream) = if single_thread {
+        (Some((backend, source)), None)
+    } else {
+        (None, Some(output::play(backend, source)?))
+    };
+
+    let mut controller = Controller {
+        loader,
+        parameters,
+        info: plugin_info,
+        macros: args.macros,
+        scenes: HashMap::new(),
+        transitions: transition_sender,
+        hold,
+        bypass,
+        blind: None,
+        chord_commands: chord_sender,
+        arpeggiator,
+        arpeggiator_settings: arpeggiator_sender,
+        stereo,
+        stereo_settings: stereo_sender,
+        transport,
+        activity,
+        editor_open: editor.is_some(),
+        generic_ui: false,
+        memory_baseline,
+        replacements: replacement_sender,
+        retired: retired_receiver,
+        instances: 1,
+        path,
+        unloaded: None,
+        hung: false,
+        abandoned: 0,
+        clip,
+        input: args.play_input.clone(),
+        freezes: freeze_sender,
+        thawed: thawed_receiver,
+        frozen: false,
+        surface,
+        state_file: args.state_file.clone(),
+        saved: Instant::now(),
+        autosave: args.autosave.then(|| Autosave::new(&args.session_dir)),
+        // The plugin is loaded already, and the rest is applied once the controller runs
+        config: args.config.clone().map(|path| {
+            let plugin = config.and_then(|config| config.plugin);
+            Watched::new(
+                path,
+                Config {
+                    plugin,
+                    ..Config::default()
+                },
+            )
+        }),
+        session_dir: args.session_dir.clone(),
+        pre_roll: args.pre_roll,
+        recording: None,
+        recordings: recording_sender,
+        buffers,
+        pre_rolls: pre_roll_sender,
+        dropped,
+        underruns,
+        underruns_reported: 0,
+        automation: args.record_automation.then_some(automation),
+        cc_mappings: args.cc_mappings,
+        preset: args
+            .preset
+            .as_ref()
+            .map(|path| fs::canonicalize(path).unwrap_or_else(|_| path.clone()))
+            .or_else(|| restored.and_then(|settings| settings.preset)),
+        preset_tag: args.preset_tag.clone(),
+        expert: args.expert,
+        setup,
+        single_thread,
+
+        sample_rate,
+        length,
+        block_size,
+        factor,
+    };
+
+    #[cfg(unix)]
+    if let Some((backend, source)) = inline {
+        drop((bus, commands));
+        return controller.run_inline(&args.output, backend, source, output);
+    }
+
+    #[cfg(unix)]
+    if args.keyboard || args.generic_ui {
+        // The terminal modes take the terminal, but the controller still runs commands from
+        // triggers and program changes, follows the config and saves the state on its own thread
+        let parameters = controller.parameters.clone();
+        let info = controller.info.clone();
+        let activity = controller.activity.clone();
+        controller.generic_ui = args.generic_ui;
+        let controller = thread::spawn(move || controller.run(commands));
+
+        let result = if args.keyboard {
+            keyboard::run(live_midi_sender, output)
+        } else {
+            generic::run(parameters, properties, &info, &activity, output)
+        };
+        // The controller stops once the bus is gone, saving the state one last time
+        drop(bus);
+        controller
+            .join()
+            .map_err(|_| anyhow!("the controller panicked"))??;
+        return result;
+    }
+
+    #[cfg(unix)]
+    let socket_file = listener.map(|(listener, file)| {
+        bus.attach(Socket::new(listener));
+        file
+    });
+    #[cfg(unix)]
+    if socket_file.is_none() {
+        bus.attach(Stdin::new(output));
+    }
+    #[cfg(not(unix))]
+    bus.attach(Stdin::new(output));
+    // Presets dropped onto the editor window are loaded through the bus as well
+    let drops = bus.downgrade();
+    // The controller stops once the surfaces have ended and dropped their handles
+    drop(bus);
+
+    if let Some(editor) = editor {
+        let event_loop = EventLoop::with_user_event();
+        let proxy = event_loop.create_proxy();
+        // Replacing the plugin is refused while the editor is open, so `effect` stays valid
+        let mut editor_host =
+            WinitEditorHost::new(&event_loop, effect, &controller.info, &args.window)?;
+        let deadline = Deadline::new(
+            Duration::from_secs_f64(args.editor_timeout),
+            "opening the editor",
+        );
+        let success = editor_host.open(editor)?;
+        drop(deadline);
+
+        info!("Successfully created window for editor: {}", success);
+
+        thread::spawn(move || {
+            if let Err(err) = controller.run(commands) {
+                error!("{err:#}");
+            }
+            let _ = proxy.send_event(());
+        });
+
+        let idle_interval = quirks
+            .editor_idle
+            .map(|rate| Duration::from_secs_f64(1. / rate));
+        let mut next_idle = Instant::now();
+
+        event_loop.run(move |event, elwt, control_flow| {
+            trace!("{event:?}, {elwt:?}");
+            *control_flow = match (event, idle_interval) {
+                (WindowEvent::UserEvent(()), _) => {
+                    // The event loop exits the process without returning, so the plugin is
+                    // disposed of here: the editor is closed first, then dropping the stream
+                    // stops, suspends and closes the plugin and unloads its library
+                    editor_host.close();
+                    drop(stream.take());
+                    ControlFlow::Exit
+                }
+                (
+                    WindowEvent::WindowEvent {
+                        event: winit::event::WindowEvent::DroppedFile(path),
+                        ..
+                    },
+                    _,
+                ) => {
+                    drop_file(&drops, &path);
+                    *control_flow
+                }
+                (_, Some(interval)) => {
+                    if Instant::now() >= next_idle {
+                        editor_host.idle();
+                        next_idle = Instant::now() + interval;
+                    }
+                    ControlFlow::WaitUntil(next_idle)
+                }
+                _ => ControlFlow::Wait,
+            };
+        })
+    }
+
+    controller.run(commands)
+}
+
+/// Loads a preset dropped onto the editor window
+fn drop_file(bus: &WeakBus, path: &Path) {
+    let preset = path.extension().is_some_and(|extension| {
+        extension.eq_ignore_ascii_case("fxp") || extension.eq_ignore_ascii_case("fxb")
+    });
+    if !preset {
+        warn!(
+            "can't load {}: only FXP and FXB presets can be dropped onto the editor, since the \
+             plugin can't be replaced while its editor is open",
+            path.display()
+        );
+        return;
+    }
+
+    send_logged(bus, format!("preset {}", path.display()));
+}
+
+/// Parses a decimal or `0x` hexadecimal integer, which may be negative
+fn parse_integer(text: &str) -> Result<i64> {
+    let (negative, digits) = match text.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, text),
+    };
+    let magnitude = match digits.strip_prefix("0x") {
+        Some(hex) => i64::from_str_radix(hex, 16),
+        None => digits.parse(),
+    }
+    .with_context(|| format!("invalid number {text:?}"))?;
+
+    Ok(if negative { -magnitude } else { magnitude })
+}
+
+/// Parses bytes written as pairs of hexadecimal digits, like `00ff`
+fn parse_hex(text: &str) -> Result<Vec<u8>> {
+    ensure!(
+        text.len().is_multiple_of(2),
+        "hex data needs two digits for every byte"
+    );
+    (0..text.len())
+        .step_by(2)
+        .map(|i| {
+            u8::from_str_radix(text.get(i..i + 2).context("invalid hex data")?, 16)
+                .context("invalid hex data")
+        })
+        .collect()
+}
+
+/// Runs `line` on behalf of something other than a control surface, logging its output
+fn send_logged(bus: &WeakBus, line: String) {
+    match bus.send(line) {
+        Some(output) if output.starts_with("error: ") => {
+            warn!("{}", output["error: ".len()..].trim_end())
+        }
+        Some(output) => info!("{}", output.trim_end()),
+        None => warn!("the host has stopped taking commands"),
+    }
+}
+
+/// What a newly loaded plugin is sent ahead of the first block: MTS tuning and MPE configuration
+struct Setup {
+    sysex: Vec<Vec<u8>>,
+    midi: Vec<event::MidiEvent>,
+}
+
+impl Setup {
+    fn apply(&self, instance: &mut Instance) {
+        for message in &self.sysex {
+            instance.send_sysex(message.clone());
+        }
+        for &event in &self.midi {
+            instance.send_midi(event);
+        }
+    }
+}
+
+/// Sends a midi on event on channel 0 with velocity 0x7f
+#[allow(dead_code)]
+fn send_midi(plugin: &mut PluginInstance, data: [u8; 3]) {
+    let num_events = 1;
+    let _reserved = 0;
+    let mut event_0 = MidiEvent {
+        event_type: EventType::Midi,
+        byte_size: std::mem::size_of::<MidiEvent>() as i32,
+        delta_frames: 0,
+        flags: 0,
+        note_length: 0,
+        note_offset: 0,
+        midi_data: data,
+        _midi_reserved: 0,
+        detune: 0,
+        note_off_velocity: 0,
+        _reserved1: 0,
+        _reserved2: 0,
+    };
+    let events = [&mut event_0 as *mut _ as *mut Event, std::ptr::null_mut()];
+    let events_struct = Events {
+        num_events,
+        _reserved,
+        events,
+    };
+
+    plugin.process_events(&events_struct);
+}
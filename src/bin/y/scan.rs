@@ -0,0 +1,66 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use anyhow::Result;
+use vst::{
+    host::{Host, PluginLoader},
+    plugin::Plugin,
+};
+use y::{
+    diagnose,
+    search::{self, Database, Entry},
+    timeout::with_timeout,
+};
+
+/// Loads every plugin in VST_PATH and the standard directories and records their names, so they
+/// can be found with `--plugin`
+#[derive(clap::Args)]
+pub struct Args {
+    /// Seconds to wait for each plugin to load
+    #[clap(long, default_value_t = 10.)]
+    timeout: f64,
+}
+
+struct MyHost;
+
+impl Host for MyHost {}
+
+pub fn main(args: Args) -> Result<()> {
+    let timeout = Duration::from_secs_f64(args.timeout);
+
+    let mut database = Database::default();
+    for path in search::plugins() {
+        let owned_path = path.clone();
+        let info = with_timeout(timeout, "loading the plugin", move || {
+            let host = Arc::new(Mutex::new(MyHost));
+            let mut loader = PluginLoader::load(&owned_path, host)
+                .map_err(|err| diagnose::explain(&owned_path, err))?;
+            Ok::<_, anyhow::Error>(loader.instance()?.get_info())
+        })
+        .and_then(|info| info);
+
+        match info {
+            Ok(info) => {
+                println!("{} by {} ({})", info.name, info.vendor, path.display());
+                database.entries.push(Entry {
+                    path,
+                    unique_id: info.unique_id,
+                    vendor: info.vendor,
+                    product: info.name,
+                });
+            }
+            Err(err) => eprintln!("skipping {}: {err:#}", path.display()),
+        }
+    }
+
+    let path = database.save()?;
+    println!(
+        "Found {} plugins, saved to {}",
+        database.entries.len(),
+        path.display()
+    );
+
+    Ok(())
+}
@@ -0,0 +1,132 @@
+use std::{path::PathBuf, process};
+
+use anyhow::{Context, Result};
+use hound::WavReader;
+use y::{
+    render::{flush_denormals, read_wav, write_wav, BitDepth, Level, Renderer, Routing, Tail},
+    search,
+};
+
+/// Renders an input through a plugin and compares the result with a stored reference, for
+/// regression tests of plugins in CI.
+///
+/// Exits with status 1 when the output differs from the reference by more than the tolerance.
+#[derive(clap::Args)]
+pub struct Args {
+    /// Path of the plugin, or its file or product name
+    plugin: PathBuf,
+
+    /// WAV file rendered through the plugin
+    #[clap(long)]
+    input: PathBuf,
+
+    /// WAV file the output is compared with
+    #[clap(long)]
+    reference: PathBuf,
+
+    /// Largest difference allowed between any output sample and the reference, like -90dBFS
+    #[clap(long, default_value = "-90dBFS", allow_hyphen_values = true)]
+    tolerance: Level,
+
+    /// Write the output as the new reference instead of comparing with it
+    #[clap(long)]
+    update: bool,
+
+    /// Number of samples processed per call to `process()`
+    #[clap(long, default_value_t = 512)]
+    block_size: usize,
+
+    #[clap(flatten)]
+    tail: Tail,
+
+    #[clap(flatten)]
+    routing: Routing,
+}
+
+pub fn main(args: Args) -> Result<()> {
+    let plugin_path = search::resolve(&args.plugin)?;
+
+    // The same conditions as `y render --deterministic`, so the output only changes with the plugin
+    flush_denormals();
+    let sample_rate = WavReader::open(&args.input)
+        .with_context(|| format!("failed to read {}", args.input.display()))?
+        .spec()
+        .sample_rate as f32;
+    let mut renderer = Renderer::new(&plugin_path, args.block_size, sample_rate)?;
+    renderer.instance.set_zero_outputs(true);
+    let (spec, output) = renderer.render(&args.input, &args.routing, &args.tail, |_, _| {})?;
+
+    if args.update {
+        // Float samples, so the reference isn't rounded
+        write_wav(&args.reference, spec, &output, Some(BitDepth::Float), false)?;
+        println!("Updated {}", args.reference.display());
+        return Ok(());
+    }
+
+    let (reference_spec, reference) = read_wav(&args.reference)?;
+    let channels = spec.channels as usize;
+    let mut failures = Vec::new();
+    if reference_spec.channels != spec.channels {
+        failures.push(format!(
+            "the output has {} channels, the reference {}",
+            spec.channels, reference_spec.channels
+        ));
+    }
+    if reference_spec.sample_rate != spec.sample_rate {
+        failures.push(format!(
+            "the output is at {} Hz, the reference at {} Hz",
+            spec.sample_rate, reference_spec.sample_rate
+        ));
+    }
+    if reference.len() != output.len() {
+        failures.push(format!(
+            "the output is {} frames long, the reference {}",
+            output.len() / channels.max(1),
+            reference.len() / reference_spec.channels.max(1) as usize
+        ));
+    }
+
+    // The largest difference, as (difference, frame, channel)
+    let mut largest = (0f32, 0, 0);
+    if failures.is_empty() {
+        for (index, (output, reference)) in output.iter().zip(&reference).enumerate() {
+            let difference = (output - reference).abs();
+            if difference > largest.0 || difference.is_nan() {
+                largest = (difference, index / channels, index % channels);
+            }
+        }
+    }
+    let (difference, frame, channel) = largest;
+    let difference_db = 20. * difference.log10();
+    if !(difference_db <= args.tolerance.0 || difference == 0.) {
+        failures.push(format!(
+            "the largest difference is {difference_db:.1} dBFS at frame {frame} on channel \
+             {channel}, over the tolerance of {} dBFS",
+            args.tolerance.0
+        ));
+    }
+
+    if failures.is_empty() {
+        if difference == 0. {
+            println!(
+                "PASS: the output matches {} exactly",
+                args.reference.display()
+            );
+        } else {
+            println!(
+                "PASS: the largest difference from {} is {difference_db:.1} dBFS",
+                args.reference.display()
+            );
+        }
+        Ok(())
+    } else {
+        println!(
+            "FAIL: the output doesn't match {}",
+            args.reference.display()
+        );
+        for failure in failures {
+            println!("    {failure}");
+        }
+        process::exit(1);
+    }
+}
@@ -0,0 +1,117 @@
+use std::{
+    fmt::Write,
+    str::FromStr,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::bail;
+
+/// One of the two buttons of a blind test
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Side {
+    A,
+    B,
+}
+
+impl FromStr for Side {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "a" | "A" => Ok(Side::A),
+            "b" | "B" => Ok(Side::B),
+            _ => bail!("expected a or b"),
+        }
+    }
+}
+
+struct Round {
+    swapped: bool,
+    vote: Side,
+}
+
+/// A blind comparison of two versions, hidden behind A and B.
+///
+/// Which version A and B play is drawn at random again for every round, so each vote is made
+/// without knowing what was picked before.
+pub struct BlindTest {
+    /// What the two versions are, like `processed` and `bypassed`
+    labels: [String; 2],
+    /// Whether A plays the second version in the current round
+    swapped: bool,
+    rounds: Vec<Round>,
+    /// Xorshift state
+    state: u64,
+}
+
+impl BlindTest {
+    pub fn new(labels: [String; 2]) -> Self {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |time| time.as_nanos() as u64);
+        let mut test = Self {
+            labels,
+            swapped: false,
+            rounds: Vec::new(),
+            state: nanos | 1,
+        };
+        test.swapped = test.coin();
+        test
+    }
+
+    fn coin(&mut self) -> bool {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 7;
+        self.state ^= self.state << 17;
+        self.state >> 63 == 1
+    }
+
+    /// Index of the version `side` plays in the current round
+    pub fn version(&self, side: Side) -> usize {
+        (side == Side::B) as usize ^ self.swapped as usize
+    }
+
+    /// Number of the current round, from 1
+    pub fn round(&self) -> usize {
+        self.rounds.len() + 1
+    }
+
+    /// Records a preference for `side` and starts the next round
+    pub fn vote(&mut self, side: Side) {
+        self.rounds.push(Round {
+            swapped: self.swapped,
+            vote: side,
+        });
+        self.swapped = self.coin();
+    }
+
+    /// Describes what A and B were in every round and how often each version was preferred
+    pub fn reveal(&self) -> String {
+        let mut text = String::new();
+        let mut preferred = [0; 2];
+        for (index, round) in self.rounds.iter().enumerate() {
+            let version = (round.vote == Side::B) as usize ^ round.swapped as usize;
+            preferred[version] += 1;
+            let _ = writeln!(
+                text,
+                "Round {}: A was {}, B was {}, you preferred {:?} ({})",
+                index + 1,
+                self.labels[round.swapped as usize],
+                self.labels[!round.swapped as usize],
+                round.vote,
+                self.labels[version]
+            );
+        }
+        let _ = write!(
+            text,
+            "Preferred {} {} of {} times, {} {} times",
+            self.labels[0],
+            preferred[0],
+            self.rounds.len(),
+            self.labels[1],
+            preferred[1]
+        );
+
+        text
+    }
+}
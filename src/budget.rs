@@ -0,0 +1,72 @@
+use std::{net::SocketAddr, sync::mpsc::Receiver, thread};
+
+use anyhow::Result;
+
+use crate::osc;
+
+/// How much of each buffer's duration a plugin may spend processing it, so a plugin that keeps
+/// going over can be dealt with before the output drops out
+pub struct Budget {
+    /// The share of a buffer's duration allowed, from 0 to 1
+    share: f64,
+    /// Buffers in a row over the budget that make the plugin count as overloaded
+    patience: usize,
+    /// Buffers in a row over the budget so far, and the shares they took in total
+    over: usize,
+    total: f64,
+    /// Whether the current run of buffers over the budget has been reported
+    reported: bool,
+}
+
+impl Budget {
+    pub fn new(share: f64, patience: usize) -> Self {
+        Self {
+            share,
+            patience,
+            over: 0,
+            total: 0.,
+            reported: false,
+        }
+    }
+
+    /// Counts a buffer the plugin took `share` of the duration of to process.
+    ///
+    /// Once `patience` buffers in a row went over the budget, returns the average share they took,
+    /// a single time until a buffer keeps to the budget again.
+    pub fn check(&mut self, share: f64) -> Option<f64> {
+        if share <= self.share {
+            self.over = 0;
+            self.total = 0.;
+            self.reported = false;
+            return None;
+        }
+
+        self.over += 1;
+        self.total += share;
+        if self.over < self.patience || self.reported {
+            return None;
+        }
+        self.reported = true;
+        Some(self.total / self.over as f64)
+    }
+}
+
+/// Sends every overload from `overloads` to `target` as an OSC message until the sender is
+/// dropped.
+///
+/// Each message is `/y/budget/overload` with the share of the buffers the plugin took, in
+/// percent.
+pub fn publish(target: SocketAddr, overloads: Receiver<f32>) -> Result<()> {
+    let socket = osc::socket(target)?;
+
+    thread::spawn(move || {
+        for share in overloads {
+            let message = osc::message("/y/budget/overload", &[share * 100.]);
+            if let Err(err) = socket.send_to(&message, target) {
+                log::warn!("failed to send an overload to {target}: {err}");
+            }
+        }
+    });
+
+    Ok(())
+}
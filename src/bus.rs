@@ -0,0 +1,224 @@
+#[cfg(unix)]
+use std::os::unix::net::UnixListener;
+use std::{
+    io::{self, BufRead, BufReader, Write},
+    sync::{
+        mpsc::{self, Receiver, SyncSender, TrySendError},
+        Arc, Weak,
+    },
+    thread,
+};
+
+use anyhow::{anyhow, Result};
+
+/// Commands waiting for the controller before control surfaces have to wait as well
+const PENDING_COMMANDS: usize = 16;
+
+/// Capacity of the queues from the controller to the audio thread. The audio thread empties them
+/// every buffer, so they only fill up once it has stalled.
+pub const AUDIO_QUEUE: usize = 16;
+
+/// A command line from a control surface, and where its output goes
+pub struct Command {
+    pub line: String,
+    reply: SyncSender<String>,
+}
+
+impl Command {
+    /// Sends the command's output back to the surface it came from
+    pub fn reply(self, output: String) {
+        let _ = self.reply.send(output);
+    }
+}
+
+/// Carries commands from every control surface to the controller, which runs them one at a time.
+///
+/// The host stops taking commands once the controller quits or every surface has ended.
+#[derive(Clone)]
+pub struct Bus(Arc<SyncSender<Command>>);
+
+impl Bus {
+    pub fn new() -> (Self, Receiver<Command>) {
+        let (sender, receiver) = mpsc::sync_channel(PENDING_COMMANDS);
+        (Self(Arc::new(sender)), receiver)
+    }
+
+    /// A handle for sending commands that doesn't keep the host taking them
+    pub fn downgrade(&self) -> WeakBus {
+        WeakBus(Arc::downgrade(&self.0))
+    }
+
+    /// Runs `line` and waits for its output, or returns `None` once the host has stopped taking
+    /// commands
+    pub fn send(&self, line: String) -> Option<String> {
+        let (reply, output) = mpsc::sync_channel(1);
+        self.0.send(Command { line, reply }).ok()?;
+        output.recv().ok()
+    }
+
+    /// Runs `surface` on a thread of its own until it ends
+    pub fn attach(&self, surface: impl ControlSurface + 'static) {
+        let bus = self.clone();
+        thread::spawn(move || {
+            let name = surface.name();
+            if let Err(err) = surface.run(bus) {
+                log::error!("{name}: {err:#}");
+            }
+        });
+    }
+}
+
+/// Sends commands to a bus for as long as some control surface keeps it open, for things like the
+/// editor window which shouldn't keep the host running by themselves
+#[derive(Clone)]
+pub struct WeakBus(Weak<SyncSender<Command>>);
+
+impl WeakBus {
+    /// Like [`Bus::send`], also returning `None` once every surface has ended
+    pub fn send(&self, line: String) -> Option<String> {
+        Bus(self.0.upgrade()?).send(line)
+    }
+}
+
+/// Somewhere commands come from, like the terminal or the control socket
+pub trait ControlSurface: Send {
+    fn name(&self) -> String;
+
+    /// Sends commands to `bus` until the surface is closed or the host stops taking them
+    fn run(self, bus: Bus) -> Result<()>;
+}
+
+/// Command lines typed on standard input, with their output written to `out`
+pub struct Stdin<W> {
+    out: W,
+}
+
+impl<W: Write + Send> Stdin<W> {
+    pub fn new(out: W) -> Self {
+        Self { out }
+    }
+}
+
+impl<W: Write + Send> ControlSurface for Stdin<W> {
+    fn name(&self) -> String {
+        "standard input".to_owned()
+    }
+
+    fn run(mut self, bus: Bus) -> Result<()> {
+        for line in io::stdin().lines() {
+            match bus.send(line?) {
+                Some(output) => self.out.write_all(output.as_bytes())?,
+                None => break,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Command lines typed on standard input, read without blocking whenever the host gets round to
+/// it, for hosts with no thread to run a surface on
+#[cfg(unix)]
+pub struct PolledStdin {
+    /// What was read after the last complete line
+    pending: Vec<u8>,
+    closed: bool,
+}
+
+#[cfg(unix)]
+impl PolledStdin {
+    pub fn new() -> Self {
+        Self {
+            pending: Vec::new(),
+            closed: false,
+        }
+    }
+
+    /// The next complete line, if one has been typed
+    pub fn poll(&mut self) -> Result<Option<String>> {
+        if !self.closed && !self.pending.contains(&b'\n') {
+            let mut stdin = libc::pollfd {
+                fd: 0,
+                events: libc::POLLIN,
+                revents: 0,
+            };
+            if unsafe { libc::poll(&mut stdin, 1, 0) } > 0 {
+                let mut buffer = [0u8; 1024];
+                let read = unsafe { libc::read(0, buffer.as_mut_ptr().cast(), buffer.len()) };
+                match read {
+                    0 => {
+                        // The last line may not have been ended
+                        if !self.pending.is_empty() {
+                            self.pending.push(b'\n');
+                        }
+                        self.closed = true;
+                    }
+                    read if read < 0 => return Err(io::Error::last_os_error().into()),
+                    read => self.pending.extend_from_slice(&buffer[..read as usize]),
+                }
+            }
+        }
+
+        let Some(end) = self.pending.iter().position(|&byte| byte == b'\n') else {
+            return Ok(None);
+        };
+        let line: Vec<u8> = self.pending.drain(..=end).collect();
+        Ok(Some(String::from_utf8_lossy(&line).trim_end().to_owned()))
+    }
+
+    /// Whether standard input was closed and every line on it has been read
+    pub fn closed(&self) -> bool {
+        self.closed && !self.pending.contains(&b'\n')
+    }
+}
+
+#[cfg(unix)]
+impl Default for PolledStdin {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Clients of a control socket, one connection after another.
+///
+/// Every connection can send any number of command lines and receives their output.
+#[cfg(unix)]
+pub struct Socket {
+    listener: UnixListener,
+}
+
+#[cfg(unix)]
+impl Socket {
+    pub fn new(listener: UnixListener) -> Self {
+        Self { listener }
+    }
+}
+
+#[cfg(unix)]
+impl ControlSurface for Socket {
+    fn name(&self) -> String {
+        "control socket".to_owned()
+    }
+
+    fn run(self, bus: Bus) -> Result<()> {
+        for stream in self.listener.incoming() {
+            let stream = stream?;
+            for line in BufReader::new(&stream).lines() {
+                match bus.send(line?) {
+                    Some(output) => (&stream).write_all(output.as_bytes())?,
+                    None => return Ok(()),
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Queues `message` for the audio thread without waiting for room
+pub fn feed<T>(queue: &SyncSender<T>, message: T) -> Result<()> {
+    queue.try_send(message).map_err(|err| match err {
+        TrySendError::Full(_) => anyhow!("the audio thread has fallen behind"),
+        TrySendError::Disconnected(_) => anyhow!("the audio stream has stopped"),
+    })
+}
@@ -0,0 +1,80 @@
+use std::{
+    fs::File,
+    io::{self, BufRead, BufReader},
+    os::unix::io::{FromRawFd, RawFd},
+    thread,
+};
+
+use log::Level;
+
+fn check(result: libc::c_int) -> io::Result<libc::c_int> {
+    if result < 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(result)
+    }
+}
+
+/// Redirects the process's stdout and stderr through a pipe, logging every line written to them.
+///
+/// Plugins print to the standard streams directly, so this is the only way to tell their output
+/// apart from the host's. The original streams are restored when this is dropped.
+pub struct Capture {
+    stdout: RawFd,
+    stderr: RawFd,
+}
+
+impl Capture {
+    /// Starts capturing, logging each line under `target` at `level`
+    pub fn start(target: String, level: Level) -> io::Result<Self> {
+        unsafe {
+            let stdout = check(libc::dup(libc::STDOUT_FILENO))?;
+            let stderr = check(libc::dup(libc::STDERR_FILENO))?;
+
+            let mut fds = [0; 2];
+            check(libc::pipe(fds.as_mut_ptr()))?;
+            let [read, write] = fds;
+
+            check(libc::dup2(write, libc::STDOUT_FILENO))?;
+            check(libc::dup2(write, libc::STDERR_FILENO))?;
+            libc::close(write);
+
+            let pipe = File::from_raw_fd(read);
+            thread::spawn(move || {
+                for line in BufReader::new(pipe).lines() {
+                    match line {
+                        Ok(line) => log::log!(target: &target, level, "{line}"),
+                        Err(_) => break,
+                    }
+                }
+            });
+
+            Ok(Self { stdout, stderr })
+        }
+    }
+
+    /// A handle to the original stdout, for output that shouldn't be captured
+    pub fn stdout(&self) -> io::Result<File> {
+        Self::duplicate(self.stdout)
+    }
+
+    /// A handle to the original stderr, for output that shouldn't be captured
+    pub fn stderr(&self) -> io::Result<File> {
+        Self::duplicate(self.stderr)
+    }
+
+    fn duplicate(fd: RawFd) -> io::Result<File> {
+        unsafe { Ok(File::from_raw_fd(check(libc::dup(fd))?)) }
+    }
+}
+
+impl Drop for Capture {
+    fn drop(&mut self) {
+        unsafe {
+            libc::dup2(self.stdout, libc::STDOUT_FILENO);
+            libc::dup2(self.stderr, libc::STDERR_FILENO);
+            libc::close(self.stdout);
+            libc::close(self.stderr);
+        }
+    }
+}
@@ -0,0 +1,153 @@
+use std::str::FromStr;
+
+use anyhow::{ensure, Context};
+use vst::event::MidiEvent;
+
+/// Intervals in semitones from the played note, up to eight of them
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Intervals {
+    semitones: [i8; 8],
+    len: u8,
+}
+
+impl Intervals {
+    pub fn iter(&self) -> impl Iterator<Item = i8> + '_ {
+        self.semitones[..self.len as usize].iter().copied()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+impl FromStr for Intervals {
+    type Err = anyhow::Error;
+
+    /// Parses a list of intervals like `0,4,7`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut intervals = Intervals::default();
+        for interval in s.split(',') {
+            ensure!(intervals.len < 8, "a chord has at most eight notes");
+            intervals.semitones[intervals.len as usize] =
+                interval.trim().parse().context("invalid interval")?;
+            intervals.len += 1;
+        }
+
+        Ok(intervals)
+    }
+}
+
+/// What the chord trigger should do, sent from the control thread
+#[derive(Clone, Copy, Debug)]
+pub enum Command {
+    Off,
+    Play(Intervals),
+    /// Learn the next chord played, and play it from then on
+    Learn,
+}
+
+/// Plays a chord for every note
+pub struct ChordTrigger {
+    intervals: Intervals,
+    learning: bool,
+    /// Keys held down while learning
+    learn_held: Vec<u8>,
+    /// Every key pressed since learning started, until they're all released
+    learned: Vec<u8>,
+    /// The chord each key is sounding, so note-offs match even if the chord changed in between
+    sounding: [Intervals; 128],
+    output: Vec<MidiEvent>,
+}
+
+impl ChordTrigger {
+    pub fn new(intervals: Option<Intervals>) -> Self {
+        Self {
+            intervals: intervals.unwrap_or_default(),
+            learning: false,
+            learn_held: Vec::new(),
+            learned: Vec::new(),
+            sounding: [Intervals::default(); 128],
+            output: Vec::new(),
+        }
+    }
+
+    pub fn command(&mut self, command: Command) {
+        match command {
+            Command::Off => self.intervals = Intervals::default(),
+            Command::Play(intervals) => self.intervals = intervals,
+            Command::Learn => {
+                self.learning = true;
+                self.learn_held.clear();
+                self.learned.clear();
+            }
+        }
+    }
+
+    /// Rewrites the events of a block, expanding notes into chords
+    pub fn process(&mut self, events: &mut Vec<MidiEvent>) {
+        if self.intervals.is_empty() && !self.learning && self.sounding.iter().all(|i| i.is_empty())
+        {
+            return;
+        }
+
+        self.output.clear();
+        for event in events.drain(..) {
+            let [status, key, velocity] = event.data;
+            let note_on = status & 0xf0 == 0x90 && velocity > 0;
+            let note_off = status & 0xf0 == 0x80 || (status & 0xf0 == 0x90 && velocity == 0);
+
+            if self.learning {
+                if note_on {
+                    self.learn_held.push(key);
+                    self.learned.push(key);
+                } else if note_off {
+                    self.learn_held.retain(|&k| k != key);
+                    if self.learn_held.is_empty() && !self.learned.is_empty() {
+                        self.learn();
+                    }
+                }
+                self.output.push(event);
+                continue;
+            }
+
+            let intervals = if note_on {
+                self.sounding[key as usize & 0x7f] = self.intervals;
+                self.intervals
+            } else if note_off {
+                std::mem::take(&mut self.sounding[key as usize & 0x7f])
+            } else {
+                Intervals::default()
+            };
+
+            if intervals.is_empty() {
+                self.output.push(event);
+                continue;
+            }
+            for interval in intervals.iter() {
+                let key = key as i16 + interval as i16;
+                if (0..128).contains(&key) {
+                    let mut event = event;
+                    event.data[1] = key as u8;
+                    self.output.push(event);
+                }
+            }
+        }
+
+        events.append(&mut self.output);
+    }
+
+    /// Turns the learned keys into intervals from the lowest one
+    fn learn(&mut self) {
+        self.learning = false;
+        self.learned.sort_unstable();
+        self.learned.dedup();
+
+        let root = self.learned[0];
+        let mut intervals = Intervals::default();
+        for &key in self.learned.iter().take(8) {
+            intervals.semitones[intervals.len as usize] = (key - root) as i8;
+            intervals.len += 1;
+        }
+        self.intervals = intervals;
+    }
+}
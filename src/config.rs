@@ -0,0 +1,308 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt, fs,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use anyhow::{bail, ensure, Context, Result};
+
+/// A session described in a file that the host watches, so editing the file changes the running
+/// session.
+///
+/// Each line holds a setting followed by its value, and settings left out are left as they are:
+///
+/// ```text
+/// plugin /usr/lib/vst/synth.so
+/// preset /home/user/presets/pad.fxp
+/// bypass off
+/// hold on
+/// parameter 3 0.25
+/// ```
+///
+/// Empty lines and lines starting with `#` are ignored.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Config {
+    pub plugin: Option<PathBuf>,
+    pub preset: Option<PathBuf>,
+    pub bypass: Option<bool>,
+    pub hold: Option<bool>,
+    /// Values of parameters, by index
+    pub parameters: BTreeMap<i32, f32>,
+}
+
+/// One difference between two versions of a config
+#[derive(Clone, Debug, PartialEq)]
+pub enum Change {
+    Plugin(PathBuf),
+    Preset(PathBuf),
+    Bypass(bool),
+    Hold(bool),
+    Parameter(i32, f32),
+    /// A setting that was taken out, which stays as it is
+    Removed(String),
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let switch = |on| if on { "on" } else { "off" };
+        match self {
+            Change::Plugin(path) => write!(f, "plugin {}", path.display()),
+            Change::Preset(path) => write!(f, "preset {}", path.display()),
+            Change::Bypass(on) => write!(f, "bypass {}", switch(*on)),
+            Change::Hold(on) => write!(f, "hold {}", switch(*on)),
+            Change::Parameter(index, value) => write!(f, "parameter {index} {value}"),
+            Change::Removed(setting) => write!(f, "{setting} removed, left as it is"),
+        }
+    }
+}
+
+impl Config {
+    pub fn read(path: &Path) -> Result<Self> {
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("failed to read {}", path.display()))?;
+        Self::parse(&text, &path.display().to_string())
+    }
+
+    /// Parses the config in `text`, read from `origin`
+    pub fn parse(text: &str, origin: &str) -> Result<Self> {
+        let mut config = Self::default();
+
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let (name, value) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+            let value = value.trim();
+            let location = || format!("{origin}:{}", number + 1);
+            let switch = || match value {
+                "on" => Ok(true),
+                "off" => Ok(false),
+                _ => bail!("{}: expected {name} on|off", location()),
+            };
+            let path = || {
+                ensure!(!value.is_empty(), "{}: expected {name} <path>", location());
+                Ok(PathBuf::from(value))
+            };
+            match name {
+                "plugin" => config.plugin = Some(path()?),
+                "preset" => config.preset = Some(path()?),
+                "bypass" => config.bypass = Some(switch()?),
+                "hold" => config.hold = Some(switch()?),
+                "parameter" => {
+                    let (index, value) =
+                        value.split_once(char::is_whitespace).with_context(|| {
+                            format!("{}: expected parameter <index> <value>", location())
+                        })?;
+                    let index = index
+                        .parse()
+                        .with_context(|| format!("{}: invalid parameter", location()))?;
+                    let value: f32 = value
+                        .trim()
+                        .parse()
+                        .with_context(|| format!("{}: invalid value", location()))?;
+                    ensure!(
+                        (0. ..=1.).contains(&value),
+                        "{}: parameter values go from 0 to 1",
+                        location()
+                    );
+                    config.parameters.insert(index, value);
+                }
+                _ => bail!("{}: unknown setting {name:?}", location()),
+            }
+        }
+
+        Ok(config)
+    }
+
+    /// What has to change to go from this config to `new`. A new plugin is set up with all of
+    /// `new`, rather than only what changed.
+    pub fn changes(&self, new: &Config) -> Vec<Change> {
+        let mut changes = Vec::new();
+        compare(
+            &mut changes,
+            "plugin",
+            self.plugin.clone().map(Change::Plugin),
+            new.plugin.clone().map(Change::Plugin),
+        );
+        let fresh = Config::default();
+        let old = match changes.first() {
+            Some(Change::Plugin(_)) => &fresh,
+            _ => self,
+        };
+
+        compare(
+            &mut changes,
+            "preset",
+            old.preset.clone().map(Change::Preset),
+            new.preset.clone().map(Change::Preset),
+        );
+        compare(
+            &mut changes,
+            "bypass",
+            old.bypass.map(Change::Bypass),
+            new.bypass.map(Change::Bypass),
+        );
+        compare(
+            &mut changes,
+            "hold",
+            old.hold.map(Change::Hold),
+            new.hold.map(Change::Hold),
+        );
+        let indices: BTreeSet<i32> = old
+            .parameters
+            .keys()
+            .chain(new.parameters.keys())
+            .copied()
+            .collect();
+        for index in indices {
+            let parameter = |config: &Config| {
+                config
+                    .parameters
+                    .get(&index)
+                    .map(|&value| Change::Parameter(index, value))
+            };
+            compare(
+                &mut changes,
+                &format!("parameter {index}"),
+                parameter(old),
+                parameter(new),
+            );
+        }
+
+        changes
+    }
+}
+
+/// Adds `new` to `changes` if it differs from `old`, or notes that the setting called `name` was
+/// taken out
+fn compare(changes: &mut Vec<Change>, name: &str, old: Option<Change>, new: Option<Change>) {
+    match new {
+        Some(new) if old.as_ref() != Some(&new) => changes.push(new),
+        None if old.is_some() => changes.push(Change::Removed(name.to_owned())),
+        _ => {}
+    }
+}
+
+/// A config file and the version of it applied last
+pub struct Watched {
+    pub path: PathBuf,
+    /// When the file was changed as of the last read, `None` before the first
+    modified: Option<SystemTime>,
+    pub applied: Config,
+}
+
+impl Watched {
+    /// Watches `path`, whose first read is compared with `applied`
+    pub fn new(path: PathBuf, applied: Config) -> Self {
+        Self {
+            path,
+            modified: None,
+            applied,
+        }
+    }
+
+    /// Reads the file again if it changed since it was last read
+    pub fn poll(&mut self) -> Result<Option<Config>> {
+        let modified = fs::metadata(&self.path)
+            .and_then(|metadata| metadata.modified())
+            .with_context(|| format!("failed to read {}", self.path.display()))?;
+        if self.modified == Some(modified) {
+            return Ok(None);
+        }
+        self.modified = Some(modified);
+        Config::read(&self.path).map(Some)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Change, Config};
+
+    fn parse(text: &str) -> Config {
+        Config::parse(text, "test").unwrap()
+    }
+
+    #[test]
+    fn parses_every_setting() {
+        let config = parse(
+            "# a session\n\nplugin /vst/synth.so\npreset  pad.fxp\nbypass off\nhold on\n\
+             parameter 3 0.25\n",
+        );
+        assert_eq!(config.plugin, Some("/vst/synth.so".into()));
+        assert_eq!(config.preset, Some("pad.fxp".into()));
+        assert_eq!(config.bypass, Some(false));
+        assert_eq!(config.hold, Some(true));
+        assert_eq!(
+            config.parameters.into_iter().collect::<Vec<_>>(),
+            [(3, 0.25)]
+        );
+    }
+
+    #[test]
+    fn errors_point_at_the_line() {
+        let error = |text| Config::parse(text, "test").unwrap_err().to_string();
+        assert_eq!(
+            error("hold on\nbypass maybe"),
+            "test:2: expected bypass on|off"
+        );
+        assert_eq!(error("volume 11"), "test:1: unknown setting \"volume\"");
+        assert_eq!(error("hold on\npreset"), "test:2: expected preset <path>");
+        assert_eq!(error("plugin   "), "test:1: expected plugin <path>");
+        assert_eq!(
+            error("parameter 3"),
+            "test:1: expected parameter <index> <value>"
+        );
+        assert_eq!(
+            error("parameter 3 2"),
+            "test:1: parameter values go from 0 to 1"
+        );
+    }
+
+    #[test]
+    fn unchanged_configs_change_nothing() {
+        let config = parse("plugin synth.so\nhold on\nparameter 1 0.5");
+        assert_eq!(config.changes(&config.clone()), []);
+    }
+
+    #[test]
+    fn only_what_changed_is_applied() {
+        let old = parse("plugin synth.so\nhold on\nparameter 1 0.5\nparameter 2 0.5");
+        let new = parse("plugin synth.so\nhold off\nparameter 1 0.5\nparameter 2 0.75");
+        assert_eq!(
+            old.changes(&new),
+            [Change::Hold(false), Change::Parameter(2, 0.75)]
+        );
+    }
+
+    #[test]
+    fn removed_settings_are_reported() {
+        let old = parse("plugin synth.so\nbypass on\nparameter 3 0.25");
+        let new = parse("plugin synth.so");
+        let changes = old.changes(&new);
+        assert_eq!(
+            changes,
+            [
+                Change::Removed("bypass".to_owned()),
+                Change::Removed("parameter 3".to_owned())
+            ]
+        );
+        assert_eq!(changes[0].to_string(), "bypass removed, left as it is");
+    }
+
+    #[test]
+    fn a_new_plugin_gets_every_setting() {
+        let old = parse("plugin synth.so\nhold on\nparameter 1 0.5");
+        let new = parse("plugin organ.so\nhold on\nparameter 1 0.5");
+        assert_eq!(
+            old.changes(&new),
+            [
+                Change::Plugin("organ.so".into()),
+                Change::Hold(true),
+                Change::Parameter(1, 0.5)
+            ]
+        );
+    }
+}
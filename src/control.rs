@@ -0,0 +1,36 @@
+use std::{env, fs, os::unix::net::UnixListener, path::PathBuf};
+
+use anyhow::{Context, Result};
+
+/// The control socket used when none is given on the command line.
+///
+/// This lives in `$XDG_RUNTIME_DIR` when it is set, and in the temporary directory otherwise.
+pub fn default_socket_path() -> PathBuf {
+    match env::var_os("XDG_RUNTIME_DIR") {
+        Some(dir) => PathBuf::from(dir).join("y.sock"),
+        None => env::temp_dir().join(format!("y-{}.sock", unsafe { libc::getuid() })),
+    }
+}
+
+/// The file of a bound control socket, removed when this is dropped so it doesn't outlive the
+/// host whichever way it stops
+pub struct SocketFile(PathBuf);
+
+impl SocketFile {
+    /// Listens on a socket at `path`, replacing one left behind by a host that didn't stop cleanly
+    pub fn bind(path: PathBuf) -> Result<(UnixListener, Self)> {
+        if path.exists() {
+            fs::remove_file(&path)
+                .with_context(|| format!("failed to remove {}", path.display()))?;
+        }
+        let listener = UnixListener::bind(&path)
+            .with_context(|| format!("failed to bind {}", path.display()))?;
+        Ok((listener, Self(path)))
+    }
+}
+
+impl Drop for SocketFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.0);
+    }
+}
@@ -0,0 +1,117 @@
+use std::{
+    fs,
+    os::{raw::c_void, unix::process::ExitStatusExt},
+    path::Path,
+    process::{Command, ExitStatus},
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, Context, Result};
+
+/// Signals a crashing plugin takes the host down with
+const CRASHES: [(libc::c_int, &str); 5] = [
+    (libc::SIGSEGV, "segmentation fault"),
+    (libc::SIGBUS, "bus error"),
+    (libc::SIGILL, "illegal instruction"),
+    (libc::SIGFPE, "arithmetic exception"),
+    (libc::SIGABRT, "abort"),
+];
+
+/// Restarts after which a host that keeps crashing is given up on
+const MAX_RESTARTS: usize = 3;
+/// Period in which `MAX_RESTARTS` are allowed
+const RESTART_WINDOW: Duration = Duration::from_secs(60);
+
+extern "C" {
+    fn backtrace(buffer: *mut *mut c_void, size: libc::c_int) -> libc::c_int;
+    fn backtrace_symbols_fd(buffer: *const *mut c_void, size: libc::c_int, fd: libc::c_int);
+}
+
+/// Prints a backtrace to stderr when the process crashes, before it dies the way it would have.
+///
+/// Crashes are nearly always inside the plugin, so the backtrace shows which of its functions
+/// went wrong and which call from the host got it there.
+pub fn install_handlers() {
+    // The first call loads the unwinder, which can't happen inside a signal handler
+    let mut frames = [std::ptr::null_mut(); 1];
+    unsafe { backtrace(frames.as_mut_ptr(), 1) };
+
+    for (signal, _) in CRASHES {
+        unsafe {
+            let mut action: libc::sigaction = std::mem::zeroed();
+            action.sa_sigaction = handle as extern "C" fn(libc::c_int) as libc::sighandler_t;
+            // Runs on the alternate stack std sets up, so stack overflows are reported as well
+            action.sa_flags = libc::SA_ONSTACK | libc::SA_RESETHAND;
+            libc::sigemptyset(&mut action.sa_mask);
+            libc::sigaction(signal, &action, std::ptr::null_mut());
+        }
+    }
+}
+
+/// Only calls async-signal-safe functions: the process may have crashed inside `malloc`
+extern "C" fn handle(signal: libc::c_int) {
+    let name = CRASHES
+        .iter()
+        .find(|(crash, _)| *crash == signal)
+        .map_or("signal", |(_, name)| name);
+    let write = |text: &str| unsafe { libc::write(2, text.as_ptr() as *const c_void, text.len()) };
+    write("\ny crashed with a ");
+    write(name);
+    write(", backtrace:\n");
+
+    let mut frames = [std::ptr::null_mut(); 64];
+    unsafe {
+        let count = backtrace(frames.as_mut_ptr(), frames.len() as libc::c_int);
+        backtrace_symbols_fd(frames.as_ptr(), count, 2);
+        // The handler was reset, so this ends the process with the signal and a core dump
+        libc::raise(signal);
+    }
+}
+
+/// How a process that exited with `status` crashed, like `segmentation fault`, if it did
+pub fn crash_of(status: ExitStatus) -> Option<&'static str> {
+    let signal = status.signal()?;
+    CRASHES
+        .iter()
+        .find(|(crash, _)| *crash == signal)
+        .map(|(_, name)| *name)
+}
+
+/// Runs the host as `command` and starts it again whenever it crashes, until it exits by itself.
+///
+/// The host is expected to keep the plugin's state in `state_file` and restore it from there, which
+/// is removed once the host is done.
+pub fn supervise(mut command: Command, state_file: &Path) -> Result<()> {
+    let mut restarts: Vec<Instant> = Vec::new();
+
+    let result = loop {
+        let status = command.status().context("failed to start the host")?;
+        let name = match crash_of(status) {
+            Some(name) => name,
+            None if status.success() => break Ok(()),
+            None => break Err(anyhow!("the host exited with {status}")),
+        };
+
+        restarts.retain(|restart| restart.elapsed() < RESTART_WINDOW);
+        if restarts.len() == MAX_RESTARTS {
+            break Err(anyhow!(
+                "the host crashed {} times within {RESTART_WINDOW:?}, giving up",
+                MAX_RESTARTS + 1
+            ));
+        }
+        restarts.push(Instant::now());
+
+        if state_file.exists() {
+            log::warn!(
+                "the host crashed with a {name}, restarting it with the plugin's last state"
+            );
+        } else {
+            log::warn!("the host crashed with a {name} before saving any state, restarting it");
+        }
+    };
+
+    if state_file.exists() {
+        fs::remove_file(state_file)?;
+    }
+    result
+}
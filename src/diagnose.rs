@@ -0,0 +1,166 @@
+use std::{fs::File, io::Read, path::Path};
+
+use libloading::Library;
+use vst::host::PluginLoadError;
+
+/// What kind of library a file is, from its header
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Format {
+    /// ELF, with whether it's 64-bit and the machine it's built for
+    Elf {
+        is_64: bool,
+        machine: u16,
+    },
+    /// A Windows DLL, with the machine it's built for
+    Pe {
+        machine: u16,
+    },
+    MachO,
+    Unknown,
+}
+
+impl Format {
+    fn read(path: &Path) -> Format {
+        let mut header = Vec::new();
+        let read = File::open(path).and_then(|file| file.take(4096).read_to_end(&mut header));
+        if read.is_err() {
+            return Format::Unknown;
+        }
+
+        match header.as_slice() {
+            [0x7f, b'E', b'L', b'F', class, data, ..] if header.len() >= 20 => {
+                let bytes = [header[18], header[19]];
+                let machine = match data {
+                    2 => u16::from_be_bytes(bytes),
+                    _ => u16::from_le_bytes(bytes),
+                };
+                Format::Elf {
+                    is_64: *class == 2,
+                    machine,
+                }
+            }
+            [b'M', b'Z', ..] if header.len() >= 0x40 => {
+                let offset = u32::from_le_bytes(header[0x3c..0x40].try_into().unwrap()) as usize;
+                let machine = header
+                    .get(offset..offset + 6)
+                    .filter(|pe| pe.starts_with(b"PE\0\0"))
+                    .map_or(0, |pe| u16::from_le_bytes([pe[4], pe[5]]));
+                Format::Pe { machine }
+            }
+            [0xfe, 0xed, 0xfa, 0xce | 0xcf, ..]
+            | [0xce | 0xcf, 0xfa, 0xed, 0xfe, ..]
+            | [0xca, 0xfe, 0xba, 0xbe, ..] => Format::MachO,
+            _ => Format::Unknown,
+        }
+    }
+}
+
+/// Name of an ELF `e_machine` or PE machine type, for the common architectures
+fn architecture(format: Format) -> Option<&'static str> {
+    match format {
+        Format::Elf { machine: 0x03, .. } | Format::Pe { machine: 0x14c } => Some("x86"),
+        Format::Elf { machine: 0x3e, .. } | Format::Pe { machine: 0x8664 } => Some("x86_64"),
+        Format::Elf { machine: 0x28, .. }
+        | Format::Pe {
+            machine: 0x1c0 | 0x1c4,
+        } => Some("arm"),
+        Format::Elf { machine: 0xb7, .. } | Format::Pe { machine: 0xaa64 } => Some("aarch64"),
+        _ => None,
+    }
+}
+
+/// Explains why loading the plugin at `path` failed.
+///
+/// `vst` only reports that a library couldn't be opened or has no entry point, so this looks at
+/// the file itself to say what's wrong with it, such as it being built for another architecture.
+pub fn explain(path: &Path, error: PluginLoadError) -> anyhow::Error {
+    let error = anyhow::Error::msg(error.to_string());
+    match diagnose(path) {
+        Some(diagnosis) => error.context(format!("{}: {diagnosis}", path.display())),
+        None => error.context(format!("failed to load {}", path.display())),
+    }
+}
+
+fn diagnose(path: &Path) -> Option<String> {
+    let extension = path.extension().and_then(|extension| extension.to_str());
+
+    if !path.exists() {
+        return Some("no such file".to_string());
+    }
+    if extension == Some("vst3") {
+        return Some("this is a VST3 plugin, but only VST2 plugins are supported".to_string());
+    }
+    if path.is_dir() {
+        if extension == Some("vst") {
+            return Some(format!(
+                "this is a macOS bundle, load the library in {} instead",
+                path.join("Contents/MacOS").display()
+            ));
+        }
+        return Some("this is a directory, not a plugin library".to_string());
+    }
+
+    let format = Format::read(path);
+    let host_architecture = std::env::consts::ARCH;
+    match format {
+        Format::Elf { .. } if !cfg!(target_os = "linux") => {
+            return Some("this is a Linux plugin".to_string())
+        }
+        Format::Pe { .. } if !cfg!(windows) => {
+            return Some(
+                "this is a Windows plugin, which needs a bridge such as yabridge".to_string(),
+            )
+        }
+        Format::MachO if !cfg!(target_os = "macos") => {
+            return Some("this is a macOS plugin".to_string())
+        }
+        Format::Elf { is_64, .. } if is_64 != cfg!(target_pointer_width = "64") => {
+            let bits = if is_64 { 64 } else { 32 };
+            let host_bits = if cfg!(target_pointer_width = "64") {
+                64
+            } else {
+                32
+            };
+            return Some(format!(
+                "this is a {bits}-bit plugin, but the host is {host_bits}-bit"
+            ));
+        }
+        Format::Elf { .. } | Format::Pe { .. } => match architecture(format) {
+            Some(architecture) if architecture != host_architecture => {
+                return Some(format!(
+                    "this plugin is built for {architecture}, but the host is {host_architecture}"
+                ));
+            }
+            _ => {}
+        },
+        Format::MachO => {}
+        Format::Unknown => return Some("this is not a shared library".to_string()),
+    }
+
+    // The format matches, so ask the dynamic loader itself
+    let library = match unsafe { Library::new(path) } {
+        Ok(library) => library,
+        Err(err) => {
+            let message = err.to_string();
+            return Some(if message.contains("undefined symbol") {
+                format!("the plugin uses a symbol nothing provides, it may need a newer system library ({message})")
+            } else if message.contains("cannot open shared object file") {
+                format!("a library the plugin depends on is missing ({message})")
+            } else {
+                message
+            });
+        }
+    };
+
+    let has_symbol = |symbol: &[u8]| unsafe { library.get::<*const ()>(symbol) }.is_ok();
+    if has_symbol(b"GetPluginFactory") {
+        return Some("this is a VST3 plugin, but only VST2 plugins are supported".to_string());
+    }
+    if !has_symbol(b"VSTPluginMain") && !has_symbol(b"main") && !has_symbol(b"main_macho") {
+        return Some(
+            "the library doesn't export VSTPluginMain, so it isn't a VST2 plugin".to_string(),
+        );
+    }
+
+    None
+}
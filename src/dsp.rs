@@ -0,0 +1,64 @@
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use libloading::Library;
+
+/// Name of the function a DSP library has to export
+const SYMBOL: &[u8] = b"y_process";
+
+/// `y_process(channels, channel_count, length, sample_rate)`, processing `length` samples of each
+/// of the `channel_count` buffers in `channels` in place
+type ProcessFn = unsafe extern "C" fn(*const *mut f32, u32, u32, f32);
+
+/// A user DSP function loaded from a dynamic library, run on every block before or after the
+/// plugin.
+///
+/// The function is called on the audio thread, so it shouldn't block or allocate. Any state it
+/// keeps is shared between all hooks loaded from the same library.
+pub struct Hook {
+    process: ProcessFn,
+    /// Scratch space for the channel pointers passed to `process`
+    pointers: Vec<*mut f32>,
+    // Keeps `process` loaded, declared last so it's dropped last
+    _library: Library,
+}
+
+// The pointers are only valid during `process`, and the function itself is expected to be callable
+// from any thread
+unsafe impl Send for Hook {}
+
+impl Hook {
+    pub fn load(path: &Path) -> Result<Self> {
+        let library = unsafe { Library::new(path) }
+            .with_context(|| format!("failed to load {}", path.display()))?;
+        let process = *unsafe { library.get::<ProcessFn>(SYMBOL) }.with_context(|| {
+            format!(
+                "{} doesn't export {}",
+                path.display(),
+                String::from_utf8_lossy(SYMBOL)
+            )
+        })?;
+
+        Ok(Self {
+            process,
+            pointers: Vec::new(),
+            _library: library,
+        })
+    }
+
+    /// Processes the first `length` samples of every channel in place
+    pub fn process(&mut self, channels: &mut [Vec<f32>], length: usize, sample_rate: f32) {
+        self.pointers.clear();
+        self.pointers
+            .extend(channels.iter_mut().map(|channel| channel.as_mut_ptr()));
+
+        unsafe {
+            (self.process)(
+                self.pointers.as_ptr(),
+                self.pointers.len() as u32,
+                length as u32,
+                sample_rate,
+            )
+        };
+    }
+}
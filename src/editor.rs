@@ -0,0 +1,225 @@
+use std::{ffi::c_void, fs, path::PathBuf, ptr, str::FromStr};
+
+use anyhow::{bail, Context, Result};
+use raw_window_handle::{HasRawWindowHandle, RawWindowHandle};
+use vst::{
+    api::AEffect,
+    editor::Editor,
+    plugin::{Info, OpCode},
+};
+use winit::{
+    dpi::{PhysicalPosition, PhysicalSize},
+    event_loop::EventLoop,
+    window::{Icon, Window},
+};
+
+use crate::{effect::dispatch, profile::config_dir};
+
+/// Width and height of the window icon
+const ICON_SIZE: u32 = 32;
+
+/// Embeds a plugin's editor into a window of the host.
+///
+/// Each windowing system, or way of embedding editors, gets its own implementation, so the rest
+/// of the host doesn't deal with window handles.
+pub trait EditorHost {
+    /// Opens `editor` inside the host's window and fits the window to it.
+    ///
+    /// Returns whether the plugin reported opening it.
+    fn open(&mut self, editor: Box<dyn Editor>) -> Result<bool>;
+
+    /// Resizes the window around the editor
+    fn resize(&mut self, width: i32, height: i32);
+
+    /// Gives the editor time to redraw, for plugins that only do so from idle calls
+    fn idle(&mut self);
+
+    /// Closes the editor, after which the window can be dropped
+    fn close(&mut self);
+}
+
+/// A point on the screen in pixels, written as `x,y`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Position {
+    pub x: i32,
+    pub y: i32,
+}
+
+impl FromStr for Position {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (x, y) = s.split_once(',').context("expected x,y")?;
+        Ok(Self {
+            x: x.trim().parse().context("invalid x")?,
+            y: y.trim().parse().context("invalid y")?,
+        })
+    }
+}
+
+/// How the editor's window is shown
+#[derive(clap::Args, Clone, Debug, Default)]
+pub struct WindowOptions {
+    /// Keep the editor's window above other windows
+    #[clap(long)]
+    pub on_top: bool,
+
+    /// Where to put the editor's window, as `x,y` in pixels. Without it, each plugin's window
+    /// opens where it was last closed.
+    #[clap(long, value_name = "X,Y")]
+    pub window_pos: Option<Position>,
+}
+
+/// Opens editors in a winit window, on Windows, X11 and macOS
+pub struct WinitEditorHost {
+    window: Window,
+    effect: *mut AEffect,
+    editor: Option<Box<dyn Editor>>,
+    /// Whose window position is remembered when the editor is closed
+    unique_id: i32,
+}
+
+impl WinitEditorHost {
+    /// Creates the window for the editor of the plugin behind `effect`, described by `info`
+    pub fn new<T>(
+        event_loop: &EventLoop<T>,
+        effect: *mut AEffect,
+        info: &Info,
+        options: &WindowOptions,
+    ) -> Result<Self> {
+        let window = Window::new(event_loop)?;
+        window.set_resizable(false);
+        window.set_title(&match info.vendor.as_str() {
+            "" => info.name.clone(),
+            vendor => format!("{} - {vendor}", info.name),
+        });
+        window.set_window_icon(Some(icon()));
+        window.set_always_on_top(options.on_top);
+        if let Some(position) = options
+            .window_pos
+            .or_else(|| remembered_position(info.unique_id))
+        {
+            window.set_outer_position(PhysicalPosition::new(position.x, position.y));
+        }
+
+        Ok(Self {
+            window,
+            effect,
+            editor: None,
+            unique_id: info.unique_id,
+        })
+    }
+}
+
+impl EditorHost for WinitEditorHost {
+    fn open(&mut self, mut editor: Box<dyn Editor>) -> Result<bool> {
+        let parent = parent_handle(self.window.raw_window_handle())?;
+        let opened = editor.open(parent);
+
+        let (width, height) = editor.size();
+        if width > 0 && height > 0 {
+            self.resize(width, height);
+        }
+        self.editor = Some(editor);
+
+        Ok(opened)
+    }
+
+    fn resize(&mut self, width: i32, height: i32) {
+        self.window
+            .set_inner_size(PhysicalSize::new(width as u32, height as u32));
+    }
+
+    fn idle(&mut self) {
+        if self.editor.is_some() {
+            unsafe { dispatch(self.effect, OpCode::EditorIdle, 0, 0, ptr::null_mut(), 0.) };
+        }
+    }
+
+    fn close(&mut self) {
+        if let Some(mut editor) = self.editor.take() {
+            editor.close();
+        }
+
+        if let Ok(position) = self.window.outer_position() {
+            let position = Position {
+                x: position.x,
+                y: position.y,
+            };
+            if let Err(err) = remember_position(self.unique_id, position) {
+                log::warn!("failed to remember the editor's position: {err:#}");
+            }
+        }
+    }
+}
+
+/// The pointer VST 2 editors expect as their parent on each platform
+fn parent_handle(handle: RawWindowHandle) -> Result<*mut c_void> {
+    match handle {
+        RawWindowHandle::Win32(handle) => Ok(handle.hwnd),
+        RawWindowHandle::Xlib(handle) => Ok(handle.window as *mut c_void),
+        RawWindowHandle::Xcb(handle) => Ok(handle.window as usize as *mut c_void),
+        RawWindowHandle::AppKit(handle) => Ok(handle.ns_view),
+        _ => bail!("plugin editors can't be embedded into {handle:?} windows"),
+    }
+}
+
+/// The host's window icon, drawn here rather than shipped as an image: a light ring on a dark
+/// square
+fn icon() -> Icon {
+    let centre = ICON_SIZE as f32 / 2.;
+    let mut rgba = Vec::with_capacity((ICON_SIZE * ICON_SIZE * 4) as usize);
+    for y in 0..ICON_SIZE {
+        for x in 0..ICON_SIZE {
+            let distance = (x as f32 + 0.5 - centre).hypot(y as f32 + 0.5 - centre);
+            let ring = (distance - centre * 0.6).abs() < centre * 0.15;
+            rgba.extend_from_slice(if ring {
+                &[0xe0, 0xe0, 0xe0, 0xff]
+            } else {
+                &[0x20, 0x20, 0x28, 0xff]
+            });
+        }
+    }
+
+    Icon::from_rgba(rgba, ICON_SIZE, ICON_SIZE).expect("the icon has the right size")
+}
+
+/// Where editor windows were last closed, one `<unique id> <x>,<y>` line per plugin, in
+/// `$XDG_CONFIG_HOME/y/windows.txt` by default
+fn positions_path() -> Option<PathBuf> {
+    Some(config_dir()?.join("windows.txt"))
+}
+
+fn read_positions() -> Vec<(i32, Position)> {
+    let text = positions_path()
+        .and_then(|path| fs::read_to_string(path).ok())
+        .unwrap_or_default();
+
+    text.lines()
+        .filter_map(|line| {
+            let (id, position) = line.split_once(' ')?;
+            Some((id.parse().ok()?, position.parse().ok()?))
+        })
+        .collect()
+}
+
+fn remembered_position(unique_id: i32) -> Option<Position> {
+    read_positions()
+        .into_iter()
+        .find(|(id, _)| *id == unique_id)
+        .map(|(_, position)| position)
+}
+
+fn remember_position(unique_id: i32, position: Position) -> Result<()> {
+    let path = positions_path().context("no configuration directory, HOME isn't set")?;
+    let mut positions = read_positions();
+    positions.retain(|(id, _)| *id != unique_id);
+    positions.push((unique_id, position));
+
+    let text: String = positions
+        .iter()
+        .map(|(id, position)| format!("{id} {},{}\n", position.x, position.y))
+        .collect();
+    fs::create_dir_all(path.parent().unwrap())?;
+    fs::write(&path, text).with_context(|| format!("failed to write {}", path.display()))
+}
@@ -0,0 +1,102 @@
+use std::{
+    convert::TryFrom,
+    os::raw::{c_char, c_void},
+    sync::Arc,
+};
+
+use vst::{
+    api::{AEffect, DispatcherProc},
+    host::PluginInstance,
+    plugin::{OpCode, Plugin},
+};
+
+use crate::trace::TraceFile;
+
+/// Returns the `AEffect` of a loaded plugin, for opcodes that `vst` doesn't wrap.
+///
+/// `vst` keeps this pointer private. The parameter object of a `PluginInstance` is a struct whose
+/// only field is the `AEffect` pointer though, so it can be read through the returned `Arc`.
+pub fn effect_of(plugin: &mut PluginInstance) -> *mut AEffect {
+    let parameters = plugin.get_parameter_object();
+    unsafe { *(Arc::as_ptr(&parameters) as *const *mut AEffect) }
+}
+
+/// Calls the plugin's dispatcher directly.
+///
+/// # Safety
+///
+/// `effect` has to belong to a plugin that is still loaded, and `ptr` has to be valid for the
+/// opcode.
+pub unsafe fn dispatch(
+    effect: *mut AEffect,
+    opcode: OpCode,
+    index: i32,
+    value: isize,
+    ptr: *mut c_void,
+    opt: f32,
+) -> isize {
+    ((*effect).dispatcher)(effect, opcode.into(), index, value, ptr, opt)
+}
+
+/// A string the plugin wrote into a fixed size buffer, which it should have terminated
+pub fn c_string(buffer: &[c_char]) -> String {
+    let bytes: Vec<u8> = buffer
+        .iter()
+        .map(|&c| c as u8)
+        .take_while(|&byte| byte != 0)
+        .collect();
+    String::from_utf8_lossy(&bytes).trim().to_string()
+}
+
+/// What the tracing dispatcher needs to forward calls, stored behind `AEffect::reserved2`
+struct TracedDispatcher {
+    dispatcher: DispatcherProc,
+    trace: Arc<TraceFile>,
+}
+
+/// Logs every dispatcher call made into the plugin to `trace`.
+///
+/// This swaps the plugin's dispatcher for one that records the opcode and its arguments before
+/// forwarding the call, so it covers calls made by `vst` on our behalf as well.
+pub fn trace_dispatcher(plugin: &mut PluginInstance, trace: Arc<TraceFile>) {
+    let effect = effect_of(plugin);
+
+    unsafe {
+        let state = TracedDispatcher {
+            dispatcher: (*effect).dispatcher,
+            trace,
+        };
+        (*effect).reserved2 = Box::into_raw(Box::new(state)) as isize;
+        (*effect).dispatcher = traced_dispatcher;
+    }
+}
+
+extern "C" fn traced_dispatcher(
+    effect: *mut AEffect,
+    opcode: i32,
+    index: i32,
+    value: isize,
+    ptr: *mut c_void,
+    opt: f32,
+) -> isize {
+    // The plugin frees `effect` on shutdown, so this has to be read before forwarding the call
+    let state = unsafe { (*effect).reserved2 as *mut TracedDispatcher };
+    let result = unsafe { ((*state).dispatcher)(effect, opcode, index, value, ptr, opt) };
+
+    let name = match OpCode::try_from(opcode) {
+        Ok(name) => format!("{name:?}"),
+        Err(_) => format!("opcode {opcode}"),
+    };
+    unsafe { &(*state).trace }.call(
+        &name,
+        format_args!("index {index}, value {value}, ptr {ptr:?}, opt {opt}"),
+        &result,
+    );
+
+    // Nothing can call into the plugin after it was shut down
+    if let Ok(OpCode::Shutdown) = OpCode::try_from(opcode) {
+        unsafe { drop(Box::from_raw(state)) };
+    }
+
+    result
+}
@@ -0,0 +1,74 @@
+use std::ops::Range;
+
+use vst::plugin::PluginParameters;
+
+/// A peak envelope follower with separate attack and release times
+pub struct EnvelopeFollower {
+    attack: f32,
+    release: f32,
+    level: f32,
+}
+
+impl EnvelopeFollower {
+    /// Creates a follower reaching about 63% of a step within `attack` or `release` seconds
+    pub fn new(attack: f32, release: f32, sample_rate: f32) -> Self {
+        let coefficient = |time: f32| {
+            if time > 0. {
+                (-1. / (time * sample_rate)).exp()
+            } else {
+                0.
+            }
+        };
+
+        Self {
+            attack: coefficient(attack),
+            release: coefficient(release),
+            level: 0.,
+        }
+    }
+
+    /// Follows `samples`, returning the level after the last one
+    pub fn process(&mut self, samples: &[f32]) -> f32 {
+        for sample in samples {
+            let input = sample.abs();
+            let coefficient = if input > self.level {
+                self.attack
+            } else {
+                self.release
+            };
+            self.level = input + coefficient * (self.level - input);
+        }
+
+        self.level
+    }
+}
+
+/// Modulates a plugin parameter from the envelope of an input channel
+pub struct Sidechain {
+    pub follower: EnvelopeFollower,
+    /// The input channel to follow
+    pub channel: usize,
+    pub parameter: i32,
+    /// The parameter's value while the input is silent
+    pub base: f32,
+    /// How far a full-scale input moves the parameter away from `base`
+    pub amount: f32,
+}
+
+impl Sidechain {
+    /// Follows `range` of the sidechain input and sets the parameter accordingly
+    pub fn process(
+        &mut self,
+        inputs: &[Vec<f32>],
+        range: Range<usize>,
+        parameters: &dyn PluginParameters,
+    ) {
+        let level = match inputs.get(self.channel) {
+            Some(samples) => self.follower.process(&samples[range]),
+            None => 0.,
+        };
+
+        let value = (self.base + self.amount * level).clamp(0., 1.);
+        parameters.set_parameter(self.parameter, value);
+    }
+}
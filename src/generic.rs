@@ -0,0 +1,244 @@
+use std::{io::Write, time::Duration};
+
+use anyhow::Result;
+use vst::plugin::Info;
+
+use crate::{
+    activity::Activity,
+    instance::Parameters,
+    keyboard::RawTerminal,
+    properties::{self, Category, Properties},
+};
+
+const HELP: &str = "Up/down or k/j select, left/right or h/l change by a step, [ and ] by a large \
+step, / search, q quit";
+
+/// Parameters listed at once around the selected one
+const ROWS: usize = 20;
+const BAR_WIDTH: usize = 20;
+/// How often values changed from elsewhere, like automation or MIDI, and activity are picked up
+const REFRESH: Duration = Duration::from_millis(100);
+
+/// The parameter list and what's selected in it
+struct View<'a> {
+    parameters: Parameters,
+    activity: &'a Activity,
+    title: String,
+    names: Vec<String>,
+    /// What the plugin says about how each parameter is shown
+    properties: Vec<Option<Properties>>,
+    /// Parameter indices in the order the plugin would list them
+    order: Vec<i32>,
+    /// Only parameters whose names contain this are listed
+    filter: String,
+    searching: bool,
+    /// Position of the selected parameter among those listed
+    selected: usize,
+}
+
+impl View<'_> {
+    /// Indices of the parameters matching the filter
+    fn listed(&self) -> Vec<i32> {
+        let filter = self.filter.to_lowercase();
+        self.order
+            .iter()
+            .copied()
+            .filter(|&index| self.names[index as usize].to_lowercase().contains(&filter))
+            .collect()
+    }
+
+    fn category(&self, index: i32) -> Option<&Category> {
+        self.properties[index as usize]
+            .as_ref()
+            .and_then(|properties| properties.category.as_ref())
+    }
+
+    /// What's shown that can change without input: the values and the activity lights
+    fn state(&self) -> (Vec<f32>, String) {
+        let values = (0..self.names.len() as i32)
+            .map(|index| self.parameters.get_parameter(index))
+            .collect();
+        (values, self.activity.summary())
+    }
+
+    fn draw(&mut self, out: &mut impl Write) -> Result<()> {
+        let listed = self.listed();
+        self.selected = self.selected.min(listed.len().saturating_sub(1));
+
+        // Clear the screen and start at the top
+        write!(out, "\x1b[H\x1b[2J")?;
+        writeln!(out, "{} | {}", self.title, self.activity.summary())?;
+        if self.searching || !self.filter.is_empty() {
+            writeln!(
+                out,
+                "Search: {}{}",
+                self.filter,
+                if self.searching { "_" } else { "" }
+            )?;
+        } else {
+            writeln!(out, "{HELP}")?;
+        }
+        writeln!(out)?;
+
+        let categorised = self
+            .order
+            .iter()
+            .any(|&index| self.category(index).is_some());
+        let mut shown_category = None;
+        let first = self.selected.saturating_sub(ROWS / 2);
+        for (position, &index) in listed.iter().enumerate().skip(first).take(ROWS) {
+            let category = self.category(index);
+            if categorised && shown_category != Some(category) {
+                shown_category = Some(category);
+                match category {
+                    Some(category) if !category.label.is_empty() => {
+                        writeln!(out, "  -- {} --", category.label)?
+                    }
+                    Some(category) => writeln!(out, "  -- Category {} --", category.index)?,
+                    None => writeln!(out, "  -- Other --")?,
+                }
+            }
+            let value = self.parameters.get_parameter(index);
+            let filled = (value.clamp(0., 1.) * BAR_WIDTH as f32).round() as usize;
+            writeln!(
+                out,
+                "{} {:<24.24} [{}{}] {} {}",
+                if position == self.selected { '>' } else { ' ' },
+                self.names[index as usize],
+                "#".repeat(filled),
+                " ".repeat(BAR_WIDTH - filled),
+                self.parameters.get_parameter_text(index),
+                self.parameters.get_parameter_label(index)
+            )?;
+        }
+        if listed.is_empty() {
+            writeln!(out, "  No parameters match")?;
+        }
+        out.flush()?;
+
+        Ok(())
+    }
+
+    /// Moves the selected parameter a step in `direction`, or a large step. Steps are the plugin's
+    /// if it says, one value of parameters only taking a few, and otherwise 1% and 10%.
+    fn adjust(&self, direction: f32, large: bool) {
+        let Some(&index) = self.listed().get(self.selected) else {
+            return;
+        };
+        let properties = self.properties[index as usize].as_ref();
+        let plugin_step = properties
+            .and_then(|properties| properties.step)
+            .filter(|&(step, large_step)| step > 0. && large_step > 0.);
+        let (step, large_step) = match (plugin_step, properties.and_then(Properties::steps)) {
+            (Some(steps), _) => steps,
+            (None, Some(steps)) if steps > 1 => {
+                let step = 1. / (steps - 1) as f32;
+                (step, step.max(0.1))
+            }
+            _ => (0.01, 0.1),
+        };
+        let step = if large { large_step } else { step };
+
+        let value = (self.parameters.get_parameter(index) + direction * step).clamp(0., 1.);
+        let value = properties.map_or(value, |properties| properties.snap(value));
+        self.parameters.set_parameter(index, value);
+    }
+}
+
+/// Shows every parameter of the plugin described by `info` in the terminal, for plugins without
+/// an editor, until the user quits
+pub fn run(
+    parameters: Parameters,
+    properties: Vec<Option<Properties>>,
+    info: &Info,
+    activity: &Activity,
+    mut out: impl Write,
+) -> Result<()> {
+    let _terminal = RawTerminal::enable()?;
+
+    let mut view = View {
+        title: format!("{} - {} parameters", info.name, info.parameters),
+        names: (0..info.parameters)
+            .map(|index| parameters.get_parameter_name(index))
+            .collect(),
+        order: properties::display_order(&properties),
+        properties,
+        parameters,
+        activity,
+        filter: String::new(),
+        searching: false,
+        selected: 0,
+    };
+    let mut drawn = view.state();
+    view.draw(&mut out)?;
+
+    loop {
+        let mut poll = libc::pollfd {
+            fd: 0,
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        if unsafe { libc::poll(&mut poll, 1, REFRESH.as_millis() as i32) } <= 0 {
+            let state = view.state();
+            if state != drawn {
+                drawn = state;
+                view.draw(&mut out)?;
+            }
+            continue;
+        }
+
+        let mut buffer = [0u8; 16];
+        let read = unsafe { libc::read(0, buffer.as_mut_ptr().cast(), buffer.len()) };
+        if read <= 0 {
+            break;
+        }
+        let input = &buffer[..read as usize];
+
+        match input {
+            // Arrow keys
+            b"\x1b[A" => view.selected = view.selected.saturating_sub(1),
+            b"\x1b[B" => view.selected += 1,
+            b"\x1b[C" => view.adjust(1., false),
+            b"\x1b[D" => view.adjust(-1., false),
+            // Other escape sequences
+            [0x1b, _, ..] => {}
+            _ if view.searching => {
+                for &key in input {
+                    match key {
+                        b'\r' | b'\n' => view.searching = false,
+                        0x1b => {
+                            view.searching = false;
+                            view.filter.clear();
+                        }
+                        0x7f | 0x08 => {
+                            view.filter.pop();
+                        }
+                        b' '..=b'~' => view.filter.push(key as char),
+                        _ => {}
+                    }
+                }
+                view.selected = 0;
+            }
+            _ => {
+                for &key in input {
+                    match key {
+                        0x1b | b'q' | 0x03 | 0x04 => return Ok(()),
+                        b'k' => view.selected = view.selected.saturating_sub(1),
+                        b'j' => view.selected += 1,
+                        b'l' => view.adjust(1., false),
+                        b'h' => view.adjust(-1., false),
+                        b']' => view.adjust(1., true),
+                        b'[' => view.adjust(-1., true),
+                        b'/' => view.searching = true,
+                        _ => {}
+                    }
+                }
+            }
+        }
+
+        drawn = view.state();
+        view.draw(&mut out)?;
+    }
+
+    Ok(())
+}
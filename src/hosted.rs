@@ -0,0 +1,203 @@
+use std::{ffi::CString, os::raw::c_void, ptr};
+
+use vst::{
+    api::AEffect,
+    buffer::SendEventBuffer,
+    editor::Editor,
+    event::{Event, MidiEvent, SysExEvent},
+    host::{HostBuffer, PluginInstance},
+    plugin::{Info, OpCode, Plugin},
+};
+
+use crate::{
+    effect::{dispatch, effect_of},
+    instance::Parameters,
+    lifecycle::Step,
+    properties::Properties,
+};
+
+/// A loaded plugin, whatever format it's in.
+///
+/// Everything past loading — the audio thread, rendering, mappings and scenes — goes through this
+/// trait, so another plugin format only needs a loader and an implementation of it.
+pub trait HostedPlugin: Send {
+    /// Describes the plugin in VST 2 terms, which other formats map onto
+    fn info(&self) -> Info;
+
+    /// Makes the call that moves the plugin through one step of its lifecycle
+    fn perform(&mut self, step: Step);
+
+    /// Only called while the plugin is suspended
+    fn set_sample_rate(&mut self, sample_rate: f32);
+
+    /// Only called while the plugin is suspended
+    fn set_block_size(&mut self, block_size: usize);
+
+    /// Tells the plugin how many samples an offline render is going to process, for plugins that
+    /// prepare for the whole length, like lookahead limiters
+    fn set_total_samples(&mut self, total: usize);
+
+    /// Sends the events of the next call to `process`, with SysEx messages before them
+    fn process_events(&mut self, events: &[MidiEvent], sysex: &[Vec<u8>]);
+
+    /// Processes one block, as long as the channels of `outputs`
+    fn process(&mut self, inputs: &[Vec<f32>], outputs: &mut [Vec<f32>]);
+
+    fn parameters(&mut self) -> Parameters;
+
+    /// How parameter `index` is shown, if the plugin says
+    fn parameter_properties(&mut self, index: i32) -> Option<Properties>;
+
+    /// The plugin's full state, in a format only `load_state` has to understand
+    fn save_state(&mut self) -> Vec<u8>;
+
+    fn load_state(&mut self, state: &[u8]);
+
+    fn editor(&mut self) -> Option<Box<dyn Editor>>;
+}
+
+/// A VST 2 plugin, with the buffers `vst` needs to talk to it
+pub struct Vst2Plugin {
+    plugin: PluginInstance,
+    host_buffer: HostBuffer<f32>,
+    event_buffer: SendEventBuffer,
+}
+
+// `HostBuffer` holds raw pointers which are only valid while it is bound during `process`
+unsafe impl Send for Vst2Plugin {}
+
+impl Vst2Plugin {
+    pub fn new(plugin: PluginInstance) -> Self {
+        let info = plugin.get_info();
+
+        Self {
+            plugin,
+            host_buffer: HostBuffer::from_info(&info),
+            event_buffer: SendEventBuffer::default(),
+        }
+    }
+
+    /// The plugin's `AEffect`, for what only VST 2 has
+    pub fn effect(&mut self) -> *mut AEffect {
+        effect_of(&mut self.plugin)
+    }
+
+    /// Asks the plugin whether it supports `feature`, like `MPE`, `None` if it doesn't know
+    pub fn can_do(&mut self, feature: &str) -> Option<bool> {
+        let feature = CString::new(feature).ok()?;
+        let answer = unsafe {
+            dispatch(
+                self.effect(),
+                OpCode::CanDo,
+                0,
+                0,
+                feature.as_ptr() as *mut c_void,
+                0.,
+            )
+        };
+        match answer {
+            0 => None,
+            answer => Some(answer > 0),
+        }
+    }
+}
+
+impl HostedPlugin for Vst2Plugin {
+    fn info(&self) -> Info {
+        self.plugin.get_info()
+    }
+
+    fn perform(&mut self, step: Step) {
+        match step {
+            Step::Initialise => self.plugin.init(),
+            Step::Resume => self.plugin.resume(),
+            Step::Suspend => self.plugin.suspend(),
+            // `PluginInstance` doesn't forward these
+            Step::StartProcess | Step::StopProcess => {
+                let opcode = match step {
+                    Step::StartProcess => OpCode::StartProcess,
+                    _ => OpCode::StopProcess,
+                };
+                unsafe { dispatch(self.effect(), opcode, 0, 0, ptr::null_mut(), 0.) };
+            }
+        }
+    }
+
+    fn set_sample_rate(&mut self, sample_rate: f32) {
+        self.plugin.set_sample_rate(sample_rate);
+    }
+
+    fn set_block_size(&mut self, block_size: usize) {
+        self.plugin.set_block_size(block_size as i64);
+    }
+
+    fn set_total_samples(&mut self, total: usize) {
+        // `PluginInstance` doesn't forward this either
+        let effect = self.effect();
+        unsafe {
+            dispatch(
+                effect,
+                OpCode::SetTotalSampleToProcess,
+                0,
+                total as isize,
+                ptr::null_mut(),
+                0.,
+            )
+        };
+    }
+
+    fn process_events(&mut self, events: &[MidiEvent], sysex: &[Vec<u8>]) {
+        let sysex = sysex.iter().map(|payload| {
+            Event::SysEx(SysExEvent {
+                payload,
+                delta_frames: 0,
+            })
+        });
+        let events = events.iter().map(|&event| Event::Midi(event));
+        self.event_buffer.store_events(sysex.chain(events));
+        self.plugin.process_events(self.event_buffer.events());
+    }
+
+    fn process(&mut self, inputs: &[Vec<f32>], outputs: &mut [Vec<f32>]) {
+        let mut audio_buffer = self.host_buffer.bind(inputs, outputs);
+        self.plugin.process(&mut audio_buffer);
+    }
+
+    fn parameters(&mut self) -> Parameters {
+        Parameters::of(&mut self.plugin)
+    }
+
+    fn parameter_properties(&mut self, index: i32) -> Option<Properties> {
+        unsafe { Properties::query(self.effect(), index) }
+    }
+
+    /// The plugin's chunk if it has one, otherwise the values of its parameters
+    fn save_state(&mut self) -> Vec<u8> {
+        let info = self.plugin.get_info();
+        let parameters = self.plugin.get_parameter_object();
+        if info.preset_chunks {
+            parameters.get_preset_data()
+        } else {
+            (0..info.parameters)
+                .flat_map(|index| parameters.get_parameter(index).to_le_bytes())
+                .collect()
+        }
+    }
+
+    fn load_state(&mut self, state: &[u8]) {
+        let info = self.plugin.get_info();
+        let parameters = self.plugin.get_parameter_object();
+        if info.preset_chunks {
+            parameters.load_preset_data(state);
+        } else {
+            for (index, value) in state.chunks_exact(4).enumerate() {
+                let value = f32::from_le_bytes(value.try_into().unwrap());
+                parameters.set_parameter(index as i32, value);
+            }
+        }
+    }
+
+    fn editor(&mut self) -> Option<Box<dyn Editor>> {
+        self.plugin.get_editor()
+    }
+}
@@ -0,0 +1,207 @@
+use std::{collections::HashMap, ops::Deref, ops::Range, sync::Arc};
+
+use vst::{
+    api::AEffect,
+    event::MidiEvent,
+    host::PluginInstance,
+    plugin::{Info, Plugin, PluginParameters},
+};
+
+use crate::{
+    hosted::HostedPlugin,
+    lifecycle::{Lifecycle, State},
+    oversample::{Downsampler, Upsampler},
+};
+
+/// A handle to a hosted plugin's parameters that can be moved to other threads.
+///
+/// The parameter object of a `PluginInstance` only wraps the plugin's `AEffect` pointer and is
+/// `Send` and `Sync` itself, but `get_parameter_object` erases it into an
+/// `Arc<dyn PluginParameters>`, which is not `Send`.
+#[derive(Clone)]
+pub struct Parameters(Arc<dyn PluginParameters>);
+
+unsafe impl Send for Parameters {}
+
+impl Parameters {
+    pub fn of(plugin: &mut PluginInstance) -> Self {
+        Self(plugin.get_parameter_object())
+    }
+
+    /// The plugin's `AEffect`, read the same way as in [`effect_of`](crate::effect::effect_of)
+    pub fn effect(&self) -> *mut AEffect {
+        unsafe { *(Arc::as_ptr(&self.0) as *const *mut AEffect) }
+    }
+}
+
+impl Deref for Parameters {
+    type Target = dyn PluginParameters;
+
+    fn deref(&self) -> &Self::Target {
+        &*self.0
+    }
+}
+
+/// Copies as much state as possible from one plugin to another.
+///
+/// Two instances of the same plugin exchange their full state, either as a chunk or parameter by
+/// parameter. Otherwise only parameters with matching names are copied.
+pub fn transfer_state(
+    from: &dyn PluginParameters,
+    from_info: &Info,
+    to: &dyn PluginParameters,
+    to_info: &Info,
+) {
+    if from_info.unique_id == to_info.unique_id {
+        if from_info.preset_chunks {
+            to.load_preset_data(&from.get_preset_data());
+        } else {
+            for index in 0..from_info.parameters.min(to_info.parameters) {
+                to.set_parameter(index, from.get_parameter(index));
+            }
+        }
+    } else {
+        let names: HashMap<String, i32> = (0..to_info.parameters)
+            .map(|index| (to.get_parameter_name(index), index))
+            .collect();
+
+        for index in 0..from_info.parameters {
+            if let Some(&target) = names.get(&from.get_parameter_name(index)) {
+                to.set_parameter(target, from.get_parameter(index));
+            }
+        }
+    }
+}
+
+/// A plugin together with the buffers and resamplers it processes through
+pub struct Instance {
+    pub plugin: Box<dyn HostedPlugin>,
+    pub info: Info,
+    lifecycle: Lifecycle,
+    /// The plugin's output at the host sample rate
+    pub outputs: Vec<Vec<f32>>,
+    block_inputs: Vec<Vec<f32>>,
+    block_outputs: Vec<Vec<f32>>,
+    upsamplers: Vec<Upsampler>,
+    downsamplers: Vec<Downsampler>,
+    /// SysEx messages and MIDI sent ahead of the events of the next call
+    sysex: Vec<Vec<u8>>,
+    queued: Vec<MidiEvent>,
+    /// Whether output buffers are zeroed before every call, so output a plugin leaves unwritten
+    /// is silence rather than whatever the previous call left there
+    zero_outputs: bool,
+}
+
+impl Instance {
+    /// Wraps an initialised plugin processing buffers of `length` samples in sub-blocks of
+    /// `block_size`, oversampled by `factor`.
+    ///
+    /// The plugin's sample rate and block size have to be set already, as it's resumed and
+    /// started here.
+    pub fn new(
+        plugin: impl HostedPlugin + 'static,
+        length: usize,
+        block_size: usize,
+        factor: usize,
+    ) -> Self {
+        let mut plugin: Box<dyn HostedPlugin> = Box::new(plugin);
+        let mut lifecycle = Lifecycle::new(State::Suspended);
+        lifecycle.set(&mut *plugin, State::Processing);
+
+        let info = plugin.info();
+        let inputs = info.inputs as usize;
+        let outputs = info.outputs as usize;
+
+        Self {
+            plugin,
+            lifecycle,
+            outputs: vec![vec![0.; length]; outputs],
+            block_inputs: vec![vec![0.; block_size * factor]; inputs],
+            block_outputs: vec![vec![0.; block_size * factor]; outputs],
+            upsamplers: (0..inputs).map(|_| Upsampler::new(factor)).collect(),
+            downsamplers: (0..outputs).map(|_| Downsampler::new(factor)).collect(),
+            sysex: Vec::new(),
+            queued: Vec::new(),
+            zero_outputs: false,
+            info,
+        }
+    }
+
+    /// Sends `message` to the plugin before the events of the next call to `process`
+    pub fn send_sysex(&mut self, message: Vec<u8>) {
+        self.sysex.push(message);
+    }
+
+    /// Sends `event` to the plugin before the events of the next call to `process`
+    pub fn send_midi(&mut self, event: MidiEvent) {
+        self.queued.push(event);
+    }
+
+    pub fn set_zero_outputs(&mut self, zero_outputs: bool) {
+        self.zero_outputs = zero_outputs;
+    }
+
+    /// Suspends and resumes the plugin at `sample_rate`, which clears tails and other state kept
+    /// between blocks in most plugins
+    pub fn reset(&mut self, sample_rate: f32) {
+        self.lifecycle.set(&mut *self.plugin, State::Suspended);
+        self.plugin.set_sample_rate(sample_rate);
+        self.lifecycle.set(&mut *self.plugin, State::Processing);
+    }
+
+    /// Processes `range` of `inputs` into the same range of `outputs`, sending `events` first.
+    ///
+    /// If the plugin has more inputs than there are channels in `inputs`, the channels are
+    /// repeated.
+    pub fn process(&mut self, inputs: &[Vec<f32>], events: &[MidiEvent], range: Range<usize>) {
+        let events = if self.queued.is_empty() {
+            events
+        } else {
+            self.queued.extend_from_slice(events);
+            &self.queued
+        };
+        if !events.is_empty() || !self.sysex.is_empty() {
+            self.plugin.process_events(events, &self.sysex);
+        }
+
+        for (channel, (block, upsampler)) in self
+            .block_inputs
+            .iter_mut()
+            .zip(&mut self.upsamplers)
+            .enumerate()
+        {
+            match inputs.get(channel % inputs.len().max(1)) {
+                Some(input) => upsampler.process(&input[range.clone()], block),
+                None => block.fill(0.),
+            }
+        }
+
+        if self.zero_outputs {
+            for block in &mut self.block_outputs {
+                block.fill(0.);
+            }
+        }
+
+        self.plugin
+            .process(&self.block_inputs, &mut self.block_outputs);
+        // The plugin only copies SysEx out of the events while processing the block
+        self.sysex.clear();
+        self.queued.clear();
+
+        for ((output, block), downsampler) in self
+            .outputs
+            .iter_mut()
+            .zip(&self.block_outputs)
+            .zip(&mut self.downsamplers)
+        {
+            downsampler.process(block, &mut output[range.clone()]);
+        }
+    }
+}
+
+impl Drop for Instance {
+    /// Stops and suspends the plugin before it's shut down
+    fn drop(&mut self) {
+        self.lifecycle.set(&mut *self.plugin, State::Suspended);
+    }
+}
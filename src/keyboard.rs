@@ -0,0 +1,149 @@
+use std::{
+    io::{self, Write},
+    mem::MaybeUninit,
+    sync::mpsc::Sender,
+    time::{Duration, Instant},
+};
+
+use anyhow::{bail, Result};
+
+/// Keys playing notes, by semitone from the base note, laid out like a piano keyboard
+const KEYS: &[u8] = b"awsedftgyhujkolp;'";
+
+/// How long a note sounds without the key repeating. Terminals only report key presses, so
+/// releases are guessed from auto-repeat stopping.
+const FIRST_REPEAT: Duration = Duration::from_millis(600);
+const REPEAT: Duration = Duration::from_millis(120);
+
+const HELP: &str = "Play notes on a s d f g h j k l ; ' (sharps on w e t y u o p), \
+z/x change octave, c/v change velocity, Escape or q to quit";
+
+/// Puts the terminal into raw mode, restoring it when dropped
+pub(crate) struct RawTerminal {
+    original: libc::termios,
+}
+
+impl RawTerminal {
+    pub(crate) fn enable() -> Result<Self> {
+        unsafe {
+            if libc::isatty(0) == 0 {
+                bail!("standard input is not a terminal");
+            }
+
+            let mut original = MaybeUninit::uninit();
+            if libc::tcgetattr(0, original.as_mut_ptr()) != 0 {
+                return Err(io::Error::last_os_error().into());
+            }
+            let original = original.assume_init();
+
+            let mut raw = original;
+            // Ctrl-C arrives as a key, so the terminal is restored when quitting with it
+            raw.c_lflag &= !(libc::ICANON | libc::ECHO | libc::ISIG);
+            if libc::tcsetattr(0, libc::TCSANOW, &raw) != 0 {
+                return Err(io::Error::last_os_error().into());
+            }
+
+            Ok(Self { original })
+        }
+    }
+}
+
+impl Drop for RawTerminal {
+    fn drop(&mut self) {
+        unsafe { libc::tcsetattr(0, libc::TCSANOW, &self.original) };
+    }
+}
+
+/// Plays notes from key presses on the terminal until the user quits.
+///
+/// Notes are sent as MIDI messages to `midi`, and status messages are written to `out`.
+pub fn run(midi: Sender<[u8; 3]>, mut out: impl Write) -> Result<()> {
+    let _terminal = RawTerminal::enable()?;
+    writeln!(out, "{HELP}")?;
+    out.flush()?;
+
+    let mut base = 60u8;
+    let mut velocity = 100u8;
+    // Sounding notes and when they're released unless their key repeats
+    let mut sounding: Vec<(u8, Instant)> = Vec::new();
+
+    loop {
+        let now = Instant::now();
+        sounding.retain(|&(note, release)| {
+            let keep = release > now;
+            if !keep {
+                let _ = midi.send([0x80, note, 0]);
+            }
+            keep
+        });
+
+        let timeout = match sounding.iter().map(|&(_, release)| release).min() {
+            Some(release) => (release - now).as_millis() as i32 + 1,
+            None => -1,
+        };
+        let mut poll = libc::pollfd {
+            fd: 0,
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        if unsafe { libc::poll(&mut poll, 1, timeout) } <= 0 {
+            continue;
+        }
+
+        let mut buffer = [0u8; 16];
+        let read = unsafe { libc::read(0, buffer.as_mut_ptr().cast(), buffer.len()) };
+        if read <= 0 {
+            break;
+        }
+        let input = &buffer[..read as usize];
+
+        // Escape sequences from arrow and function keys
+        if input.len() > 1 && input[0] == 0x1b {
+            continue;
+        }
+
+        for &key in input {
+            match key {
+                0x1b | b'q' | 0x03 | 0x04 => {
+                    for (note, _) in sounding.drain(..) {
+                        let _ = midi.send([0x80, note, 0]);
+                    }
+                    return Ok(());
+                }
+                b'z' | b'x' => {
+                    base = match key {
+                        b'z' => base.saturating_sub(12).max(12),
+                        _ => (base + 12).min(108),
+                    };
+                    writeln!(out, "Octave {}", base as i32 / 12 - 1)?;
+                }
+                b'c' | b'v' => {
+                    velocity = match key {
+                        b'c' => velocity.saturating_sub(10).max(1),
+                        _ => (velocity + 10).min(127),
+                    };
+                    writeln!(out, "Velocity {velocity}")?;
+                }
+                _ => {
+                    let offset = match KEYS.iter().position(|&k| k == key) {
+                        Some(offset) => offset as u8,
+                        None => continue,
+                    };
+                    let note = (base + offset).min(127);
+
+                    match sounding.iter_mut().find(|(n, _)| *n == note) {
+                        // Auto-repeat, the key is still down
+                        Some((_, release)) => *release = Instant::now() + REPEAT,
+                        None => {
+                            let _ = midi.send([0x90, note, velocity]);
+                            sounding.push((note, Instant::now() + FIRST_REPEAT));
+                        }
+                    }
+                }
+            }
+        }
+        out.flush()?;
+    }
+
+    Ok(())
+}
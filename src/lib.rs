@@ -0,0 +1,62 @@
+pub mod activity;
+pub mod arpeggiator;
+pub mod blind;
+pub mod budget;
+pub mod bus;
+#[cfg(unix)]
+pub mod capture;
+pub mod chord;
+pub mod config;
+#[cfg(unix)]
+pub mod control;
+#[cfg(unix)]
+pub mod crash;
+pub mod diagnose;
+pub mod dsp;
+pub mod editor;
+pub mod effect;
+pub mod envelope;
+#[cfg(unix)]
+pub mod generic;
+pub mod hosted;
+pub mod instance;
+pub mod json;
+#[cfg(unix)]
+pub mod keyboard;
+pub mod library;
+pub mod lifecycle;
+pub mod limiter;
+pub mod logging;
+pub mod mapping;
+pub mod mcu;
+pub mod memory;
+pub mod meter;
+pub mod metronome;
+pub mod midi;
+pub mod mpe;
+pub mod osc;
+pub mod output;
+pub mod oversample;
+pub mod pool;
+pub mod preset;
+pub mod profile;
+pub mod properties;
+pub mod quirks;
+pub mod render;
+pub mod route;
+#[cfg(all(target_os = "linux", target_pointer_width = "64"))]
+pub mod rt_check;
+pub mod rtp;
+pub mod scene;
+pub mod search;
+pub mod session;
+pub mod smf;
+pub mod stereo;
+pub mod sustain;
+pub mod take;
+pub mod timeout;
+pub mod trace;
+pub mod transport;
+pub mod tuning;
+pub mod velocity;
+pub mod watchdog;
@@ -0,0 +1,257 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Context, Result};
+
+use crate::{
+    preset::Preset,
+    profile::{cache_dir, config_dir},
+    search::file_name,
+};
+
+/// How deep preset directories are searched, enough for vendor, plugin and category subdirectories
+const MAX_DEPTH: usize = 4;
+
+/// The tag marking favourite presets
+pub const FAVORITE: &str = "favorite";
+
+/// Directories indexed when none are given, `$XDG_CONFIG_HOME/y/presets` by default
+pub fn default_directory() -> Option<PathBuf> {
+    Some(config_dir()?.join("presets"))
+}
+
+/// Every FXP and FXB file in `directories` and their subdirectories
+pub fn preset_files(directories: &[PathBuf]) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    for directory in directories {
+        collect(directory, MAX_DEPTH, &mut files);
+    }
+    files.sort();
+    files.dedup();
+    files
+}
+
+fn collect(directory: &Path, depth: usize, files: &mut Vec<PathBuf>) {
+    let entries = match fs::read_dir(directory) {
+        Ok(entries) => entries,
+        Err(_) => return,
+    };
+
+    for path in entries.flatten().map(|entry| entry.path()) {
+        if is_preset(&path) {
+            files.push(path);
+        } else if path.is_dir() && depth > 0 {
+            collect(&path, depth - 1, files);
+        }
+    }
+}
+
+/// Whether `path` is named like an FXP or FXB file
+pub fn is_preset(path: &Path) -> bool {
+    path.extension().is_some_and(|extension| {
+        extension.eq_ignore_ascii_case("fxp") || extension.eq_ignore_ascii_case("fxb")
+    })
+}
+
+/// A preset in the library
+#[derive(Clone, Debug)]
+pub struct Entry {
+    pub path: PathBuf,
+    /// ID of the plugin the preset is for
+    pub unique_id: i32,
+    /// The program's name, or the file's for banks and unnamed programs
+    pub name: String,
+    pub tags: BTreeSet<String>,
+}
+
+impl Entry {
+    /// Reads the plugin ID and name of the preset at `path`
+    pub fn read(path: &Path) -> Result<Self> {
+        let preset = Preset::read(path)?;
+        let name = match &preset {
+            Preset::Program { program, .. } if !program.name.trim().is_empty() => {
+                program.name.trim().to_owned()
+            }
+            _ => file_name(path),
+        };
+
+        Ok(Self {
+            path: path.to_owned(),
+            unique_id: preset.plugin_id(),
+            name,
+            tags: BTreeSet::new(),
+        })
+    }
+
+    pub fn is_favorite(&self) -> bool {
+        self.tags.contains(FAVORITE)
+    }
+
+    /// Whether every word of `query` appears in the preset's name, file name or tags, ignoring
+    /// case
+    pub fn matches(&self, query: &str) -> bool {
+        let haystack = format!(
+            "{} {} {}",
+            self.name,
+            file_name(&self.path),
+            self.tags.iter().cloned().collect::<Vec<_>>().join(" ")
+        )
+        .to_lowercase();
+        query
+            .split_whitespace()
+            .all(|word| haystack.contains(&word.to_lowercase()))
+    }
+}
+
+/// Presets indexed by `y presets index`, with the tags given to them.
+///
+/// The index is kept in the cache directory and can be rebuilt at any time, while tags are kept in
+/// the configuration directory so that reindexing doesn't lose them.
+#[derive(Debug, Default)]
+pub struct Library {
+    pub entries: Vec<Entry>,
+}
+
+impl Library {
+    pub fn load() -> Result<Self> {
+        let path = index_path().context("can't find the cache directory")?;
+        let text = fs::read_to_string(&path).with_context(|| {
+            format!(
+                "failed to read the preset index {}, run presets index to create it",
+                path.display()
+            )
+        })?;
+        let tags = Tags::load()?;
+
+        // One preset per line as `path<TAB>unique id<TAB>name`
+        let mut library = Library::default();
+        for (number, line) in text.lines().enumerate() {
+            let (preset, unique_id, name) = match line.split('\t').collect::<Vec<_>>().as_slice() {
+                [preset, unique_id, name] => (PathBuf::from(preset), *unique_id, *name),
+                _ => bail!("{}:{}: expected three fields", path.display(), number + 1),
+            };
+            library.entries.push(Entry {
+                tags: tags.of(&preset).clone(),
+                unique_id: unique_id
+                    .parse()
+                    .with_context(|| format!("{}:{}", path.display(), number + 1))?,
+                name: name.to_owned(),
+                path: preset,
+            });
+        }
+
+        Ok(library)
+    }
+
+    /// Writes the index, leaving tags alone
+    pub fn save(&self) -> Result<PathBuf> {
+        let path = index_path().context("can't find the cache directory")?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let mut text = String::new();
+        for entry in &self.entries {
+            text.push_str(&format!(
+                "{}\t{}\t{}\n",
+                entry.path.display(),
+                entry.unique_id,
+                entry.name.replace(['\t', '\n'], " ")
+            ));
+        }
+        fs::write(&path, text).with_context(|| format!("failed to write {}", path.display()))?;
+
+        Ok(path)
+    }
+
+    /// The presets for the plugin with `unique_id`, only those tagged `tag` if given, ordered by
+    /// name. This is the order presets are stepped through in.
+    pub fn for_plugin(&self, unique_id: i32, tag: Option<&str>) -> Vec<&Entry> {
+        let mut entries: Vec<&Entry> = self
+            .entries
+            .iter()
+            .filter(|entry| entry.unique_id == unique_id)
+            .filter(|entry| tag.is_none_or(|tag| entry.tags.contains(tag)))
+            .collect();
+        entries.sort_by(|a, b| {
+            (a.name.to_lowercase(), &a.path).cmp(&(b.name.to_lowercase(), &b.path))
+        });
+        entries
+    }
+}
+
+/// Tags given to presets, by path
+#[derive(Debug, Default)]
+pub struct Tags(BTreeMap<PathBuf, BTreeSet<String>>);
+
+impl Tags {
+    pub fn load() -> Result<Self> {
+        let path = match tags_path().filter(|path| path.exists()) {
+            Some(path) => path,
+            None => return Ok(Self::default()),
+        };
+        let text = fs::read_to_string(&path)
+            .with_context(|| format!("failed to read {}", path.display()))?;
+
+        // One preset per line as `path<TAB>tag tag ...`
+        let mut tags = Self::default();
+        for line in text.lines() {
+            if let Some((preset, preset_tags)) = line.split_once('\t') {
+                tags.0.insert(
+                    PathBuf::from(preset),
+                    preset_tags.split_whitespace().map(str::to_owned).collect(),
+                );
+            }
+        }
+
+        Ok(tags)
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let path = tags_path().context("no configuration directory, HOME isn't set")?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let text: String = self
+            .0
+            .iter()
+            .filter(|(_, tags)| !tags.is_empty())
+            .map(|(preset, tags)| {
+                let tags: Vec<&str> = tags.iter().map(String::as_str).collect();
+                format!("{}\t{}\n", preset.display(), tags.join(" "))
+            })
+            .collect();
+        fs::write(&path, text).with_context(|| format!("failed to write {}", path.display()))
+    }
+
+    pub fn of(&self, preset: &Path) -> &BTreeSet<String> {
+        static NONE: BTreeSet<String> = BTreeSet::new();
+        self.0.get(preset).unwrap_or(&NONE)
+    }
+
+    pub fn add(&mut self, preset: &Path, tag: &str) {
+        self.0
+            .entry(preset.to_owned())
+            .or_default()
+            .insert(tag.to_owned());
+    }
+
+    /// Returns whether the preset had the tag
+    pub fn remove(&mut self, preset: &Path, tag: &str) -> bool {
+        self.0.get_mut(preset).is_some_and(|tags| tags.remove(tag))
+    }
+}
+
+/// Where the preset index is kept, `$XDG_CACHE_HOME/y/presets.txt` by default
+pub fn index_path() -> Option<PathBuf> {
+    Some(cache_dir()?.join("presets.txt"))
+}
+
+/// Where the tags of presets are kept, `$XDG_CONFIG_HOME/y/preset-tags.txt` by default
+pub fn tags_path() -> Option<PathBuf> {
+    Some(config_dir()?.join("preset-tags.txt"))
+}
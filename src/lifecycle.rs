@@ -0,0 +1,126 @@
+use crate::hosted::HostedPlugin;
+
+/// Where a plugin is in the VST 2 lifecycle, in the order it moves through them
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum State {
+    /// Instantiated, but not initialised yet
+    Created,
+    /// Initialised, but switched off. Sample rate and block size may only change in this state.
+    Suspended,
+    /// Switched on with `effMainsChanged`
+    Resumed,
+    /// Between `effStartProcess` and `effStopProcess`, ready for `process()`
+    Processing,
+}
+
+/// A call moving a plugin from one state to a neighbouring one
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Step {
+    Initialise,
+    Resume,
+    StartProcess,
+    StopProcess,
+    Suspend,
+}
+
+/// The state closest to `to` reachable from `from`.
+///
+/// A plugin can't be uninitialised again, so going back to [`State::Created`] stops at
+/// [`State::Suspended`].
+fn reachable(from: State, to: State) -> State {
+    if from > State::Created {
+        to.max(State::Suspended)
+    } else {
+        to
+    }
+}
+
+/// The calls needed to get from `from` as close to `to` as possible
+pub fn path(from: State, to: State) -> Vec<Step> {
+    let to = reachable(from, to);
+
+    let mut steps = Vec::new();
+    let mut state = from;
+    while state < to {
+        let (step, next) = match state {
+            State::Created => (Step::Initialise, State::Suspended),
+            State::Suspended => (Step::Resume, State::Resumed),
+            State::Resumed => (Step::StartProcess, State::Processing),
+            State::Processing => unreachable!(),
+        };
+        steps.push(step);
+        state = next;
+    }
+    while state > to {
+        let (step, next) = match state {
+            State::Processing => (Step::StopProcess, State::Resumed),
+            State::Resumed => (Step::Suspend, State::Suspended),
+            State::Suspended | State::Created => unreachable!(),
+        };
+        steps.push(step);
+        state = next;
+    }
+
+    steps
+}
+
+/// Tracks a plugin's lifecycle state, so it's always moved through it in the right order
+#[derive(Debug)]
+pub struct Lifecycle {
+    state: State,
+}
+
+impl Lifecycle {
+    /// Starts tracking a plugin that is in `state`
+    pub fn new(state: State) -> Self {
+        Self { state }
+    }
+
+    pub fn state(&self) -> State {
+        self.state
+    }
+
+    /// Moves `plugin` to `target`, making every call on the way
+    pub fn set(&mut self, plugin: &mut dyn HostedPlugin, target: State) {
+        for step in path(self.state, target) {
+            log::trace!("{step:?}");
+            plugin.perform(step);
+        }
+        self.state = reachable(self.state, target);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{path, State, Step};
+
+    #[test]
+    fn starts_up_in_order() {
+        assert_eq!(
+            path(State::Created, State::Processing),
+            [Step::Initialise, Step::Resume, Step::StartProcess]
+        );
+    }
+
+    #[test]
+    fn shuts_down_in_order() {
+        assert_eq!(
+            path(State::Processing, State::Suspended),
+            [Step::StopProcess, Step::Suspend]
+        );
+    }
+
+    #[test]
+    fn staying_put_takes_no_steps() {
+        assert!(path(State::Resumed, State::Resumed).is_empty());
+    }
+
+    #[test]
+    fn never_uninitialises() {
+        assert_eq!(
+            path(State::Processing, State::Created),
+            [Step::StopProcess, Step::Suspend]
+        );
+        assert!(path(State::Suspended, State::Created).is_empty());
+    }
+}
@@ -0,0 +1,152 @@
+use clap::ArgEnum;
+
+/// How the host protects its outputs from whatever the plugin produces
+#[derive(ArgEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Protection {
+    /// Brickwall limiter: instant attack, smooth release
+    Limit,
+    /// Hard clip at the ceiling
+    Clip,
+    /// Pass the plugin's output through untouched
+    Off,
+}
+
+/// Converts a level in dBFS to a linear gain
+pub fn db_to_gain(db: f32) -> f32 {
+    10f32.powf(db / 20.)
+}
+
+/// Output protection applied after the plugin.
+///
+/// The gain is linked across channels so that limiting doesn't shift the stereo image. Non-finite
+/// samples (NaN, infinity) are always replaced with silence unless the protection is turned off.
+pub struct Limiter {
+    protection: Protection,
+    ceiling: f32,
+    gain: f32,
+    release: f32,
+}
+
+impl Limiter {
+    /// Release time of the limiter, in seconds
+    const RELEASE_TIME: f32 = 0.05;
+
+    pub fn new(protection: Protection, ceiling_db: f32, sample_rate: f32) -> Self {
+        Self {
+            protection,
+            ceiling: db_to_gain(ceiling_db),
+            gain: 1.,
+            release: 1. - (-1. / (Self::RELEASE_TIME * sample_rate)).exp(),
+        }
+    }
+
+    /// Processes `length` frames of `outputs` in place
+    pub fn process(&mut self, outputs: &mut [Vec<f32>], length: usize) {
+        if self.protection == Protection::Off {
+            return;
+        }
+
+        for i in 0..length {
+            let mut peak = 0f32;
+            for channel in outputs.iter_mut() {
+                let sample = &mut channel[i];
+                if !sample.is_finite() {
+                    *sample = 0.;
+                }
+                peak = peak.max(sample.abs());
+            }
+
+            match self.protection {
+                Protection::Limit => {
+                    let target = if peak > self.ceiling {
+                        self.ceiling / peak
+                    } else {
+                        1.
+                    };
+
+                    if target < self.gain {
+                        self.gain = target;
+                    } else {
+                        self.gain += (target - self.gain) * self.release;
+                    }
+
+                    for channel in outputs.iter_mut() {
+                        channel[i] *= self.gain;
+                    }
+                }
+                Protection::Clip => {
+                    for channel in outputs.iter_mut() {
+                        channel[i] = channel[i].clamp(-self.ceiling, self.ceiling);
+                    }
+                }
+                Protection::Off => unreachable!(),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{db_to_gain, Limiter, Protection};
+
+    fn peak(outputs: &[Vec<f32>]) -> f32 {
+        outputs
+            .iter()
+            .flatten()
+            .fold(0f32, |peak, sample| peak.max(sample.abs()))
+    }
+
+    #[test]
+    fn limiting_keeps_the_output_under_the_ceiling() {
+        let mut limiter = Limiter::new(Protection::Limit, -6., 44_100.);
+        let mut outputs = vec![
+            (0..4096).map(|i| (i as f32 * 0.05).sin() * 4.).collect(),
+            vec![0.25; 4096],
+        ];
+        limiter.process(&mut outputs, 4096);
+        assert!(peak(&outputs) <= db_to_gain(-6.));
+    }
+
+    #[test]
+    fn limiting_turns_every_channel_down_together() {
+        let mut limiter = Limiter::new(Protection::Limit, 0., 44_100.);
+        let mut outputs = vec![vec![2.], vec![0.5]];
+        limiter.process(&mut outputs, 1);
+        assert_eq!(outputs, [[1.], [0.25]]);
+    }
+
+    #[test]
+    fn quiet_output_passes_the_limiter_untouched() {
+        let mut limiter = Limiter::new(Protection::Limit, -1., 44_100.);
+        let mut outputs = vec![vec![0.5, -0.25, 0.125]];
+        limiter.process(&mut outputs, 3);
+        assert_eq!(outputs, [[0.5, -0.25, 0.125]]);
+    }
+
+    #[test]
+    fn clipping_stops_at_the_ceiling() {
+        let mut limiter = Limiter::new(Protection::Clip, -6., 44_100.);
+        let ceiling = db_to_gain(-6.);
+        let mut outputs = vec![vec![2., -2., 0.25]];
+        limiter.process(&mut outputs, 3);
+        assert_eq!(outputs, [[ceiling, -ceiling, 0.25]]);
+    }
+
+    #[test]
+    fn non_finite_samples_become_silence() {
+        for protection in [Protection::Limit, Protection::Clip] {
+            let mut limiter = Limiter::new(protection, -1., 44_100.);
+            let mut outputs = vec![vec![f32::NAN, f32::INFINITY, f32::NEG_INFINITY]];
+            limiter.process(&mut outputs, 3);
+            assert_eq!(outputs, [[0., 0., 0.]]);
+        }
+    }
+
+    #[test]
+    fn turning_protection_off_leaves_the_output_alone() {
+        let mut limiter = Limiter::new(Protection::Off, -6., 44_100.);
+        let mut outputs = vec![vec![2., f32::INFINITY]];
+        limiter.process(&mut outputs, 2);
+        assert_eq!(outputs, [[2., f32::INFINITY]]);
+    }
+}
@@ -0,0 +1,68 @@
+use std::{
+    io::Write,
+    sync::{Arc, Mutex},
+};
+
+use log::{LevelFilter, Log, Metadata, Record};
+
+use crate::trace::TraceFile;
+
+/// A minimal logger writing `[LEVEL target] message` lines
+struct Logger {
+    level: LevelFilter,
+    out: Mutex<Box<dyn Write + Send>>,
+    /// Receives everything the `vst` crate logs, which includes the host callbacks it handles
+    /// without involving our `Host` implementation
+    trace: Option<Arc<TraceFile>>,
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.level
+    }
+
+    fn log(&self, record: &Record) {
+        if let Some(trace) = &self.trace {
+            if record.target().starts_with("vst") {
+                trace.line(*record.args());
+            }
+        }
+
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+
+        let mut out = self.out.lock().unwrap();
+        let _ = writeln!(
+            out,
+            "[{} {}] {}",
+            record.level(),
+            record.target(),
+            record.args()
+        );
+    }
+
+    fn flush(&self) {
+        let _ = self.out.lock().unwrap().flush();
+    }
+}
+
+/// Installs the global logger, writing messages up to `level` to `out` and, if given, everything
+/// logged by the `vst` crate to `trace`
+pub fn init(level: LevelFilter, out: Box<dyn Write + Send>, trace: Option<Arc<TraceFile>>) {
+    let max_level = if trace.is_some() {
+        LevelFilter::Trace
+    } else {
+        level
+    };
+
+    let logger = Logger {
+        level,
+        out: Mutex::new(out),
+        trace,
+    };
+
+    if log::set_boxed_logger(Box::new(logger)).is_ok() {
+        log::set_max_level(max_level);
+    }
+}
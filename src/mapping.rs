@@ -0,0 +1,453 @@
+use std::str::FromStr;
+
+use anyhow::{anyhow, ensure, Context, Result};
+use vst::{event::MidiEvent, plugin::PluginParameters};
+
+use crate::mpe::Zones;
+
+/// How a control's position maps onto a parameter
+#[derive(Clone, Debug, PartialEq)]
+pub enum Curve {
+    Linear,
+    /// Rises quickly at first, for frequency-like parameters
+    Log,
+    /// Rises slowly at first, for gain-like parameters
+    Exp,
+    /// Linear interpolation between evenly spaced points
+    Table(Vec<f32>),
+}
+
+impl Curve {
+    /// Steepness of the logarithmic and exponential curves
+    const STEEPNESS: f32 = 100.;
+
+    /// Maps `x` from 0 to 1 onto the curve, which also goes from 0 to 1 for the built-in curves
+    pub fn apply(&self, x: f32) -> f32 {
+        let x = x.clamp(0., 1.);
+        match self {
+            Curve::Linear => x,
+            Curve::Log => (Self::STEEPNESS * x).ln_1p() / Self::STEEPNESS.ln_1p(),
+            Curve::Exp => (Self::STEEPNESS.ln_1p() * x).exp_m1() / Self::STEEPNESS,
+            Curve::Table(points) => {
+                let position = x * (points.len() - 1) as f32;
+                let index = (position as usize).min(points.len() - 2);
+                let fraction = position - index as f32;
+                points[index] + (points[index + 1] - points[index]) * fraction
+            }
+        }
+    }
+
+    /// The position the curve maps onto `y`, undoing [`Curve::apply`], or `None` if it never
+    /// reaches `y`. Tables going back and forth give the first position.
+    pub fn position(&self, y: f32) -> Option<f32> {
+        if !matches!(self, Curve::Table(_)) && !(0. ..=1.).contains(&y) {
+            return None;
+        }
+        match self {
+            Curve::Linear => Some(y),
+            Curve::Log => Some((Self::STEEPNESS.ln_1p() * y).exp_m1() / Self::STEEPNESS),
+            Curve::Exp => Some((Self::STEEPNESS * y).ln_1p() / Self::STEEPNESS.ln_1p()),
+            Curve::Table(points) => points.windows(2).enumerate().find_map(|(index, pair)| {
+                let (low, high) = (pair[0].min(pair[1]), pair[0].max(pair[1]));
+                if !(low..=high).contains(&y) {
+                    return None;
+                }
+                let fraction = if pair[0] == pair[1] {
+                    0.
+                } else {
+                    (y - pair[0]) / (pair[1] - pair[0])
+                };
+                Some((index as f32 + fraction) / (points.len() - 1) as f32)
+            }),
+        }
+    }
+}
+
+impl FromStr for Curve {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "linear" => Ok(Curve::Linear),
+            "log" => Ok(Curve::Log),
+            "exp" => Ok(Curve::Exp),
+            _ => {
+                let points = s
+                    .strip_prefix("table=")
+                    .ok_or_else(|| anyhow!("expected linear, log, exp or table=<points>"))?;
+                let points = points
+                    .split(',')
+                    .map(|point| point.trim().parse())
+                    .collect::<Result<Vec<f32>, _>>()?;
+                ensure!(points.len() >= 2, "a table needs at least two points");
+                Ok(Curve::Table(points))
+            }
+        }
+    }
+}
+
+/// A curve, optionally running backwards
+#[derive(Clone, Debug, PartialEq)]
+pub struct Transfer {
+    pub curve: Curve,
+    pub invert: bool,
+}
+
+impl Transfer {
+    pub fn apply(&self, x: f32) -> f32 {
+        let x = if self.invert { 1. - x } else { x };
+        self.curve.apply(x)
+    }
+
+    /// The position that gives `y`, undoing [`Transfer::apply`]
+    pub fn position(&self, y: f32) -> Option<f32> {
+        let x = self.curve.position(y)?;
+        Some(if self.invert { 1. - x } else { x })
+    }
+
+    /// Parses the optional `curve` and `invert` fields following a mapping
+    fn parse<'a>(fields: impl Iterator<Item = &'a str>) -> Result<Self> {
+        let mut transfer = Transfer {
+            curve: Curve::Linear,
+            invert: false,
+        };
+
+        for field in fields {
+            match field {
+                "invert" => transfer.invert = true,
+                curve => transfer.curve = curve.parse()?,
+            }
+        }
+
+        Ok(transfer)
+    }
+}
+
+/// One parameter controlled by a macro, written as `<parameter>:<min>:<max>[:<curve>][:invert]`
+#[derive(Clone, Debug, PartialEq)]
+pub struct MacroTarget {
+    pub parameter: i32,
+    /// The parameter's value with the macro at 0
+    pub min: f32,
+    /// The parameter's value with the macro at 1
+    pub max: f32,
+    pub transfer: Transfer,
+}
+
+impl FromStr for MacroTarget {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut fields = s.split(':');
+        let mut next = |what: &str| {
+            fields
+                .next()
+                .with_context(|| format!("expected <parameter>:<min>:<max>, missing {what}"))
+        };
+        let parameter = next("parameter")?.parse().context("invalid parameter")?;
+        let min = next("min")?.parse().context("invalid min")?;
+        let max = next("max")?.parse().context("invalid max")?;
+
+        Ok(Self {
+            parameter,
+            min,
+            max,
+            transfer: Transfer::parse(fields)?,
+        })
+    }
+}
+
+/// A single control moving several parameters, written as `<name>=<target>,<target>,...`
+#[derive(Clone, Debug, PartialEq)]
+pub struct Macro {
+    pub name: String,
+    pub targets: Vec<MacroTarget>,
+}
+
+impl Macro {
+    /// Sets every target for the macro at `value`, from 0 to 1
+    pub fn apply(&self, value: f32, parameters: &dyn PluginParameters) {
+        for target in &self.targets {
+            let x = target.transfer.apply(value);
+            let value = target.min + (target.max - target.min) * x;
+            parameters.set_parameter(target.parameter, value.clamp(0., 1.));
+        }
+    }
+}
+
+impl FromStr for Macro {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, targets) = s
+            .split_once('=')
+            .context("expected <name>=<target>,<target>,...")?;
+        ensure!(!name.is_empty(), "the macro needs a name");
+        let targets = targets
+            .split(',')
+            .map(|target| target.parse().with_context(|| format!("in {target:?}")))
+            .collect::<Result<_>>()?;
+
+        Ok(Self {
+            name: name.to_owned(),
+            targets,
+        })
+    }
+}
+
+/// What a controller mapping moves
+#[derive(Clone, Debug, PartialEq)]
+pub enum Target {
+    Parameter(i32),
+    /// A macro, by name
+    Macro(String),
+}
+
+/// Maps a MIDI controller onto a parameter or a macro, written as
+/// `<cc>:<parameter>[:<curve>][:invert]` or `<cc>:@<macro>[:<curve>][:invert]`
+#[derive(Clone, Debug, PartialEq)]
+pub struct CcMapping {
+    pub controller: u8,
+    pub target: Target,
+    pub transfer: Transfer,
+}
+
+impl FromStr for CcMapping {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut fields = s.split(':');
+        let controller = fields
+            .next()
+            .unwrap()
+            .parse()
+            .context("invalid controller")?;
+        ensure!(controller <= 127, "controllers go from 0 to 127");
+        let target = fields.next().context("expected <cc>:<parameter>")?;
+        let target = match target.strip_prefix('@') {
+            Some(name) => Target::Macro(name.to_owned()),
+            None => Target::Parameter(target.parse().context("invalid parameter")?),
+        };
+
+        Ok(Self {
+            controller,
+            target,
+            transfer: Transfer::parse(fields)?,
+        })
+    }
+}
+
+/// Turns control changes matching a mapping into parameter changes, removing them from `events`.
+///
+/// Controllers on the member channels of MPE `zones` are per-note expression, which is always
+/// passed on to the plugin.
+pub fn apply_cc(
+    mappings: &[CcMapping],
+    macros: &[Macro],
+    events: &mut Vec<MidiEvent>,
+    parameters: &dyn PluginParameters,
+    zones: Option<Zones>,
+) {
+    events.retain(|event| {
+        let [status, controller, value] = event.data;
+        if status & 0xf0 != 0xb0 || zones.is_some_and(|zones| zones.is_member(status & 0x0f)) {
+            return true;
+        }
+
+        let mut mapped = false;
+        for mapping in mappings.iter().filter(|m| m.controller == controller) {
+            let x = mapping.transfer.apply(value as f32 / 127.);
+            match &mapping.target {
+                Target::Parameter(parameter) => parameters.set_parameter(*parameter, x),
+                Target::Macro(name) => {
+                    if let Some(macro_) = macros.iter().find(|macro_| &macro_.name == name) {
+                        macro_.apply(x, parameters);
+                    }
+                }
+            }
+            mapped = true;
+        }
+        !mapped
+    });
+}
+
+/// The control changes on channel 1 that set `parameter` to `value`, from the first controller
+/// mapped onto it that can reach the value. Unmapped parameters get an NRPN numbered after them
+/// with a 14-bit value, and those past 16383 get nothing.
+pub fn reverse_cc(mappings: &[CcMapping], parameter: i32, value: f32) -> Vec<[u8; 3]> {
+    let position = mappings
+        .iter()
+        .filter(|mapping| mapping.target == Target::Parameter(parameter))
+        .find_map(|mapping| Some((mapping.controller, mapping.transfer.position(value)?)));
+    if let Some((controller, position)) = position {
+        return vec![[
+            0xb0,
+            controller,
+            (position.clamp(0., 1.) * 127.).round() as u8,
+        ]];
+    }
+
+    if !(0..0x4000).contains(&parameter) {
+        return Vec::new();
+    }
+    let value = (value.clamp(0., 1.) * 0x3fff as f32).round() as u16;
+    vec![
+        [0xb0, 99, (parameter >> 7) as u8],
+        [0xb0, 98, (parameter & 0x7f) as u8],
+        [0xb0, 6, (value >> 7) as u8],
+        [0xb0, 38, (value & 0x7f) as u8],
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use vst::{event::MidiEvent, plugin::PluginParameters};
+
+    use super::{apply_cc, reverse_cc, CcMapping, Curve, Macro, Target, Transfer};
+    use crate::mpe::Zones;
+
+    #[derive(Default)]
+    struct Recorder(Mutex<Vec<(i32, f32)>>);
+
+    impl PluginParameters for Recorder {
+        fn set_parameter(&self, index: i32, value: f32) {
+            self.0.lock().unwrap().push((index, value));
+        }
+    }
+
+    fn cc(channel: u8, controller: u8, value: u8) -> MidiEvent {
+        MidiEvent {
+            data: [0xb0 | channel, controller, value],
+            delta_frames: 0,
+            live: false,
+            note_length: None,
+            note_offset: None,
+            detune: 0,
+            note_off_velocity: 0,
+        }
+    }
+
+    #[test]
+    fn built_in_curves_go_from_0_to_1() {
+        for curve in [Curve::Linear, Curve::Log, Curve::Exp] {
+            assert!(curve.apply(0.).abs() < 1e-6, "{curve:?}");
+            assert!((curve.apply(1.) - 1.).abs() < 1e-6, "{curve:?}");
+            assert!(curve.apply(0.25) < curve.apply(0.75), "{curve:?}");
+        }
+        assert!(Curve::Log.apply(0.5) > 0.5);
+        assert!(Curve::Exp.apply(0.5) < 0.5);
+    }
+
+    #[test]
+    fn positions_undo_the_curves() {
+        for curve in [Curve::Linear, Curve::Log, Curve::Exp] {
+            for x in [0., 0.1, 0.5, 0.9, 1.] {
+                let position = curve.position(curve.apply(x)).unwrap();
+                assert!((position - x).abs() < 1e-4, "{curve:?} at {x}");
+            }
+        }
+    }
+
+    #[test]
+    fn tables_interpolate_between_their_points() {
+        let curve: Curve = "table=0,1,0".parse().unwrap();
+        assert_eq!(curve, Curve::Table(vec![0., 1., 0.]));
+        assert_eq!(curve.apply(0.25), 0.5);
+        assert_eq!(curve.apply(0.5), 1.);
+        assert_eq!(curve.apply(1.), 0.);
+        assert_eq!(curve.position(0.5), Some(0.25));
+    }
+
+    #[test]
+    fn positions_out_of_reach_are_none() {
+        assert_eq!(Curve::Linear.position(1.5), None);
+        assert_eq!(Curve::Log.position(-0.5), None);
+        assert_eq!(Curve::Table(vec![0.25, 0.75]).position(0.9), None);
+    }
+
+    #[test]
+    fn bad_curves_are_rejected() {
+        assert!("square".parse::<Curve>().is_err());
+        assert!("table=1".parse::<Curve>().is_err());
+        assert!("table=0,x".parse::<Curve>().is_err());
+    }
+
+    #[test]
+    fn inverted_transfers_run_backwards() {
+        let transfer = Transfer {
+            curve: Curve::Linear,
+            invert: true,
+        };
+        assert_eq!(transfer.apply(0.25), 0.75);
+        assert_eq!(transfer.position(0.75), Some(0.25));
+    }
+
+    #[test]
+    fn parses_cc_mappings() {
+        let mapping: CcMapping = "74:3:log:invert".parse().unwrap();
+        assert_eq!(mapping.controller, 74);
+        assert_eq!(mapping.target, Target::Parameter(3));
+        assert_eq!(mapping.transfer.curve, Curve::Log);
+        assert!(mapping.transfer.invert);
+
+        let mapping: CcMapping = "1:@filter".parse().unwrap();
+        assert_eq!(mapping.target, Target::Macro("filter".to_owned()));
+        assert_eq!(mapping.transfer.curve, Curve::Linear);
+
+        assert!("128:0".parse::<CcMapping>().is_err());
+        assert!("1".parse::<CcMapping>().is_err());
+    }
+
+    #[test]
+    fn mapped_controllers_move_parameters_and_macros() {
+        let mappings = ["7:2".parse().unwrap(), "1:@both".parse().unwrap()];
+        let macros = ["both=0:0:1,1:1:0".parse::<Macro>().unwrap()];
+        let parameters = Recorder::default();
+        let mut events = vec![cc(0, 7, 127), cc(0, 1, 0), cc(0, 10, 64)];
+        apply_cc(&mappings, &macros, &mut events, &parameters, None);
+
+        assert_eq!(events, [cc(0, 10, 64)]);
+        assert_eq!(*parameters.0.lock().unwrap(), [(2, 1.), (0, 0.), (1, 1.)]);
+    }
+
+    #[test]
+    fn mpe_member_channels_keep_their_controllers() {
+        let mappings = ["74:0".parse().unwrap()];
+        let parameters = Recorder::default();
+        let mut events = vec![cc(0, 74, 127), cc(1, 74, 127)];
+        let zones = Some(Zones {
+            lower: 15,
+            upper: 0,
+        });
+        apply_cc(&mappings, &[], &mut events, &parameters, zones);
+
+        assert_eq!(events, [cc(1, 74, 127)]);
+        assert_eq!(*parameters.0.lock().unwrap(), [(0, 1.)]);
+    }
+
+    #[test]
+    fn reverse_cc_uses_the_first_mapping_that_reaches_the_value() {
+        let mappings = [
+            "20:5:table=0.5,1".parse().unwrap(),
+            "21:5:invert".parse().unwrap(),
+        ];
+        assert_eq!(reverse_cc(&mappings, 5, 0.25), [[0xb0, 21, 95]]);
+        assert_eq!(reverse_cc(&mappings, 5, 0.75), [[0xb0, 20, 64]]);
+    }
+
+    #[test]
+    fn unmapped_parameters_get_an_nrpn() {
+        assert_eq!(
+            reverse_cc(&[], 130, 1.),
+            [
+                [0xb0, 99, 1],
+                [0xb0, 98, 2],
+                [0xb0, 6, 0x7f],
+                [0xb0, 38, 0x7f]
+            ]
+        );
+        assert!(reverse_cc(&[], 0x4000, 1.).is_empty());
+    }
+}
@@ -0,0 +1,281 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, Write},
+    path::Path,
+    sync::mpsc::{self, Receiver, RecvTimeoutError, Sender},
+    thread,
+    time::Duration,
+};
+
+use anyhow::{Context, Result};
+use vst::plugin::Info;
+
+use crate::{instance::Parameters, midi::read_messages};
+
+/// Channel strips on a Mackie Control, each with a fader, a V-Pot and a column of the display
+const STRIPS: usize = 8;
+/// Characters per strip on each line of the display
+const STRIP_WIDTH: usize = 7;
+/// How often parameters changed from elsewhere, like the plugin's editor, are sent to the surface
+const REFRESH: Duration = Duration::from_millis(50);
+
+/// Notes sent by the bank and channel buttons, moving by eight parameters or one
+const BANK_LEFT: u8 = 0x2e;
+const BANK_RIGHT: u8 = 0x2f;
+const CHANNEL_LEFT: u8 = 0x30;
+const CHANNEL_RIGHT: u8 = 0x31;
+/// Note sent when the first fader is touched or released, followed by the other seven
+const FADER_TOUCH: u8 = 0x68;
+/// Controller sent by the first V-Pot when turned, and the one setting its LED ring
+const VPOT: u8 = 0x10;
+const VPOT_RING: u8 = 0x30;
+
+/// Fraction of a parameter's range changed by one step of a V-Pot
+const VPOT_STEP: f32 = 1. / 200.;
+
+enum Event {
+    Midi([u8; 3]),
+    Plugin(Parameters, Info),
+    /// Stop touching the plugin until the next one arrives, and say so on the sender
+    Detach(Sender<()>),
+}
+
+/// Controls a plugin's parameters from a Mackie Control surface, eight at a time.
+///
+/// The faders and V-Pots set the parameters shown on their strips, with names on the top line of
+/// the display and values on the bottom line. The bank and channel buttons move through the
+/// parameters.
+pub struct Surface {
+    events: Sender<Event>,
+}
+
+impl Surface {
+    /// Starts talking to the surface connected to the raw MIDI devices `input` and `output`,
+    /// which can be the same device
+    pub fn open(input: &Path, output: &Path, parameters: Parameters, info: Info) -> Result<Self> {
+        let input_file =
+            File::open(input).with_context(|| format!("failed to open {}", input.display()))?;
+        let output_file = OpenOptions::new()
+            .write(true)
+            .open(output)
+            .with_context(|| format!("failed to open {}", output.display()))?;
+
+        let (events, receiver) = mpsc::channel();
+        let midi = events.clone();
+        thread::spawn(move || {
+            read_messages(input_file, |message| {
+                midi.send(Event::Midi(message)).is_ok()
+            });
+            log::warn!("the control surface's input was closed");
+        });
+
+        let state = State {
+            output: output_file,
+            parameters,
+            info,
+            bank: 0,
+            shown: [None; STRIPS],
+            touched: [false; STRIPS],
+        };
+        thread::spawn(move || state.run(receiver));
+
+        Ok(Self { events })
+    }
+
+    /// Switches to controlling another plugin, e.g. after the current one was replaced
+    pub fn set_plugin(&self, parameters: Parameters, info: Info) {
+        let _ = self.events.send(Event::Plugin(parameters, info));
+    }
+
+    /// Stops controlling the plugin until `set_plugin` is called, e.g. before it's unloaded.
+    ///
+    /// Returns once the surface's thread has let go of the plugin.
+    pub fn detach(&self) {
+        let (done, detached) = mpsc::channel();
+        if self.events.send(Event::Detach(done)).is_ok() {
+            let _ = detached.recv_timeout(Duration::from_secs(1));
+        }
+    }
+}
+
+/// The surface's thread, applying its messages and keeping it up to date
+struct State {
+    output: File,
+    parameters: Parameters,
+    info: Info,
+    /// Index of the parameter on the first strip
+    bank: i32,
+    /// The value each strip last showed, so only changes are sent
+    shown: [Option<f32>; STRIPS],
+    /// Which faders are being touched, and so shouldn't be moved by the host
+    touched: [bool; STRIPS],
+}
+
+impl State {
+    fn run(mut self, events: Receiver<Event>) {
+        let mut result = self.show_bank();
+
+        while result.is_ok() {
+            result = match events.recv_timeout(REFRESH) {
+                Ok(Event::Midi(message)) => self.handle(message),
+                Ok(Event::Plugin(parameters, info)) => self.set_plugin(parameters, info),
+                Ok(Event::Detach(done)) => {
+                    let _ = done.send(());
+                    // Messages from the surface are dropped until there's a plugin again
+                    loop {
+                        match events.recv() {
+                            Ok(Event::Plugin(parameters, info)) => {
+                                break self.set_plugin(parameters, info)
+                            }
+                            Ok(Event::Midi(_) | Event::Detach(_)) => {}
+                            Err(_) => return,
+                        }
+                    }
+                }
+                Err(RecvTimeoutError::Timeout) => Ok(()),
+                Err(RecvTimeoutError::Disconnected) => return,
+            }
+            .and_then(|()| self.update());
+        }
+
+        if let Err(err) = result {
+            log::warn!("failed to write to the control surface: {err}");
+        }
+    }
+
+    fn set_plugin(&mut self, parameters: Parameters, info: Info) -> io::Result<()> {
+        self.parameters = parameters;
+        self.info = info;
+        self.bank = 0;
+        self.show_bank()
+    }
+
+    /// The parameter on `strip`, if there is one
+    fn parameter(&self, strip: usize) -> Option<i32> {
+        let index = self.bank + strip as i32;
+        (index < self.info.parameters).then_some(index)
+    }
+
+    fn handle(&mut self, [status, first, second]: [u8; 3]) -> io::Result<()> {
+        let strip = (status & 0x0f) as usize;
+        match status & 0xf0 {
+            // Faders send their position as 14-bit pitch bend, one channel per strip
+            0xe0 if strip < STRIPS => {
+                if let Some(index) = self.parameter(strip) {
+                    let value = (first as u16 | (second as u16) << 7) as f32 / 16383.;
+                    self.parameters.set_parameter(index, value);
+                }
+            }
+            0x90 if (FADER_TOUCH..FADER_TOUCH + STRIPS as u8).contains(&first) => {
+                let strip = (first - FADER_TOUCH) as usize;
+                self.touched[strip] = second > 0;
+                // Put the fader where the parameter ended up once it's let go
+                self.shown[strip] = None;
+            }
+            0x90 if second > 0 => {
+                let step = match first {
+                    BANK_LEFT => -(STRIPS as i32),
+                    BANK_RIGHT => STRIPS as i32,
+                    CHANNEL_LEFT => -1,
+                    CHANNEL_RIGHT => 1,
+                    _ => return Ok(()),
+                };
+                let last = (self.info.parameters - STRIPS as i32).max(0);
+                let bank = (self.bank + step).clamp(0, last);
+                if bank != self.bank {
+                    self.bank = bank;
+                    self.show_bank()?;
+                }
+            }
+            // V-Pots turn by a number of steps, with bit 6 set when turned to the left
+            0xb0 if (VPOT..VPOT + STRIPS as u8).contains(&first) => {
+                if let Some(index) = self.parameter((first - VPOT) as usize) {
+                    let steps = (second & 0x3f) as f32;
+                    let steps = if second & 0x40 != 0 { -steps } else { steps };
+                    let value = self.parameters.get_parameter(index) + steps * VPOT_STEP;
+                    self.parameters.set_parameter(index, value.clamp(0., 1.));
+                }
+            }
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    /// Writes the names of the parameters in the current bank and clears strips without one
+    fn show_bank(&mut self) -> io::Result<()> {
+        let mut names = String::new();
+        for strip in 0..STRIPS {
+            match self.parameter(strip) {
+                Some(index) => names.push_str(&fit(&self.parameters.get_parameter_name(index))),
+                None => {
+                    names.push_str(&fit(""));
+                    self.write_value(strip, 0.)?;
+                    self.write_text(STRIPS * STRIP_WIDTH + strip * STRIP_WIDTH, &fit(""))?;
+                }
+            }
+        }
+        self.shown = [None; STRIPS];
+
+        self.write_text(0, &names)
+    }
+
+    /// Sends the values of parameters that changed since they were last shown
+    fn update(&mut self) -> io::Result<()> {
+        for strip in 0..STRIPS {
+            let index = match self.parameter(strip) {
+                Some(index) => index,
+                None => continue,
+            };
+            let value = self.parameters.get_parameter(index);
+            if self.shown[strip] == Some(value) {
+                continue;
+            }
+            self.shown[strip] = Some(value);
+
+            if !self.touched[strip] {
+                self.write_value(strip, value)?;
+            }
+            let text = self.parameters.get_parameter_text(index)
+                + &self.parameters.get_parameter_label(index);
+            self.write_text(STRIPS * STRIP_WIDTH + strip * STRIP_WIDTH, &fit(&text))?;
+        }
+
+        Ok(())
+    }
+
+    /// Moves the fader and sets the V-Pot ring of `strip` to `value`
+    fn write_value(&mut self, strip: usize, value: f32) -> io::Result<()> {
+        let position = (value.clamp(0., 1.) * 16383.).round() as u16;
+        // The ring shows one of its 11 LEDs
+        let led = 1 + (value.clamp(0., 1.) * 10.).round() as u8;
+        self.output.write_all(&[
+            0xe0 | strip as u8,
+            (position & 0x7f) as u8,
+            (position >> 7) as u8,
+            0xb0,
+            VPOT_RING + strip as u8,
+            led,
+        ])
+    }
+
+    /// Writes `text` to the display starting at `offset`, where the bottom line starts at 56
+    fn write_text(&mut self, offset: usize, text: &str) -> io::Result<()> {
+        let mut message = vec![0xf0, 0x00, 0x00, 0x66, 0x14, 0x12, offset as u8];
+        message.extend(text.chars().map(|c| {
+            if c.is_ascii() && !c.is_ascii_control() {
+                c as u8
+            } else {
+                b'?'
+            }
+        }));
+        message.push(0xf7);
+        self.output.write_all(&message)
+    }
+}
+
+/// Shortens or pads `text` to fill a strip, leaving a space between strips
+fn fit(text: &str) -> String {
+    let text: String = text.trim().chars().take(STRIP_WIDTH - 1).collect();
+    format!("{text:<STRIP_WIDTH$}")
+}
@@ -0,0 +1,33 @@
+use std::fmt;
+
+/// Resident set size of this process in bytes, where the platform reports it
+pub fn resident() -> Option<u64> {
+    #[cfg(target_os = "linux")]
+    {
+        // Sizes in pages: total program size, then resident set size
+        let statm = std::fs::read_to_string("/proc/self/statm").ok()?;
+        let pages: u64 = statm.split_whitespace().nth(1)?.parse().ok()?;
+        let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) };
+        Some(pages * page_size.max(0) as u64)
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    None
+}
+
+/// A change in memory use, shown in MiB with its sign
+#[derive(Clone, Copy, Debug)]
+pub struct Growth(pub i64);
+
+impl Growth {
+    /// Growth from `before` to now, if the platform reports resident size
+    pub fn since(before: Option<u64>) -> Option<Self> {
+        Some(Self(resident()? as i64 - before? as i64))
+    }
+}
+
+impl fmt::Display for Growth {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:+.1} MiB", self.0 as f64 / (1 << 20) as f64)
+    }
+}
@@ -0,0 +1,205 @@
+use std::{
+    f64::consts::PI,
+    net::SocketAddr,
+    sync::mpsc::{self, Receiver, SyncSender},
+    thread,
+};
+
+use anyhow::Result;
+
+use crate::osc;
+
+/// Lowest level reported, standing in for silence
+const FLOOR: f32 = -120.;
+
+/// Length of the blocks loudness is measured in, in seconds
+const BLOCK_LENGTH: f64 = 0.1;
+/// Blocks making up the 400 ms window of momentary loudness
+const MOMENTARY_BLOCKS: usize = 4;
+
+/// Frames waiting to be sent before new ones are dropped
+const PENDING_FRAMES: usize = 4;
+
+/// Levels measured over one period of the meter, in dBFS and LUFS
+#[derive(Clone, Debug)]
+pub struct Frame {
+    /// Highest sample of each channel
+    pub peak: Vec<f32>,
+    pub rms: Vec<f32>,
+    /// Momentary loudness of all channels, over the last 400 ms
+    pub momentary: f32,
+}
+
+/// A second order IIR filter, in transposed direct form II
+#[derive(Clone, Copy)]
+struct Biquad {
+    b: [f64; 3],
+    a: [f64; 2],
+    z: [f64; 2],
+}
+
+impl Biquad {
+    fn process(&mut self, x: f64) -> f64 {
+        let y = self.b[0] * x + self.z[0];
+        self.z[0] = self.b[1] * x - self.a[0] * y + self.z[1];
+        self.z[1] = self.b[2] * x - self.a[1] * y;
+        y
+    }
+}
+
+/// The two stages of the K-weighting filter of ITU-R BS.1770 at `sample_rate`: a high shelf
+/// modelling the head, then a high-pass
+fn k_weighting(sample_rate: f64) -> [Biquad; 2] {
+    let shelf = {
+        let k = (PI * 1681.974450955533 / sample_rate).tan();
+        let q = 0.7071752369554196;
+        let vh = 10f64.powf(3.999843853973347 / 20.);
+        let vb = vh.powf(0.4996667741545416);
+        let a0 = 1. + k / q + k * k;
+        Biquad {
+            b: [
+                (vh + vb * k / q + k * k) / a0,
+                2. * (k * k - vh) / a0,
+                (vh - vb * k / q + k * k) / a0,
+            ],
+            a: [2. * (k * k - 1.) / a0, (1. - k / q + k * k) / a0],
+            z: [0.; 2],
+        }
+    };
+    let high_pass = {
+        let k = (PI * 38.13547087602444 / sample_rate).tan();
+        let q = 0.5003270373238773;
+        let a0 = 1. + k / q + k * k;
+        Biquad {
+            b: [1., -2., 1.],
+            a: [2. * (k * k - 1.) / a0, (1. - k / q + k * k) / a0],
+            z: [0.; 2],
+        }
+    };
+
+    [shelf, high_pass]
+}
+
+fn to_db(gain: f32) -> f32 {
+    (20. * gain.log10()).max(FLOOR)
+}
+
+/// Measures the output on the audio thread and hands over a `Frame` every period.
+///
+/// Frames are dropped rather than holding up the audio thread when whatever receives them falls
+/// behind.
+pub struct Meter {
+    weighting: Vec<[Biquad; 2]>,
+    /// Mean square of the K-weighted channels, summed, for the last blocks
+    blocks: [f64; MOMENTARY_BLOCKS],
+    next_block: usize,
+    block: f64,
+    block_position: usize,
+    block_length: usize,
+
+    peak: Vec<f32>,
+    squares: Vec<f64>,
+    position: usize,
+    period: usize,
+    frames: SyncSender<Frame>,
+}
+
+impl Meter {
+    /// Measures `channels` channels at `sample_rate`, with `rate` frames per second arriving on
+    /// the receiver
+    pub fn new(channels: usize, sample_rate: f64, rate: f64) -> (Self, Receiver<Frame>) {
+        let (frames, receiver) = mpsc::sync_channel(PENDING_FRAMES);
+        let meter = Self {
+            weighting: vec![k_weighting(sample_rate); channels],
+            blocks: [0.; MOMENTARY_BLOCKS],
+            next_block: 0,
+            block: 0.,
+            block_position: 0,
+            block_length: (BLOCK_LENGTH * sample_rate) as usize,
+
+            peak: vec![0.; channels],
+            squares: vec![0.; channels],
+            position: 0,
+            period: ((sample_rate / rate) as usize).max(1),
+            frames,
+        };
+        (meter, receiver)
+    }
+
+    /// Measures the first `length` samples of `channels`
+    pub fn process(&mut self, channels: &[Vec<f32>], length: usize) {
+        for i in 0..length {
+            for (index, channel) in channels.iter().enumerate().take(self.peak.len()) {
+                let sample = channel[i];
+                self.peak[index] = self.peak[index].max(sample.abs());
+                self.squares[index] += (sample as f64).powi(2);
+
+                let weighted = self.weighting[index]
+                    .iter_mut()
+                    .fold(sample as f64, |x, filter| filter.process(x));
+                self.block += weighted * weighted;
+            }
+
+            self.block_position += 1;
+            if self.block_position == self.block_length {
+                self.blocks[self.next_block] = self.block / self.block_length as f64;
+                self.next_block = (self.next_block + 1) % MOMENTARY_BLOCKS;
+                self.block = 0.;
+                self.block_position = 0;
+            }
+
+            self.position += 1;
+            if self.position == self.period {
+                self.send();
+            }
+        }
+    }
+
+    fn send(&mut self) {
+        let energy = self.blocks.iter().sum::<f64>() / MOMENTARY_BLOCKS as f64;
+        let frame = Frame {
+            peak: self.peak.iter().map(|&peak| to_db(peak)).collect(),
+            rms: self
+                .squares
+                .iter()
+                .map(|&squares| to_db((squares / self.position as f64).sqrt() as f32))
+                .collect(),
+            momentary: ((-0.691 + 10. * energy.log10()) as f32).max(FLOOR),
+        };
+        let _ = self.frames.try_send(frame);
+
+        self.peak.fill(0.);
+        self.squares.fill(0.);
+        self.position = 0;
+    }
+}
+
+/// Sends every frame from `frames` to `target` as an OSC bundle until the meter is dropped.
+///
+/// Each bundle holds `/y/meter/peak` and `/y/meter/rms` with one float per channel in dBFS, and
+/// `/y/meter/momentary` with the loudness in LUFS.
+pub fn publish(target: SocketAddr, frames: Receiver<Frame>) -> Result<()> {
+    let socket = osc::socket(target)?;
+
+    thread::spawn(move || {
+        let mut failed = false;
+        for frame in frames {
+            let bundle = osc::bundle(&[
+                osc::message("/y/meter/peak", &frame.peak),
+                osc::message("/y/meter/rms", &frame.rms),
+                osc::message("/y/meter/momentary", &[frame.momentary]),
+            ]);
+            match socket.send_to(&bundle, target) {
+                Ok(_) => failed = false,
+                // Only reported once until sending works again, rather than for every frame
+                Err(err) if !failed => {
+                    log::warn!("failed to send meters to {target}: {err}");
+                    failed = true;
+                }
+                Err(_) => {}
+            }
+        }
+    });
+
+    Ok(())
+}
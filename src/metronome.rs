@@ -0,0 +1,65 @@
+use std::f32::consts::TAU;
+
+use crate::transport::Transport;
+
+/// A click generator following the host transport
+pub struct Metronome {
+    sample_rate: f32,
+    level: f32,
+    last_beat: Option<i64>,
+    /// Samples since the start of the current click, if one is playing
+    click_position: Option<usize>,
+    accent: bool,
+}
+
+impl Metronome {
+    /// Length of a click, in seconds
+    const CLICK_LENGTH: f32 = 0.03;
+
+    pub fn new(sample_rate: f32, level: f32) -> Self {
+        Self {
+            sample_rate,
+            level,
+            last_beat: None,
+            click_position: None,
+            accent: false,
+        }
+    }
+
+    /// Mixes clicks into `length` frames of `outputs`, the first of which plays at `start` seconds
+    pub fn process(
+        &mut self,
+        transport: &Transport,
+        start: f64,
+        outputs: &mut [Vec<f32>],
+        length: usize,
+    ) {
+        let click_length = (Self::CLICK_LENGTH * self.sample_rate) as usize;
+
+        for i in 0..length {
+            let seconds = start + i as f64 / self.sample_rate as f64;
+            let (beat, downbeat) = transport.tempo_map().beat_at(seconds);
+
+            if self.last_beat != Some(beat) {
+                self.last_beat = Some(beat);
+                self.click_position = Some(0);
+                self.accent = downbeat;
+            }
+
+            let position = match self.click_position {
+                Some(position) if position < click_length => position,
+                _ => continue,
+            };
+            self.click_position = Some(position + 1);
+
+            let frequency = if self.accent { 1500. } else { 1000. };
+            let t = position as f32 / self.sample_rate;
+            let envelope = 1. - position as f32 / click_length as f32;
+            let sample = (TAU * frequency * t).sin() * envelope * envelope * self.level;
+
+            for channel in outputs.iter_mut() {
+                channel[i] += sample;
+            }
+        }
+    }
+}
@@ -0,0 +1,162 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::{Read, Write},
+    path::Path,
+    sync::mpsc::{self, Receiver, Sender},
+    thread,
+};
+
+use anyhow::{Context, Result};
+use vst::event::MidiEvent;
+
+/// Somewhere MIDI comes from, asked for its events once per block
+pub trait MidiSource: Send {
+    /// Appends the events falling into a block of `length` samples starting at `start` seconds
+    /// to `events`
+    fn events(&mut self, start: f64, length: usize, sample_rate: f64, events: &mut Vec<MidiEvent>);
+}
+
+/// Somewhere MIDI sent by the plugin goes
+pub trait MidiSink: Send {
+    fn send(&mut self, data: [u8; 3]);
+}
+
+/// Collects the events of every source for a block into `events`, ordered by time.
+///
+/// Events at the same time stay in the order of their sources.
+pub fn merge(
+    sources: &mut [Box<dyn MidiSource>],
+    start: f64,
+    length: usize,
+    sample_rate: f64,
+    events: &mut Vec<MidiEvent>,
+) {
+    for source in sources {
+        source.events(start, length, sample_rate, events);
+    }
+    events.sort_by_key(|event| event.delta_frames);
+}
+
+/// MIDI played on other threads, such as the terminal keyboard, which arrives at the start of the
+/// next block
+pub struct Live(Receiver<[u8; 3]>);
+
+impl Live {
+    pub fn new() -> (Sender<[u8; 3]>, Self) {
+        let (sender, receiver) = mpsc::channel();
+        (sender, Self(receiver))
+    }
+
+    /// Plays what arrives on the raw MIDI device at `path`, like /dev/snd/midiC1D0, except for the
+    /// messages `intercept` handles itself by returning `true`
+    pub fn open(
+        path: &Path,
+        mut intercept: impl FnMut([u8; 3]) -> bool + Send + 'static,
+    ) -> Result<Self> {
+        let input =
+            File::open(path).with_context(|| format!("failed to open {}", path.display()))?;
+        let (sender, live) = Self::new();
+        let name = path.display().to_string();
+        thread::spawn(move || {
+            read_messages(input, |message| {
+                intercept(message) || sender.send(message).is_ok()
+            });
+            log::warn!("{name} was closed");
+        });
+
+        Ok(live)
+    }
+}
+
+impl MidiSource for Live {
+    fn events(&mut self, _: f64, _: usize, _: f64, events: &mut Vec<MidiEvent>) {
+        events.extend(self.0.try_iter().map(|data| MidiEvent {
+            data,
+            delta_frames: 0,
+            live: true,
+            note_length: None,
+            note_offset: None,
+            detune: 0,
+            note_off_velocity: 0,
+        }));
+    }
+}
+
+/// A raw MIDI device written to directly
+pub struct Device {
+    file: File,
+    failed: bool,
+}
+
+impl Device {
+    pub fn create(path: &Path) -> Result<Self> {
+        let file = OpenOptions::new()
+            .write(true)
+            .open(path)
+            .with_context(|| format!("failed to open {}", path.display()))?;
+
+        Ok(Self {
+            file,
+            failed: false,
+        })
+    }
+}
+
+impl MidiSink for Device {
+    fn send(&mut self, data: [u8; 3]) {
+        let length = match data[0] & 0xf0 {
+            0xc0 | 0xd0 => 2,
+            _ => 3,
+        };
+        if let Err(err) = self.file.write_all(&data[..length]) {
+            // Only reported once, rather than for every message
+            if !self.failed {
+                log::warn!("failed to send MIDI: {err}");
+                self.failed = true;
+            }
+        }
+    }
+}
+
+/// Reads channel messages from `input` until it ends or `send` returns `false`, skipping system
+/// exclusive and real-time messages
+pub fn read_messages(mut input: impl Read, mut send: impl FnMut([u8; 3]) -> bool) {
+    let mut status = 0;
+    let mut data = Vec::with_capacity(2);
+    let mut buffer = [0; 256];
+
+    loop {
+        let read = match input.read(&mut buffer) {
+            Ok(0) | Err(_) => return,
+            Ok(read) => read,
+        };
+
+        for &byte in &buffer[..read] {
+            match byte {
+                0xf8..=0xff => {}
+                0x80..=0xf7 => {
+                    status = byte;
+                    data.clear();
+                }
+                // Data of a system message, or before the first status byte
+                _ if !(0x80..0xf0).contains(&status) => {}
+                _ => {
+                    data.push(byte);
+                    let length = if matches!(status & 0xf0, 0xc0 | 0xd0) {
+                        1
+                    } else {
+                        2
+                    };
+                    // Running status: later messages may leave out the status byte
+                    if data.len() == length {
+                        let message = [status, data[0], data.get(1).copied().unwrap_or(0)];
+                        data.clear();
+                        if !send(message) {
+                            return;
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
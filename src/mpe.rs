@@ -0,0 +1,105 @@
+use std::str::FromStr;
+
+use anyhow::{bail, ensure, Context};
+use vst::event::MidiEvent;
+
+/// The MPE zones: how many member channels the lower zone, mastered on channel 1, and the upper
+/// zone, mastered on channel 16, each have
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Zones {
+    pub lower: u8,
+    pub upper: u8,
+}
+
+impl Zones {
+    /// The master channel of the zone `channel` belongs to, from 0, if it's a master or member
+    /// channel
+    pub fn master_of(&self, channel: u8) -> Option<u8> {
+        if self.lower > 0 && channel <= self.lower {
+            Some(0)
+        } else if self.upper > 0 && channel >= 15 - self.upper {
+            Some(15)
+        } else {
+            None
+        }
+    }
+
+    /// Whether `channel`, from 0, carries the notes and per-note expression of a zone
+    pub fn is_member(&self, channel: u8) -> bool {
+        self.master_of(channel)
+            .is_some_and(|master| master != channel)
+    }
+
+    /// The MPE Configuration Message of each zone, followed by the pitch bend range of its member
+    /// channels in semitones
+    pub fn configuration(&self, bend_range: u8) -> Vec<MidiEvent> {
+        let mut events = Vec::new();
+        let mut rpn = |channel: u8, number: u8, value: u8| {
+            for (controller, value) in [
+                (101, 0),
+                (100, number),
+                (6, value),
+                (38, 0),
+                (101, 127),
+                (100, 127),
+            ] {
+                events.push(MidiEvent {
+                    data: [0xb0 | channel, controller, value],
+                    delta_frames: 0,
+                    live: true,
+                    note_length: None,
+                    note_offset: None,
+                    detune: 0,
+                    note_off_velocity: 0,
+                });
+            }
+        };
+
+        for (master, members) in [(0, self.lower), (15, self.upper)] {
+            if members == 0 {
+                continue;
+            }
+            // RPN 6 sets up the zone, RPN 0 the pitch bend range
+            rpn(master, 6, members);
+            for channel in 0..16 {
+                if self.master_of(channel) == Some(master) && channel != master {
+                    rpn(channel, 0, bend_range);
+                }
+            }
+        }
+        events
+    }
+}
+
+impl FromStr for Zones {
+    type Err = anyhow::Error;
+
+    /// Parses zones like `lower:15` or `lower:7,upper:7`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut zones = Zones { lower: 0, upper: 0 };
+        for zone in s.split(',') {
+            let (name, members) = zone
+                .split_once(':')
+                .context("expected lower:<members> or upper:<members>")?;
+            let members: u8 = members
+                .parse()
+                .context("invalid number of member channels")?;
+            ensure!(
+                (1..=15).contains(&members),
+                "a zone has from 1 to 15 member channels"
+            );
+            match name {
+                "lower" => zones.lower = members,
+                "upper" => zones.upper = members,
+                _ => bail!("unknown zone {name:?}, expected lower or upper"),
+            }
+        }
+        // Two zones share the channels between their two masters
+        ensure!(
+            zones.lower == 0 || zones.upper == 0 || zones.lower + zones.upper <= 14,
+            "two zones have at most 14 member channels between them"
+        );
+
+        Ok(zones)
+    }
+}
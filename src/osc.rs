@@ -0,0 +1,49 @@
+use std::net::{SocketAddr, UdpSocket};
+
+use anyhow::{Context, Result};
+
+/// Encodes an OSC message to `address` with float arguments
+pub fn message(address: &str, arguments: &[f32]) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    string(&mut bytes, address);
+
+    let mut tags = ",".to_owned();
+    tags.extend(arguments.iter().map(|_| 'f'));
+    string(&mut bytes, &tags);
+
+    for argument in arguments {
+        bytes.extend_from_slice(&argument.to_be_bytes());
+    }
+    bytes
+}
+
+/// Encodes `messages` as one bundle, so receivers handle them together
+pub fn bundle(messages: &[Vec<u8>]) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    string(&mut bytes, "#bundle");
+    // The time tag 1 means immediately
+    bytes.extend_from_slice(&1u64.to_be_bytes());
+
+    for message in messages {
+        bytes.extend_from_slice(&(message.len() as i32).to_be_bytes());
+        bytes.extend_from_slice(message);
+    }
+    bytes
+}
+
+/// Opens a socket to send OSC to `target` from
+pub fn socket(target: SocketAddr) -> Result<UdpSocket> {
+    let local: SocketAddr = if target.is_ipv4() {
+        ([0, 0, 0, 0], 0).into()
+    } else {
+        ([0u16; 8], 0).into()
+    };
+    UdpSocket::bind(local).context("failed to open a socket for OSC")
+}
+
+/// Appends `s` terminated by a null byte and padded to a multiple of four bytes
+fn string(bytes: &mut Vec<u8>, s: &str) {
+    bytes.extend_from_slice(s.as_bytes());
+    let padding = 4 - s.len() % 4;
+    bytes.extend(std::iter::repeat_n(0, padding));
+}
@@ -0,0 +1,417 @@
+use std::{
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, ensure, Context, Result};
+use clap::ArgEnum;
+use rodio::{
+    cpal::{
+        self,
+        traits::{HostTrait, StreamTrait},
+        BufferSize, SampleFormat, SampleRate, StreamConfig,
+    },
+    source::UniformSourceIterator,
+    DeviceTrait, Source,
+};
+
+/// Fills a buffer of interleaved samples, called from the backend's audio thread
+pub type Process = Box<dyn FnMut(&mut [f32]) + Send>;
+
+/// Something the host's audio can be played through
+pub trait AudioBackend {
+    fn channels(&self) -> u16;
+
+    fn sample_rate(&self) -> u32;
+
+    /// Frames asked for at a time, if the backend always asks for the same number
+    fn buffer_size(&self) -> Option<u32>;
+
+    /// Starts calling `process` for every buffer, until the backend is dropped
+    fn start(&mut self, process: Process) -> Result<()>;
+
+    /// Counts the times the device ran out of output, as far as the backend can tell
+    fn underruns(&self) -> Arc<AtomicUsize>;
+}
+
+#[derive(ArgEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Backend {
+    /// The platform's audio API: ALSA, CoreAudio or WASAPI
+    #[default]
+    Cpal,
+    /// No device, buffers are processed in real time and thrown away
+    Null,
+}
+
+/// Which audio device the host plays through, and how
+#[derive(clap::Args, Clone, Debug, Default)]
+pub struct Output {
+    /// Where to play the output
+    #[clap(long, arg_enum, default_value = "cpal")]
+    pub backend: Backend,
+
+    /// Name of the output device, as listed by `y devices`, instead of the default one
+    #[clap(long)]
+    pub output_device: Option<String>,
+
+    /// Number of frames the device asks for, and the plugin processes, at a time instead of the
+    /// device's default
+    #[clap(long)]
+    pub buffer_size: Option<u32>,
+
+    /// Sample rate to run the device at, which the plugin runs at as well
+    #[clap(long)]
+    pub sample_rate: Option<u32>,
+}
+
+impl Output {
+    /// Opens the backend, ready to be started
+    pub fn open(&self) -> Result<Box<dyn AudioBackend>> {
+        let backend: Box<dyn AudioBackend> = match self.backend {
+            Backend::Cpal => Box::new(CpalBackend::open(self)?),
+            Backend::Null => {
+                ensure!(
+                    self.output_device.is_none(),
+                    "the null backend has no devices"
+                );
+                Box::new(NullBackend::new(self))
+            }
+        };
+        log::info!(
+            "playing through {:?} at {} Hz, {} channels, {} buffers",
+            self.backend,
+            backend.sample_rate(),
+            backend.channels(),
+            backend
+                .buffer_size()
+                .map_or("default".to_owned(), |size| format!("{size} frame"))
+        );
+
+        Ok(backend)
+    }
+
+    /// Processes `source` like the null backend opened as `backend`, but on the calling thread
+    /// rather than one of its own. `between` is called before every buffer and stops playback by
+    /// returning `false`.
+    pub fn play_inline<S>(
+        &self,
+        backend: Box<dyn AudioBackend>,
+        source: S,
+        mut between: impl FnMut() -> Result<bool>,
+    ) -> Result<()>
+    where
+        S: Source<Item = f32>,
+    {
+        ensure!(
+            self.backend == Backend::Null,
+            "only the null backend can play without a thread of its own"
+        );
+        let frames = backend
+            .buffer_size()
+            .context("the null backend always has a buffer size")?;
+        log::info!("processing on the calling thread");
+        let mut samples = Samples::new(source, backend.channels(), backend.sample_rate());
+        let mut buffer = vec![0.; frames as usize * backend.channels() as usize];
+        let period = Duration::from_secs_f64(frames as f64 / backend.sample_rate() as f64);
+
+        let underruns = backend.underruns();
+        let mut next = Instant::now();
+        while between()? {
+            samples.fill(&mut buffer);
+            next = NullBackend::wait(next + period, &underruns);
+        }
+
+        Ok(())
+    }
+}
+
+/// Starts playing `source` through `backend`.
+///
+/// Playback stops when the returned backend is dropped.
+pub fn play<S>(mut backend: Box<dyn AudioBackend>, source: S) -> Result<Box<dyn AudioBackend>>
+where
+    S: Source<Item = f32> + Send + 'static,
+{
+    let mut samples = Samples::new(source, backend.channels(), backend.sample_rate());
+    backend.start(Box::new(move |buffer| samples.fill(buffer)))?;
+
+    Ok(backend)
+}
+
+/// A source's samples, resampled and remixed to fit a backend only if it doesn't already
+enum Samples<S: Source<Item = f32>> {
+    Direct(S),
+    Converted(UniformSourceIterator<S, f32>),
+}
+
+impl<S: Source<Item = f32>> Samples<S> {
+    fn new(source: S, channels: u16, sample_rate: u32) -> Self {
+        if source.channels() == channels && source.sample_rate() == sample_rate {
+            return Samples::Direct(source);
+        }
+        log::info!(
+            "converting the output from {} Hz and {} channels",
+            source.sample_rate(),
+            source.channels()
+        );
+        Samples::Converted(UniformSourceIterator::new(source, channels, sample_rate))
+    }
+
+    /// Fills `buffer` with the next samples, or silence once there are none left
+    fn fill(&mut self, buffer: &mut [f32]) {
+        for sample in buffer {
+            let next = match self {
+                Samples::Direct(source) => source.next(),
+                Samples::Converted(source) => source.next(),
+            };
+            *sample = next.unwrap_or(0.);
+        }
+    }
+}
+
+/// Plays through a device of the platform's audio API
+struct CpalBackend {
+    device: cpal::Device,
+    name: String,
+    config: StreamConfig,
+    sample_format: SampleFormat,
+    stream: Option<cpal::Stream>,
+    underruns: Arc<AtomicUsize>,
+}
+
+impl CpalBackend {
+    fn open(output: &Output) -> Result<Self> {
+        let host = cpal::default_host();
+        let device = match &output.output_device {
+            Some(name) => host
+                .output_devices()?
+                .find(|device| device.name().ok().as_ref() == Some(name))
+                .with_context(|| format!("there is no output device called {name:?}"))?,
+            None => host
+                .default_output_device()
+                .context("there is no output device")?,
+        };
+
+        let name = device.name().unwrap_or_default();
+        let default = device
+            .default_output_config()
+            .with_context(|| format!("{name} has no output configuration"))?;
+        let sample_rate = output.sample_rate.map_or(default.sample_rate(), SampleRate);
+        ensure!(
+            device.supported_output_configs()?.any(|config| {
+                config.channels() == default.channels()
+                    && config.sample_format() == default.sample_format()
+                    && (config.min_sample_rate()..=config.max_sample_rate()).contains(&sample_rate)
+            }),
+            "{name} doesn't run at {} Hz",
+            sample_rate.0
+        );
+
+        Ok(Self {
+            device,
+            name,
+            config: StreamConfig {
+                channels: default.channels(),
+                sample_rate,
+                buffer_size: output
+                    .buffer_size
+                    .map_or(BufferSize::Default, BufferSize::Fixed),
+            },
+            sample_format: default.sample_format(),
+            stream: None,
+            underruns: Arc::new(AtomicUsize::new(0)),
+        })
+    }
+
+    fn build<T: cpal::Sample>(&self, mut process: Process) -> Result<cpal::Stream> {
+        let mut buffer = Vec::new();
+        let underruns = self.underruns.clone();
+        let errors = self.underruns.clone();
+        let mut started = false;
+        self.device
+            .build_output_stream::<T, _, _>(
+                &self.config,
+                move |data, info: &cpal::OutputCallbackInfo| {
+                    // Output still queued puts this buffer's playback after the callback, so a
+                    // buffer that plays straight away means the device ran dry. The first buffer
+                    // always does.
+                    let timestamp = info.timestamp();
+                    let queued = timestamp.playback.duration_since(&timestamp.callback);
+                    if started && queued.is_none_or(|queued| queued.is_zero()) {
+                        underruns.fetch_add(1, Ordering::Relaxed);
+                    }
+                    started = true;
+
+                    buffer.resize(data.len(), 0.);
+                    process(&mut buffer);
+                    for (sample, value) in data.iter_mut().zip(&buffer) {
+                        *sample = T::from(value);
+                    }
+                },
+                // Some APIs report underruns here, like JACK's xruns
+                move |err| {
+                    errors.fetch_add(1, Ordering::Relaxed);
+                    log::error!("output stream failed: {err}");
+                },
+            )
+            .map_err(|err| anyhow!(err))
+            .with_context(|| format!("failed to open {}", self.name))
+    }
+}
+
+impl AudioBackend for CpalBackend {
+    fn channels(&self) -> u16 {
+        self.config.channels
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.config.sample_rate.0
+    }
+
+    fn buffer_size(&self) -> Option<u32> {
+        match self.config.buffer_size {
+            BufferSize::Fixed(size) => Some(size),
+            BufferSize::Default => None,
+        }
+    }
+
+    fn start(&mut self, process: Process) -> Result<()> {
+        let stream = match self.sample_format {
+            SampleFormat::F32 => self.build::<f32>(process),
+            SampleFormat::I16 => self.build::<i16>(process),
+            SampleFormat::U16 => self.build::<u16>(process),
+        }?;
+        stream.play()?;
+        self.stream = Some(stream);
+
+        Ok(())
+    }
+
+    fn underruns(&self) -> Arc<AtomicUsize> {
+        self.underruns.clone()
+    }
+}
+
+/// Processes buffers at the pace a device would without playing them, for machines without
+/// audio hardware
+struct NullBackend {
+    sample_rate: u32,
+    buffer_size: u32,
+    stopped: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+    underruns: Arc<AtomicUsize>,
+}
+
+impl NullBackend {
+    fn new(output: &Output) -> Self {
+        Self {
+            sample_rate: output.sample_rate.unwrap_or(44_100),
+            buffer_size: output.buffer_size.unwrap_or(1024),
+            stopped: Arc::new(AtomicBool::new(false)),
+            thread: None,
+            underruns: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    /// How long a device would take to play one buffer
+    fn period(&self) -> Duration {
+        Duration::from_secs_f64(self.buffer_size as f64 / self.sample_rate as f64)
+    }
+
+    /// Sleeps until `deadline`, the latest a device could have taken the buffer just processed.
+    /// Missing it counts as an underrun and the next deadline is measured from now instead.
+    fn wait(deadline: Instant, underruns: &AtomicUsize) -> Instant {
+        let now = Instant::now();
+        if now > deadline {
+            underruns.fetch_add(1, Ordering::Relaxed);
+            return now;
+        }
+        thread::sleep(deadline - now);
+        deadline
+    }
+}
+
+impl AudioBackend for NullBackend {
+    fn channels(&self) -> u16 {
+        2
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn buffer_size(&self) -> Option<u32> {
+        Some(self.buffer_size)
+    }
+
+    fn start(&mut self, mut process: Process) -> Result<()> {
+        let mut buffer = vec![0.; self.buffer_size as usize * self.channels() as usize];
+        let period = self.period();
+        let stopped = self.stopped.clone();
+        let underruns = self.underruns.clone();
+
+        self.thread = Some(thread::spawn(move || {
+            let mut next = Instant::now();
+            while !stopped.load(Ordering::Relaxed) {
+                process(&mut buffer);
+                next = NullBackend::wait(next + period, &underruns);
+            }
+        }));
+
+        Ok(())
+    }
+
+    fn underruns(&self) -> Arc<AtomicUsize> {
+        self.underruns.clone()
+    }
+}
+
+impl Drop for NullBackend {
+    /// Waits for the last buffer, so what `process` owns is dropped before this returns
+    fn drop(&mut self) {
+        self.stopped.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// The output devices of the platform's audio API, and whether each is the default
+pub fn devices() -> Result<Vec<(cpal::Device, bool)>> {
+    let host = cpal::default_host();
+    let default = host
+        .default_output_device()
+        .and_then(|device| device.name().ok());
+
+    Ok(host
+        .output_devices()?
+        .map(|device| {
+            let is_default = device.name().ok() == default;
+            (device, is_default)
+        })
+        .collect())
+}
+
+/// Describes the configurations `device` supports, one per line
+pub fn describe(device: &cpal::Device) -> Result<String> {
+    let mut lines = Vec::new();
+    for config in device.supported_output_configs()? {
+        lines.push(format!(
+            "{} channels, {} to {} Hz, {:?}, buffers of {}",
+            config.channels(),
+            config.min_sample_rate().0,
+            config.max_sample_rate().0,
+            config.sample_format(),
+            match config.buffer_size() {
+                cpal::SupportedBufferSize::Range { min, max } => format!("{min} to {max} frames"),
+                cpal::SupportedBufferSize::Unknown => "any size".to_owned(),
+            }
+        ));
+    }
+
+    Ok(lines.join("\n"))
+}
@@ -0,0 +1,202 @@
+use std::f32::consts::PI;
+
+use clap::ArgEnum;
+
+/// Oversampling factor applied around the plugin
+#[derive(ArgEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Oversample {
+    #[clap(name = "1x")]
+    X1,
+    #[clap(name = "2x")]
+    X2,
+    #[clap(name = "4x")]
+    X4,
+}
+
+impl Oversample {
+    pub fn factor(self) -> usize {
+        match self {
+            Oversample::X1 => 1,
+            Oversample::X2 => 2,
+            Oversample::X4 => 4,
+        }
+    }
+}
+
+/// Number of filter taps per polyphase branch
+const TAPS_PER_PHASE: usize = 16;
+
+/// Designs a Blackman-windowed sinc lowpass for `factor` times oversampling.
+///
+/// The kernel has `TAPS_PER_PHASE * factor` taps and unity DC gain. With a factor of one the
+/// kernel is a single unit impulse, which turns the resamplers into plain copies.
+fn kernel(factor: usize) -> Vec<f32> {
+    if factor == 1 {
+        return vec![1.];
+    }
+
+    let taps = TAPS_PER_PHASE * factor;
+    // Leave a little room below the original Nyquist frequency for the transition band
+    let cutoff = 0.45 / factor as f32;
+    let middle = (taps - 1) as f32 / 2.;
+
+    let mut kernel: Vec<f32> = (0..taps)
+        .map(|n| {
+            let x = n as f32 - middle;
+            let sinc = if x == 0. {
+                2. * cutoff
+            } else {
+                (2. * PI * cutoff * x).sin() / (PI * x)
+            };
+            let phase = 2. * PI * n as f32 / (taps - 1) as f32;
+            let window = 0.42 - 0.5 * phase.cos() + 0.08 * (2. * phase).cos();
+            sinc * window
+        })
+        .collect();
+
+    let sum: f32 = kernel.iter().sum();
+    kernel.iter_mut().for_each(|tap| *tap /= sum);
+    kernel
+}
+
+/// Raises the sample rate of a single channel by an integer factor
+pub struct Upsampler {
+    factor: usize,
+    kernel: Vec<f32>,
+    history: Vec<f32>,
+    position: usize,
+}
+
+impl Upsampler {
+    pub fn new(factor: usize) -> Self {
+        let kernel = kernel(factor);
+        let history = vec![0.; kernel.len() / factor];
+
+        Self {
+            factor,
+            kernel,
+            history,
+            position: 0,
+        }
+    }
+
+    /// Writes `input.len() * factor` samples to `output`
+    pub fn process(&mut self, input: &[f32], output: &mut [f32]) {
+        let len = self.history.len();
+
+        for (&sample, frame) in input.iter().zip(output.chunks_exact_mut(self.factor)) {
+            self.position = (self.position + 1) % len;
+            self.history[self.position] = sample;
+
+            // Only every `factor`th sample of the zero-stuffed signal is nonzero, so each output
+            // phase only needs every `factor`th tap
+            for (phase, out) in frame.iter_mut().enumerate() {
+                let mut acc = 0.;
+                for k in 0..len {
+                    acc += self.kernel[k * self.factor + phase]
+                        * self.history[(self.position + len - k) % len];
+                }
+                *out = acc * self.factor as f32;
+            }
+        }
+    }
+}
+
+/// Lowers the sample rate of a single channel by an integer factor
+pub struct Downsampler {
+    factor: usize,
+    kernel: Vec<f32>,
+    history: Vec<f32>,
+    position: usize,
+}
+
+impl Downsampler {
+    pub fn new(factor: usize) -> Self {
+        let kernel = kernel(factor);
+        let history = vec![0.; kernel.len()];
+
+        Self {
+            factor,
+            kernel,
+            history,
+            position: 0,
+        }
+    }
+
+    /// Writes `input.len() / factor` samples to `output`
+    pub fn process(&mut self, input: &[f32], output: &mut [f32]) {
+        let len = self.history.len();
+
+        for (frame, out) in input.chunks_exact(self.factor).zip(output.iter_mut()) {
+            for &sample in frame {
+                self.position = (self.position + 1) % len;
+                self.history[self.position] = sample;
+            }
+
+            let mut acc = 0.;
+            for (k, tap) in self.kernel.iter().enumerate() {
+                acc += tap * self.history[(self.position + len - k) % len];
+            }
+            *out = acc;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::PI;
+
+    use super::{Downsampler, Upsampler};
+
+    fn sine(frequency: f32, sample_rate: f32, length: usize) -> Vec<f32> {
+        (0..length)
+            .map(|i| (2. * PI * frequency * i as f32 / sample_rate).sin())
+            .collect()
+    }
+
+    fn rms(samples: &[f32]) -> f32 {
+        (samples.iter().map(|sample| sample * sample).sum::<f32>() / samples.len() as f32).sqrt()
+    }
+
+    #[test]
+    fn one_times_copies_the_signal() {
+        let input = [0.5, -0.25, 1., 0.];
+        let mut output = [0.; 4];
+        Upsampler::new(1).process(&input, &mut output);
+        assert_eq!(output, input);
+        Downsampler::new(1).process(&input, &mut output);
+        assert_eq!(output, input);
+    }
+
+    #[test]
+    fn the_passband_comes_back_at_the_same_level() {
+        for factor in [2, 4] {
+            let input = sine(1000., 44_100., 4096);
+            let mut upsampled = vec![0.; input.len() * factor];
+            let mut output = vec![0.; input.len()];
+            Upsampler::new(factor).process(&input, &mut upsampled);
+            Downsampler::new(factor).process(&upsampled, &mut output);
+
+            // Past the filters' delay
+            let gain = rms(&output[1024..]) / rms(&input[1024..]);
+            assert!(
+                (gain - 1.).abs() < 0.01,
+                "{factor}x changes the level by {gain}"
+            );
+        }
+    }
+
+    #[test]
+    fn downsampling_removes_what_the_lower_rate_cant_hold() {
+        for factor in [2, 4] {
+            let sample_rate = 44_100. * factor as f32;
+            // Above the Nyquist frequency of 44.1 kHz, so it would alias
+            let input = sine(35_000., sample_rate, 8192 * factor);
+            let mut output = vec![0.; 8192];
+            Downsampler::new(factor).process(&input, &mut output);
+
+            let gain = rms(&output[1024..]) / rms(&input);
+            assert!(gain < 0.01, "{factor}x only attenuates to {gain}");
+        }
+    }
+}
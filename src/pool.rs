@@ -0,0 +1,59 @@
+use std::sync::mpsc::{self, Receiver, SyncSender};
+
+/// Buffers of interleaved output allocated up front, which the audio thread fills and sends to
+/// other threads and those threads give back once they're done, so the audio thread never
+/// allocates or frees one.
+pub struct Pool {
+    free: Receiver<Vec<f32>>,
+    returns: Returns,
+}
+
+impl Pool {
+    /// Allocates `count` buffers of `size` samples
+    pub fn new(count: usize, size: usize) -> Self {
+        let (returns, free) = mpsc::sync_channel(count);
+        for _ in 0..count {
+            let _ = returns.try_send(Vec::with_capacity(size));
+        }
+        Self {
+            free,
+            returns: Returns(returns),
+        }
+    }
+
+    /// An empty buffer, or `None` while every buffer is in use
+    pub fn take(&self) -> Option<Vec<f32>> {
+        self.free.try_recv().ok()
+    }
+
+    /// Puts a buffer that couldn't be sent back in the pool
+    pub fn give(&self, buffer: Vec<f32>) {
+        self.returns.give(buffer);
+    }
+
+    /// A handle for giving buffers back from other threads
+    pub fn returns(&self) -> Returns {
+        self.returns.clone()
+    }
+}
+
+/// Gives buffers back to a [`Pool`]
+#[derive(Clone)]
+pub struct Returns(SyncSender<Vec<f32>>);
+
+impl Returns {
+    /// Empties `buffer` and puts it back in the pool, or drops it if the pool is gone
+    pub fn give(&self, mut buffer: Vec<f32>) {
+        buffer.clear();
+        let _ = self.0.try_send(buffer);
+    }
+}
+
+/// Fills `buffer` with the first `length` samples of `channels`, interleaved. Doesn't allocate as
+/// long as the buffer has room for them.
+pub fn interleave(channels: &[Vec<f32>], length: usize, buffer: &mut Vec<f32>) {
+    buffer.clear();
+    for i in 0..length {
+        buffer.extend(channels.iter().map(|channel| channel[i]));
+    }
+}
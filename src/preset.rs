@@ -0,0 +1,644 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use anyhow::{bail, ensure, Context, Result};
+use vst::plugin::Info;
+
+use crate::{instance::Parameters, timeout::with_timeout};
+
+/// Largest file accepted as a preset, well above any real chunk but small enough that a corrupt
+/// size field can't make the host allocate gigabytes
+const MAX_SIZE: usize = 256 << 20;
+
+/// Length of the name field of a program
+const NAME_LENGTH: usize = 28;
+
+/// The contents of a program, either parameter values or a chunk only the plugin understands
+#[derive(Clone, Debug)]
+pub enum Data {
+    Parameters(Vec<f32>),
+    Chunk(Vec<u8>),
+}
+
+#[derive(Clone, Debug)]
+pub struct Program {
+    pub name: String,
+    pub data: Data,
+}
+
+/// A preset read from an FXP or FXB file
+#[derive(Clone, Debug)]
+pub enum Preset {
+    /// A single program, from an FXP file
+    Program { plugin_id: i32, program: Program },
+    /// Every program of the plugin, from an FXB file
+    Bank {
+        plugin_id: i32,
+        /// The program to switch to after loading, saved by version 2 banks
+        current: Option<i32>,
+        programs: Vec<Program>,
+    },
+    /// A bank saved as one chunk, from an FXB file
+    BankChunk {
+        plugin_id: i32,
+        current: Option<i32>,
+        chunk: Vec<u8>,
+    },
+}
+
+/// Reads the big-endian fields of a preset, failing on truncated data rather than reading past it
+struct Reader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, length: usize, what: &str) -> Result<&'a [u8]> {
+        let end = self
+            .position
+            .checked_add(length)
+            .filter(|&end| end <= self.bytes.len())
+            .with_context(|| {
+                format!(
+                    "the file ends in the {what} at byte {}, {length} bytes are missing",
+                    self.position
+                )
+            })?;
+        let bytes = &self.bytes[self.position..end];
+        self.position = end;
+        Ok(bytes)
+    }
+
+    fn i32(&mut self, what: &str) -> Result<i32> {
+        Ok(i32::from_be_bytes(self.bytes(4, what)?.try_into().unwrap()))
+    }
+
+    /// Reads a count or size, which can't be negative
+    fn size(&mut self, what: &str) -> Result<usize> {
+        let size = self.i32(what)?;
+        ensure!(size >= 0, "the {what} is negative ({size})");
+        Ok(size as usize)
+    }
+
+    fn magic(&mut self, what: &str) -> Result<[u8; 4]> {
+        Ok(self.bytes(4, what)?.try_into().unwrap())
+    }
+}
+
+impl Preset {
+    pub fn read(path: &Path) -> Result<Self> {
+        let size = fs::metadata(path)
+            .with_context(|| format!("failed to read {}", path.display()))?
+            .len();
+        ensure!(
+            size as usize <= MAX_SIZE,
+            "{} is too large to be a preset ({size} bytes)",
+            path.display()
+        );
+
+        let bytes = fs::read(path).with_context(|| format!("failed to read {}", path.display()))?;
+        Self::parse(&bytes).with_context(|| format!("{} is not a valid preset", path.display()))
+    }
+
+    /// Parses the contents of an FXP or FXB file
+    pub fn parse(bytes: &[u8]) -> Result<Self> {
+        let mut reader = Reader { bytes, position: 0 };
+
+        let (header, plugin_id, count) = read_header(&mut reader)?;
+        match &header {
+            b"FxCk" | b"FPCh" => {
+                let program = read_program(&mut reader, &header, count)?;
+                Ok(Preset::Program { plugin_id, program })
+            }
+            b"FxBk" | b"FBCh" => {
+                // Version 2 banks store the current program in the first of 128 reserved bytes
+                let reserved = reader.bytes(128, "bank header")?;
+                let current = i32::from_be_bytes(reserved[..4].try_into().unwrap());
+                let current = (current > 0 && (current as usize) < count.max(1)).then_some(current);
+
+                if &header == b"FBCh" {
+                    let size = reader.size("chunk size")?;
+                    let chunk = reader.bytes(size, "chunk")?.to_vec();
+                    return Ok(Preset::BankChunk {
+                        plugin_id,
+                        current,
+                        chunk,
+                    });
+                }
+
+                let mut programs = Vec::with_capacity(count.min(1024));
+                for index in 0..count {
+                    let (header, program_id, parameters) = read_header(&mut reader)
+                        .with_context(|| format!("in program {index} of the bank"))?;
+                    ensure!(
+                        program_id == plugin_id,
+                        "program {index} is for plugin {program_id}, the bank for {plugin_id}"
+                    );
+                    let program = read_program(&mut reader, &header, parameters)
+                        .with_context(|| format!("in program {index} of the bank"))?;
+                    programs.push(program);
+                }
+
+                Ok(Preset::Bank {
+                    plugin_id,
+                    current,
+                    programs,
+                })
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    pub fn plugin_id(&self) -> i32 {
+        match self {
+            Preset::Program { plugin_id, .. }
+            | Preset::Bank { plugin_id, .. }
+            | Preset::BankChunk { plugin_id, .. } => *plugin_id,
+        }
+    }
+
+    /// Checks that the preset is for the plugin described by `info` and fits it
+    pub fn check(&self, info: &Info) -> Result<()> {
+        ensure!(
+            self.plugin_id() == info.unique_id,
+            "the preset is for the plugin with ID {}, not {} ({})",
+            self.plugin_id(),
+            info.name,
+            info.unique_id
+        );
+
+        let programs = match self {
+            Preset::Program { program, .. } => std::slice::from_ref(program),
+            Preset::Bank { programs, .. } => {
+                ensure!(
+                    programs.len() <= info.presets.max(0) as usize,
+                    "the bank has {} programs, but {} only has {}",
+                    programs.len(),
+                    info.name,
+                    info.presets
+                );
+                programs
+            }
+            Preset::BankChunk { .. } => &[],
+        };
+        let chunk = matches!(self, Preset::BankChunk { .. })
+            || programs
+                .iter()
+                .any(|program| matches!(program.data, Data::Chunk(_)));
+        ensure!(
+            !chunk || info.preset_chunks,
+            "the preset is a chunk, but {} doesn't load chunks",
+            info.name
+        );
+
+        for program in programs {
+            if let Data::Parameters(values) = &program.data {
+                ensure!(
+                    values.len() == info.parameters as usize,
+                    "the preset has {} parameters, but {} has {}",
+                    values.len(),
+                    info.name,
+                    info.parameters
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The program the plugin behind `parameters` is playing now, as a chunk if it saves chunks
+    pub fn capture(parameters: &Parameters, info: &Info) -> Self {
+        let data = if info.preset_chunks {
+            Data::Chunk(parameters.get_preset_data())
+        } else {
+            Data::Parameters(
+                (0..info.parameters)
+                    .map(|index| parameters.get_parameter(index))
+                    .collect(),
+            )
+        };
+
+        Preset::Program {
+            plugin_id: info.unique_id,
+            program: Program {
+                name: parameters.get_preset_name(parameters.get_preset_num()),
+                data,
+            },
+        }
+    }
+
+    /// Writes a single program as an FXP file
+    pub fn write(&self, path: &Path) -> Result<()> {
+        let (plugin_id, program) = match self {
+            Preset::Program { plugin_id, program } => (*plugin_id, program),
+            _ => bail!("only single programs can be written"),
+        };
+
+        let (magic, count, data) = match &program.data {
+            Data::Parameters(values) => (
+                b"FxCk",
+                values.len(),
+                values
+                    .iter()
+                    .flat_map(|value| value.to_be_bytes())
+                    .collect(),
+            ),
+            Data::Chunk(chunk) => {
+                let mut data = (chunk.len() as i32).to_be_bytes().to_vec();
+                data.extend_from_slice(chunk);
+                (b"FPCh", 0, data)
+            }
+        };
+        let mut name = [0; NAME_LENGTH];
+        // The last byte stays zero, to terminate the name
+        let length = program.name.len().min(NAME_LENGTH - 1);
+        name[..length].copy_from_slice(&program.name.as_bytes()[..length]);
+
+        let mut bytes = b"CcnK".to_vec();
+        // The size counts everything after itself
+        let size = 4 * 5 + NAME_LENGTH + data.len();
+        bytes.extend_from_slice(&(size as i32).to_be_bytes());
+        bytes.extend_from_slice(magic);
+        for field in [1, plugin_id, 1, count as i32] {
+            bytes.extend_from_slice(&field.to_be_bytes());
+        }
+        bytes.extend_from_slice(&name);
+        bytes.extend_from_slice(&data);
+
+        fs::write(path, bytes).with_context(|| format!("failed to write {}", path.display()))
+    }
+
+    /// Loads the preset into the plugin, giving up after `timeout` in case the plugin chokes on it.
+    /// A plugin that timed out can't be called into again, see [`with_timeout`].
+    pub fn apply(self, parameters: Parameters, timeout: Duration) -> Result<()> {
+        with_timeout(timeout, "loading the preset", move || match self {
+            Preset::Program { program, .. } => load_program(&parameters, program),
+            Preset::Bank {
+                current, programs, ..
+            } => {
+                for (index, program) in programs.into_iter().enumerate() {
+                    parameters.change_preset(index as i32);
+                    load_program(&parameters, program);
+                }
+                parameters.change_preset(current.unwrap_or(0));
+            }
+            Preset::BankChunk { current, chunk, .. } => {
+                parameters.load_bank_data(&chunk);
+                parameters.change_preset(current.unwrap_or(0));
+            }
+        })
+    }
+}
+
+/// Saves every program of the plugin as an FXP file of its own in `directory`, named after its
+/// number and name like `003 Warm Pad.fxp`, with as many digits as the last number so they sort in
+/// order, and returns how many there were.
+///
+/// The plugin is switched through its programs to save them and back to its current one after, so
+/// it should be bypassed meanwhile. That happens on a helper thread, giving up after `timeout`,
+/// after which the plugin can't be called into again, see [`with_timeout`].
+pub fn export_programs(
+    parameters: &Parameters,
+    info: &Info,
+    directory: &Path,
+    timeout: Duration,
+) -> Result<usize> {
+    fs::create_dir_all(directory)
+        .with_context(|| format!("failed to create {}", directory.display()))?;
+
+    let programs = {
+        let parameters = parameters.clone();
+        let info = info.clone();
+        with_timeout(timeout, "saving the programs", move || {
+            let current = parameters.get_preset_num();
+            let programs: Vec<_> = (0..info.presets)
+                .map(|index| {
+                    parameters.change_preset(index);
+                    let preset = Preset::capture(&parameters, &info);
+                    (parameters.get_preset_name(index), preset)
+                })
+                .collect();
+            parameters.change_preset(current);
+            programs
+        })?
+    };
+
+    let digits = info.presets.max(1).to_string().len();
+    for (index, (name, preset)) in programs.iter().enumerate() {
+        // Only characters that are safe in file names on every system are kept
+        let name: String = name
+            .chars()
+            .map(|c| {
+                if c.is_alphanumeric() || " -_".contains(c) {
+                    c
+                } else {
+                    '_'
+                }
+            })
+            .collect();
+        let name = match name.trim() {
+            "" => "Program",
+            name => name,
+        };
+        preset.write(&directory.join(format!("{:0digits$} {name}.fxp", index + 1)))?;
+    }
+
+    Ok(programs.len())
+}
+
+/// Loads the FXP files in `directory` into the plugin's programs, the first file by name into the
+/// first program and so on, and returns how many were loaded.
+///
+/// Every file is checked before any is loaded, so a bad one leaves the programs as they were. A
+/// plugin that times out loading them can't be called into again, see [`with_timeout`].
+pub fn import_programs(
+    parameters: Parameters,
+    info: &Info,
+    directory: &Path,
+    timeout: Duration,
+) -> Result<usize> {
+    let mut paths: Vec<PathBuf> = fs::read_dir(directory)
+        .with_context(|| format!("failed to read {}", directory.display()))?
+        .filter_map(|entry| Some(entry.ok()?.path()))
+        .filter(|path| {
+            path.extension()
+                .is_some_and(|extension| extension.eq_ignore_ascii_case("fxp"))
+        })
+        .collect();
+    paths.sort();
+    ensure!(
+        !paths.is_empty(),
+        "{} has no FXP files",
+        directory.display()
+    );
+    ensure!(
+        paths.len() <= info.presets.max(0) as usize,
+        "{} has {} FXP files, but {} only has {} programs",
+        directory.display(),
+        paths.len(),
+        info.name,
+        info.presets
+    );
+
+    let mut programs = Vec::new();
+    for path in &paths {
+        let preset = Preset::read(path)?;
+        preset
+            .check(info)
+            .with_context(|| format!("can't load {}", path.display()))?;
+        match preset {
+            Preset::Program { program, .. } => programs.push(program),
+            _ => bail!("{} is a bank, not a single program", path.display()),
+        }
+    }
+
+    let count = programs.len();
+    with_timeout(timeout, "loading the programs", move || {
+        let current = parameters.get_preset_num();
+        for (index, program) in programs.into_iter().enumerate() {
+            parameters.change_preset(index as i32);
+            load_program(&parameters, program);
+        }
+        parameters.change_preset(current);
+    })?;
+
+    Ok(count)
+}
+
+/// Reads the header shared by programs and banks, returning its magic number, the plugin ID and
+/// the parameter or program count
+fn read_header(reader: &mut Reader) -> Result<([u8; 4], i32, usize)> {
+    let chunk_magic = reader.magic("file magic")?;
+    ensure!(
+        &chunk_magic == b"CcnK",
+        "expected the magic number CcnK, found {}",
+        String::from_utf8_lossy(&chunk_magic)
+    );
+
+    let byte_size = reader.size("byte size")?;
+    let remaining = reader.bytes.len() - reader.position;
+    ensure!(
+        byte_size <= remaining,
+        "the header says the preset is {byte_size} bytes long, but only {remaining} follow"
+    );
+
+    let magic = reader.magic("preset type")?;
+    if !matches!(&magic, b"FxCk" | b"FPCh" | b"FxBk" | b"FBCh") {
+        bail!(
+            "unknown preset type {}, expected FxCk, FPCh, FxBk or FBCh",
+            String::from_utf8_lossy(&magic)
+        );
+    }
+
+    let _format_version = reader.i32("format version")?;
+    let plugin_id = reader.i32("plugin ID")?;
+    let _plugin_version = reader.i32("plugin version")?;
+    let count = reader.size("parameter or program count")?;
+
+    Ok((magic, plugin_id, count))
+}
+
+/// Reads the rest of a program after its header
+fn read_program(reader: &mut Reader, magic: &[u8; 4], parameters: usize) -> Result<Program> {
+    let name = reader.bytes(NAME_LENGTH, "program name")?;
+    let name = name.split(|&byte| byte == 0).next().unwrap_or_default();
+    let name = String::from_utf8_lossy(name).into_owned();
+
+    let data = if magic == b"FPCh" {
+        let size = reader.size("chunk size")?;
+        Data::Chunk(reader.bytes(size, "chunk")?.to_vec())
+    } else {
+        let bytes = reader.bytes(
+            parameters.checked_mul(4).context("too many parameters")?,
+            "parameter values",
+        )?;
+        let values: Vec<f32> = bytes
+            .chunks_exact(4)
+            .map(|value| f32::from_be_bytes(value.try_into().unwrap()))
+            .collect();
+        if let Some(index) = values.iter().position(|value| !(0. ..=1.).contains(value)) {
+            bail!(
+                "parameter {index} of program {name:?} is {}, outside 0 to 1",
+                values[index]
+            );
+        }
+        Data::Parameters(values)
+    };
+
+    Ok(Program { name, data })
+}
+
+fn load_program(parameters: &Parameters, program: Program) {
+    match program.data {
+        Data::Parameters(values) => {
+            for (index, value) in values.into_iter().enumerate() {
+                parameters.set_parameter(index as i32, value);
+            }
+        }
+        Data::Chunk(chunk) => parameters.load_preset_data(&chunk),
+    }
+    parameters.set_preset_name(program.name);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, process};
+
+    use vst::plugin::Info;
+
+    use super::{Data, Preset, Program};
+
+    /// A `CcnK` chunk of type `magic` with the header fields, `count` and `body` after them
+    fn chunk(magic: &[u8; 4], plugin_id: i32, count: i32, body: &[u8]) -> Vec<u8> {
+        let mut bytes = b"CcnK".to_vec();
+        bytes.extend((4 * 5 + body.len() as i32).to_be_bytes());
+        bytes.extend(magic);
+        for field in [1, plugin_id, 1, count] {
+            bytes.extend(field.to_be_bytes());
+        }
+        bytes.extend(body);
+        bytes
+    }
+
+    fn program(plugin_id: i32, name: &str, values: &[f32]) -> Vec<u8> {
+        let mut body = name.as_bytes().to_vec();
+        body.resize(28, 0);
+        body.extend(values.iter().flat_map(|value| value.to_be_bytes()));
+        chunk(b"FxCk", plugin_id, values.len() as i32, &body)
+    }
+
+    fn info(parameters: i32) -> Info {
+        Info {
+            name: "Synth".to_owned(),
+            unique_id: 1234,
+            presets: 2,
+            parameters,
+            ..Info::default()
+        }
+    }
+
+    fn round_trip(preset: &Preset) -> Preset {
+        let path = env::temp_dir().join(format!("y-preset-test-{}.fxp", process::id()));
+        preset.write(&path).unwrap();
+        let read = Preset::read(&path);
+        fs::remove_file(&path).unwrap();
+        read.unwrap()
+    }
+
+    #[test]
+    fn written_programs_read_back() {
+        let preset = round_trip(&Preset::Program {
+            plugin_id: 1234,
+            program: Program {
+                name: "Warm Pad".to_owned(),
+                data: Data::Parameters(vec![0., 0.5, 1.]),
+            },
+        });
+        match preset {
+            Preset::Program {
+                plugin_id: 1234,
+                program:
+                    Program {
+                        name,
+                        data: Data::Parameters(values),
+                    },
+            } => {
+                assert_eq!(name, "Warm Pad");
+                assert_eq!(values, [0., 0.5, 1.]);
+            }
+            preset => panic!("expected the program back, got {preset:?}"),
+        }
+
+        let preset = round_trip(&Preset::Program {
+            plugin_id: 1234,
+            program: Program {
+                name: "a name far longer than the field has room for".to_owned(),
+                data: Data::Chunk(vec![1, 2, 3]),
+            },
+        });
+        match preset {
+            Preset::Program {
+                program:
+                    Program {
+                        name,
+                        data: Data::Chunk(chunk),
+                    },
+                ..
+            } => {
+                assert_eq!(name, "a name far longer than the ");
+                assert_eq!(chunk, [1, 2, 3]);
+            }
+            preset => panic!("expected the chunk back, got {preset:?}"),
+        }
+    }
+
+    #[test]
+    fn parses_banks() {
+        let mut body = vec![0; 128];
+        body[3] = 1;
+        body.extend(program(1234, "First", &[0.25]));
+        body.extend(program(1234, "Second", &[0.75]));
+        let bank = Preset::parse(&chunk(b"FxBk", 1234, 2, &body)).unwrap();
+
+        match &bank {
+            Preset::Bank {
+                plugin_id: 1234,
+                current: Some(1),
+                programs,
+            } => {
+                let names: Vec<_> = programs.iter().map(|p| p.name.as_str()).collect();
+                assert_eq!(names, ["First", "Second"]);
+                assert!(matches!(&programs[1].data, Data::Parameters(v) if v == &[0.75]));
+            }
+            bank => panic!("expected a bank of two programs, got {bank:?}"),
+        }
+        bank.check(&info(1)).unwrap();
+        assert!(bank.check(&info(2)).is_err());
+    }
+
+    #[test]
+    fn banks_hold_programs_of_their_own_plugin() {
+        let mut body = vec![0; 128];
+        body.extend(program(99, "Stray", &[0.5]));
+        assert!(Preset::parse(&chunk(b"FxBk", 1234, 1, &body)).is_err());
+    }
+
+    #[test]
+    fn truncated_files_are_rejected() {
+        let bytes = program(1234, "Short", &[0.5, 0.5]);
+        let error = Preset::parse(&bytes[..bytes.len() - 2]).unwrap_err();
+        assert!(format!("{error:#}").contains("only"), "{error:#}");
+
+        let mut bytes = program(1234, "Short", &[0.5, 0.5]);
+        bytes.truncate(bytes.len() - 4);
+        let size = bytes.len() as i32 - 8;
+        bytes[4..8].copy_from_slice(&size.to_be_bytes());
+        let error = Preset::parse(&bytes).unwrap_err();
+        assert!(
+            format!("{error:#}").contains("parameter values"),
+            "{error:#}"
+        );
+    }
+
+    #[test]
+    fn values_outside_0_to_1_are_rejected() {
+        let error = Preset::parse(&program(1234, "Loud", &[0.5, 2.])).unwrap_err();
+        assert!(format!("{error:#}").contains("parameter 1"), "{error:#}");
+    }
+
+    #[test]
+    fn presets_must_fit_the_plugin() {
+        let preset = Preset::parse(&program(1234, "Pad", &[0.5, 0.5])).unwrap();
+        preset.check(&info(2)).unwrap();
+        assert!(preset.check(&info(3)).is_err());
+        assert!(preset
+            .check(&Info {
+                unique_id: 4321,
+                ..info(2)
+            })
+            .is_err());
+    }
+}
@@ -0,0 +1,122 @@
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{ensure, Context, Result};
+
+/// Options for `y run` kept in a file, so a setup doesn't have to be typed out every time.
+///
+/// Each line of a profile holds an option of `y run` without its leading dashes, followed by its
+/// value if it takes one:
+///
+/// ```text
+/// host-vendor Steinberg
+/// load-timeout 10
+/// map-cc 1:0:log
+/// disable-editor
+/// ```
+///
+/// The exception is `search-path <directory>`, which adds a directory to look for plugins in for
+/// every command. Empty lines and lines starting with `#` are ignored.
+#[derive(Debug, Default)]
+pub struct Profile {
+    /// Options for `y run` without their dashes, with their values if they take one
+    pub options: Vec<(String, Option<String>)>,
+    pub search_paths: Vec<PathBuf>,
+}
+
+impl Profile {
+    /// Loads the profile called `name` from the configuration directory
+    pub fn load(name: &str) -> Result<Self> {
+        let path = profile_path(name).context("no configuration directory, HOME isn't set")?;
+        let text = fs::read_to_string(&path)
+            .with_context(|| format!("failed to read profile {name:?} from {}", path.display()))?;
+        Self::parse(&text, &path.display().to_string())
+    }
+
+    /// Loads the profile called `default` if there is one
+    pub fn load_default() -> Result<Option<Self>> {
+        match profile_path("default").filter(|path| path.exists()) {
+            Some(_) => Self::load("default").map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Parses the profile in `text`, read from `origin`
+    pub fn parse(text: &str, origin: &str) -> Result<Self> {
+        let mut profile = Self::default();
+
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let (option, value) = match line.split_once(char::is_whitespace) {
+                Some((option, value)) => (option, Some(value.trim())),
+                None => (line, None),
+            };
+            ensure!(
+                !option.starts_with('-'),
+                "{origin}:{}: options are written without dashes",
+                number + 1
+            );
+
+            if option == "search-path" {
+                let path = value.with_context(|| {
+                    format!("{origin}:{}: search-path needs a directory", number + 1)
+                })?;
+                profile.search_paths.push(path.into());
+            } else {
+                profile
+                    .options
+                    .push((option.to_owned(), value.map(str::to_owned)));
+            }
+        }
+
+        Ok(profile)
+    }
+
+    /// Adds the profile's search paths after those in `VST_PATH`.
+    ///
+    /// This changes the environment, so it has to happen before any threads are started.
+    pub fn apply_search_paths(&self) -> Result<()> {
+        if self.search_paths.is_empty() {
+            return Ok(());
+        }
+
+        let mut paths: Vec<PathBuf> = env::var_os("VST_PATH")
+            .map(|paths| env::split_paths(&paths).collect())
+            .unwrap_or_default();
+        paths.extend(self.search_paths.iter().cloned());
+        env::set_var("VST_PATH", env::join_paths(paths)?);
+
+        Ok(())
+    }
+}
+
+/// The host's configuration directory, `$XDG_CONFIG_HOME/y` by default
+pub fn config_dir() -> Option<PathBuf> {
+    let config = match env::var_os("XDG_CONFIG_HOME") {
+        Some(config) => PathBuf::from(config),
+        None => Path::new(&env::var_os("HOME")?).join(".config"),
+    };
+
+    Some(config.join("y"))
+}
+
+/// The host's cache directory, `$XDG_CACHE_HOME/y` by default
+pub fn cache_dir() -> Option<PathBuf> {
+    let cache = match env::var_os("XDG_CACHE_HOME") {
+        Some(cache) => PathBuf::from(cache),
+        None => Path::new(&env::var_os("HOME")?).join(".cache"),
+    };
+
+    Some(cache.join("y"))
+}
+
+/// Where the profile called `name` lives, `$XDG_CONFIG_HOME/y/<name>.profile` by default
+pub fn profile_path(name: &str) -> Option<PathBuf> {
+    Some(config_dir()?.join(format!("{name}.profile")))
+}
@@ -0,0 +1,189 @@
+use std::{fmt::Write as _, ops::RangeInclusive, os::raw::c_char, ptr};
+
+use vst::{api::AEffect, plugin::OpCode};
+
+use crate::{
+    effect::{c_string, dispatch},
+    json,
+};
+
+const IS_SWITCH: i32 = 1 << 0;
+const USES_INTEGER_MIN_MAX: i32 = 1 << 1;
+const USES_FLOAT_STEP: i32 = 1 << 2;
+const USES_INT_STEP: i32 = 1 << 3;
+const SUPPORTS_DISPLAY_INDEX: i32 = 1 << 4;
+const SUPPORTS_DISPLAY_CATEGORY: i32 = 1 << 5;
+
+/// `VstParameterProperties`, which `vst` doesn't define
+#[repr(C)]
+#[allow(dead_code)]
+struct VstParameterProperties {
+    step_float: f32,
+    small_step_float: f32,
+    large_step_float: f32,
+    label: [c_char; 64],
+    flags: i32,
+    min_integer: i32,
+    max_integer: i32,
+    step_integer: i32,
+    large_step_integer: i32,
+    short_label: [c_char; 8],
+    display_index: i16,
+    category: i16,
+    parameters_in_category: i16,
+    reserved: i16,
+    category_label: [c_char; 24],
+    future: [c_char; 16],
+}
+
+/// A group of parameters the plugin lists together
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Category {
+    /// Starts at 1
+    pub index: i16,
+    pub label: String,
+}
+
+/// What a plugin says about how one of its parameters is shown, beyond its name and value
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Properties {
+    /// Whether the parameter is either on or off
+    pub switch: bool,
+    /// The whole numbers the parameter stands for, spread evenly over 0 to 1
+    pub integer: Option<RangeInclusive<i32>>,
+    /// How far the value moves in one step, then in one large step, on the 0 to 1 scale
+    pub step: Option<(f32, f32)>,
+    /// Where the plugin would list the parameter, which may differ from its index
+    pub display_index: Option<i16>,
+    pub category: Option<Category>,
+}
+
+impl Properties {
+    /// Asks the plugin behind `effect` about parameter `index`, `None` if it doesn't say.
+    ///
+    /// # Safety
+    ///
+    /// `effect` has to belong to a plugin that is still loaded.
+    pub unsafe fn query(effect: *mut AEffect, index: i32) -> Option<Self> {
+        let mut raw: VstParameterProperties = std::mem::zeroed();
+        let supported = dispatch(
+            effect,
+            OpCode::GetParamInfo,
+            index,
+            0,
+            ptr::addr_of_mut!(raw).cast(),
+            0.,
+        );
+        (supported != 0).then(|| Self::from_raw(&raw))
+    }
+
+    fn from_raw(raw: &VstParameterProperties) -> Self {
+        let flag = |flag| raw.flags & flag != 0;
+        let step = flag(USES_FLOAT_STEP).then_some((raw.step_float, raw.large_step_float));
+
+        Self {
+            switch: flag(IS_SWITCH),
+            integer: flag(USES_INTEGER_MIN_MAX)
+                .then_some(raw.min_integer..=raw.max_integer)
+                .filter(|range| !range.is_empty()),
+            step: step.or_else(|| {
+                // Integer steps count whole numbers, which only mean something with a range
+                let range = raw.max_integer - raw.min_integer;
+                (flag(USES_INT_STEP) && flag(USES_INTEGER_MIN_MAX) && range > 0).then(|| {
+                    (
+                        raw.step_integer.max(1) as f32 / range as f32,
+                        raw.large_step_integer.max(1) as f32 / range as f32,
+                    )
+                })
+            }),
+            display_index: flag(SUPPORTS_DISPLAY_INDEX).then_some(raw.display_index),
+            category: (flag(SUPPORTS_DISPLAY_CATEGORY) && raw.category > 0).then(|| Category {
+                index: raw.category,
+                label: c_string(&raw.category_label),
+            }),
+        }
+    }
+
+    /// How many values the parameter can take, if it can only take a few
+    pub fn steps(&self) -> Option<usize> {
+        if self.switch {
+            Some(2)
+        } else {
+            self.integer
+                .as_ref()
+                .map(|range| (*range.end() - *range.start()) as usize + 1)
+        }
+    }
+
+    /// Snaps `value` to the nearest of the parameter's steps, if it has any
+    pub fn snap(&self, value: f32) -> f32 {
+        match self.steps() {
+            Some(steps) if steps > 1 => {
+                let last = (steps - 1) as f32;
+                (value.clamp(0., 1.) * last).round() / last
+            }
+            _ => value,
+        }
+    }
+
+    /// Describes the kind of parameter, like `switch` or `integer 0 to 7`, or nothing for a plain
+    /// continuous one
+    pub fn kind(&self) -> Option<String> {
+        if self.switch {
+            Some("switch".to_string())
+        } else {
+            self.integer
+                .as_ref()
+                .map(|range| format!("integer {} to {}", range.start(), range.end()))
+        }
+    }
+
+    pub fn json(&self) -> String {
+        let mut json = format!("{{\"switch\": {}", self.switch);
+        if let Some(range) = &self.integer {
+            let _ = write!(
+                json,
+                ", \"min_integer\": {}, \"max_integer\": {}",
+                range.start(),
+                range.end()
+            );
+        }
+        if let Some(steps) = self.steps() {
+            let _ = write!(json, ", \"steps\": {steps}");
+        }
+        if let Some((step, large_step)) = self.step {
+            let _ = write!(json, ", \"step\": {step}, \"large_step\": {large_step}");
+        }
+        if let Some(index) = self.display_index {
+            let _ = write!(json, ", \"display_index\": {index}");
+        }
+        if let Some(category) = &self.category {
+            let _ = write!(
+                json,
+                ", \"category\": {}, \"category_label\": {}",
+                category.index,
+                json::string(&category.label)
+            );
+        }
+        json.push('}');
+        json
+    }
+}
+
+/// Orders parameter indices the way the plugin would list them: by category, then by display
+/// index, with parameters the plugin says nothing about last in their original order
+pub fn display_order(properties: &[Option<Properties>]) -> Vec<i32> {
+    let mut order: Vec<i32> = (0..properties.len() as i32).collect();
+    order.sort_by_key(|&index| {
+        let properties = properties[index as usize].as_ref();
+        let category = properties.and_then(|properties| properties.category.as_ref());
+        (
+            category.map_or(i16::MAX, |category| category.index),
+            properties
+                .and_then(|properties| properties.display_index)
+                .unwrap_or(i16::MAX),
+            index,
+        )
+    });
+    order
+}
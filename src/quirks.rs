@@ -0,0 +1,119 @@
+use std::{collections::HashMap, fs, path::PathBuf};
+
+use anyhow::{bail, ensure, Context, Result};
+
+use crate::profile::config_dir;
+
+/// The quirk database shipped with the host
+const BUNDLED: &str = include_str!("../quirks.txt");
+
+/// Workarounds the host applies for a specific plugin
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Quirks {
+    /// Always call `process()` with sub-blocks of this many samples
+    pub block_size: Option<usize>,
+    /// Never open the plugin's editor
+    pub no_editor: bool,
+    /// Send `effEditIdle` to an open editor this many times per second
+    pub editor_idle: Option<f64>,
+}
+
+/// Quirks for plugins by unique id.
+///
+/// Each line of a quirk file holds a unique id, either as a number or as four characters in
+/// single quotes like `'Abcd'`, followed by a quirk and its value if it takes one:
+///
+/// ```text
+/// 1234567 block-size 512
+/// 'Abcd'  editor-idle 60
+/// 'Abcd'  no-editor
+/// ```
+///
+/// Empty lines and lines starting with `#` are ignored.
+#[derive(Default)]
+pub struct QuirkDatabase {
+    plugins: HashMap<i32, Quirks>,
+}
+
+impl QuirkDatabase {
+    /// Loads the bundled database, followed by the user's database if there is one
+    pub fn load() -> Result<Self> {
+        let mut database = Self::default();
+        database.parse(BUNDLED, "bundled quirks")?;
+
+        if let Some(path) = user_database_path().filter(|path| path.exists()) {
+            let text = fs::read_to_string(&path)
+                .with_context(|| format!("failed to read {}", path.display()))?;
+            database.parse(&text, &path.display().to_string())?;
+        }
+
+        Ok(database)
+    }
+
+    /// Adds the quirks in `text`, read from `origin`
+    pub fn parse(&mut self, text: &str, origin: &str) -> Result<()> {
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            self.parse_line(line)
+                .with_context(|| format!("{origin}:{}: invalid quirk", number + 1))?;
+        }
+
+        Ok(())
+    }
+
+    fn parse_line(&mut self, line: &str) -> Result<()> {
+        let mut fields = line.split_whitespace();
+        let id = parse_unique_id(fields.next().context("missing unique id")?)?;
+        let quirk = fields.next().context("missing quirk")?;
+        let value = fields.next();
+        ensure!(fields.next().is_none(), "unexpected trailing fields");
+
+        let quirks = self.plugins.entry(id).or_default();
+        match quirk {
+            "block-size" => {
+                let block_size = value.context("missing block size")?.parse()?;
+                ensure!(block_size > 0, "block size must be positive");
+                quirks.block_size = Some(block_size);
+            }
+            "no-editor" => quirks.no_editor = true,
+            "editor-idle" => {
+                let rate = value.context("missing idle rate")?.parse()?;
+                ensure!(rate > 0., "idle rate must be positive");
+                quirks.editor_idle = Some(rate);
+            }
+            _ => bail!("unknown quirk {quirk:?}"),
+        }
+
+        Ok(())
+    }
+
+    /// The quirks for the plugin with `unique_id`, if it has any
+    pub fn get(&self, unique_id: i32) -> Option<&Quirks> {
+        self.plugins.get(&unique_id)
+    }
+}
+
+/// Parses a unique id given as a number or as four characters like `'Abcd'`
+fn parse_unique_id(text: &str) -> Result<i32> {
+    if let Some(code) = text
+        .strip_prefix('\'')
+        .and_then(|text| text.strip_suffix('\''))
+    {
+        let bytes: [u8; 4] = code
+            .as_bytes()
+            .try_into()
+            .context("expected four characters")?;
+        return Ok(i32::from_be_bytes(bytes));
+    }
+
+    Ok(text.parse()?)
+}
+
+/// Where the user's quirk database lives, `$XDG_CONFIG_HOME/y/quirks.txt` by default
+pub fn user_database_path() -> Option<PathBuf> {
+    Some(config_dir()?.join("quirks.txt"))
+}
@@ -0,0 +1,581 @@
+use std::{
+    path::Path,
+    str::FromStr,
+    sync::{Arc, Mutex},
+};
+
+use anyhow::{bail, ensure, Context, Result};
+use clap::ArgEnum;
+use hound::{SampleFormat, WavReader, WavSpec, WavWriter};
+use vst::host::{Host, PluginLoader};
+
+use crate::{
+    diagnose,
+    hosted::{HostedPlugin, Vst2Plugin},
+    instance::Instance,
+    lifecycle::{Lifecycle, State},
+    limiter::db_to_gain,
+    scene::Length,
+};
+
+/// A level in dBFS, written with or without the unit
+#[derive(Clone, Copy, Debug)]
+pub struct Level(pub f32);
+
+impl FromStr for Level {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let number = s
+            .strip_suffix("dBFS")
+            .or_else(|| s.strip_suffix("dB"))
+            .unwrap_or(s);
+        Ok(Level(number.trim().parse().context("invalid level")?))
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum BitDepth {
+    Int(u16),
+    Float,
+}
+
+impl FromStr for BitDepth {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "16" => Ok(BitDepth::Int(16)),
+            "24" => Ok(BitDepth::Int(24)),
+            "32f" => Ok(BitDepth::Float),
+            _ => bail!("unsupported bit depth {s:?}, expected 16, 24 or 32f"),
+        }
+    }
+}
+
+/// A channel for each position, or none for silence
+#[derive(Clone, Debug)]
+pub struct ChannelMap(pub Vec<Option<usize>>);
+
+impl FromStr for ChannelMap {
+    type Err = anyhow::Error;
+
+    /// Parses maps like `0,1` or `0,-`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.split(',')
+            .map(|channel| match channel.trim() {
+                "-" => Ok(None),
+                channel => Ok(Some(channel.parse().context("invalid channel")?)),
+            })
+            .collect::<Result<_>>()
+            .map(ChannelMap)
+    }
+}
+
+#[derive(ArgEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Downmix {
+    /// Mix extra channels into the inputs, channel n going to input n modulo the input count
+    Mix,
+    /// Drop the extra channels
+    Drop,
+}
+
+/// How file channels are routed to the plugin's inputs, and its outputs back to a file
+#[derive(clap::Args, Clone, Debug)]
+pub struct Routing {
+    /// File channel feeding each plugin input, like `0,0` to feed the left channel to both inputs
+    /// or `1,-` to leave the second input silent. By default channels go to the input of the same
+    /// number, mono files feed every input, and extra channels are handled by `--downmix`.
+    #[clap(long)]
+    pub input_map: Option<ChannelMap>,
+
+    /// What to do with file channels beyond the plugin's inputs when there's no `--input-map`
+    #[clap(long, arg_enum, default_value = "mix")]
+    pub downmix: Downmix,
+
+    /// Plugin outputs written to the file, like `0,1` for the first two. By default every output
+    /// is written.
+    #[clap(long)]
+    pub output_map: Option<ChannelMap>,
+}
+
+/// How long to keep processing after the input ends
+#[derive(clap::Args, Clone, Debug)]
+pub struct Tail {
+    /// Seconds of silence to keep processing after the input, so reverb and delay tails aren't
+    /// cut off
+    #[clap(long, default_value_t = 0., conflicts_with = "until-silence")]
+    pub tail: f64,
+
+    /// Keep processing after the input until the output stays below this level, like -90dB
+    #[clap(long, allow_hyphen_values = true)]
+    pub until_silence: Option<Level>,
+
+    /// Longest tail processed with `--until-silence`, like 30s
+    #[clap(long, default_value = "30s", parse(try_from_str = parse_seconds))]
+    pub max: f64,
+}
+
+impl Tail {
+    /// How long the output has to stay below the threshold to count as silent
+    const HOLD: f64 = 0.1;
+}
+
+fn parse_seconds(s: &str) -> Result<f64> {
+    match s.parse()? {
+        Length::Seconds(seconds) => Ok(seconds),
+        _ => bail!("expected a time like 30s or 500ms"),
+    }
+}
+
+struct RenderHost;
+
+impl Host for RenderHost {}
+
+/// A plugin set up for offline processing, with the state it started in
+pub struct Renderer {
+    pub instance: Instance,
+    /// The plugin's state right after loading, restored between files
+    initial: Vec<u8>,
+    block_size: usize,
+}
+
+impl Renderer {
+    pub fn new(path: &Path, block_size: usize, sample_rate: f32) -> Result<Self> {
+        let host = Arc::new(Mutex::new(RenderHost));
+        let mut loader =
+            PluginLoader::load(path, host).map_err(|err| diagnose::explain(path, err))?;
+        let mut plugin = Vst2Plugin::new(loader.instance()?);
+
+        Lifecycle::new(State::Created).set(&mut plugin, State::Suspended);
+        plugin.set_sample_rate(sample_rate);
+        plugin.set_block_size(block_size);
+        let initial = plugin.save_state();
+
+        Ok(Self {
+            instance: Instance::new(plugin, block_size, block_size, 1),
+            initial,
+            block_size,
+        })
+    }
+
+    /// Puts the plugin back into the state it was loaded in
+    pub fn reset(&mut self, sample_rate: f32) {
+        self.instance.plugin.load_state(&self.initial);
+        self.instance.reset(sample_rate);
+    }
+
+    /// Processes the WAV file at `input` followed by silence for as long as `tail` says, returning
+    /// the interleaved output and the format of the input with the output's channel count.
+    ///
+    /// `progress` is called after every block with the frames done and the total.
+    pub fn render(
+        &mut self,
+        input: &Path,
+        routing: &Routing,
+        tail: &Tail,
+        mut progress: impl FnMut(usize, usize),
+    ) -> Result<(WavSpec, Vec<f32>)> {
+        let (spec, samples) = read_wav(input)?;
+        let channels = spec.channels as usize;
+        let frames = samples.len() / channels;
+        let sample_rate = spec.sample_rate as f64;
+        let (total, mut silence) = match tail.until_silence {
+            Some(level) => (
+                frames + (tail.max * sample_rate) as usize,
+                Some(Silence {
+                    threshold: db_to_gain(level.0),
+                    hold: (Tail::HOLD * sample_rate) as usize,
+                    input: frames,
+                    since: None,
+                }),
+            ),
+            None => (frames + (tail.tail * sample_rate) as usize, None),
+        };
+
+        let matrix = input_matrix(
+            channels,
+            self.instance.info.inputs as usize,
+            routing.input_map.as_ref(),
+            routing.downmix,
+        )?;
+        let outputs = self.instance.outputs.len();
+        let output_map: Vec<Option<usize>> = match &routing.output_map {
+            Some(map) => {
+                for &output in map.0.iter().flatten() {
+                    ensure!(output < outputs, "the plugin has no output {output}");
+                }
+                map.0.clone()
+            }
+            None => (0..outputs).map(Some).collect(),
+        };
+        ensure!(!output_map.is_empty(), "no outputs to write");
+
+        // With a tail until silence this is the longest the render can take
+        self.instance.plugin.set_total_samples(total);
+
+        let mut rendered = Vec::with_capacity(total * output_map.len());
+        let mut inputs = vec![vec![0.; self.block_size]; matrix.len()];
+        for start in (0..total).step_by(self.block_size) {
+            let length = self.block_size.min(total - start);
+            for (input, sources) in inputs.iter_mut().zip(&matrix) {
+                for (i, sample) in input.iter_mut().enumerate() {
+                    let frame = start + i;
+                    *sample = if i < length && frame < frames {
+                        sources
+                            .iter()
+                            .map(|&(channel, gain)| samples[frame * channels + channel] * gain)
+                            .sum()
+                    } else {
+                        0.
+                    };
+                }
+            }
+
+            self.instance.process(&inputs, &[], 0..self.block_size);
+            progress(start + length, total);
+
+            for i in 0..length {
+                let frame = start + i;
+                let mut peak = 0f32;
+                for output in &output_map {
+                    let sample = output.map_or(0., |output| self.instance.outputs[output][i]);
+                    peak = peak.max(sample.abs());
+                    rendered.push(sample);
+                }
+
+                if let Some(silence) = &mut silence {
+                    silence.frame(frame, peak);
+                }
+            }
+
+            let end = start + length;
+            if let Some(quiet) = silence.as_ref().and_then(|silence| silence.end(end)) {
+                // The quiet part is left out, as if the tail had been cut at the threshold
+                rendered.truncate(quiet * output_map.len());
+                progress(total, total);
+                break;
+            }
+        }
+
+        let spec = WavSpec {
+            channels: output_map.len() as u16,
+            ..spec
+        };
+        Ok((spec, rendered))
+    }
+}
+
+/// Watches the output for where it goes quiet for good after the input ends
+struct Silence {
+    threshold: f32,
+    /// Frames the output has to stay below the threshold
+    hold: usize,
+    /// Frames of input, during which the output never counts as quiet
+    input: usize,
+    /// Where the output last went quiet, while it stays quiet
+    since: Option<usize>,
+}
+
+impl Silence {
+    /// Takes the peak of the output channels at `frame`
+    fn frame(&mut self, frame: usize, peak: f32) {
+        if frame < self.input || peak >= self.threshold {
+            self.since = None;
+        } else if self.since.is_none() {
+            self.since = Some(frame);
+        }
+    }
+
+    /// Where the output went quiet, once it has stayed quiet for long enough by frame `end`
+    fn end(&self, end: usize) -> Option<usize> {
+        self.since.filter(|&since| end - since >= self.hold)
+    }
+}
+
+/// The file channels and gains summed into each of the plugin's `inputs`
+fn input_matrix(
+    channels: usize,
+    inputs: usize,
+    map: Option<&ChannelMap>,
+    downmix: Downmix,
+) -> Result<Vec<Vec<(usize, f32)>>> {
+    if let Some(map) = map {
+        ensure!(
+            map.0.len() <= inputs,
+            "the input map has {} entries but the plugin only has {inputs} inputs",
+            map.0.len()
+        );
+        let mut matrix = vec![Vec::new(); inputs];
+        for (sources, channel) in matrix.iter_mut().zip(&map.0) {
+            if let Some(channel) = *channel {
+                ensure!(channel < channels, "the file has no channel {channel}");
+                sources.push((channel, 1.));
+            }
+        }
+        return Ok(matrix);
+    }
+
+    let mut matrix = vec![Vec::new(); inputs];
+    if channels == 1 {
+        for sources in &mut matrix {
+            sources.push((0, 1.));
+        }
+    } else {
+        for channel in 0..channels {
+            if channel < inputs || downmix == Downmix::Mix {
+                if let Some(sources) = matrix.get_mut(channel % inputs.max(1)) {
+                    sources.push((channel, 1.));
+                }
+            }
+        }
+        // Keep the level of mixed inputs the same as the channels they're mixed from
+        for sources in &mut matrix {
+            let gain = 1. / sources.len().max(1) as f32;
+            for (_, source_gain) in sources.iter_mut() {
+                *source_gain = gain;
+            }
+        }
+    }
+
+    Ok(matrix)
+}
+
+/// Reads a WAV file as interleaved samples from -1 to 1
+pub fn read_wav(path: &Path) -> Result<(WavSpec, Vec<f32>)> {
+    let mut reader =
+        WavReader::open(path).with_context(|| format!("failed to read {}", path.display()))?;
+    let spec = reader.spec();
+
+    let samples = match spec.sample_format {
+        SampleFormat::Float => reader.samples::<f32>().collect::<Result<_, _>>()?,
+        SampleFormat::Int => {
+            let scale = 1. / (1u64 << (spec.bits_per_sample - 1)) as f32;
+            reader
+                .samples::<i32>()
+                .map(|sample| sample.map(|sample| sample as f32 * scale))
+                .collect::<Result<_, _>>()?
+        }
+    };
+
+    Ok((spec, samples))
+}
+
+/// Scales `samples` so their peak is at `level` dBFS
+pub fn normalize(samples: &mut [f32], level: f32) {
+    let peak = samples
+        .iter()
+        .fold(0f32, |peak, sample| peak.max(sample.abs()));
+    if peak > 0. {
+        let gain = db_to_gain(level) / peak;
+        for sample in samples {
+            *sample *= gain;
+        }
+    }
+}
+
+/// Writes interleaved `samples` as a WAV file in the format of `spec`, or at `bit_depth`, adding
+/// dither to integer samples if `dither` is set
+pub fn write_wav(
+    path: &Path,
+    spec: WavSpec,
+    samples: &[f32],
+    bit_depth: Option<BitDepth>,
+    dither: bool,
+) -> Result<()> {
+    let spec = match bit_depth {
+        Some(BitDepth::Int(bits)) => WavSpec {
+            bits_per_sample: bits,
+            sample_format: SampleFormat::Int,
+            ..spec
+        },
+        Some(BitDepth::Float) => WavSpec {
+            bits_per_sample: 32,
+            sample_format: SampleFormat::Float,
+            ..spec
+        },
+        None => spec,
+    };
+
+    let mut writer = WavWriter::create(path, spec)?;
+    match spec.sample_format {
+        SampleFormat::Float => {
+            for &sample in samples {
+                writer.write_sample(sample)?;
+            }
+        }
+        SampleFormat::Int => {
+            let max = ((1u64 << (spec.bits_per_sample - 1)) - 1) as f32;
+            let mut dither = dither.then(Tpdf::new);
+            for &sample in samples {
+                let noise = dither.as_mut().map_or(0., Tpdf::next);
+                let value = (sample * max + noise).round().clamp(-max - 1., max);
+                writer.write_sample(value as i32)?;
+            }
+        }
+    }
+
+    writer.finalize()?;
+    Ok(())
+}
+
+/// Triangular dither noise of up to one step either way
+struct Tpdf {
+    random: u32,
+}
+
+impl Tpdf {
+    fn new() -> Self {
+        Self {
+            random: 0x2545_f491,
+        }
+    }
+
+    fn uniform(&mut self) -> f32 {
+        // xorshift32
+        self.random ^= self.random << 13;
+        self.random ^= self.random >> 17;
+        self.random ^= self.random << 5;
+        self.random as f32 / u32::MAX as f32
+    }
+
+    fn next(&mut self) -> f32 {
+        self.uniform() - self.uniform()
+    }
+}
+
+/// Makes the floating point unit treat denormal numbers as zero on this thread, so results don't
+/// depend on whatever mode the thread happened to start in
+pub fn flush_denormals() {
+    // Flush to zero and denormals are zero in MXCSR
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    #[allow(deprecated)]
+    unsafe {
+        #[cfg(target_arch = "x86")]
+        use std::arch::x86::{_mm_getcsr, _mm_setcsr};
+        #[cfg(target_arch = "x86_64")]
+        use std::arch::x86_64::{_mm_getcsr, _mm_setcsr};
+
+        _mm_setcsr(_mm_getcsr() | 0x8040);
+    }
+
+    // Flush to zero in FPCR
+    #[cfg(target_arch = "aarch64")]
+    unsafe {
+        let mut fpcr: u64;
+        std::arch::asm!("mrs {}, fpcr", out(reg) fpcr);
+        fpcr |= 1 << 24;
+        std::arch::asm!("msr fpcr, {}", in(reg) fpcr);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, process};
+
+    use hound::{SampleFormat, WavReader, WavSpec};
+
+    use super::{input_matrix, write_wav, BitDepth, ChannelMap, Downmix, Silence, Tpdf};
+
+    #[test]
+    fn mono_files_feed_every_input() {
+        let matrix = input_matrix(1, 2, None, Downmix::Mix).unwrap();
+        assert_eq!(matrix, [[(0, 1.)], [(0, 1.)]]);
+    }
+
+    #[test]
+    fn channels_go_to_the_input_of_the_same_number() {
+        let matrix = input_matrix(2, 3, None, Downmix::Mix).unwrap();
+        assert_eq!(matrix, [vec![(0, 1.)], vec![(1, 1.)], vec![]]);
+    }
+
+    #[test]
+    fn extra_channels_are_mixed_at_the_same_level() {
+        let matrix = input_matrix(2, 1, None, Downmix::Mix).unwrap();
+        assert_eq!(matrix, [[(0, 0.5), (1, 0.5)]]);
+        let matrix = input_matrix(3, 2, None, Downmix::Mix).unwrap();
+        assert_eq!(matrix, [vec![(0, 0.5), (2, 0.5)], vec![(1, 1.)]]);
+    }
+
+    #[test]
+    fn extra_channels_can_be_dropped() {
+        let matrix = input_matrix(4, 2, None, Downmix::Drop).unwrap();
+        assert_eq!(matrix, [[(0, 1.)], [(1, 1.)]]);
+    }
+
+    #[test]
+    fn input_maps_pick_channels() {
+        let map: ChannelMap = "1,-,1".parse().unwrap();
+        let matrix = input_matrix(2, 4, Some(&map), Downmix::Mix).unwrap();
+        assert_eq!(matrix, [vec![(1, 1.)], vec![], vec![(1, 1.)], vec![]]);
+
+        assert!(input_matrix(2, 2, Some(&map), Downmix::Mix).is_err());
+        let map: ChannelMap = "0,2".parse().unwrap();
+        assert!(input_matrix(2, 2, Some(&map), Downmix::Mix).is_err());
+        assert!("0,x".parse::<ChannelMap>().is_err());
+    }
+
+    #[test]
+    fn dither_stays_within_a_step_and_averages_out() {
+        let mut tpdf = Tpdf::new();
+        let noise: Vec<f32> = (0..100_000).map(|_| tpdf.next()).collect();
+        assert!(noise.iter().all(|noise| (-1. ..=1.).contains(noise)));
+        let mean = noise.iter().sum::<f32>() / noise.len() as f32;
+        assert!(mean.abs() < 0.01, "mean {mean}");
+        assert!(noise.iter().any(|&noise| noise.abs() > 0.5));
+    }
+
+    #[test]
+    fn dithered_integers_stay_within_a_step() {
+        let path = env::temp_dir().join(format!("y-render-test-{}.wav", process::id()));
+        let spec = WavSpec {
+            channels: 1,
+            sample_rate: 44_100,
+            bits_per_sample: 32,
+            sample_format: SampleFormat::Float,
+        };
+        let samples = vec![0.5; 1000];
+        let written = write_wav(&path, spec, &samples, Some(BitDepth::Int(16)), true);
+        let read = WavReader::open(&path).map(|reader| {
+            let spec = reader.spec();
+            let samples: Vec<i32> = reader.into_samples().map(Result::unwrap).collect();
+            (spec, samples)
+        });
+        fs::remove_file(&path).unwrap();
+
+        written.unwrap();
+        let (spec, read) = read.unwrap();
+        assert_eq!(spec.bits_per_sample, 16);
+        assert!(read.iter().all(|sample| (16382..=16385).contains(sample)));
+        assert!(read.iter().any(|&sample| sample != read[0]));
+    }
+
+    #[test]
+    fn silence_starts_after_the_input() {
+        let mut silence = Silence {
+            threshold: 0.1,
+            hold: 3,
+            input: 2,
+            since: None,
+        };
+        for (frame, peak) in [0., 0., 0.5, 0.01].into_iter().enumerate() {
+            silence.frame(frame, peak);
+        }
+        assert_eq!(silence.end(4), None);
+        assert_eq!(silence.end(6), Some(3));
+    }
+
+    #[test]
+    fn loud_frames_reset_the_silence() {
+        let mut silence = Silence {
+            threshold: 0.1,
+            hold: 2,
+            input: 0,
+            since: None,
+        };
+        for (frame, peak) in [0., 0.2, 0., 0.].into_iter().enumerate() {
+            silence.frame(frame, peak);
+        }
+        assert_eq!(silence.end(4), Some(2));
+    }
+}
@@ -0,0 +1,161 @@
+use std::str::FromStr;
+
+use anyhow::{bail, ensure, Context};
+
+/// Which channels feed which, as (from, to) pairs. A channel fed by several others gets their
+/// sum, one feeding several is duplicated, and one feeding none is dropped.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Matrix(pub Vec<(usize, usize)>);
+
+impl Matrix {
+    /// Number of channels routed from, counting the unused ones below the last
+    pub fn sources(&self) -> usize {
+        self.0.iter().map(|&(from, _)| from + 1).max().unwrap_or(0)
+    }
+
+    /// Number of channels routed to, counting the unused ones below the last
+    pub fn destinations(&self) -> usize {
+        self.0.iter().map(|&(_, to)| to + 1).max().unwrap_or(0)
+    }
+
+    /// Fills `output` with the sum of the channels of `sources` routed to `channel`
+    pub fn mix(&self, sources: &[Vec<f32>], channel: usize, output: &mut [f32]) {
+        output.fill(0.);
+        for &(from, to) in &self.0 {
+            if to != channel {
+                continue;
+            }
+            if let Some(source) = sources.get(from) {
+                for (sample, source) in output.iter_mut().zip(source) {
+                    *sample += source;
+                }
+            }
+        }
+    }
+}
+
+impl FromStr for Matrix {
+    type Err = anyhow::Error;
+
+    /// Parses routes like `0>1,1>0`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.split(',')
+            .map(|route| {
+                let (from, to) = route
+                    .trim()
+                    .split_once('>')
+                    .context("expected routes like 0>1")?;
+                let from = from.trim().parse().context("invalid channel")?;
+                let to = to.trim().parse().context("invalid channel")?;
+                Ok((from, to))
+            })
+            .collect::<Result<_, Self::Err>>()
+            .map(Matrix)
+    }
+}
+
+/// How the host's channels reach the plugin's inputs, and its outputs reach the host's channels
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Routes {
+    /// From the channels of the input file to the plugin's inputs
+    pub input: Option<Matrix>,
+    /// From the plugin's outputs to the host's output channels
+    pub output: Option<Matrix>,
+}
+
+impl FromStr for Routes {
+    type Err = anyhow::Error;
+
+    /// Parses routes like `in:0>0,0>1 out:0>0,1>0`, either side being optional
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut routes = Routes::default();
+        for side in s.split_whitespace() {
+            let (name, matrix) = side
+                .split_once(':')
+                .context("expected in:<routes> or out:<routes>")?;
+            let matrix = Some(matrix.parse()?);
+            match name {
+                "in" if routes.input.is_none() => routes.input = matrix,
+                "out" if routes.output.is_none() => routes.output = matrix,
+                "in" | "out" => bail!("the {name} routes are given twice"),
+                _ => bail!("unknown side {name:?}, expected in or out"),
+            }
+        }
+        ensure!(
+            routes.input.is_some() || routes.output.is_some(),
+            "expected in:<routes> or out:<routes>"
+        );
+
+        Ok(routes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Matrix, Routes};
+
+    #[test]
+    fn routes_to_the_same_channel_sum() {
+        let matrix: Matrix = "0>0, 1>0".parse().unwrap();
+        assert_eq!(matrix, Matrix(vec![(0, 0), (1, 0)]));
+
+        let mut output = [1.; 2];
+        matrix.mix(&[vec![0.25, 0.5], vec![0.5, -0.5]], 0, &mut output);
+        assert_eq!(output, [0.75, 0.]);
+    }
+
+    #[test]
+    fn channels_can_feed_several_others() {
+        let matrix: Matrix = "0>0,0>1".parse().unwrap();
+        let sources = [vec![0.5]];
+        let mut left = [0.];
+        let mut right = [0.];
+        matrix.mix(&sources, 0, &mut left);
+        matrix.mix(&sources, 1, &mut right);
+        assert_eq!((left, right), ([0.5], [0.5]));
+    }
+
+    #[test]
+    fn channels_nothing_feeds_are_silent() {
+        let matrix: Matrix = "0>1,5>0".parse().unwrap();
+        let mut output = [1.];
+        matrix.mix(&[vec![0.5]], 0, &mut output);
+        assert_eq!(output, [0.]);
+        matrix.mix(&[vec![0.5]], 2, &mut output);
+        assert_eq!(output, [0.]);
+    }
+
+    #[test]
+    fn counts_sources_and_destinations() {
+        let matrix: Matrix = "3>0,1>2".parse().unwrap();
+        assert_eq!(matrix.sources(), 4);
+        assert_eq!(matrix.destinations(), 3);
+        assert_eq!(Matrix(Vec::new()).sources(), 0);
+    }
+
+    #[test]
+    fn bad_matrices_are_rejected() {
+        assert!("".parse::<Matrix>().is_err());
+        assert!("0-1".parse::<Matrix>().is_err());
+        assert!("0>x".parse::<Matrix>().is_err());
+        assert!("-1>0".parse::<Matrix>().is_err());
+    }
+
+    #[test]
+    fn parses_either_side() {
+        let routes: Routes = "in:0>0,0>1 out:1>0".parse().unwrap();
+        assert_eq!(routes.input, Some(Matrix(vec![(0, 0), (0, 1)])));
+        assert_eq!(routes.output, Some(Matrix(vec![(1, 0)])));
+
+        let routes: Routes = "out:0>1".parse().unwrap();
+        assert_eq!(routes.input, None);
+    }
+
+    #[test]
+    fn bad_routes_are_rejected() {
+        assert!("".parse::<Routes>().is_err());
+        assert!("in:0>0 in:1>1".parse::<Routes>().is_err());
+        assert!("side:0>0".parse::<Routes>().is_err());
+        assert!("0>0".parse::<Routes>().is_err());
+    }
+}
@@ -0,0 +1,402 @@
+use std::{
+    cell::Cell,
+    ffi::{CStr, CString, OsStr},
+    fs, mem,
+    os::{
+        raw::{c_char, c_int, c_void},
+        unix::ffi::OsStrExt,
+    },
+    path::{Path, PathBuf},
+    slice,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use anyhow::{bail, Context, Result};
+
+const DT_NULL: i64 = 0;
+const DT_PLTRELSZ: i64 = 2;
+const DT_STRTAB: i64 = 5;
+const DT_SYMTAB: i64 = 6;
+const DT_RELA: i64 = 7;
+const DT_RELASZ: i64 = 8;
+const DT_PLTREL: i64 = 20;
+const DT_JMPREL: i64 = 23;
+
+/// `Elf64_Dyn`, which `libc` doesn't define
+#[repr(C)]
+struct Elf64Dyn {
+    tag: i64,
+    value: u64,
+}
+
+/// `Elf64_Rela`, which `libc` doesn't define
+#[repr(C)]
+struct Elf64Rela {
+    offset: u64,
+    info: u64,
+    addend: i64,
+}
+
+/// `Elf64_Sym`, which `libc` doesn't define
+#[repr(C)]
+#[allow(dead_code)]
+struct Elf64Sym {
+    name: u32,
+    info: u8,
+    other: u8,
+    section: u16,
+    value: u64,
+    size: u64,
+}
+
+/// Why calling a function on the audio thread is a problem
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Kind {
+    /// The allocator can take locks or ask the system for memory
+    Allocation,
+    /// Waits for whichever thread holds the lock
+    Locking,
+    /// Waits for the disk or whatever else is behind the file
+    FileIo,
+}
+
+impl Kind {
+    pub fn describe(self) -> &'static str {
+        match self {
+            Kind::Allocation => "allocation",
+            Kind::Locking => "locking",
+            Kind::FileIo => "file I/O",
+        }
+    }
+}
+
+thread_local! {
+    /// Whether calls on this thread are being counted, see [`check`]
+    static CHECKING: Cell<bool> = const { Cell::new(false) };
+}
+
+/// A function whose calls from a plugin library are counted
+struct Hook {
+    name: &'static str,
+    /// What the library links against, which is the name unless it's mangled
+    symbol: &'static str,
+    kind: Kind,
+    /// The function the hook forwards to, found the first time a library is hooked
+    original: AtomicUsize,
+    calls: AtomicUsize,
+}
+
+impl Hook {
+    const fn new(name: &'static str, symbol: &'static str, kind: Kind) -> Self {
+        Self {
+            name,
+            symbol,
+            kind,
+            original: AtomicUsize::new(0),
+            calls: AtomicUsize::new(0),
+        }
+    }
+
+    /// Counts a call, if it's made while checking. Neither allocates nor locks, since it runs
+    /// inside `malloc` and friends.
+    fn called(&self) {
+        // The thread local is gone while the thread exits, which still frees memory
+        if CHECKING.try_with(Cell::get).unwrap_or(false) {
+            self.calls.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+/// Defines a static [`Hook`] and a hook function forwarding to the original for every function
+/// listed, and `hooks()` pairing each with the address of its hook function
+macro_rules! hooks {
+    (@symbol $name:literal) => { $name };
+    (@symbol $name:literal $symbol:literal) => { $symbol };
+    ($(
+        $hook:ident: $kind:ident $name:literal $(as $symbol:literal)?
+        fn $function:ident($($argument:ident: $type:ty),*) $(-> $return:ty)?;
+    )*) => {
+        $(
+            static $hook: Hook =
+                Hook::new($name, hooks!(@symbol $name $($symbol)?), Kind::$kind);
+
+            extern "C" fn $function($($argument: $type),*) $(-> $return)? {
+                $hook.called();
+                let original: extern "C" fn($($type),*) $(-> $return)? =
+                    unsafe { mem::transmute($hook.original.load(Ordering::Relaxed)) };
+                original($($argument),*)
+            }
+        )*
+
+        fn hooks() -> Vec<(&'static Hook, usize)> {
+            vec![$((&$hook, $function as *const () as usize)),*]
+        }
+    };
+}
+
+// `open` takes its mode as a variadic argument, which is passed like any other on the platforms
+// this supports, so forwarding a third argument passes it on whether or not there is one
+hooks! {
+    MALLOC: Allocation "malloc" fn malloc(size: usize) -> *mut c_void;
+    CALLOC: Allocation "calloc" fn calloc(count: usize, size: usize) -> *mut c_void;
+    REALLOC: Allocation "realloc" fn realloc(pointer: *mut c_void, size: usize) -> *mut c_void;
+    FREE: Allocation "free" fn free(pointer: *mut c_void);
+    POSIX_MEMALIGN: Allocation "posix_memalign"
+        fn posix_memalign(pointer: *mut *mut c_void, alignment: usize, size: usize) -> c_int;
+    ALIGNED_ALLOC: Allocation "aligned_alloc"
+        fn aligned_alloc(alignment: usize, size: usize) -> *mut c_void;
+    NEW: Allocation "operator new" as "_Znwm" fn new(size: usize) -> *mut c_void;
+    NEW_ARRAY: Allocation "operator new[]" as "_Znam" fn new_array(size: usize) -> *mut c_void;
+    DELETE: Allocation "operator delete" as "_ZdlPv" fn delete(pointer: *mut c_void);
+    DELETE_ARRAY: Allocation "operator delete[]" as "_ZdaPv"
+        fn delete_array(pointer: *mut c_void);
+    DELETE_SIZED: Allocation "sized operator delete" as "_ZdlPvm"
+        fn delete_sized(pointer: *mut c_void, size: usize);
+    DELETE_ARRAY_SIZED: Allocation "sized operator delete[]" as "_ZdaPvm"
+        fn delete_array_sized(pointer: *mut c_void, size: usize);
+    MUTEX_LOCK: Locking "pthread_mutex_lock" fn mutex_lock(mutex: *mut c_void) -> c_int;
+    COND_WAIT: Locking "pthread_cond_wait"
+        fn cond_wait(condition: *mut c_void, mutex: *mut c_void) -> c_int;
+    RWLOCK_RDLOCK: Locking "pthread_rwlock_rdlock" fn rwlock_rdlock(lock: *mut c_void) -> c_int;
+    RWLOCK_WRLOCK: Locking "pthread_rwlock_wrlock" fn rwlock_wrlock(lock: *mut c_void) -> c_int;
+    SEM_WAIT: Locking "sem_wait" fn sem_wait(semaphore: *mut c_void) -> c_int;
+    OPEN: FileIo "open" fn open(path: *const c_char, flags: c_int, mode: c_int) -> c_int;
+    FOPEN: FileIo "fopen" fn fopen(path: *const c_char, mode: *const c_char) -> *mut c_void;
+    READ: FileIo "read" fn read(fd: c_int, buffer: *mut c_void, count: usize) -> isize;
+    WRITE: FileIo "write" fn write(fd: c_int, buffer: *const c_void, count: usize) -> isize;
+    FREAD: FileIo "fread"
+        fn fread(buffer: *mut c_void, size: usize, count: usize, file: *mut c_void) -> usize;
+    FWRITE: FileIo "fwrite"
+        fn fwrite(buffer: *const c_void, size: usize, count: usize, file: *mut c_void) -> usize;
+}
+
+/// Sends the calls the library at `path` makes to the hooked functions through their hooks, by
+/// rewriting its global offset table. The library has to be loaded already.
+///
+/// Calls the library makes on any thread go through the hooks from then on, but they're only
+/// counted inside [`check`].
+pub fn hook(path: &Path) -> Result<()> {
+    let name = CString::new(path.as_os_str().as_bytes())?;
+    let handle = unsafe { libc::dlopen(name.as_ptr(), libc::RTLD_LAZY | libc::RTLD_NOLOAD) };
+    if handle.is_null() {
+        bail!("{} isn't loaded", path.display());
+    }
+    // The originals are looked up from the library, so they're what it would have called
+    for (hook, _) in hooks() {
+        let symbol = CString::new(hook.symbol)?;
+        let original = unsafe { libc::dlsym(handle, symbol.as_ptr()) } as usize;
+        if original != 0 {
+            let _ =
+                hook.original
+                    .compare_exchange(0, original, Ordering::Relaxed, Ordering::Relaxed);
+        }
+    }
+    unsafe { libc::dlclose(handle) };
+
+    let (base, dynamic) = find(path)?;
+    unsafe { patch(base, dynamic) }
+}
+
+/// The load address and dynamic section of the loaded library at `path`
+fn find(path: &Path) -> Result<(usize, *const Elf64Dyn)> {
+    /// The callback runs with the loader's lock held, so the paths are resolved before and only
+    /// compared inside it
+    struct Search {
+        /// The library's path as given, which is what the loader keeps, and resolved
+        paths: [PathBuf; 2],
+        found: Option<(usize, *const Elf64Dyn)>,
+    }
+
+    unsafe extern "C" fn visit(
+        info: *mut libc::dl_phdr_info,
+        _size: usize,
+        data: *mut c_void,
+    ) -> c_int {
+        let search = &mut *(data as *mut Search);
+        let info = &*info;
+        if info.dlpi_name.is_null() {
+            return 0;
+        }
+        let name = CStr::from_ptr(info.dlpi_name);
+        let path = Path::new(OsStr::from_bytes(name.to_bytes()));
+        if !search.paths.iter().any(|candidate| candidate == path) {
+            return 0;
+        }
+
+        let headers = slice::from_raw_parts(info.dlpi_phdr, info.dlpi_phnum as usize);
+        search.found = headers
+            .iter()
+            .find(|header| header.p_type == libc::PT_DYNAMIC)
+            .map(|header| {
+                let base = info.dlpi_addr as usize;
+                (base, (base + header.p_vaddr as usize) as *const Elf64Dyn)
+            });
+        1
+    }
+
+    let mut search = Search {
+        paths: [path.to_owned(), fs::canonicalize(path)?],
+        found: None,
+    };
+    unsafe { libc::dl_iterate_phdr(Some(visit), &mut search as *mut Search as *mut c_void) };
+    search
+        .found
+        .with_context(|| format!("{} has no dynamic section", path.display()))
+}
+
+/// Points every relocation of the library at `base` that refers to a hooked function at its
+/// hook instead
+unsafe fn patch(base: usize, dynamic: *const Elf64Dyn) -> Result<()> {
+    // The loader usually rewrites these to addresses, but not everywhere
+    let address = |value: u64| {
+        let value = value as usize;
+        if value < base {
+            base + value
+        } else {
+            value
+        }
+    };
+
+    let (mut strings, mut symbols) = (0, 0);
+    let mut tables = [(0, 0); 2];
+    let mut entry = dynamic;
+    while (*entry).tag != DT_NULL {
+        let value = (*entry).value;
+        match (*entry).tag {
+            DT_STRTAB => strings = address(value),
+            DT_SYMTAB => symbols = address(value),
+            DT_RELA => tables[0].0 = address(value),
+            DT_RELASZ => tables[0].1 = value as usize,
+            DT_JMPREL => tables[1].0 = address(value),
+            DT_PLTRELSZ => tables[1].1 = value as usize,
+            DT_PLTREL if value as i64 != DT_RELA => bail!("only RELA relocations are supported"),
+            _ => {}
+        }
+        entry = entry.add(1);
+    }
+    if strings == 0 || symbols == 0 {
+        bail!("the library has no dynamic symbols");
+    }
+
+    let hooks = hooks();
+    let page_size = libc::sysconf(libc::_SC_PAGESIZE) as usize;
+    let protections = protections()?;
+    for (table, size) in tables {
+        if table == 0 {
+            continue;
+        }
+        let relocations = slice::from_raw_parts(
+            table as *const Elf64Rela,
+            size / mem::size_of::<Elf64Rela>(),
+        );
+        for relocation in relocations {
+            let index = (relocation.info >> 32) as usize;
+            if index == 0 || relocation.addend != 0 {
+                continue;
+            }
+            let symbol = &*(symbols as *const Elf64Sym).add(index);
+            let name = CStr::from_ptr((strings + symbol.name as usize) as *const c_char);
+            let Ok(name) = name.to_str() else { continue };
+            // Without an original there'd be nothing to forward to
+            let hooked = hooks.iter().find(|(hook, _)| {
+                hook.symbol == name && hook.original.load(Ordering::Relaxed) != 0
+            });
+            let Some(&(_, function)) = hooked else {
+                continue;
+            };
+
+            // Tables protected by RELRO are read-only by now, so they're made writable for the
+            // write and protected again after it
+            let slot = (base + relocation.offset as usize) as *mut usize;
+            let page = slot as usize & !(page_size - 1);
+            let protection = protections
+                .iter()
+                .find(|(start, end, _)| (*start..*end).contains(&page))
+                .map(|&(_, _, protection)| protection)
+                .context("the offset table isn't mapped")?;
+            if protection & libc::PROT_WRITE != 0 {
+                *slot = function;
+                continue;
+            }
+            let page = page as *mut c_void;
+            if libc::mprotect(page, page_size, protection | libc::PROT_WRITE) != 0 {
+                bail!(
+                    "failed to make the offset table writable: {}",
+                    std::io::Error::last_os_error()
+                );
+            }
+            *slot = function;
+            if libc::mprotect(page, page_size, protection) != 0 {
+                bail!(
+                    "failed to protect the offset table again: {}",
+                    std::io::Error::last_os_error()
+                );
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// The start, end and protection of every mapping in the process
+fn protections() -> Result<Vec<(usize, usize, c_int)>> {
+    let maps = fs::read_to_string("/proc/self/maps").context("failed to read /proc/self/maps")?;
+    maps.lines()
+        .map(|line| {
+            let mut fields = line.split_whitespace();
+            let (start, end) = fields
+                .next()
+                .and_then(|range| range.split_once('-'))
+                .with_context(|| format!("unexpected mapping {line:?}"))?;
+            let permissions = fields.next().unwrap_or_default();
+            let protection = [
+                ('r', libc::PROT_READ),
+                ('w', libc::PROT_WRITE),
+                ('x', libc::PROT_EXEC),
+            ]
+            .into_iter()
+            .filter(|&(flag, _)| permissions.contains(flag))
+            .fold(libc::PROT_NONE, |protection, (_, bit)| protection | bit);
+            Ok((
+                usize::from_str_radix(start, 16)?,
+                usize::from_str_radix(end, 16)?,
+                protection,
+            ))
+        })
+        .collect()
+}
+
+/// Runs `f`, counting the hooked calls it makes on this thread
+pub fn check<T>(f: impl FnOnce() -> T) -> T {
+    CHECKING.with(|checking| checking.set(true));
+    let result = f();
+    CHECKING.with(|checking| checking.set(false));
+    result
+}
+
+/// Total calls counted so far
+pub fn total() -> usize {
+    hooks()
+        .iter()
+        .map(|(hook, _)| hook.calls.load(Ordering::Relaxed))
+        .sum()
+}
+
+/// The hooked functions called so far with what they mean and how often they were called, most
+/// called first
+pub fn report() -> Vec<(&'static str, Kind, usize)> {
+    let mut calls: Vec<_> = hooks()
+        .iter()
+        .map(|(hook, _)| (hook.name, hook.kind, hook.calls.load(Ordering::Relaxed)))
+        .filter(|&(_, _, calls)| calls > 0)
+        .collect();
+    calls.sort_by_key(|&(_, _, calls)| std::cmp::Reverse(calls));
+    calls
+}
+
+/// Starts counting from 0 again, for the next plugin
+pub fn reset() {
+    for (hook, _) in hooks() {
+        hook.calls.store(0, Ordering::Relaxed);
+    }
+}
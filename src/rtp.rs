@@ -0,0 +1,100 @@
+use std::{
+    net::{SocketAddr, UdpSocket},
+    sync::mpsc::{self, SyncSender},
+    thread,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{Context, Result};
+
+use crate::pool::Returns;
+
+/// Buffers waiting to be sent before new ones are dropped, a couple of seconds' worth at the
+/// usual buffer sizes
+pub const PENDING_BUFFERS: usize = 64;
+
+/// Frames per packet, which keeps stereo packets well under the usual 1500 byte MTU
+const FRAMES_PER_PACKET: usize = 256;
+
+/// Where the audio thread sends its output, interleaved, to be streamed
+pub type Feed = SyncSender<Vec<f32>>;
+
+/// The RTP payload type of 16-bit linear PCM with `channels` channels at `sample_rate`. RFC 3551
+/// assigns 10 and 11 to stereo and mono at 44.1 kHz, anything else uses the first dynamic type.
+fn payload_type(channels: usize, sample_rate: u32) -> u8 {
+    match (channels, sample_rate) {
+        (2, 44_100) => 10,
+        (1, 44_100) => 11,
+        _ => 96,
+    }
+}
+
+/// A session description for the stream, which receivers like ffplay or VLC open to listen
+pub fn sdp(target: SocketAddr, channels: usize, sample_rate: u32) -> String {
+    let (family, address) = match target {
+        SocketAddr::V4(address) => ("IP4", address.ip().to_string()),
+        SocketAddr::V6(address) => ("IP6", address.ip().to_string()),
+    };
+    let payload_type = payload_type(channels, sample_rate);
+    format!(
+        "v=0\r\no=- 0 0 IN {family} {address}\r\ns=y\r\nc=IN {family} {address}\r\nt=0 0\r\n\
+         m=audio {} RTP/AVP {payload_type}\r\na=rtpmap:{payload_type} L16/{sample_rate}/{channels}\r\n",
+        target.port(),
+    )
+}
+
+/// Streams the output sent to the returned feed to `target` over RTP, as 16-bit linear PCM, giving
+/// each buffer back to `returns` once it's sent
+pub fn stream(
+    target: SocketAddr,
+    channels: usize,
+    sample_rate: u32,
+    returns: Returns,
+) -> Result<Feed> {
+    let local: SocketAddr = if target.is_ipv4() {
+        ([0, 0, 0, 0], 0).into()
+    } else {
+        ([0u16; 8], 0).into()
+    };
+    let socket = UdpSocket::bind(local).context("failed to open a socket for RTP")?;
+    let (feed, buffers) = mpsc::sync_channel::<Vec<f32>>(PENDING_BUFFERS);
+
+    // Only has to differ between streams, so the time it started is random enough
+    let ssrc = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.subsec_nanos());
+
+    thread::spawn(move || {
+        let mut sequence = 0u16;
+        let mut timestamp = 0u32;
+        let mut failed = false;
+        let mut packet = Vec::with_capacity(12 + FRAMES_PER_PACKET * channels * 2);
+        for buffer in buffers {
+            for samples in buffer.chunks(FRAMES_PER_PACKET * channels) {
+                packet.clear();
+                packet.extend([0x80, payload_type(channels, sample_rate)]);
+                packet.extend(sequence.to_be_bytes());
+                packet.extend(timestamp.to_be_bytes());
+                packet.extend(ssrc.to_be_bytes());
+                for &sample in samples {
+                    let sample = (sample.clamp(-1., 1.) * i16::MAX as f32) as i16;
+                    packet.extend(sample.to_be_bytes());
+                }
+                match socket.send_to(&packet, target) {
+                    Ok(_) => failed = false,
+                    // Only reported once until sending works again, rather than for every packet
+                    Err(err) if !failed => {
+                        log::warn!("failed to stream to {target}: {err}");
+                        failed = true;
+                    }
+                    Err(_) => {}
+                }
+                sequence = sequence.wrapping_add(1);
+                timestamp = timestamp.wrapping_add((samples.len() / channels) as u32);
+            }
+            returns.give(buffer);
+        }
+    });
+
+    Ok(feed)
+}
@@ -0,0 +1,93 @@
+use std::str::FromStr;
+
+use anyhow::{anyhow, Context};
+use vst::plugin::PluginParameters;
+
+use crate::transport::Transport;
+
+/// A snapshot of every parameter of a plugin
+#[derive(Clone, Debug)]
+pub struct Scene {
+    values: Vec<f32>,
+}
+
+impl Scene {
+    pub fn capture(parameters: &dyn PluginParameters, count: i32) -> Self {
+        Self {
+            values: (0..count)
+                .map(|index| parameters.get_parameter(index))
+                .collect(),
+        }
+    }
+}
+
+/// How long a transition takes, in seconds or in musical time
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Length {
+    Seconds(f64),
+    Beats(f64),
+    Bars(f64),
+}
+
+impl Length {
+    /// When a transition of this length starting at `start` seconds ends, following the tempo map
+    pub fn end(&self, transport: &Transport, start: f64) -> f64 {
+        let tempo_map = transport.tempo_map();
+        let time_signature = tempo_map.time_signature_at(start);
+        let quarters = match *self {
+            Length::Seconds(seconds) => return start + seconds,
+            Length::Beats(beats) => beats * time_signature.beat_length(),
+            Length::Bars(bars) => bars * time_signature.bar_length(),
+        };
+
+        tempo_map.seconds_at(tempo_map.quarters_at(start) + quarters)
+    }
+}
+
+impl FromStr for Length {
+    type Err = anyhow::Error;
+
+    /// Parses lengths like `4s`, `500ms`, `8beats` or `2bars`, plain numbers being seconds
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let split = s.find(|c: char| c.is_ascii_alphabetic()).unwrap_or(s.len());
+        let (number, unit) = s.split_at(split);
+        let number: f64 = number.parse().context("invalid length")?;
+
+        match unit {
+            "" | "s" => Ok(Length::Seconds(number)),
+            "ms" => Ok(Length::Seconds(number / 1000.)),
+            "beat" | "beats" => Ok(Length::Beats(number)),
+            "bar" | "bars" => Ok(Length::Bars(number)),
+            _ => Err(anyhow!(
+                "unknown unit {unit:?}, expected s, ms, beats or bars"
+            )),
+        }
+    }
+}
+
+/// A ramp of every parameter from one scene to another
+pub struct Transition {
+    pub from: Scene,
+    pub to: Scene,
+    /// When the ramp starts and ends, in seconds on the transport
+    pub start: f64,
+    pub end: f64,
+}
+
+impl Transition {
+    /// Sets the parameters for the transport being at `seconds`, returning whether the
+    /// transition has finished
+    pub fn process(&self, seconds: f64, parameters: &dyn PluginParameters) -> bool {
+        let progress = if self.end > self.start {
+            ((seconds - self.start) / (self.end - self.start)).clamp(0., 1.) as f32
+        } else {
+            1.
+        };
+
+        for (index, (from, to)) in self.from.values.iter().zip(&self.to.values).enumerate() {
+            parameters.set_parameter(index as i32, from + (to - from) * progress);
+        }
+
+        progress >= 1.
+    }
+}
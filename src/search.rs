@@ -0,0 +1,250 @@
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Context, Result};
+
+use crate::profile::cache_dir;
+
+/// How deep plugin directories are searched, enough for vendor and category subdirectories
+const MAX_DEPTH: usize = 4;
+
+/// Directories searched for plugins: those in `VST_PATH`, then the platform's usual locations
+pub fn search_paths() -> Vec<PathBuf> {
+    let mut paths: Vec<PathBuf> = env::var_os("VST_PATH")
+        .map(|paths| env::split_paths(&paths).collect())
+        .unwrap_or_default();
+
+    let home = env::var_os("HOME").map(PathBuf::from);
+    if cfg!(target_os = "macos") {
+        paths.extend(home.map(|home| home.join("Library/Audio/Plug-Ins/VST")));
+        paths.push("/Library/Audio/Plug-Ins/VST".into());
+    } else if cfg!(windows) {
+        for program_files in ["ProgramFiles", "ProgramFiles(x86)"] {
+            if let Some(program_files) = env::var_os(program_files).map(PathBuf::from) {
+                paths.push(program_files.join("VSTPlugins"));
+                paths.push(program_files.join("Steinberg").join("VstPlugins"));
+            }
+        }
+    } else {
+        if let Some(home) = home {
+            paths.push(home.join(".vst"));
+            paths.push(home.join(".lxvst"));
+        }
+        for prefix in ["/usr/lib", "/usr/local/lib"] {
+            paths.push(Path::new(prefix).join("vst"));
+            paths.push(Path::new(prefix).join("lxvst"));
+        }
+    }
+
+    paths
+}
+
+/// Every plugin library in the search paths
+pub fn plugins() -> Vec<PathBuf> {
+    let mut plugins = Vec::new();
+    for path in search_paths() {
+        collect(&path, MAX_DEPTH, &mut plugins);
+    }
+    plugins.sort();
+    plugins.dedup();
+    plugins
+}
+
+fn collect(directory: &Path, depth: usize, plugins: &mut Vec<PathBuf>) {
+    let entries = match fs::read_dir(directory) {
+        Ok(entries) => entries,
+        Err(_) => return,
+    };
+
+    for path in entries.flatten().map(|entry| entry.path()) {
+        let extension = path.extension().and_then(|extension| extension.to_str());
+        if matches!(extension, Some("so" | "dll" | "vst")) {
+            plugins.push(path);
+        } else if path.is_dir() && depth > 0 {
+            collect(&path, depth - 1, plugins);
+        }
+    }
+}
+
+/// Name of the plugin at `path`, its file name without the extension
+pub fn file_name(path: &Path) -> String {
+    path.file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+/// Finds the plugin `plugin` refers to: a path, or the file or product name of a plugin in the
+/// search paths such as `Diva`
+pub fn resolve(plugin: &Path) -> Result<PathBuf> {
+    if plugin.exists() || plugin.components().count() > 1 {
+        return Ok(plugin.to_owned());
+    }
+
+    let name = plugin.to_string_lossy();
+    let mut matches: Vec<PathBuf> = plugins()
+        .into_iter()
+        .filter(|path| file_name(path).eq_ignore_ascii_case(&name))
+        .collect();
+    if let Ok(database) = Database::load() {
+        matches.extend(
+            database
+                .entries
+                .into_iter()
+                .filter(|entry| entry.product.eq_ignore_ascii_case(&name))
+                .map(|entry| entry.path),
+        );
+    }
+    matches.sort();
+    matches.dedup();
+
+    match matches.as_slice() {
+        [] => bail!("no plugin called {name:?} found in VST_PATH or the standard directories"),
+        [path] => Ok(path.clone()),
+        _ => bail!(
+            "several plugins are called {name:?}, pass one of their paths instead:\n{}",
+            candidates(&matches)
+        ),
+    }
+}
+
+/// Finds the plugin in the scan database best matching `query`, such as `tal reverb`.
+///
+/// Every word of the query has to appear in the plugin's vendor, product or file name, ignoring
+/// case and punctuation.
+pub fn find(query: &str) -> Result<PathBuf> {
+    let database = Database::load()?;
+    let words: Vec<String> = query.split_whitespace().map(normalise).collect();
+    let matches: Vec<&Entry> = database
+        .entries
+        .iter()
+        .filter(|entry| {
+            let haystack = normalise(&format!(
+                "{} {} {}",
+                entry.vendor,
+                entry.product,
+                file_name(&entry.path)
+            ));
+            words.iter().all(|word| haystack.contains(word.as_str()))
+        })
+        .collect();
+
+    // A product named exactly like the query wins over products merely containing it
+    let exact: Vec<&Entry> = matches
+        .iter()
+        .copied()
+        .filter(|entry| normalise(&entry.product) == words.concat())
+        .collect();
+
+    match (matches.as_slice(), exact.as_slice()) {
+        ([], _) => bail!("no plugin in the scan database matches {query:?}"),
+        ([entry], _) | (_, [entry]) => Ok(entry.path.clone()),
+        _ => bail!(
+            "several plugins match {query:?}, be more specific or pass a path:\n{}",
+            matches
+                .iter()
+                .map(|entry| format!(
+                    "    {} by {} ({})",
+                    entry.product,
+                    entry.vendor,
+                    entry.path.display()
+                ))
+                .collect::<Vec<_>>()
+                .join("\n")
+        ),
+    }
+}
+
+/// Lowercases `s` and drops everything but letters and digits
+fn normalise(s: &str) -> String {
+    s.chars()
+        .filter(|c| c.is_alphanumeric())
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+/// A plugin found by the scanner
+#[derive(Clone, Debug)]
+pub struct Entry {
+    pub path: PathBuf,
+    pub unique_id: i32,
+    pub vendor: String,
+    pub product: String,
+}
+
+/// Names of the plugins in the search paths, written by `y scan` so plugins can be found by
+/// name without loading each of them
+#[derive(Debug, Default)]
+pub struct Database {
+    pub entries: Vec<Entry>,
+}
+
+impl Database {
+    pub fn load() -> Result<Self> {
+        let path = database_path().context("can't find the cache directory")?;
+        let text = fs::read_to_string(&path).with_context(|| {
+            format!(
+                "failed to read the scan database {}, run scan to create it",
+                path.display()
+            )
+        })?;
+
+        // One plugin per line as `path<TAB>unique id<TAB>vendor<TAB>product`
+        let mut database = Database::default();
+        for (number, line) in text.lines().enumerate() {
+            let fields: Vec<&str> = line.split('\t').collect();
+            let (path, unique_id, vendor, product) = match fields.as_slice() {
+                [path, unique_id, vendor, product] => (path, unique_id, vendor, product),
+                _ => bail!("{}:{}: expected four fields", path.display(), number + 1),
+            };
+            database.entries.push(Entry {
+                path: PathBuf::from(path),
+                unique_id: unique_id
+                    .parse()
+                    .with_context(|| format!("{}:{}", path, number + 1))?,
+                vendor: vendor.to_string(),
+                product: product.to_string(),
+            });
+        }
+
+        Ok(database)
+    }
+
+    pub fn save(&self) -> Result<PathBuf> {
+        let path = database_path().context("can't find the cache directory")?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let mut text = String::new();
+        for entry in &self.entries {
+            // Tabs and newlines would break the format, and don't belong in names anyway
+            let clean = |s: &str| s.replace(['\t', '\n'], " ");
+            text.push_str(&format!(
+                "{}\t{}\t{}\t{}\n",
+                entry.path.display(),
+                entry.unique_id,
+                clean(&entry.vendor),
+                clean(&entry.product)
+            ));
+        }
+        fs::write(&path, text).with_context(|| format!("failed to write {}", path.display()))?;
+
+        Ok(path)
+    }
+}
+
+/// Where the scan database is kept, `$XDG_CACHE_HOME/y/plugins.txt`
+pub fn database_path() -> Option<PathBuf> {
+    Some(cache_dir()?.join("plugins.txt"))
+}
+
+/// Lists paths one per line, for error messages
+pub fn candidates(paths: &[PathBuf]) -> String {
+    paths
+        .iter()
+        .map(|path| format!("    {}", path.display()))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
@@ -0,0 +1,152 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Context, Result};
+
+use crate::preset::Preset;
+
+/// Autosaves kept before the oldest is overwritten
+const SLOTS: usize = 8;
+
+/// What the host was doing at an autosave, besides the plugin's state.
+///
+/// Written as lines of a setting followed by its value:
+///
+/// ```text
+/// plugin /usr/lib/vst/synth.so
+/// preset /home/user/presets/pad.fxp
+/// bypass off
+/// hold on
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Settings {
+    pub plugin: PathBuf,
+    /// The preset loaded last, if any
+    pub preset: Option<PathBuf>,
+    pub bypass: bool,
+    /// Whether note-offs were held back
+    pub hold: bool,
+}
+
+impl Settings {
+    fn text(&self) -> String {
+        let switch = |on| if on { "on" } else { "off" };
+        let mut text = format!("plugin {}\n", self.plugin.display());
+        if let Some(preset) = &self.preset {
+            text.push_str(&format!("preset {}\n", preset.display()));
+        }
+        text.push_str(&format!("bypass {}\n", switch(self.bypass)));
+        text.push_str(&format!("hold {}\n", switch(self.hold)));
+        text
+    }
+
+    /// Parses the settings in `text`, read from `origin`
+    pub fn parse(text: &str, origin: &str) -> Result<Self> {
+        let mut plugin = None;
+        let mut settings = Settings {
+            plugin: PathBuf::new(),
+            preset: None,
+            bypass: false,
+            hold: false,
+        };
+
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            let (name, value) = line.split_once(' ').unwrap_or((line, ""));
+            let switch = || match value {
+                "on" => Ok(true),
+                "off" => Ok(false),
+                _ => bail!("{origin}:{}: expected {name} on|off", number + 1),
+            };
+            match name {
+                "plugin" => plugin = Some(PathBuf::from(value)),
+                "preset" => settings.preset = Some(PathBuf::from(value)),
+                "bypass" => settings.bypass = switch()?,
+                "hold" => settings.hold = switch()?,
+                _ => bail!("{origin}:{}: unknown setting {name:?}", number + 1),
+            }
+        }
+        settings.plugin = plugin.with_context(|| format!("{origin}: no plugin"))?;
+
+        Ok(settings)
+    }
+}
+
+/// A rotating set of autosaves in the `autosave` directory of a session, so a crash or power loss
+/// while one is written still leaves the ones before it.
+///
+/// Each autosave is the plugin's state in `autosave-<slot>.fxp` and the [`Settings`] in
+/// `autosave-<slot>.txt`, the settings being written last.
+pub struct Autosave {
+    directory: PathBuf,
+    next: usize,
+}
+
+impl Autosave {
+    /// Continues after the latest autosave in `session_dir`, if there is one
+    pub fn new(session_dir: &Path) -> Self {
+        let directory = session_dir.join("autosave");
+        let next = latest_slot(&directory).map_or(0, |slot| (slot + 1) % SLOTS);
+        Self { directory, next }
+    }
+
+    /// Writes an autosave over the oldest one
+    pub fn save(&mut self, state: &Preset, settings: &Settings) -> Result<()> {
+        fs::create_dir_all(&self.directory)
+            .with_context(|| format!("failed to create {}", self.directory.display()))?;
+        let stem = self.directory.join(format!("autosave-{}", self.next));
+
+        // Written next to them first, so a crash while saving leaves the files intact
+        let state_path = stem.with_extension("fxp");
+        let partial = stem.with_extension("fxp.partial");
+        state.write(&partial)?;
+        fs::rename(&partial, &state_path)?;
+        let settings_path = stem.with_extension("txt");
+        let partial = stem.with_extension("txt.partial");
+        fs::write(&partial, settings.text())
+            .with_context(|| format!("failed to write {}", partial.display()))?;
+        fs::rename(&partial, &settings_path)?;
+
+        self.next = (self.next + 1) % SLOTS;
+        Ok(())
+    }
+}
+
+/// The plugin's state and the settings of the latest autosave in `session_dir`, if there is one
+pub fn latest(session_dir: &Path) -> Result<Option<(Preset, Settings)>> {
+    let directory = session_dir.join("autosave");
+    let Some(slot) = latest_slot(&directory) else {
+        return Ok(None);
+    };
+    let stem = directory.join(format!("autosave-{slot}"));
+
+    let settings_path = stem.with_extension("txt");
+    let text = fs::read_to_string(&settings_path)
+        .with_context(|| format!("failed to read {}", settings_path.display()))?;
+    let settings = Settings::parse(&text, &settings_path.display().to_string())?;
+    let state = Preset::read(&stem.with_extension("fxp"))?;
+
+    Ok(Some((state, settings)))
+}
+
+/// The slot whose settings were written last, among those with both files
+fn latest_slot(directory: &Path) -> Option<usize> {
+    (0..SLOTS)
+        .filter_map(|slot| {
+            let stem = directory.join(format!("autosave-{slot}"));
+            if !stem.with_extension("fxp").exists() {
+                return None;
+            }
+            let modified = fs::metadata(stem.with_extension("txt"))
+                .and_then(|metadata| metadata.modified())
+                .ok()?;
+            Some((modified, slot))
+        })
+        .max()
+        .map(|(_, slot)| slot)
+}
@@ -0,0 +1,421 @@
+use std::{fs, path::Path};
+
+use anyhow::{bail, ensure, Context, Result};
+use vst::event::MidiEvent;
+
+use crate::{midi::MidiSource, transport::TempoMap};
+
+/// A channel message from a MIDI file, timed in seconds from the start of the file
+#[derive(Clone, Copy, Debug)]
+pub struct Message {
+    pub tick: u64,
+    pub seconds: f64,
+    pub data: [u8; 3],
+}
+
+/// The channel messages of a standard MIDI file, merged across tracks and sorted by time
+#[derive(Clone)]
+pub struct Sequence {
+    pub messages: Vec<Message>,
+    /// Ticks per quarter note, unless the file uses SMPTE timing
+    pub ticks_per_quarter: Option<u16>,
+}
+
+/// Microseconds per quarter note until the first tempo event
+const DEFAULT_TEMPO: u32 = 500_000;
+/// Time division of the files written, fine enough for controller changes
+const TICKS_PER_QUARTER: u16 = 480;
+
+enum Event {
+    Tempo(u32),
+    Message([u8; 3]),
+}
+
+/// Reads big-endian and variable-length numbers out of a chunk
+struct Reader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data, position: 0 }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.position >= self.data.len()
+    }
+
+    fn bytes(&mut self, count: usize) -> Result<&'a [u8]> {
+        let end = self.position + count;
+        ensure!(end <= self.data.len(), "unexpected end of data");
+        let bytes = &self.data[self.position..end];
+        self.position = end;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16> {
+        let bytes = self.bytes(2)?;
+        Ok(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        let bytes = self.bytes(4)?;
+        Ok(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    fn var_len(&mut self) -> Result<u32> {
+        let mut value = 0;
+        for _ in 0..4 {
+            let byte = self.u8()?;
+            value = (value << 7) | (byte & 0x7f) as u32;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        bail!("variable-length number is too long")
+    }
+
+    /// Reads a chunk, returning its type and contents
+    fn chunk(&mut self) -> Result<(&'a [u8], &'a [u8])> {
+        let kind = self.bytes(4)?;
+        let length = self.u32()? as usize;
+        Ok((kind, self.bytes(length)?))
+    }
+}
+
+impl Sequence {
+    pub fn read(path: &Path) -> Result<Self> {
+        let data = fs::read(path).with_context(|| format!("failed to read {}", path.display()))?;
+        Self::parse(&data).with_context(|| format!("failed to parse {}", path.display()))
+    }
+
+    pub fn parse(data: &[u8]) -> Result<Self> {
+        let mut reader = Reader::new(data);
+
+        let (kind, header) = reader.chunk()?;
+        ensure!(kind == b"MThd", "not a standard MIDI file");
+        let mut header = Reader::new(header);
+        let format = header.u16()?;
+        let _tracks = header.u16()?;
+        let division = header.u16()?;
+        ensure!(format != 2, "format 2 MIDI files are not supported");
+
+        // Events from all tracks as (tick, track, event), so that sorting keeps the track order
+        // for simultaneous events
+        let mut events = Vec::new();
+        let mut track = 0;
+        while !reader.is_empty() {
+            let (kind, data) = reader.chunk()?;
+            if kind == b"MTrk" {
+                parse_track(data, track, &mut events)
+                    .with_context(|| format!("in track {track}"))?;
+                track += 1;
+            }
+        }
+        events.sort_by_key(|&(tick, track, _)| (tick, track));
+
+        // With SMPTE timing, ticks have a fixed length and tempo events don't matter
+        let smpte = division & 0x8000 != 0;
+        let smpte_tick = {
+            let frames = match -((division >> 8) as i8) {
+                29 => 29.97,
+                frames => frames as f64,
+            };
+            1. / (frames * (division & 0xff) as f64)
+        };
+        ensure!(smpte || division != 0, "invalid time division");
+
+        let mut tempo = DEFAULT_TEMPO;
+        let mut last_tick = 0;
+        let mut seconds = 0.;
+        let mut messages = Vec::new();
+        for (tick, _, event) in events {
+            seconds += (tick - last_tick) as f64
+                * if smpte {
+                    smpte_tick
+                } else {
+                    tempo as f64 / 1e6 / division as f64
+                };
+            last_tick = tick;
+
+            match event {
+                Event::Tempo(new_tempo) => tempo = new_tempo,
+                Event::Message(data) => messages.push(Message {
+                    tick,
+                    seconds,
+                    data,
+                }),
+            }
+        }
+
+        Ok(Self {
+            messages,
+            ticks_per_quarter: (!smpte).then_some(division),
+        })
+    }
+
+    /// Retimes the messages to follow `tempo_map` instead of the file's own tempo events.
+    ///
+    /// Files with SMPTE timing aren't tied to a tempo and keep their timing.
+    pub fn follow(&mut self, tempo_map: &TempoMap) {
+        if let Some(ticks_per_quarter) = self.ticks_per_quarter {
+            for message in &mut self.messages {
+                let quarters = message.tick as f64 / ticks_per_quarter as f64;
+                message.seconds = tempo_map.seconds_at(quarters);
+            }
+        }
+    }
+}
+
+/// Writes channel messages, timed in seconds and in order, to a standard MIDI file of a single
+/// track at a constant `tempo` in beats per minute
+pub fn write(path: &Path, tempo: f64, messages: &[(f64, [u8; 3])]) -> Result<()> {
+    let mut track = Vec::new();
+    let tempo_event = ((60e6 / tempo) as u32).to_be_bytes();
+    track.extend([0, 0xff, 0x51, 3]);
+    track.extend(&tempo_event[1..]);
+
+    let ticks_per_second = tempo / 60. * TICKS_PER_QUARTER as f64;
+    let mut last_tick = 0;
+    for &(seconds, data) in messages {
+        let tick = (seconds.max(0.) * ticks_per_second).round() as u32;
+        push_var_len(&mut track, tick.saturating_sub(last_tick));
+        last_tick = last_tick.max(tick);
+        match data[0] & 0xf0 {
+            0xc0 | 0xd0 => track.extend(&data[..2]),
+            _ => track.extend(data),
+        }
+    }
+    track.extend([0, 0xff, 0x2f, 0]);
+
+    let mut file = Vec::new();
+    file.extend(b"MThd");
+    file.extend(6u32.to_be_bytes());
+    // Format 0 with one track
+    file.extend(0u16.to_be_bytes());
+    file.extend(1u16.to_be_bytes());
+    file.extend(TICKS_PER_QUARTER.to_be_bytes());
+    file.extend(b"MTrk");
+    file.extend((track.len() as u32).to_be_bytes());
+    file.extend(track);
+
+    fs::write(path, file).with_context(|| format!("failed to write {}", path.display()))
+}
+
+fn push_var_len(data: &mut Vec<u8>, value: u32) {
+    let mut groups = vec![(value & 0x7f) as u8];
+    let mut rest = value >> 7;
+    while rest > 0 {
+        groups.push((rest & 0x7f) as u8 | 0x80);
+        rest >>= 7;
+    }
+    data.extend(groups.iter().rev());
+}
+
+fn parse_track(data: &[u8], track: usize, events: &mut Vec<(u64, usize, Event)>) -> Result<()> {
+    let mut reader = Reader::new(data);
+    let mut tick = 0;
+    let mut running_status = None;
+
+    while !reader.is_empty() {
+        tick += reader.var_len()? as u64;
+
+        let mut status = reader.u8()?;
+        let first = if status & 0x80 == 0 {
+            // Running status, the byte we read is already the first data byte
+            let first = status;
+            status = running_status.context("data byte without a status byte")?;
+            first
+        } else {
+            match status {
+                0xff => {
+                    let kind = reader.u8()?;
+                    let length = reader.var_len()? as usize;
+                    let data = reader.bytes(length)?;
+                    match kind {
+                        0x2f => break,
+                        0x51 if length == 3 => {
+                            let tempo = u32::from_be_bytes([0, data[0], data[1], data[2]]);
+                            events.push((tick, track, Event::Tempo(tempo)));
+                        }
+                        _ => {}
+                    }
+                    continue;
+                }
+                0xf0 | 0xf7 => {
+                    let length = reader.var_len()? as usize;
+                    reader.bytes(length)?;
+                    running_status = None;
+                    continue;
+                }
+                0xf1..=0xfe => bail!("unexpected system message {status:#x}"),
+                _ => {
+                    running_status = Some(status);
+                    reader.u8()?
+                }
+            }
+        };
+
+        let second = match status & 0xf0 {
+            0xc0 | 0xd0 => 0,
+            _ => reader.u8()?,
+        };
+        events.push((tick, track, Event::Message([status, first, second])));
+    }
+
+    Ok(())
+}
+
+/// Plays a sequence against the host's sample clock
+pub struct Player {
+    sequence: Sequence,
+    /// Index of the next message to play
+    next: usize,
+}
+
+impl Player {
+    pub fn new(sequence: Sequence) -> Self {
+        Self { sequence, next: 0 }
+    }
+}
+
+impl MidiSource for Player {
+    fn events(&mut self, start: f64, length: usize, sample_rate: f64, events: &mut Vec<MidiEvent>) {
+        let end = start + length as f64 / sample_rate;
+
+        while let Some(message) = self.sequence.messages.get(self.next) {
+            if message.seconds >= end {
+                break;
+            }
+            self.next += 1;
+
+            let delta_frames = ((message.seconds - start) * sample_rate) as i32;
+            events.push(MidiEvent {
+                data: message.data,
+                delta_frames: delta_frames.clamp(0, length as i32 - 1),
+                live: false,
+                note_length: None,
+                note_offset: None,
+                detune: 0,
+                note_off_velocity: 0,
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, process};
+
+    use super::{write, Sequence};
+    use crate::transport::{TempoMap, TimeSignature};
+
+    /// A format 0 file holding `track`
+    fn file(division: u16, track: &[u8]) -> Vec<u8> {
+        let mut file = b"MThd\0\0\0\x06\0\0\0\x01".to_vec();
+        file.extend(division.to_be_bytes());
+        file.extend(b"MTrk");
+        file.extend((track.len() as u32).to_be_bytes());
+        file.extend(track);
+        file
+    }
+
+    fn timing(sequence: &Sequence) -> Vec<(f64, [u8; 3])> {
+        sequence
+            .messages
+            .iter()
+            .map(|message| (message.seconds, message.data))
+            .collect()
+    }
+
+    #[test]
+    fn follows_tempo_changes_and_running_status() {
+        let sequence = Sequence::parse(&file(
+            96,
+            &[
+                0x00, 0x90, 60, 100, // note on
+                0x60, 60, 0, // running status, half a second later at 120 bpm
+                0x00, 0xff, 0x51, 3, 0x0f, 0x42, 0x40, // 60 bpm
+                0x60, 62, 100, // running status, a second later
+                0x00, 0xff, 0x2f, 0,
+            ],
+        ))
+        .unwrap();
+
+        assert_eq!(sequence.ticks_per_quarter, Some(96));
+        assert_eq!(
+            timing(&sequence),
+            [
+                (0., [0x90, 60, 100]),
+                (0.5, [0x90, 60, 0]),
+                (1.5, [0x90, 62, 100])
+            ]
+        );
+    }
+
+    #[test]
+    fn smpte_timing_ignores_the_tempo() {
+        // 25 frames a second of 40 ticks each, so a tick is a millisecond
+        let sequence = Sequence::parse(&file(
+            0xe728,
+            &[
+                0x00, 0xff, 0x51, 3, 0x0f, 0x42, 0x40, // 60 bpm
+                0x64, 0x90, 60, 100, // 100 ticks in
+                0x00, 0xff, 0x2f, 0,
+            ],
+        ))
+        .unwrap();
+
+        assert_eq!(sequence.ticks_per_quarter, None);
+        assert_eq!(sequence.messages.len(), 1);
+        assert!((sequence.messages[0].seconds - 0.1).abs() < 1e-9);
+    }
+
+    #[test]
+    fn data_bytes_need_a_status_byte() {
+        let error = Sequence::parse(&file(96, &[0x00, 60, 100])).err().unwrap();
+        assert!(format!("{error:#}").contains("data byte without a status byte"));
+        assert!(Sequence::parse(b"RIFF\0\0\0\0").is_err());
+    }
+
+    #[test]
+    fn written_files_read_back() {
+        let path = env::temp_dir().join(format!("y-smf-test-{}.mid", process::id()));
+        let messages = [
+            (0., [0x90, 60, 100]),
+            (0.5, [0xc0, 5, 0]),
+            (1., [0x80, 60, 0]),
+        ];
+        write(&path, 120., &messages).unwrap();
+        let sequence = Sequence::read(&path);
+        fs::remove_file(&path).unwrap();
+
+        let sequence = sequence.unwrap();
+        assert_eq!(sequence.ticks_per_quarter, Some(480));
+        assert_eq!(timing(&sequence), messages);
+    }
+
+    #[test]
+    fn following_a_tempo_map_retimes_messages() {
+        let mut sequence = Sequence::parse(&file(
+            96,
+            &[0x00, 0x90, 60, 100, 0x60, 60, 0, 0x00, 0xff, 0x2f, 0],
+        ))
+        .unwrap();
+        let time_signature = TimeSignature {
+            numerator: 4,
+            denominator: 4,
+        };
+        sequence.follow(&TempoMap::constant(60., time_signature));
+
+        assert_eq!(
+            timing(&sequence),
+            [(0., [0x90, 60, 100]), (1., [0x90, 60, 0])]
+        );
+    }
+}
@@ -0,0 +1,102 @@
+use anyhow::{anyhow, ensure, Context};
+use clap::ArgEnum;
+
+/// Where the stereo tools sit in relation to the plugin
+#[derive(ArgEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Position {
+    /// On the plugin's inputs
+    Pre,
+    /// On the plugin's output
+    Post,
+}
+
+/// Channels whose polarity is inverted
+#[derive(ArgEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Polarity {
+    None,
+    Left,
+    Right,
+    Both,
+}
+
+/// How the stereo tools process the first two channels, changeable while they run
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Settings {
+    pub enabled: bool,
+    pub position: Position,
+    /// Level of the side signal, 0 being mono, 1 leaving it as it is and 2 twice as wide
+    pub width: f32,
+    /// Sums both channels to mono, to check how the output holds up on mono playback
+    pub mono: bool,
+    pub invert: Polarity,
+    /// Swaps the left and right channels
+    pub swap: bool,
+}
+
+impl Settings {
+    pub fn validate(&self) -> anyhow::Result<()> {
+        ensure!((0. ..=4.).contains(&self.width), "width goes from 0 to 4");
+        Ok(())
+    }
+
+    /// Changes a setting from a command like `width 1.5` or `invert left`
+    pub fn set(&mut self, setting: &str, value: &str) -> anyhow::Result<()> {
+        let switch = || match value {
+            "on" => Ok(true),
+            "off" => Ok(false),
+            _ => Err(anyhow!("expected {setting} on|off")),
+        };
+        let mut settings = *self;
+        match setting {
+            "position" => {
+                settings.position = Position::from_str(value, true).map_err(|err| anyhow!(err))?;
+            }
+            "width" => settings.width = value.parse().context("invalid width")?,
+            "mono" => settings.mono = switch()?,
+            "invert" => {
+                settings.invert = Polarity::from_str(value, true).map_err(|err| anyhow!(err))?;
+            }
+            "swap" => settings.swap = switch()?,
+            _ => return Err(anyhow!("unknown setting {setting:?}")),
+        }
+
+        settings.validate()?;
+        *self = settings;
+        Ok(())
+    }
+
+    /// Processes the first `length` samples of the first two channels in place, if the tools are
+    /// enabled at `position`. Channels are swapped first, then inverted, then narrowed or widened.
+    pub fn process(&self, position: Position, channels: &mut [Vec<f32>], length: usize) {
+        if !self.enabled || self.position != position {
+            return;
+        }
+        let [left, right, ..] = channels else {
+            return;
+        };
+
+        let (invert_left, invert_right) = match self.invert {
+            Polarity::None => (false, false),
+            Polarity::Left => (true, false),
+            Polarity::Right => (false, true),
+            Polarity::Both => (true, true),
+        };
+        let width = if self.mono { 0. } else { self.width };
+        for (left, right) in left[..length].iter_mut().zip(&mut right[..length]) {
+            if self.swap {
+                std::mem::swap(left, right);
+            }
+            if invert_left {
+                *left = -*left;
+            }
+            if invert_right {
+                *right = -*right;
+            }
+
+            let mid = (*left + *right) / 2.;
+            let side = (*left - *right) / 2. * width;
+            *left = mid + side;
+            *right = mid - side;
+        }
+    }
+}
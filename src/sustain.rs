@@ -0,0 +1,117 @@
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+
+use vst::event::MidiEvent;
+
+use crate::mpe::Zones;
+
+/// Host-side sustain pedal and note hold, for plugins that ignore CC64.
+///
+/// Note-offs are held back while the pedal is down on their channel or while hold is on, and sent
+/// once both are released. With MPE, the pedal on a zone's master channel holds its member
+/// channels too.
+pub struct Sustain {
+    /// Whether to handle CC64 rather than passing it on to the plugin
+    pedal_enabled: bool,
+    pedal: [bool; 16],
+    /// Held notes by channel and key
+    held: [[bool; 128]; 16],
+    hold: Arc<AtomicBool>,
+    holding: bool,
+    zones: Option<Zones>,
+    /// Scratch space for rewriting the events of a block
+    output: Vec<MidiEvent>,
+}
+
+impl Sustain {
+    pub fn new(pedal_enabled: bool, hold: Arc<AtomicBool>, zones: Option<Zones>) -> Self {
+        Self {
+            pedal_enabled,
+            pedal: [false; 16],
+            held: [[false; 128]; 16],
+            hold,
+            holding: false,
+            zones,
+            output: Vec::new(),
+        }
+    }
+
+    /// Rewrites the events of a block, holding and releasing note-offs
+    pub fn process(&mut self, events: &mut Vec<MidiEvent>) {
+        self.output.clear();
+
+        let holding = self.hold.load(Ordering::Relaxed);
+        if self.holding && !holding {
+            self.holding = false;
+            for channel in 0..16 {
+                if !self.pedal_down(channel) {
+                    self.release(channel, 0);
+                }
+            }
+        }
+        self.holding = holding;
+
+        for event in events.drain(..) {
+            let [status, key, value] = event.data;
+            let channel = (status & 0x0f) as usize;
+            let note_off = status & 0xf0 == 0x80 || (status & 0xf0 == 0x90 && value == 0);
+
+            if note_off && (self.holding || self.pedal_down(channel)) {
+                self.held[channel][key as usize & 0x7f] = true;
+            } else if self.pedal_enabled && status & 0xf0 == 0xb0 && key == 64 {
+                self.pedal[channel] = value >= 64;
+                if !self.holding {
+                    // Lifting a master channel's pedal releases its members too
+                    for member in 0..16 {
+                        if (member == channel || self.master(member) == Some(channel))
+                            && !self.pedal_down(member)
+                        {
+                            self.release(member, event.delta_frames);
+                        }
+                    }
+                }
+            } else {
+                if status & 0xf0 == 0x90 {
+                    // The plugin gets the new note-on, and a note-off when it's released again
+                    self.held[channel][key as usize & 0x7f] = false;
+                }
+                self.output.push(event);
+            }
+        }
+
+        events.append(&mut self.output);
+    }
+
+    /// The MPE master channel `channel` follows the pedal of, if it's a member channel
+    fn master(&self, channel: usize) -> Option<usize> {
+        let master = self.zones?.master_of(channel as u8)? as usize;
+        (master != channel).then_some(master)
+    }
+
+    /// Whether the pedal holds `channel`, its own or its MPE master channel's
+    fn pedal_down(&self, channel: usize) -> bool {
+        self.pedal[channel]
+            || self
+                .master(channel)
+                .is_some_and(|master| self.pedal[master])
+    }
+
+    /// Sends note-offs for the notes held on `channel`
+    fn release(&mut self, channel: usize, delta_frames: i32) {
+        for key in 0..128 {
+            if std::mem::take(&mut self.held[channel][key]) {
+                self.output.push(MidiEvent {
+                    data: [0x80 | channel as u8, key as u8, 0],
+                    delta_frames,
+                    live: true,
+                    note_length: None,
+                    note_offset: None,
+                    detune: 0,
+                    note_off_velocity: 0,
+                });
+            }
+        }
+    }
+}
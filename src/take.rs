@@ -0,0 +1,380 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::{
+        mpsc::{self, Receiver, SyncSender},
+        Mutex,
+    },
+    thread::{self, JoinHandle},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{anyhow, bail, ensure, Context, Result};
+use hound::{SampleFormat, WavSpec, WavWriter};
+
+use crate::{
+    json,
+    mapping::{self, CcMapping},
+    pool::Returns,
+    smf,
+};
+
+/// Buffers of output waiting to be written before the audio thread has to drop them, a few
+/// seconds' worth at the usual buffer sizes
+pub const PENDING_BUFFERS: usize = 128;
+
+/// A MIDI message that toggles something like recording or bypass, such as a footswitch sending a
+/// controller or a pad sending a note
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Trigger {
+    Cc(u8),
+    Note(u8),
+}
+
+impl Trigger {
+    /// Whether `message` belongs to the trigger and if so, whether it's a press, which toggles,
+    /// rather than a release
+    pub fn matches(self, [status, data, value]: [u8; 3]) -> Option<bool> {
+        match (self, status & 0xf0) {
+            (Trigger::Cc(controller), 0xb0) if data == controller => Some(value >= 64),
+            (Trigger::Note(note), 0x90) if data == note => Some(value > 0),
+            (Trigger::Note(note), 0x80) if data == note => Some(false),
+            _ => None,
+        }
+    }
+}
+
+impl FromStr for Trigger {
+    type Err = anyhow::Error;
+
+    /// Parses triggers like `cc:64` or `note:36`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (kind, number) = s
+            .split_once(':')
+            .context("expected cc:<controller> or note:<note>")?;
+        let number: u8 = number.parse().context("invalid number")?;
+        ensure!(number <= 127, "MIDI numbers go from 0 to 127");
+
+        match kind {
+            "cc" => Ok(Trigger::Cc(number)),
+            "note" => Ok(Trigger::Note(number)),
+            _ => bail!("unknown trigger {kind:?}, expected cc or note"),
+        }
+    }
+}
+
+/// Where the audio thread sends its output while a take is recorded
+pub type Tap = SyncSender<Chunk>;
+
+/// Output sent to a take's writer, which gives it back once it's written
+pub enum Chunk {
+    /// The output from before the take started, which comes first
+    PreRoll(PreRoll),
+    /// A buffer of output, interleaved, from a [`Pool`](crate::pool::Pool)
+    Buffer(Vec<f32>),
+}
+
+/// The last stretch of output, interleaved, in a ring allocated up front, which new takes start
+/// with
+pub struct PreRoll {
+    samples: Vec<f32>,
+    /// Where the next sample goes
+    next: usize,
+    /// Whether the ring has gone all the way round, so it holds samples after `next` as well
+    full: bool,
+}
+
+impl PreRoll {
+    /// Holds the last `length` samples
+    pub fn new(length: usize) -> Self {
+        Self {
+            samples: vec![0.; length],
+            next: 0,
+            full: false,
+        }
+    }
+
+    /// Adds the first `length` samples of `channels`, interleaved, over the oldest ones
+    pub fn push(&mut self, channels: &[Vec<f32>], length: usize) {
+        if self.samples.is_empty() {
+            return;
+        }
+        for i in 0..length {
+            for channel in channels {
+                self.samples[self.next] = channel[i];
+                self.next += 1;
+                if self.next == self.samples.len() {
+                    self.next = 0;
+                    self.full = true;
+                }
+            }
+        }
+    }
+
+    /// The samples held, oldest first
+    pub fn samples(&self) -> impl Iterator<Item = f32> + '_ {
+        let older = if self.full {
+            &self.samples[self.next..]
+        } else {
+            &[]
+        };
+        older.iter().chain(&self.samples[..self.next]).copied()
+    }
+
+    pub fn clear(&mut self) {
+        self.next = 0;
+        self.full = false;
+    }
+}
+
+/// What was playing when a take was recorded, saved next to it
+pub struct Sidecar {
+    pub plugin: String,
+    pub vendor: String,
+    pub plugin_path: PathBuf,
+    pub preset: Option<PathBuf>,
+    pub tempo: f64,
+    pub time_signature: (i32, i32),
+    /// Seconds of output from before recording started that the take begins with, at most, as
+    /// there may not have been that much output yet
+    pub pre_roll: f64,
+}
+
+impl Sidecar {
+    fn json(&self, started: &str, seconds: Option<f64>) -> String {
+        let path = |path: &Path| json::string(&path.display().to_string());
+        let mut json = format!(
+            "{{\"plugin\": {}, \"vendor\": {}, \"plugin_path\": {}, \"preset\": {}, \
+             \"tempo\": {}, \"time_signature\": \"{}/{}\", \"pre_roll\": {}, \"started\": {}",
+            json::string(&self.plugin),
+            json::string(&self.vendor),
+            path(&self.plugin_path),
+            self.preset.as_deref().map_or("null".to_owned(), path),
+            self.tempo,
+            self.time_signature.0,
+            self.time_signature.1,
+            self.pre_roll,
+            json::string(started),
+        );
+        if let Some(seconds) = seconds {
+            json.push_str(&format!(", \"seconds\": {seconds:.3}"));
+        }
+        json.push_str("}\n");
+        json
+    }
+}
+
+/// A take being written to a WAV file on a thread of its own
+pub struct Recording {
+    pub path: PathBuf,
+    sidecar: Sidecar,
+    started: String,
+    sample_rate: u32,
+    channels: u16,
+    writer: JoinHandle<Result<u64>>,
+}
+
+impl Recording {
+    /// Starts a new take in `directory`, named after the time so that no take is ever overwritten,
+    /// and writes `sidecar` next to it.
+    ///
+    /// The take is written as 32-bit float from what arrives on the returned tap, until the tap is
+    /// dropped. Buffers are given back to `returns` and the pre-roll to `pre_rolls`.
+    pub fn start(
+        directory: &Path,
+        channels: u16,
+        sample_rate: u32,
+        sidecar: Sidecar,
+        returns: Returns,
+        pre_rolls: SyncSender<PreRoll>,
+    ) -> Result<(Self, Tap)> {
+        fs::create_dir_all(directory)
+            .with_context(|| format!("failed to create {}", directory.display()))?;
+        let started = timestamp(SystemTime::now());
+        let path = free_path(directory, &format!("take-{started}"));
+
+        let spec = WavSpec {
+            channels,
+            sample_rate,
+            bits_per_sample: 32,
+            sample_format: SampleFormat::Float,
+        };
+        let writer = WavWriter::create(&path, spec)
+            .with_context(|| format!("failed to create {}", path.display()))?;
+        fs::write(path.with_extension("json"), sidecar.json(&started, None))?;
+
+        let (tap, chunks) = mpsc::sync_channel(PENDING_BUFFERS);
+        let writer = thread::spawn(move || write(writer, chunks, returns, pre_rolls));
+
+        let recording = Self {
+            path,
+            sidecar,
+            started,
+            sample_rate,
+            channels,
+            writer,
+        };
+        Ok((recording, tap))
+    }
+
+    /// Waits for the take to be written once its tap has been dropped, returning its length in
+    /// seconds
+    pub fn finish(self) -> Result<f64> {
+        let samples = self
+            .writer
+            .join()
+            .map_err(|_| anyhow!("the writer panicked"))?
+            .with_context(|| format!("failed to write {}", self.path.display()))?;
+        let seconds = samples as f64 / self.channels as f64 / self.sample_rate as f64;
+        fs::write(
+            self.path.with_extension("json"),
+            self.sidecar.json(&self.started, Some(seconds)),
+        )?;
+
+        Ok(seconds)
+    }
+
+    /// The tempo the take was recorded at, in beats per minute
+    pub fn tempo(&self) -> f64 {
+        self.sidecar.tempo
+    }
+}
+
+/// A parameter change the plugin reported while a take was recorded
+#[derive(Clone, Copy, Debug)]
+pub struct Change {
+    /// Seconds since the take started
+    pub seconds: f64,
+    pub parameter: i32,
+    pub value: f32,
+}
+
+/// Collects the parameter changes the plugin reports while a take is recorded, from whichever
+/// thread it reports them on
+#[derive(Default)]
+pub struct Automation {
+    /// When the take started, in seconds of the transport, and the changes since
+    take: Mutex<Option<(f64, Vec<Change>)>>,
+}
+
+impl Automation {
+    pub fn start(&self, seconds: f64) {
+        *self.take.lock().unwrap() = Some((seconds, Vec::new()));
+    }
+
+    /// Adds a change at `seconds` of the transport, unless no take is being recorded
+    pub fn record(&self, seconds: f64, parameter: i32, value: f32) {
+        if let Some((started, changes)) = &mut *self.take.lock().unwrap() {
+            changes.push(Change {
+                seconds: seconds - *started,
+                parameter,
+                value,
+            });
+        }
+    }
+
+    /// Ends the take, returning its changes in order
+    pub fn stop(&self) -> Vec<Change> {
+        let mut changes = self
+            .take
+            .lock()
+            .unwrap()
+            .take()
+            .map_or(Vec::new(), |(_, changes)| changes);
+        changes.sort_by(|a, b| a.seconds.total_cmp(&b.seconds));
+        changes
+    }
+}
+
+/// Writes `changes` to a MIDI file at `tempo` through `mappings` in reverse, so each is the
+/// controller mapped onto its parameter or else an NRPN, see [`mapping::reverse_cc`]
+pub fn write_automation(
+    path: &Path,
+    changes: &[Change],
+    mappings: &[CcMapping],
+    tempo: f64,
+) -> Result<()> {
+    let messages: Vec<(f64, [u8; 3])> = changes
+        .iter()
+        .flat_map(|change| {
+            mapping::reverse_cc(mappings, change.parameter, change.value)
+                .into_iter()
+                .map(|data| (change.seconds, data))
+        })
+        .collect();
+    smf::write(path, tempo, &messages)
+}
+
+fn write(
+    mut writer: WavWriter<std::io::BufWriter<fs::File>>,
+    chunks: Receiver<Chunk>,
+    returns: Returns,
+    pre_rolls: SyncSender<PreRoll>,
+) -> Result<u64> {
+    let mut samples = 0;
+    for chunk in chunks {
+        match chunk {
+            Chunk::PreRoll(pre_roll) => {
+                for sample in pre_roll.samples() {
+                    writer.write_sample(sample)?;
+                    samples += 1;
+                }
+                let _ = pre_rolls.try_send(pre_roll);
+            }
+            Chunk::Buffer(buffer) => {
+                for sample in &buffer {
+                    writer.write_sample(*sample)?;
+                }
+                samples += buffer.len() as u64;
+                returns.give(buffer);
+            }
+        }
+    }
+    writer.finalize()?;
+
+    Ok(samples)
+}
+
+/// `<directory>/<stem>.wav`, or with `-2`, `-3` and so on added if a take is called that already
+fn free_path(directory: &Path, stem: &str) -> PathBuf {
+    let mut path = directory.join(format!("{stem}.wav"));
+    let mut number = 1;
+    while path.exists() {
+        number += 1;
+        path = directory.join(format!("{stem}-{number}.wav"));
+    }
+    path
+}
+
+/// Formats `time` like `2022-06-01T18-30-05Z`, in UTC and without colons so it can go in file
+/// names
+fn timestamp(time: SystemTime) -> String {
+    let seconds = time
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_secs()) as i64;
+    let (days, time) = (seconds.div_euclid(86_400), seconds.rem_euclid(86_400));
+
+    // Converts days since 1970-01-01 to a date in the proleptic Gregorian calendar
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}-{:02}-{:02}Z",
+        time / 3600,
+        time / 60 % 60,
+        time % 60
+    )
+}
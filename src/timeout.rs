@@ -0,0 +1,154 @@
+use std::{
+    fmt, process,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, RecvTimeoutError, Sender},
+    },
+    thread,
+    time::Duration,
+};
+
+use anyhow::{anyhow, Result};
+
+/// Set by [`run_inline`]
+static INLINE: AtomicBool = AtomicBool::new(false);
+
+/// Makes [`with_timeout`] and [`Deadline`] run everything on the calling thread without a time
+/// limit, for running under valgrind or sanitizers, where helper threads only add noise
+pub fn run_inline() {
+    INLINE.store(true, Ordering::Relaxed);
+}
+
+/// The error [`with_timeout`] gives up with
+#[derive(Debug)]
+pub struct TimedOut {
+    timeout: Duration,
+    what: String,
+}
+
+impl fmt::Display for TimedOut {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "timed out after {:?} {}", self.timeout, self.what)
+    }
+}
+
+impl std::error::Error for TimedOut {}
+
+/// Whether `err` is [`with_timeout`] giving up, with or without context added to it
+pub fn timed_out(err: &anyhow::Error) -> bool {
+    err.is::<TimedOut>()
+}
+
+/// Runs `f` on a helper thread, giving up after `timeout`.
+///
+/// On timeout the helper thread is left behind, still blocked in whatever `f` was doing, and it
+/// carries on with the rest of `f` if the call ever returns. Whatever `f` returns that late is
+/// leaked rather than dropped. A timeout is fatal for the plugin `f` called into: nothing may call
+/// into it again, not even to shut it down, and its library has to stay loaded. Callers check for
+/// one with [`timed_out`] and leak the plugin.
+pub fn with_timeout<T, F>(timeout: Duration, what: &str, f: F) -> Result<T>
+where
+    T: Send + 'static,
+    F: FnOnce() -> T + Send + 'static,
+{
+    if INLINE.load(Ordering::Relaxed) {
+        return Ok(f());
+    }
+    let (sender, receiver) = mpsc::channel();
+
+    thread::spawn(move || {
+        // Nobody is waiting any more, and dropping a late plugin would shut it down
+        if let Err(mpsc::SendError(late)) = sender.send(f()) {
+            std::mem::forget(late);
+        }
+    });
+
+    match receiver.recv_timeout(timeout) {
+        Ok(result) => Ok(result),
+        Err(RecvTimeoutError::Timeout) => Err(TimedOut {
+            timeout,
+            what: what.to_owned(),
+        }
+        .into()),
+        Err(RecvTimeoutError::Disconnected) => Err(anyhow!("the plugin panicked while {what}")),
+    }
+}
+
+/// Exits the process unless dropped within a timeout.
+///
+/// This is for calls which can't be moved to a helper thread, such as opening an editor which has
+/// to happen on the thread owning the window.
+pub struct Deadline {
+    _disarm: Option<Sender<()>>,
+}
+
+impl Deadline {
+    pub fn new(timeout: Duration, what: &str) -> Self {
+        if INLINE.load(Ordering::Relaxed) {
+            return Self { _disarm: None };
+        }
+        let (sender, receiver) = mpsc::channel::<()>();
+        let what = what.to_owned();
+
+        thread::spawn(move || {
+            if let Err(RecvTimeoutError::Timeout) = receiver.recv_timeout(timeout) {
+                log::error!("timed out after {timeout:?} {what}");
+                process::exit(1);
+            }
+        });
+
+        Self {
+            _disarm: Some(sender),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::atomic::{AtomicBool, Ordering},
+        thread,
+        time::Duration,
+    };
+
+    use super::{timed_out, with_timeout};
+
+    #[test]
+    fn timeouts_can_be_told_apart_from_other_errors() {
+        let err = with_timeout(Duration::from_millis(10), "sleeping", || {
+            thread::sleep(Duration::from_millis(500))
+        })
+        .unwrap_err()
+        .context("in a test");
+        assert!(timed_out(&err));
+        assert_eq!(
+            format!("{err:#}"),
+            "in a test: timed out after 10ms sleeping"
+        );
+
+        let err = with_timeout(Duration::from_secs(1), "panicking", || panic!()).unwrap_err();
+        assert!(!timed_out(&err));
+    }
+
+    #[test]
+    fn a_late_result_is_never_dropped() {
+        static DROPPED: AtomicBool = AtomicBool::new(false);
+
+        struct Plugin;
+
+        impl Drop for Plugin {
+            fn drop(&mut self) {
+                DROPPED.store(true, Ordering::SeqCst);
+            }
+        }
+
+        let result = with_timeout(Duration::from_millis(10), "loading", || {
+            thread::sleep(Duration::from_millis(50));
+            Plugin
+        });
+        assert!(timed_out(&result.err().unwrap()));
+
+        thread::sleep(Duration::from_millis(500));
+        assert!(!DROPPED.load(Ordering::SeqCst));
+    }
+}
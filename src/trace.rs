@@ -0,0 +1,46 @@
+use std::{
+    fmt::{self, Debug},
+    fs::File,
+    io::{self, LineWriter, Write},
+    path::Path,
+    sync::Mutex,
+    thread,
+    time::Instant,
+};
+
+/// A file recording calls between the host and the plugin, one per line, with the time since the
+/// trace was started and the calling thread.
+///
+/// Every line is written out immediately, so the trace is complete up to a crash.
+pub struct TraceFile {
+    epoch: Instant,
+    out: Mutex<LineWriter<File>>,
+}
+
+impl TraceFile {
+    pub fn create(path: &Path) -> io::Result<Self> {
+        Ok(Self {
+            epoch: Instant::now(),
+            out: Mutex::new(LineWriter::new(File::create(path)?)),
+        })
+    }
+
+    /// Records a call to `name` with `args`, which returned `result`
+    pub fn call(&self, name: &str, args: fmt::Arguments, result: &dyn Debug) {
+        self.line(format_args!("{name}({args}) -> {result:?}"));
+    }
+
+    /// Records a free-form line
+    pub fn line(&self, message: fmt::Arguments) {
+        let elapsed = self.epoch.elapsed().as_secs_f64();
+        let thread = thread::current();
+        let thread_name = thread.name().unwrap_or("");
+
+        let mut out = self.out.lock().unwrap();
+        let _ = writeln!(
+            out,
+            "{elapsed:12.6} {:?} {thread_name:<8} {message}",
+            thread.id()
+        );
+    }
+}
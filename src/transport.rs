@@ -0,0 +1,327 @@
+use std::{
+    fs,
+    path::Path,
+    str::FromStr,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use anyhow::{anyhow, ensure, Context, Result};
+use vst::api::{TimeInfo, TimeInfoFlags};
+
+/// A time signature such as 3/4
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TimeSignature {
+    pub numerator: i32,
+    pub denominator: i32,
+}
+
+impl TimeSignature {
+    /// The length of a single beat, in quarter notes
+    pub fn beat_length(&self) -> f64 {
+        4. / self.denominator as f64
+    }
+
+    /// The length of a bar, in quarter notes
+    pub fn bar_length(&self) -> f64 {
+        self.numerator as f64 * self.beat_length()
+    }
+}
+
+impl FromStr for TimeSignature {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (numerator, denominator) = s
+            .split_once('/')
+            .ok_or_else(|| anyhow!("expected a time signature like 4/4"))?;
+        let numerator = numerator.trim().parse()?;
+        let denominator = denominator.trim().parse()?;
+        ensure!(
+            numerator > 0 && denominator > 0,
+            "time signature must be positive"
+        );
+
+        Ok(Self {
+            numerator,
+            denominator,
+        })
+    }
+}
+
+/// A point in a tempo map from which a tempo and time signature apply
+#[derive(Clone, Copy, Debug)]
+struct Segment {
+    /// Start of the segment in bars, counting from zero
+    bar: u32,
+    /// Start of the segment in beats since the beginning
+    beat: i64,
+    /// Start of the segment in quarter notes
+    quarters: f64,
+    /// Start of the segment in seconds
+    seconds: f64,
+    tempo: f64,
+    time_signature: TimeSignature,
+}
+
+/// Tempo and time signature changes over the course of a song.
+///
+/// Changes happen at the start of a bar. Each line of a tempo map file holds a bar number
+/// (counting from 1), a tempo in beats per minute and optionally a new time signature, e.g.
+/// `17 140 7/8`. Empty lines and lines starting with `#` are ignored.
+#[derive(Clone, Debug)]
+pub struct TempoMap {
+    segments: Vec<Segment>,
+}
+
+impl TempoMap {
+    /// A map with a single tempo and time signature
+    pub fn constant(tempo: f64, time_signature: TimeSignature) -> Self {
+        Self {
+            segments: vec![Segment {
+                bar: 0,
+                beat: 0,
+                quarters: 0.,
+                seconds: 0.,
+                tempo,
+                time_signature,
+            }],
+        }
+    }
+
+    /// Reads a tempo map file, starting from `tempo` and `time_signature` if it doesn't set them
+    /// for the first bar
+    pub fn read(path: &Path, tempo: f64, time_signature: TimeSignature) -> Result<Self> {
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("failed to read {}", path.display()))?;
+        let mut map = Self::constant(tempo, time_signature);
+
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            map.parse_line(line).with_context(|| {
+                format!("{}:{}: invalid tempo map entry", path.display(), number + 1)
+            })?;
+        }
+
+        Ok(map)
+    }
+
+    fn parse_line(&mut self, line: &str) -> Result<()> {
+        let mut fields = line.split_whitespace();
+        let bar: u32 = fields.next().context("missing bar")?.parse()?;
+        let tempo: f64 = fields.next().context("missing tempo")?.parse()?;
+        let time_signature = fields.next().map(str::parse).transpose()?;
+        ensure!(fields.next().is_none(), "unexpected trailing fields");
+        ensure!(bar > 0, "bars are counted from 1");
+        ensure!(tempo > 0., "tempo must be positive");
+
+        let bar = bar - 1;
+        let last = *self.segments.last().unwrap();
+        let time_signature = time_signature.unwrap_or(last.time_signature);
+
+        if bar == 0 && self.segments.len() == 1 {
+            // Replaces the defaults
+            self.segments[0].tempo = tempo;
+            self.segments[0].time_signature = time_signature;
+            return Ok(());
+        }
+        ensure!(bar > last.bar, "changes have to be in order of bars");
+
+        let bars = (bar - last.bar) as f64;
+        let quarters = bars * last.time_signature.bar_length();
+        self.segments.push(Segment {
+            bar,
+            beat: last.beat + (bar - last.bar) as i64 * last.time_signature.numerator as i64,
+            quarters: last.quarters + quarters,
+            seconds: last.seconds + quarters * 60. / last.tempo,
+            tempo,
+            time_signature,
+        });
+
+        Ok(())
+    }
+
+    fn segment_at(&self, seconds: f64) -> &Segment {
+        let index = self
+            .segments
+            .partition_point(|segment| segment.seconds <= seconds);
+        &self.segments[index.saturating_sub(1)]
+    }
+
+    /// The tempo at a time in seconds
+    pub fn tempo_at(&self, seconds: f64) -> f64 {
+        self.segment_at(seconds).tempo
+    }
+
+    /// The time signature at a time in seconds
+    pub fn time_signature_at(&self, seconds: f64) -> TimeSignature {
+        self.segment_at(seconds).time_signature
+    }
+
+    /// Converts a time in seconds to a musical position in quarter notes
+    pub fn quarters_at(&self, seconds: f64) -> f64 {
+        let segment = self.segment_at(seconds);
+        segment.quarters + (seconds - segment.seconds) * segment.tempo / 60.
+    }
+
+    /// Converts a musical position in quarter notes to a time in seconds
+    pub fn seconds_at(&self, quarters: f64) -> f64 {
+        let index = self
+            .segments
+            .partition_point(|segment| segment.quarters <= quarters);
+        let segment = &self.segments[index.saturating_sub(1)];
+        segment.seconds + (quarters - segment.quarters) * 60. / segment.tempo
+    }
+
+    /// The position of the start of the bar playing at `seconds`, in quarter notes
+    pub fn bar_start_at(&self, seconds: f64) -> f64 {
+        let segment = self.segment_at(seconds);
+        let bar_length = segment.time_signature.bar_length();
+        let bars = ((self.quarters_at(seconds) - segment.quarters) / bar_length).floor();
+        segment.quarters + bars * bar_length
+    }
+
+    /// The number of the beat playing at `seconds`, and whether it's the first beat of a bar
+    pub fn beat_at(&self, seconds: f64) -> (i64, bool) {
+        let segment = self.segment_at(seconds);
+        let beats = ((self.quarters_at(seconds) - segment.quarters)
+            / segment.time_signature.beat_length())
+        .floor() as i64;
+        let downbeat = beats.rem_euclid(segment.time_signature.numerator as i64) == 0;
+
+        (segment.beat + beats, downbeat)
+    }
+}
+
+/// The host's playback position, shared between the audio thread and the host callbacks.
+///
+/// The position is counted in samples at the rate the plugin runs at.
+pub struct Transport {
+    sample_rate: f64,
+    tempo_map: TempoMap,
+    position: AtomicU64,
+}
+
+impl Transport {
+    pub fn new(sample_rate: f64, tempo_map: TempoMap) -> Self {
+        Self {
+            sample_rate,
+            tempo_map,
+            position: AtomicU64::new(0),
+        }
+    }
+
+    pub fn tempo_map(&self) -> &TempoMap {
+        &self.tempo_map
+    }
+
+    /// The current position in samples
+    pub fn position(&self) -> u64 {
+        self.position.load(Ordering::Relaxed)
+    }
+
+    /// The current position in seconds
+    pub fn seconds(&self) -> f64 {
+        self.position() as f64 / self.sample_rate
+    }
+
+    /// Moves the playback position forward by `samples`
+    pub fn advance(&self, samples: u64) {
+        self.position.fetch_add(samples, Ordering::Relaxed);
+    }
+
+    /// Describes the current position for `audioMasterGetTime`
+    pub fn time_info(&self) -> TimeInfo {
+        let position = self.position();
+        let seconds = position as f64 / self.sample_rate;
+        let time_signature = self.tempo_map.time_signature_at(seconds);
+
+        TimeInfo {
+            sample_pos: position as f64,
+            sample_rate: self.sample_rate,
+            ppq_pos: self.tempo_map.quarters_at(seconds),
+            tempo: self.tempo_map.tempo_at(seconds),
+            bar_start_pos: self.tempo_map.bar_start_at(seconds),
+            time_sig_numerator: time_signature.numerator,
+            time_sig_denominator: time_signature.denominator,
+            flags: (TimeInfoFlags::TRANSPORT_PLAYING
+                | TimeInfoFlags::PPQ_POS_VALID
+                | TimeInfoFlags::TEMPO_VALID
+                | TimeInfoFlags::BARS_VALID
+                | TimeInfoFlags::TIME_SIG_VALID)
+                .bits(),
+            ..TimeInfo::default()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{TempoMap, TimeSignature};
+
+    const FOUR_FOUR: TimeSignature = TimeSignature {
+        numerator: 4,
+        denominator: 4,
+    };
+
+    fn map(lines: &[&str]) -> TempoMap {
+        let mut map = TempoMap::constant(120., FOUR_FOUR);
+        for line in lines {
+            map.parse_line(line).unwrap();
+        }
+        map
+    }
+
+    #[test]
+    fn parses_time_signatures() {
+        let seven_eight: TimeSignature = "7/8".parse().unwrap();
+        assert_eq!((seven_eight.numerator, seven_eight.denominator), (7, 8));
+        assert_eq!(seven_eight.bar_length(), 3.5);
+        assert!("0/4".parse::<TimeSignature>().is_err());
+        assert!("4".parse::<TimeSignature>().is_err());
+    }
+
+    #[test]
+    fn the_first_bar_replaces_the_defaults() {
+        let map = map(&["1 90 3/4"]);
+        assert_eq!(map.tempo_at(0.), 90.);
+        assert_eq!(map.time_signature_at(10.).numerator, 3);
+    }
+
+    #[test]
+    fn tempo_changes_start_at_their_bar() {
+        // Two bars of 4/4 at 120 bpm take 4 seconds
+        let map = map(&["3 60"]);
+        assert_eq!(map.tempo_at(3.9), 120.);
+        assert_eq!(map.tempo_at(4.), 60.);
+        assert_eq!(map.quarters_at(4.), 8.);
+        assert_eq!(map.quarters_at(6.), 10.);
+        assert_eq!(map.seconds_at(10.), 6.);
+        assert!((map.seconds_at(map.quarters_at(5.3)) - 5.3).abs() < 1e-9);
+    }
+
+    #[test]
+    fn bars_and_beats_follow_time_signature_changes() {
+        // The first bar of 4/4 takes 2 seconds, bars of 3/4 take 1.5
+        let map = map(&["2 120 3/4"]);
+        assert_eq!(map.bar_start_at(2.5), 4.);
+        assert_eq!(map.bar_start_at(3.6), 7.);
+        assert_eq!(map.beat_at(1.5), (3, false));
+        assert_eq!(map.beat_at(2.), (4, true));
+        assert_eq!(map.beat_at(3.), (6, false));
+        assert_eq!(map.beat_at(3.5), (7, true));
+    }
+
+    #[test]
+    fn rejects_entries_out_of_order() {
+        let mut map = map(&["3 100"]);
+        assert!(map.parse_line("2 100").is_err());
+        assert!(map.parse_line("3 100").is_err());
+        assert!(map.parse_line("0 100").is_err());
+        assert!(map.parse_line("4 -5").is_err());
+        assert!(map.parse_line("4 100 4/4 extra").is_err());
+    }
+}
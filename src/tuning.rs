@@ -0,0 +1,391 @@
+use std::{fs, path::Path};
+
+use anyhow::{bail, ensure, Context, Result};
+use clap::ArgEnum;
+use vst::event::MidiEvent;
+
+/// Messages of a single note tuning change are kept under this many keys, which fits the 7-bit
+/// count with room to spare
+const KEYS_PER_MESSAGE: usize = 64;
+
+/// How a retuning reaches the plugin
+#[derive(ArgEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mode {
+    /// MTS if the plugin says it supports single note tuning changes, detune otherwise
+    Auto,
+    /// MIDI Tuning Standard single note tuning changes, sent as SysEx once
+    Mts,
+    /// Notes are moved to the nearest equal tempered key and detuned in cents
+    Detune,
+}
+
+/// A Scala scale: its description and the pitch of every degree after the first in cents, the
+/// last one being the period it repeats at, usually the octave
+#[derive(Clone, Debug, PartialEq)]
+pub struct Scale {
+    pub description: String,
+    pub degrees: Vec<f64>,
+}
+
+impl Scale {
+    pub fn read(path: &Path) -> Result<Self> {
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("failed to read {}", path.display()))?;
+        Self::parse(&text).with_context(|| format!("failed to parse {}", path.display()))
+    }
+
+    /// Parses the contents of a `.scl` file
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut lines = text.lines().filter(|line| !line.starts_with('!'));
+        let description = lines.next().context("the description is missing")?.trim();
+        let count: usize = first_word(lines.next().context("the note count is missing")?)
+            .parse()
+            .context("invalid note count")?;
+        ensure!(count > 0, "a scale needs at least one note");
+
+        let degrees = lines
+            .take(count)
+            .map(|line| {
+                let pitch = first_word(line);
+                pitch_cents(pitch).with_context(|| format!("invalid pitch {pitch:?}"))
+            })
+            .collect::<Result<Vec<f64>>>()?;
+        ensure!(
+            degrees.len() == count,
+            "the scale has {} of its {count} notes",
+            degrees.len()
+        );
+
+        Ok(Self {
+            description: description.to_owned(),
+            degrees,
+        })
+    }
+
+    /// The pitch of `degree` in cents above degree 0, counting past the period and below 0
+    fn cents(&self, degree: i32) -> f64 {
+        let notes = self.degrees.len() as i32;
+        let period = self.degrees[self.degrees.len() - 1];
+        let within = match degree.rem_euclid(notes) {
+            0 => 0.,
+            index => self.degrees[index as usize - 1],
+        };
+        degree.div_euclid(notes) as f64 * period + within
+    }
+}
+
+/// A Scala keyboard mapping, saying which key plays which degree of the scale and how it's
+/// tuned
+#[derive(Clone, Debug, PartialEq)]
+pub struct KeyboardMap {
+    /// Keys in the pattern that repeats, 0 mapping every key to the next degree
+    size: usize,
+    first: u8,
+    last: u8,
+    /// The key playing degree 0
+    middle: u8,
+    reference_key: u8,
+    reference_frequency: f64,
+    /// The degree the pattern moves by when it repeats
+    octave_degree: i32,
+    /// The degree each key of the pattern plays, if any
+    mapping: Vec<Option<i32>>,
+}
+
+impl Default for KeyboardMap {
+    /// Middle C plays degree 0 and every key the next degree, with A above it at 440 Hz
+    fn default() -> Self {
+        Self {
+            size: 0,
+            first: 0,
+            last: 127,
+            middle: 60,
+            reference_key: 69,
+            reference_frequency: 440.,
+            octave_degree: 0,
+            mapping: Vec::new(),
+        }
+    }
+}
+
+impl KeyboardMap {
+    pub fn read(path: &Path) -> Result<Self> {
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("failed to read {}", path.display()))?;
+        Self::parse(&text).with_context(|| format!("failed to parse {}", path.display()))
+    }
+
+    /// Parses the contents of a `.kbm` file
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut values = text
+            .lines()
+            .filter(|line| !line.starts_with('!') && !line.trim().is_empty())
+            .map(first_word);
+        let mut next = |name: &str| {
+            values
+                .next()
+                .with_context(|| format!("the {name} is missing"))
+        };
+        let key = |value: &str, name: &str| -> Result<u8> {
+            let key: u8 = value.parse().with_context(|| format!("invalid {name}"))?;
+            ensure!(key <= 127, "the {name} has to be a MIDI key");
+            Ok(key)
+        };
+
+        let size: usize = next("map size")?.parse().context("invalid map size")?;
+        let first = key(next("first key")?, "first key")?;
+        let last = key(next("last key")?, "last key")?;
+        let middle = key(next("middle key")?, "middle key")?;
+        let reference_key = key(next("reference key")?, "reference key")?;
+        let reference_frequency: f64 = next("reference frequency")?
+            .parse()
+            .context("invalid reference frequency")?;
+        ensure!(
+            reference_frequency > 0.,
+            "the reference frequency has to be positive"
+        );
+        let octave_degree: i32 = next("octave degree")?
+            .parse()
+            .context("invalid octave degree")?;
+
+        // Keys left out at the end of the pattern aren't mapped
+        let mut mapping = Vec::with_capacity(size);
+        for _ in 0..size {
+            mapping.push(match values.next() {
+                Some("x") | None => None,
+                Some(degree) => Some(degree.parse().context("invalid degree in the mapping")?),
+            });
+        }
+
+        Ok(Self {
+            size,
+            first,
+            last,
+            middle,
+            reference_key,
+            reference_frequency,
+            octave_degree,
+            mapping,
+        })
+    }
+
+    /// The degree `key` plays, if it's mapped
+    fn degree(&self, key: u8) -> Option<i32> {
+        let offset = key as i32 - self.middle as i32;
+        if self.size == 0 {
+            return Some(offset);
+        }
+        let size = self.size as i32;
+        let degree = self.mapping[offset.rem_euclid(size) as usize]?;
+        Some(offset.div_euclid(size) * self.octave_degree + degree)
+    }
+}
+
+/// The pitch every MIDI key plays under a scale and keyboard mapping
+#[derive(Clone, Debug, PartialEq)]
+pub struct Tuning {
+    /// In equal tempered semitones, 69 being 440 Hz, or `None` for keys that don't play
+    semitones: [Option<f64>; 128],
+}
+
+impl Tuning {
+    pub fn new(scale: &Scale, map: &KeyboardMap) -> Result<Self> {
+        let Some(reference) = map.degree(map.reference_key) else {
+            bail!("the reference key isn't mapped");
+        };
+        let reference =
+            69. + 12. * (map.reference_frequency / 440.).log2() - scale.cents(reference) / 100.;
+
+        let mut semitones = [None; 128];
+        for (key, semitones) in semitones.iter_mut().enumerate() {
+            let key = key as u8;
+            if (map.first..=map.last).contains(&key) {
+                *semitones = map
+                    .degree(key)
+                    .map(|degree| reference + scale.cents(degree) / 100.);
+            }
+        }
+
+        Ok(Self { semitones })
+    }
+
+    /// Moves notes to the equal tempered key nearest their pitch and detunes them the rest of the
+    /// way, dropping notes on keys that don't play or would land outside the MIDI range
+    pub fn retune(&self, events: &mut Vec<MidiEvent>) {
+        events.retain_mut(|event| {
+            let [status, key, _] = &mut event.data;
+            if !matches!(*status & 0xf0, 0x80 | 0x90 | 0xa0) {
+                return true;
+            }
+            let Some(semitones) = self.semitones[*key as usize & 0x7f] else {
+                return false;
+            };
+            let nearest = semitones.round();
+            if !(0. ..=127.).contains(&nearest) {
+                return false;
+            }
+            *key = nearest as u8;
+            event.detune = ((semitones - nearest) * 100.).round() as i8;
+            true
+        });
+    }
+
+    /// Single note tuning change messages retuning every key that plays, as real-time MTS SysEx
+    /// for tuning program 0
+    pub fn sysex(&self) -> Vec<Vec<u8>> {
+        let changes: Vec<[u8; 4]> = self
+            .semitones
+            .iter()
+            .enumerate()
+            .filter_map(|(key, &semitones)| {
+                let semitones = semitones?.clamp(0., 127.);
+                let whole = semitones.floor();
+                // The rest of the semitone in 14 bits
+                let fraction = (((semitones - whole) * 16384.).round() as u16).min(16383);
+                Some([
+                    key as u8,
+                    whole as u8,
+                    (fraction >> 7) as u8,
+                    (fraction & 0x7f) as u8,
+                ])
+            })
+            .collect();
+
+        changes
+            .chunks(KEYS_PER_MESSAGE)
+            .map(|changes| {
+                let mut message = vec![0xf0, 0x7f, 0x7f, 0x08, 0x02, 0x00, changes.len() as u8];
+                message.extend(changes.iter().flatten());
+                message.push(0xf7);
+                message
+            })
+            .collect()
+    }
+}
+
+/// The first whitespace separated word of a line, where the rest is a comment
+fn first_word(line: &str) -> &str {
+    line.split_whitespace().next().unwrap_or("")
+}
+
+/// A Scala pitch in cents: cents if it has a period, otherwise a ratio like `3/2` or `2`
+fn pitch_cents(pitch: &str) -> Result<f64> {
+    if pitch.contains('.') {
+        return Ok(pitch.parse()?);
+    }
+    let (numerator, denominator) = pitch.split_once('/').unwrap_or((pitch, "1"));
+    let numerator: f64 = numerator.parse::<u64>()? as f64;
+    let denominator: f64 = denominator.parse::<u64>()? as f64;
+    ensure!(
+        numerator > 0. && denominator > 0.,
+        "ratios have to be positive"
+    );
+    Ok(1200. * (numerator / denominator).log2())
+}
+
+#[cfg(test)]
+mod tests {
+    use vst::event::MidiEvent;
+
+    use super::{KeyboardMap, Scale, Tuning};
+
+    /// 24 equal steps to the octave
+    fn quarter_tones() -> Scale {
+        Scale {
+            description: String::new(),
+            degrees: (1..=24).map(|step| step as f64 * 50.).collect(),
+        }
+    }
+
+    fn note_on(key: u8) -> MidiEvent {
+        MidiEvent {
+            data: [0x90, key, 100],
+            delta_frames: 0,
+            live: false,
+            note_length: None,
+            note_offset: None,
+            detune: 0,
+            note_off_velocity: 0,
+        }
+    }
+
+    #[test]
+    fn parses_scales() {
+        let scale =
+            Scale::parse("! fifths.scl\n!\nJust fifths\n 3\n!\n 200.0 cents\n 3/2\n 2\n").unwrap();
+        assert_eq!(scale.description, "Just fifths");
+        assert_eq!(scale.degrees.len(), 3);
+        assert_eq!(scale.degrees[0], 200.);
+        assert!((scale.degrees[1] - 701.955).abs() < 0.001);
+        assert_eq!(scale.degrees[2], 1200.);
+
+        assert!(Scale::parse("Too short\n3\n100.0\n200.0\n").is_err());
+        assert!(Scale::parse("Negative\n1\n-3/2\n").is_err());
+    }
+
+    #[test]
+    fn keys_marked_x_or_left_out_are_unmapped() {
+        let map = KeyboardMap::parse(
+            "! white keys up to G\n12\n0\n127\n60\n69\n440.0\n7\n0\nx\n1\nx\n2\n3\nx\n4\n",
+        )
+        .unwrap();
+        assert_eq!(map.degree(60), Some(0));
+        assert_eq!(map.degree(61), None);
+        assert_eq!(map.degree(62), Some(1));
+        assert_eq!(map.degree(67), Some(4));
+        // Past the end of the mapping
+        assert_eq!(map.degree(69), None);
+        assert_eq!(map.degree(72), Some(7));
+        assert_eq!(map.degree(50), Some(-6));
+    }
+
+    #[test]
+    fn unmapped_keys_dont_play() {
+        let scale = quarter_tones();
+        let map = KeyboardMap::parse("2\n0\n127\n60\n60\n440.0\n1\n0\nx\n").unwrap();
+        let tuning = Tuning::new(&scale, &map).unwrap();
+
+        let mut events = vec![note_on(60), note_on(61), note_on(62)];
+        tuning.retune(&mut events);
+        assert_eq!(
+            events.iter().map(|event| event.data[1]).collect::<Vec<_>>(),
+            [69, 70]
+        );
+    }
+
+    #[test]
+    fn retunes_to_the_nearest_key_and_detunes_the_rest() {
+        // Middle C plays degree 0 and A above it, degree 9, stays at 440 Hz
+        let tuning = Tuning::new(&quarter_tones(), &KeyboardMap::default()).unwrap();
+        let mut events = vec![note_on(69), note_on(60)];
+        tuning.retune(&mut events);
+        assert_eq!((events[0].data[1], events[0].detune), (69, 0));
+        assert_eq!((events[1].data[1], events[1].detune), (65, -50));
+    }
+
+    #[test]
+    fn sysex_carries_14_bit_fractions_of_a_semitone() {
+        let mut semitones = [None; 128];
+        semitones[60] = Some(60.25);
+        // Rounds up to a whole semitone, which doesn't fit in the fraction
+        semitones[61] = Some(61.99999);
+        let messages = Tuning { semitones }.sysex();
+        assert_eq!(
+            messages,
+            [vec![
+                0xf0, 0x7f, 0x7f, 0x08, 0x02, 0x00, 2, //
+                60, 60, 0x20, 0x00, //
+                61, 61, 0x7f, 0x7f, //
+                0xf7,
+            ]]
+        );
+    }
+
+    #[test]
+    fn sysex_splits_every_key_over_two_messages() {
+        let tuning = Tuning::new(&quarter_tones(), &KeyboardMap::default()).unwrap();
+        let messages = tuning.sysex();
+        assert_eq!(messages.len(), 2);
+        assert!(messages.iter().all(|message| message[6] == 64));
+    }
+}
@@ -0,0 +1,215 @@
+use std::str::FromStr;
+
+use anyhow::{anyhow, ensure, Context};
+use vst::event::MidiEvent;
+
+/// How played velocities map onto the velocities the plugin gets
+#[derive(Clone, Debug, PartialEq)]
+pub enum Curve {
+    Linear,
+    /// Louder for light playing, for stiff keyboards
+    Soft,
+    /// Quieter for light playing, for keyboards that are easy to play loudly
+    Hard,
+    /// Linear interpolation between (played, sent) breakpoints, sorted by played velocity
+    Table(Vec<(u8, u8)>),
+}
+
+impl Curve {
+    /// Exponent of the soft curve, the hard one uses its inverse
+    const SOFTNESS: f32 = 0.6;
+
+    /// The velocity sent for each played one, keeping note-ons from turning into note-offs
+    fn table(&self) -> [u8; 128] {
+        let mut table = [0; 128];
+        for (velocity, sent) in table.iter_mut().enumerate() {
+            let x = velocity as f32 / 127.;
+            let y = match self {
+                Curve::Linear => x,
+                Curve::Soft => x.powf(Self::SOFTNESS),
+                Curve::Hard => x.powf(1. / Self::SOFTNESS),
+                Curve::Table(points) => {
+                    let velocity = velocity as u8;
+                    let after = points
+                        .iter()
+                        .position(|&(played, _)| played >= velocity)
+                        .unwrap_or(points.len() - 1)
+                        .max(1);
+                    let ((x0, y0), (x1, y1)) = (points[after - 1], points[after]);
+                    let fraction = if x1 > x0 {
+                        ((velocity as f32 - x0 as f32) / (x1 - x0) as f32).clamp(0., 1.)
+                    } else {
+                        1.
+                    };
+                    (y0 as f32 + (y1 as f32 - y0 as f32) * fraction) / 127.
+                }
+            };
+            *sent = ((y * 127.).round() as u8).clamp(1, 127);
+        }
+        table
+    }
+}
+
+impl FromStr for Curve {
+    type Err = anyhow::Error;
+
+    /// Parses curves like `soft` or `table=0/20,64/80,127/127`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "linear" => Ok(Curve::Linear),
+            "soft" => Ok(Curve::Soft),
+            "hard" => Ok(Curve::Hard),
+            _ => {
+                let points = s
+                    .strip_prefix("table=")
+                    .ok_or_else(|| anyhow!("expected linear, soft, hard or table=<points>"))?;
+                let mut points = points
+                    .split(',')
+                    .map(|point| -> Result<(u8, u8), Self::Err> {
+                        let (played, sent) = point
+                            .trim()
+                            .split_once('/')
+                            .context("expected points like <played>/<sent>")?;
+                        let played: u8 = played.parse().context("invalid velocity")?;
+                        let sent: u8 = sent.parse().context("invalid velocity")?;
+                        ensure!(played <= 127 && sent <= 127, "velocities go from 0 to 127");
+                        Ok((played, sent))
+                    })
+                    .collect::<Result<Vec<_>, _>>()?;
+                ensure!(points.len() >= 2, "a table needs at least two points");
+                points.sort_by_key(|&(played, _)| played);
+                Ok(Curve::Table(points))
+            }
+        }
+    }
+}
+
+/// A velocity curve for one MIDI channel or all of them
+#[derive(Clone, Debug, PartialEq)]
+pub struct ChannelCurve {
+    /// From 0, or `None` for every channel
+    pub channel: Option<u8>,
+    pub curve: Curve,
+}
+
+impl FromStr for ChannelCurve {
+    type Err = anyhow::Error;
+
+    /// Parses a curve, optionally for a single channel from 1 to 16, like `10:hard`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (channel, curve) = match s.split_once(':') {
+            Some((channel, curve)) => {
+                let channel: u8 = channel.parse().context("invalid channel")?;
+                ensure!((1..=16).contains(&channel), "channels go from 1 to 16");
+                (Some(channel - 1), curve)
+            }
+            None => (None, s),
+        };
+
+        Ok(Self {
+            channel,
+            curve: curve.parse()?,
+        })
+    }
+}
+
+/// Reshapes the velocities of note-ons, per channel
+pub struct Velocity {
+    tables: [[u8; 128]; 16],
+}
+
+impl Velocity {
+    /// Curves for a single channel override one for every channel there, whichever came first
+    pub fn new(curves: &[ChannelCurve]) -> Self {
+        let mut tables = [Curve::Linear.table(); 16];
+        let (single, every): (Vec<_>, Vec<_>) =
+            curves.iter().partition(|curve| curve.channel.is_some());
+        for ChannelCurve { channel, curve } in every.into_iter().chain(single) {
+            let table = curve.table();
+            match channel {
+                Some(channel) => tables[*channel as usize] = table,
+                None => tables = [table; 16],
+            }
+        }
+
+        Self { tables }
+    }
+
+    pub fn process(&self, events: &mut [MidiEvent]) {
+        for event in events {
+            let [status, _, velocity] = &mut event.data;
+            if *status & 0xf0 == 0x90 && *velocity > 0 {
+                *velocity = self.tables[(*status & 0x0f) as usize][*velocity as usize & 0x7f];
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use vst::event::MidiEvent;
+
+    use super::{ChannelCurve, Curve, Velocity};
+
+    fn note_on(channel: u8, velocity: u8) -> MidiEvent {
+        MidiEvent {
+            data: [0x90 | channel, 60, velocity],
+            delta_frames: 0,
+            live: false,
+            note_length: None,
+            note_offset: None,
+            detune: 0,
+            note_off_velocity: 0,
+        }
+    }
+
+    #[test]
+    fn linear_keeps_every_velocity() {
+        let table = Curve::Linear.table();
+        assert!((1..128).all(|velocity| table[velocity] == velocity as u8));
+    }
+
+    #[test]
+    fn note_ons_never_turn_into_note_offs() {
+        let silent: Curve = "table=0/0,100/0,127/127".parse().unwrap();
+        for curve in [Curve::Hard, silent] {
+            assert!(curve.table()[1..].iter().all(|&velocity| velocity >= 1));
+        }
+        assert_eq!(Curve::Hard.table()[1], 1);
+    }
+
+    #[test]
+    fn tables_interpolate_between_their_points() {
+        let curve: Curve = "table=64/80,0/20,127/127".parse().unwrap();
+        let table = curve.table();
+        assert_eq!(table[0], 20);
+        assert_eq!(table[32], 50);
+        assert_eq!(table[64], 80);
+        assert_eq!(table[127], 127);
+    }
+
+    #[test]
+    fn rejects_bad_curves() {
+        assert!("table=0/20".parse::<Curve>().is_err());
+        assert!("table=0/20,128/127".parse::<Curve>().is_err());
+        assert!("squishy".parse::<Curve>().is_err());
+        assert!("17:soft".parse::<ChannelCurve>().is_err());
+    }
+
+    #[test]
+    fn single_channel_curves_win_over_ones_for_every_channel() {
+        let curves: Vec<ChannelCurve> = ["10:table=0/127,127/127", "table=0/1,127/1"]
+            .iter()
+            .map(|curve| curve.parse().unwrap())
+            .collect();
+        let velocity = Velocity::new(&curves);
+
+        let mut events = [note_on(9, 64), note_on(0, 64), note_on(0, 0)];
+        velocity.process(&mut events);
+        assert_eq!(
+            events.map(|event| event.data[2]),
+            [127, 1, 0],
+            "note-ons with velocity 0 are note-offs and stay that way"
+        );
+    }
+}
@@ -0,0 +1,72 @@
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+/// Watches for plugins that hang inside `process()`.
+///
+/// The audio thread brackets every call into the plugin with [`enter`](Self::enter) and
+/// [`leave`](Self::leave), and a background thread reports calls that take longer than a threshold.
+///
+/// The plugin runs on the output stream's thread, so a hung call stalls the stream until it
+/// returns. The watchdog can only report it, not keep the output going.
+pub struct Watchdog {
+    epoch: Instant,
+    /// Milliseconds since `epoch` plus one at which the current call started, or zero when idle
+    entered: AtomicU64,
+}
+
+impl Watchdog {
+    pub fn new() -> Self {
+        Self {
+            epoch: Instant::now(),
+            entered: AtomicU64::new(0),
+        }
+    }
+
+    fn now(&self) -> u64 {
+        self.epoch.elapsed().as_millis() as u64 + 1
+    }
+
+    pub fn enter(&self) {
+        self.entered.store(self.now(), Ordering::Relaxed);
+    }
+
+    pub fn leave(&self) {
+        self.entered.store(0, Ordering::Relaxed);
+    }
+
+    /// Starts a thread reporting calls that take longer than `threshold`
+    pub fn spawn(self: &Arc<Self>, threshold: Duration) {
+        let watchdog = self.clone();
+
+        thread::spawn(move || {
+            let mut reported = 0;
+
+            loop {
+                thread::sleep(threshold / 4);
+
+                let entered = watchdog.entered.load(Ordering::Relaxed);
+                if entered == 0 || entered == reported {
+                    continue;
+                }
+
+                let stuck = Duration::from_millis(watchdog.now() - entered);
+                if stuck >= threshold {
+                    reported = entered;
+                    log::warn!("the plugin has been stuck in process() for {stuck:?}");
+                }
+            }
+        });
+    }
+}
+
+impl Default for Watchdog {
+    fn default() -> Self {
+        Self::new()
+    }
+}